@@ -1,9 +1,11 @@
 // API client for PDF.dk
 // Handles file upload, job polling, and download
 
+use once_cell::sync::Lazy;
 use reqwest::{multipart, Client};
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
 use std::time::Duration;
 use thiserror::Error;
 use tokio::fs;
@@ -14,6 +16,16 @@ const API_BASE_URL: &str = "https://pdf.dk/api";
 const POLL_INTERVAL: Duration = Duration::from_secs(2);
 const MAX_POLL_ATTEMPTS: u32 = 300; // 10 minutes max
 
+// Session ID sent with every request - guest usage is counted against it
+static SESSION_ID: Lazy<RwLock<String>> = Lazy::new(|| RwLock::new(Uuid::new_v4().to_string()));
+
+/// Use a persistent session ID so guest quota survives app restarts
+pub fn set_session_id(session_id: &str) {
+    if let Ok(mut id) = SESSION_ID.write() {
+        *id = session_id.to_string();
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum ApiError {
     #[error("Network error: {0}")]
@@ -28,10 +40,138 @@ pub enum ApiError {
     ServerError(String),
     #[error("Unauthorized - please login again")]
     Unauthorized,
-    #[error("Monthly job limit exceeded")]
-    JobLimitExceeded,
+    #[error("Monthly job limit exceeded{}", describe_job_limit(.limit, .resets_at))]
+    JobLimitExceeded {
+        limit: Option<i32>,
+        resets_at: Option<String>,
+    },
+    #[error("Too many requests{}", describe_retry(.retry_after_secs))]
+    RateLimited { retry_after_secs: Option<u64> },
+    #[error("PDF.dk is down for maintenance{}{}", describe_message(.message), describe_retry(.retry_after_secs))]
+    Maintenance {
+        message: Option<String>,
+        retry_after_secs: Option<u64>,
+    },
     #[error("File too large for your plan (max {0} MB)")]
     FileTooLarge(i32),
+    #[error("Invalid tool options: {0}")]
+    InvalidOptions(String),
+    #[error("The PDF is damaged and could not be read: {0}")]
+    DamagedPdf(String),
+}
+
+// Error codes the API uses for PDFs it cannot parse
+const DAMAGED_PDF_ERROR_CODES: [&str; 4] = ["pdf_parse_error", "damaged_pdf", "corrupt_pdf", "invalid_pdf"];
+
+/// Max file size assumed when a 413 response doesn't say
+const DEFAULT_MAX_FILE_SIZE_MB: i32 = 100;
+
+// Error codes the API uses for the monthly job quota (as opposed to request rate limiting)
+const JOB_LIMIT_ERROR_CODES: [&str; 3] = ["job_limit_exceeded", "quota_exceeded", "monthly_limit_exceeded"];
+
+fn describe_job_limit(limit: &Option<i32>, resets_at: &Option<String>) -> String {
+    let mut text = String::new();
+    if let Some(limit) = limit {
+        text.push_str(&format!(" ({} jobs)", limit));
+    }
+    if let Some(resets_at) = resets_at {
+        text.push_str(&format!(" - resets {}", resets_at));
+    }
+    text
+}
+
+fn describe_retry(retry_after_secs: &Option<u64>) -> String {
+    match retry_after_secs {
+        Some(secs) => format!(" - try again in {} seconds", secs),
+        None => String::new(),
+    }
+}
+
+fn describe_message(message: &Option<String>) -> String {
+    match message {
+        Some(message) => format!(": {}", message),
+        None => String::new(),
+    }
+}
+
+/// Turn a failed response into a typed error using the status, the
+/// Retry-After header and whatever details the JSON body provides.
+/// Fields are read from the top level of the body or from its "data" object.
+pub fn decode_error_response(
+    status: reqwest::StatusCode,
+    retry_after: Option<&str>,
+    body: &str,
+) -> ApiError {
+    let json: serde_json::Value = serde_json::from_str(body).unwrap_or(serde_json::Value::Null);
+    let field = |key: &str| json.get(key).or_else(|| json.get("data").and_then(|d| d.get(key)));
+    let text = |key: &str| field(key).and_then(|v| v.as_str()).map(String::from);
+    let number = |key: &str| field(key).and_then(|v| v.as_i64().or_else(|| v.as_str()?.parse().ok()));
+
+    let message = text("message").or_else(|| text("error")).map(|m| crate::log_redact::error_excerpt(&m));
+    let code = text("error_code").or_else(|| text("code")).map(|c| c.to_lowercase());
+    let retry_after_secs = retry_after
+        .and_then(parse_retry_after)
+        .or_else(|| number("retry_after").map(|n| n.max(0) as u64));
+
+    match status {
+        reqwest::StatusCode::UNAUTHORIZED => ApiError::Unauthorized,
+        reqwest::StatusCode::PAYLOAD_TOO_LARGE => ApiError::FileTooLarge(
+            number("max_file_size_mb")
+                .or_else(|| number("max_size_mb"))
+                .map(|n| n as i32)
+                .unwrap_or(DEFAULT_MAX_FILE_SIZE_MB),
+        ),
+        reqwest::StatusCode::TOO_MANY_REQUESTS => {
+            let is_job_limit = code
+                .as_deref()
+                .is_some_and(|c| JOB_LIMIT_ERROR_CODES.contains(&c))
+                || field("limit").is_some();
+            if is_job_limit {
+                ApiError::JobLimitExceeded {
+                    limit: number("limit").map(|n| n as i32),
+                    resets_at: text("reset_at").or_else(|| text("resets_at")),
+                }
+            } else {
+                ApiError::RateLimited { retry_after_secs }
+            }
+        }
+        reqwest::StatusCode::SERVICE_UNAVAILABLE => ApiError::Maintenance {
+            message,
+            retry_after_secs,
+        },
+        _ if code.as_deref().is_some_and(|c| DAMAGED_PDF_ERROR_CODES.contains(&c)) => {
+            ApiError::DamagedPdf(message.unwrap_or_else(|| "unreadable PDF structure".to_string()))
+        }
+        _ => ApiError::ServerError(format!(
+            "Server returned {}: {}",
+            status,
+            message.unwrap_or_else(|| crate::log_redact::error_excerpt(body))
+        )),
+    }
+}
+
+/// Parse a Retry-After header given either as seconds or as an HTTP date
+fn parse_retry_after(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(secs);
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let secs = (date.with_timezone(&chrono::Utc) - chrono::Utc::now()).num_seconds();
+    Some(secs.max(0) as u64)
+}
+
+/// Read a failed response and decode it into a typed error
+async fn error_from_response(response: reqwest::Response) -> ApiError {
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+    let body = response.text().await.unwrap_or_default();
+    crate::log_redact::log_body(&format!("Error response {}", status), &body);
+    decode_error_response(status, retry_after.as_deref(), &body)
 }
 
 // Response from upload endpoints (compress, pdf-to-word, etc.)
@@ -74,6 +214,152 @@ pub struct UsageStatusData {
     pub max_file_size_mb: Option<i32>,
 }
 
+// Capabilities handshake response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapabilitiesResponse {
+    pub success: bool,
+    pub data: Option<ApiCapabilities>,
+    pub message: Option<String>,
+}
+
+/// Features supported by the backend, negotiated once at startup.
+/// Everything defaults to off so older servers get the plain upload/poll/download flow.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ApiCapabilities {
+    pub api_version: u32,
+    // Not consulted yet: jobs are uploaded in a single request, their status
+    // is polled and there is no region setting. Features built on these
+    // check them with `supports`.
+    pub batch_upload: bool,
+    pub sse: bool,
+    pub chunked_upload: bool,
+    pub regions: Vec<String>,
+    /// Jobs sent with `test_job` are not counted against the quota
+    pub test_jobs: bool,
+}
+
+impl ApiCapabilities {
+    /// Check a feature by name, e.g. "sse" or "chunked_upload"
+    pub fn supports(&self, feature: &str) -> bool {
+        match feature {
+            "batch_upload" => self.batch_upload,
+            "sse" => self.sse,
+            "chunked_upload" => self.chunked_upload,
+            "test_jobs" => self.test_jobs,
+            "regions" => !self.regions.is_empty(),
+            _ => false,
+        }
+    }
+}
+
+/// Hashes of a finished job, sent to the server to be signed
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReceiptClaims {
+    pub job_uuid: String,
+    pub input_sha256: String,
+    pub output_sha256: String,
+}
+
+/// Signature issued by pdf.dk over a job's receipt claims
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiptSignature {
+    pub signature: String,
+    pub key_id: String,
+    pub algorithm: String,
+    pub signed_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiptSignatureResponse {
+    pub success: bool,
+    pub data: Option<ReceiptSignature>,
+    pub message: Option<String>,
+}
+
+/// Server verdict on a receipt signature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiptVerdict {
+    pub valid: bool,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiptVerdictResponse {
+    pub success: bool,
+    pub data: Option<ReceiptVerdict>,
+    pub message: Option<String>,
+}
+
+// Tool catalog response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolsResponse {
+    pub success: bool,
+    pub data: Option<Vec<ApiTool>>,
+    pub message: Option<String>,
+}
+
+/// Tool definition as returned by the API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiTool {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub name_da: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub description_da: Option<String>,
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    #[serde(default)]
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub options_schema: Option<serde_json::Value>,
+    #[serde(default)]
+    pub output_extension: Option<String>,
+    #[serde(default)]
+    pub multi_input: bool,
+    #[serde(default)]
+    pub report_output: bool,
+    #[serde(default)]
+    pub extract_zip: bool,
+    #[serde(default)]
+    pub paired_input: bool,
+    #[serde(default)]
+    pub input_extensions: Option<Vec<String>>,
+}
+
+impl From<ApiTool> for crate::config::ToolDefinition {
+    fn from(tool: ApiTool) -> Self {
+        let has_options = tool
+            .options_schema
+            .as_ref()
+            .is_some_and(|schema| !schema.is_null() && schema.as_object().is_none_or(|o| !o.is_empty()));
+
+        Self {
+            name_da: tool.name_da.unwrap_or_else(|| tool.name.clone()),
+            description_da: tool.description_da.unwrap_or_else(|| tool.description.clone()),
+            api_endpoint: tool.endpoint.unwrap_or_else(|| tool.id.clone()),
+            icon: tool.icon.unwrap_or_else(|| "file".to_string()),
+            output_extension: tool.output_extension.unwrap_or_else(|| "pdf".to_string()),
+            options_schema: tool.options_schema,
+            has_options,
+            multi_input: tool.multi_input,
+            report_output: tool.report_output,
+            extract_zip: tool.extract_zip,
+            paired_input: tool.paired_input,
+            input_extensions: tool
+                .input_extensions
+                .map(|exts| exts.iter().map(|e| e.trim_start_matches('.').to_lowercase()).collect())
+                .unwrap_or_else(crate::config::default_input_extensions),
+            id: tool.id,
+            name: tool.name,
+            description: tool.description,
+        }
+    }
+}
+
 // Response from job status polling
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JobStatusResponse {
@@ -91,6 +377,9 @@ pub struct JobStatusData {
     pub output_path: Option<String>,
     pub output_filename: Option<String>,
     pub error: Option<String>,
+    /// File name of a report produced next to the result (e.g. PDF/A validation)
+    #[serde(default)]
+    pub report_filename: Option<String>,
     #[serde(flatten)]
     pub extra: serde_json::Value,
 }
@@ -125,43 +414,101 @@ pub struct PdfDkClient {
 
 impl PdfDkClient {
     pub fn new(auth_token: Option<String>) -> Self {
-        let client = Client::builder()
+        let client = crate::proxy::client_builder()
             .timeout(Duration::from_secs(300))
             .build()
             .expect("Failed to create HTTP client");
 
-        // Generate a session ID for this client instance
-        let session_id = Uuid::new_v4().to_string();
+        // All clients share the app's session ID
+        let session_id = SESSION_ID
+            .read()
+            .map(|id| id.clone())
+            .unwrap_or_else(|_| Uuid::new_v4().to_string());
 
         Self { client, auth_token, session_id }
     }
 
     /// Process a PDF file with the specified tool
     /// Returns the job UUID for polling
+    /// `attachments` are extra files uploaded with the job (e.g. a watermark
+    /// image), as (form field, path) pairs
     pub async fn process_file(
         &self,
         file_path: &Path,
+        attachments: &[(String, PathBuf)],
+        tool: &str,
+        options: serde_json::Value,
+    ) -> Result<String, ApiError> {
+        info!("Uploading file: {:?} for tool: {}", file_path.file_name(), tool);
+
+        let mut form = multipart::Form::new().part("file", Self::file_part(file_path).await?);
+        for (field, path) in attachments {
+            form = form.part(field.clone(), Self::file_part(path).await?);
+        }
+        self.submit_job(form, tool, options).await
+    }
+
+    /// Process several PDF files as one job (e.g. merge), in the given order
+    /// Returns the job UUID for polling
+    pub async fn process_files(
+        &self,
+        file_paths: &[PathBuf],
         tool: &str,
         options: serde_json::Value,
     ) -> Result<String, ApiError> {
+        info!("Uploading {} files for tool: {}", file_paths.len(), tool);
+
+        let mut form = multipart::Form::new();
+        for path in file_paths {
+            form = form.part("files[]", Self::file_part(path).await?);
+        }
+        self.submit_job(form, tool, options).await
+    }
+
+    async fn file_part(file_path: &Path) -> Result<multipart::Part, ApiError> {
         let file_name = file_path
             .file_name()
             .and_then(|n| n.to_str())
             .unwrap_or("file.pdf")
             .to_string();
 
-        info!("Uploading file: {} for tool: {}", file_name, tool);
+        // Reading large files at once is throttled with the other local work
+        let path = file_path.to_path_buf();
+        let file_bytes = crate::local_work::run(move || std::fs::read(path))
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))??;
 
-        let file_bytes = fs::read(file_path).await?;
+        Ok(multipart::Part::bytes(file_bytes)
+            .file_name(file_name)
+            .mime_str(Self::mime_type(file_path))
+            .unwrap())
+    }
 
-        let mut form = multipart::Form::new().part(
-            "file",
-            multipart::Part::bytes(file_bytes)
-                .file_name(file_name.clone())
-                .mime_str("application/pdf")
-                .unwrap(),
-        );
+    fn mime_type(file_path: &Path) -> &'static str {
+        let extension = file_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        match extension.as_deref() {
+            Some("pdf") => "application/pdf",
+            Some("png") => "image/png",
+            Some("jpg") | Some("jpeg") => "image/jpeg",
+            Some("tif") | Some("tiff") => "image/tiff",
+            Some("svg") => "image/svg+xml",
+            Some("docx") => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            Some("xlsx") => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            Some("pptx") => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+            _ => "application/octet-stream",
+        }
+    }
 
+    /// Upload the files in `form` with the tool options and return the job UUID
+    async fn submit_job(
+        &self,
+        mut form: multipart::Form,
+        tool: &str,
+        options: serde_json::Value,
+    ) -> Result<String, ApiError> {
         // Add options as form fields
         if let Some(obj) = options.as_object() {
             for (key, value) in obj {
@@ -190,37 +537,18 @@ impl PdfDkClient {
 
         let status = response.status();
 
-        if status == reqwest::StatusCode::UNAUTHORIZED {
-            return Err(ApiError::Unauthorized);
-        }
-
-        // Handle rate limiting (429) - job limit exceeded
-        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
-            return Err(ApiError::JobLimitExceeded);
-        }
-
-        // Handle file too large (413)
-        if status == reqwest::StatusCode::PAYLOAD_TOO_LARGE {
-            // Try to parse the response to get the max file size
-            let body = response.text().await.unwrap_or_default();
-            // Default to 100MB if we can't parse
-            return Err(ApiError::FileTooLarge(100));
+        // 401, job limit or rate limiting (429), file too large (413), maintenance (503)...
+        if !status.is_success() {
+            return Err(error_from_response(response).await);
         }
 
         let body = response.text().await.unwrap_or_default();
 
         info!("API Response status: {}", status);
-        info!("API Response body: {}", body);
-
-        if !status.is_success() {
-            return Err(ApiError::ServerError(format!(
-                "Server returned {}: {}",
-                status, body
-            )));
-        }
+        crate::log_redact::log_body("API Response body", &body);
 
         let upload_response: UploadResponse = serde_json::from_str(&body)
-            .map_err(|e| ApiError::ServerError(format!("Failed to parse response: {} - Body: {}", e, body)))?;
+            .map_err(|e| ApiError::ServerError(format!("Failed to parse response: {} - Body: {}", e, crate::log_redact::redact(&body))))?;
 
         if !upload_response.success {
             return Err(ApiError::ServerError(
@@ -259,15 +587,15 @@ impl PdfDkClient {
 
             let response = request.send().await?;
 
-            if response.status() == reqwest::StatusCode::UNAUTHORIZED {
-                return Err(ApiError::Unauthorized);
+            if !response.status().is_success() {
+                return Err(error_from_response(response).await);
             }
 
             let body = response.text().await.unwrap_or_default();
-            debug!("Poll response: {}", body);
+            crate::log_redact::log_body("Poll response", &body);
 
             let job_response: JobStatusResponse = serde_json::from_str(&body)
-                .map_err(|e| ApiError::ServerError(format!("Failed to parse poll response: {} - Body: {}", e, body)))?;
+                .map_err(|e| ApiError::ServerError(format!("Failed to parse poll response: {} - Body: {}", e, crate::log_redact::redact(&body))))?;
 
             if !job_response.success {
                 // Check if it's an auth error or actual job error
@@ -286,9 +614,16 @@ impl PdfDkClient {
                         return Ok(job);
                     }
                     JobStatus::Failed => {
-                        return Err(ApiError::JobFailed(
-                            job.error.unwrap_or_else(|| "Unknown error".to_string()),
-                        ));
+                        let message = job.error.clone().unwrap_or_else(|| "Unknown error".to_string());
+                        let damaged = job
+                            .extra
+                            .get("error_code")
+                            .and_then(|c| c.as_str())
+                            .is_some_and(|c| DAMAGED_PDF_ERROR_CODES.contains(&c.to_lowercase().as_str()));
+                        if damaged {
+                            return Err(ApiError::DamagedPdf(message));
+                        }
+                        return Err(ApiError::JobFailed(message));
                     }
                     _ => {
                         // Still processing, wait and retry
@@ -306,10 +641,19 @@ impl PdfDkClient {
     /// Download the completed file
     pub async fn download_result(&self, uuid: &str, output_path: &Path) -> Result<(), ApiError> {
         let url = format!("{}/jobs/{}/download", API_BASE_URL, uuid);
-
         info!("Downloading result to: {:?}", output_path);
+        self.download_to(&url, output_path).await
+    }
 
-        let mut request = self.client.get(&url)
+    /// Download the report of a completed job (see `JobStatusData::report_filename`)
+    pub async fn download_report(&self, uuid: &str, output_path: &Path) -> Result<(), ApiError> {
+        let url = format!("{}/jobs/{}/report", API_BASE_URL, uuid);
+        info!("Downloading report to: {:?}", output_path);
+        self.download_to(&url, output_path).await
+    }
+
+    async fn download_to(&self, url: &str, output_path: &Path) -> Result<(), ApiError> {
+        let mut request = self.client.get(url)
             .header("X-Session-ID", &self.session_id)
             .header("Accept", "application/octet-stream");
 
@@ -319,16 +663,8 @@ impl PdfDkClient {
 
         let response = request.send().await?;
 
-        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
-            return Err(ApiError::Unauthorized);
-        }
-
         if !response.status().is_success() {
-            let body = response.text().await.unwrap_or_default();
-            return Err(ApiError::ServerError(format!(
-                "Download failed: {}",
-                body
-            )));
+            return Err(error_from_response(response).await);
         }
 
         let bytes = response.bytes().await?;
@@ -364,10 +700,10 @@ impl PdfDkClient {
         }
 
         let body = response.text().await.unwrap_or_default();
-        debug!("Usage status response: {}", body);
+        crate::log_redact::log_body("Usage status response", &body);
 
         let usage_response: UsageStatusResponse = serde_json::from_str(&body)
-            .map_err(|e| ApiError::ServerError(format!("Failed to parse usage response: {} - Body: {}", e, body)))?;
+            .map_err(|e| ApiError::ServerError(format!("Failed to parse usage response: {} - Body: {}", e, crate::log_redact::redact(&body))))?;
 
         if !usage_response.success {
             return Err(ApiError::ServerError(
@@ -379,23 +715,114 @@ impl PdfDkClient {
             .ok_or(ApiError::ServerError("No usage data returned".to_string()))
     }
 
-    /// Full process: upload, poll, download
-    pub async fn process_and_download(
+    /// Have pdf.dk sign the hashes of a completed job
+    pub async fn sign_receipt(&self, claims: &ReceiptClaims) -> Result<ReceiptSignature, ApiError> {
+        let url = format!("{}/jobs/{}/receipt", API_BASE_URL, claims.job_uuid);
+        let body = self.post_json(&url, claims).await?;
+
+        let response: ReceiptSignatureResponse = serde_json::from_str(&body)
+            .map_err(|e| ApiError::ServerError(format!("Failed to parse receipt response: {} - Body: {}", e, crate::log_redact::redact(&body))))?;
+        response.data.ok_or_else(|| {
+            ApiError::ServerError(response.message.unwrap_or_else(|| "No receipt returned".to_string()))
+        })
+    }
+
+    /// Ask pdf.dk whether a receipt signature is genuine for the given claims
+    pub async fn verify_receipt(
         &self,
-        input_path: &Path,
-        output_path: &Path,
-        tool: &str,
-        options: serde_json::Value,
-    ) -> Result<(), ApiError> {
-        // Upload and start processing
-        let job_uuid = self.process_file(input_path, tool, options).await?;
+        claims: &ReceiptClaims,
+        signature: &ReceiptSignature,
+    ) -> Result<ReceiptVerdict, ApiError> {
+        let url = format!("{}/receipts/verify", API_BASE_URL);
+        let payload = serde_json::json!({ "claims": claims, "signature": signature });
+        let body = self.post_json(&url, &payload).await?;
+
+        let response: ReceiptVerdictResponse = serde_json::from_str(&body)
+            .map_err(|e| ApiError::ServerError(format!("Failed to parse verify response: {} - Body: {}", e, crate::log_redact::redact(&body))))?;
+        response.data.ok_or_else(|| {
+            ApiError::ServerError(response.message.unwrap_or_else(|| "No verification result returned".to_string()))
+        })
+    }
 
-        // Poll until complete
-        let _completed_job = self.poll_job(&job_uuid).await?;
+    async fn post_json(&self, url: &str, payload: &impl Serialize) -> Result<String, ApiError> {
+        let mut request = self.client.post(url)
+            .json(payload)
+            .header("X-Session-ID", &self.session_id)
+            .header("Accept", "application/json");
 
-        // Download result
-        self.download_result(&job_uuid, output_path).await?;
+        if let Some(ref token) = self.auth_token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
 
-        Ok(())
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(error_from_response(response).await);
+        }
+        Ok(response.text().await.unwrap_or_default())
+    }
+
+    /// Fetch the tool catalog
+    pub async fn get_tools(&self) -> Result<Vec<ApiTool>, ApiError> {
+        let url = format!("{}/tools", API_BASE_URL);
+
+        let response = self.client.get(&url)
+            .header("X-Session-ID", &self.session_id)
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::ServerError(format!(
+                "Tool catalog request failed: {}",
+                response.status()
+            )));
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        crate::log_redact::log_body("Tools response", &body);
+
+        let tools_response: ToolsResponse = serde_json::from_str(&body)
+            .map_err(|e| ApiError::ServerError(format!("Failed to parse tool catalog: {} - Body: {}", e, crate::log_redact::redact(&body))))?;
+
+        if !tools_response.success {
+            return Err(ApiError::ServerError(
+                tools_response.message.unwrap_or_else(|| "Unknown error".to_string()),
+            ));
+        }
+
+        tools_response.data
+            .ok_or(ApiError::ServerError("No tools returned".to_string()))
+    }
+
+    /// Ask the backend which optional features it supports
+    /// Servers without the endpoint are treated as supporting none of them
+    pub async fn get_capabilities(&self) -> Result<ApiCapabilities, ApiError> {
+        let url = format!("{}/capabilities", API_BASE_URL);
+
+        let response = self.client.get(&url)
+            .header("X-Session-ID", &self.session_id)
+            .header("Accept", "application/json")
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            info!("Server has no capabilities endpoint, using basic feature set");
+            return Ok(ApiCapabilities::default());
+        }
+
+        if !response.status().is_success() {
+            return Err(ApiError::ServerError(format!(
+                "Capabilities request failed: {}",
+                response.status()
+            )));
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        crate::log_redact::log_body("Capabilities response", &body);
+
+        let capabilities_response: CapabilitiesResponse = serde_json::from_str(&body)
+            .map_err(|e| ApiError::ServerError(format!("Failed to parse capabilities: {} - Body: {}", e, crate::log_redact::redact(&body))))?;
+
+        Ok(capabilities_response.data.unwrap_or_default())
     }
 }
@@ -1,13 +1,18 @@
 // Authentication module for PDF.dk Desktop
 // Handles login, token storage, and PRO subscription validation
 
-use crate::config::{self, AuthConfig};
-use reqwest::Client;
+use crate::api::PdfDkClient;
+use crate::config::{self, AuthConfig, SavedAccount};
+use crate::secrets;
 use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
 use thiserror::Error;
 
 const API_BASE_URL: &str = "https://pdf.dk/api";
 
+/// Refresh the access token when it has less than this many seconds left
+const REFRESH_MARGIN_SECS: u64 = 300;
+
 #[derive(Error, Debug)]
 pub enum AuthError {
     #[error("Network error: {0}")]
@@ -38,6 +43,18 @@ pub struct AuthState {
     pub jobs_remaining: Option<i32>,
     pub max_file_size_mb: Option<i32>,
     pub is_unlimited: Option<bool>,
+    // Access token expiry (unix seconds), if the server reported one
+    #[serde(default)]
+    pub token_expires_at: Option<u64>,
+    // Set when the token expired mid-session and could not be renewed
+    #[serde(default)]
+    pub session_expired: bool,
+    // Processing without an account, limited by the guest quota
+    #[serde(default)]
+    pub is_guest: bool,
+    // Never sent to the frontend
+    #[serde(skip)]
+    pub refresh_token: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -65,6 +82,25 @@ struct LoginResponse {
 struct LoginData {
     user: ApiUser,
     token: String,
+    #[serde(default)]
+    refresh_token: Option<String>,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RefreshResponse {
+    success: bool,
+    data: Option<RefreshData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RefreshData {
+    token: String,
+    #[serde(default)]
+    refresh_token: Option<String>,
+    #[serde(default)]
+    expires_in: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -103,7 +139,7 @@ struct ApiUserFull {
 
 /// Login to PDF.dk and get authentication token
 pub async fn login(email: &str, password: &str) -> Result<AuthState, AuthError> {
-    let client = Client::new();
+    let client = crate::proxy::client_builder().build()?;
 
     let response = client
         .post(format!("{}/auth/login", API_BASE_URL))
@@ -120,7 +156,7 @@ pub async fn login(email: &str, password: &str) -> Result<AuthState, AuthError>
     let body = response.text().await?;
 
     // Log for debugging
-    tracing::debug!("Login response status: {}, body: {}", status, &body);
+    crate::log_redact::log_body(&format!("Login response {}", status), &body);
 
     if status == reqwest::StatusCode::UNAUTHORIZED {
         return Err(AuthError::InvalidCredentials);
@@ -129,7 +165,8 @@ pub async fn login(email: &str, password: &str) -> Result<AuthState, AuthError>
     if !status.is_success() {
         return Err(AuthError::ServerError(format!(
             "Server returned {}: {}",
-            status, body
+            status,
+            crate::log_redact::error_excerpt(&body)
         )));
     }
 
@@ -166,12 +203,16 @@ pub async fn login(email: &str, password: &str) -> Result<AuthState, AuthError>
         jobs_remaining: None,
         max_file_size_mb: None,
         is_unlimited: None,
+        token_expires_at: data.expires_in.map(|secs| unix_now() + secs),
+        session_expired: false,
+        is_guest: false,
+        refresh_token: data.refresh_token,
     })
 }
 
 /// Validate an existing token and get user info
 pub async fn validate_token(token: &str) -> Result<AuthState, AuthError> {
-    let client = Client::new();
+    let client = crate::proxy::client_builder().build()?;
 
     let response = client
         .get(format!("{}/user", API_BASE_URL))
@@ -192,7 +233,7 @@ pub async fn validate_token(token: &str) -> Result<AuthState, AuthError> {
     }
 
     let body = response.text().await?;
-    tracing::debug!("User response: {}", &body);
+    crate::log_redact::log_body("User response", &body);
 
     let user_response: UserResponse = serde_json::from_str(&body)
         .map_err(|e| AuthError::ServerError(format!("Failed to parse response: {}", e)))?;
@@ -227,46 +268,376 @@ pub async fn validate_token(token: &str) -> Result<AuthState, AuthError> {
         jobs_remaining: None,
         max_file_size_mb: None,
         is_unlimited: None,
+        token_expires_at: None,
+        session_expired: false,
+        is_guest: false,
+        refresh_token: None,
     })
 }
 
-/// Save token to config file
-pub fn save_token(token: &str) -> Result<(), AuthError> {
-    let mut cfg = config::load_config().map_err(|e| AuthError::Keyring(e.to_string()))?;
+/// Exchange the refresh token for a new access token
+/// Returns a copy of the auth state with the new token and expiry - call save_session to persist it
+pub async fn refresh_session(auth_state: &AuthState) -> Result<AuthState, AuthError> {
+    let refresh_token = auth_state
+        .refresh_token
+        .clone()
+        .ok_or(AuthError::TokenExpired)?;
 
-    if cfg.auth.is_none() {
-        cfg.auth = Some(AuthConfig::default());
+    let client = crate::proxy::client_builder().build()?;
+
+    let response = client
+        .post(format!("{}/auth/refresh", API_BASE_URL))
+        .header("Content-Type", "application/json")
+        .header("Accept", "application/json")
+        .json(&serde_json::json!({
+            "refresh_token": refresh_token,
+        }))
+        .send()
+        .await?;
+
+    let status = response.status();
+
+    if status == reqwest::StatusCode::UNAUTHORIZED {
+        return Err(AuthError::TokenExpired);
     }
-    if let Some(ref mut auth) = cfg.auth {
-        auth.token = Some(token.to_string());
+
+    if !status.is_success() {
+        return Err(AuthError::ServerError(format!(
+            "Server returned {}",
+            status
+        )));
+    }
+
+    let body = response.text().await?;
+
+    let refresh_response: RefreshResponse = serde_json::from_str(&body)
+        .map_err(|e| AuthError::ServerError(format!("Failed to parse response: {}", e)))?;
+
+    if !refresh_response.success {
+        return Err(AuthError::TokenExpired);
+    }
+
+    let data = refresh_response.data.ok_or(AuthError::TokenExpired)?;
+
+    let mut result = auth_state.clone();
+    result.token = Some(data.token);
+    result.token_expires_at = data.expires_in.map(|secs| unix_now() + secs);
+    // Servers may rotate the refresh token - otherwise keep the old one
+    if data.refresh_token.is_some() {
+        result.refresh_token = data.refresh_token;
+    }
+
+    Ok(result)
+}
+
+/// Check whether the access token is about to expire and can be refreshed
+pub fn needs_refresh(auth_state: &AuthState) -> bool {
+    if auth_state.token.is_none() || auth_state.refresh_token.is_none() {
+        return false;
+    }
+    match auth_state.token_expires_at {
+        Some(expires_at) => expires_at <= unix_now() + REFRESH_MARGIN_SECS,
+        None => false,
+    }
+}
+
+/// Get a new token after a 401: try the refresh token first,
+/// then fall back to the saved "Remember me" credentials.
+/// The new session is not saved - the caller must check the account did not
+/// change meanwhile before saving it.
+pub async fn renew_session(auth_state: &AuthState) -> Result<AuthState, AuthError> {
+    if auth_state.refresh_token.is_some() {
+        match refresh_session(auth_state).await {
+            Ok(result) => return Ok(result),
+            Err(e) => tracing::debug!("Token refresh failed, falling back to saved credentials: {}", e),
+        }
+    }
+    let email = auth_state.user.as_ref().map(|u| u.email.as_str());
+    relogin_with_saved_credentials(email).await
+}
+
+/// Validate a saved session, refreshing the access token if it has expired
+pub async fn resume_session(saved: &AuthState) -> Result<AuthState, AuthError> {
+    let token = saved.token.clone().ok_or(AuthError::TokenExpired)?;
+
+    let (mut result, session) = match validate_token(&token).await {
+        Ok(result) => (result, saved.clone()),
+        Err(AuthError::TokenExpired) if saved.refresh_token.is_some() => {
+            let refreshed = refresh_session(saved).await?;
+            let result = validate_token(refreshed.token.as_deref().unwrap_or_default()).await?;
+            (result, refreshed)
+        }
+        Err(e) => return Err(e),
+    };
+
+    result.refresh_token = session.refresh_token;
+    result.token_expires_at = session.token_expires_at;
+
+    // Fetch usage status to get plan limits
+    apply_usage_status(&mut result).await;
+    Ok(result)
+}
+
+/// Fetch usage status and fill in the plan limits on an auth state
+pub async fn apply_usage_status(auth_state: &mut AuthState) {
+    // Guests have no token - their usage is looked up by session ID
+    if auth_state.token.is_some() || auth_state.is_guest {
+        let client = PdfDkClient::new(auth_state.token.clone());
+        if let Ok(usage) = client.get_usage_status().await {
+            auth_state.plan = Some(usage.plan);
+            auth_state.jobs_limit = Some(usage.limit);
+            auth_state.jobs_used = Some(usage.used);
+            auth_state.jobs_remaining = Some(usage.limit - usage.used);
+            auth_state.max_file_size_mb = usage.max_file_size_mb.or(Some(100)); // From API, fallback to 100MB
+            auth_state.is_unlimited = Some(usage.is_unlimited);
+        }
+    }
+}
+
+/// Login again using the saved "Remember me" credentials
+/// Used when the token expires while the watcher is running.
+/// If `expected_email` is given, only credentials for that account are used.
+pub async fn relogin_with_saved_credentials(expected_email: Option<&str>) -> Result<AuthState, AuthError> {
+    let (email, password) = load_credentials()?;
+    if let Some(expected) = expected_email {
+        if !expected.eq_ignore_ascii_case(&email) {
+            return Err(AuthError::Keyring(format!("No saved credentials for {}", expected)));
+        }
+    }
+    let mut result = login(&email, &password).await?;
+    apply_usage_status(&mut result).await;
+    Ok(result)
+}
+
+// Session storage
+// The active session lives under plain keys ("token", ...) in the auth namespace.
+// Every account also keeps its own copy under "accounts.<email>.<key>" so it can
+// be switched back to without logging in again.
+
+const SESSION_KEYS: [&str; 3] = ["token", "refresh_token", "token_expires_at"];
+
+fn session_key(account: Option<&str>, key: &str) -> String {
+    match account {
+        Some(email) => format!("accounts.{}.{}", email.to_lowercase(), key),
+        None => key.to_string(),
+    }
+}
+
+fn store_session(account: Option<&str>, auth_state: &AuthState) -> Result<(), AuthError> {
+    let values = [
+        auth_state.token.clone(),
+        auth_state.refresh_token.clone(),
+        auth_state.token_expires_at.map(|t| t.to_string()),
+    ];
+
+    for (key, value) in SESSION_KEYS.iter().zip(values) {
+        let name = session_key(account, key);
+        let result = match value {
+            Some(value) => secrets::set_secret(secrets::NS_AUTH, &name, &value),
+            None => secrets::delete_secret(secrets::NS_AUTH, &name),
+        };
+        result.map_err(|e| AuthError::Keyring(e.to_string()))?;
     }
 
-    config::save_config(&cfg).map_err(|e| AuthError::Keyring(e.to_string()))?;
     Ok(())
 }
 
-/// Load token from config file
-pub fn load_token() -> Result<String, AuthError> {
-    let cfg = config::load_config().map_err(|e| AuthError::Keyring(e.to_string()))?;
+fn restore_session(account: Option<&str>) -> Result<AuthState, AuthError> {
+    let get = |key: &str| {
+        secrets::get_secret(secrets::NS_AUTH, &session_key(account, key))
+            .map_err(|e| AuthError::Keyring(e.to_string()))
+    };
 
-    cfg.auth
-        .and_then(|a| a.token)
-        .ok_or_else(|| AuthError::Keyring("No saved token".to_string()))
+    let token = get("token")?.ok_or_else(|| AuthError::Keyring("No saved token".to_string()))?;
+
+    Ok(AuthState {
+        token: Some(token),
+        refresh_token: get("refresh_token")?,
+        token_expires_at: get("token_expires_at")?.and_then(|s| s.parse().ok()),
+        ..Default::default()
+    })
 }
 
-/// Clear token from config file
+fn delete_session(account: Option<&str>) -> Result<(), AuthError> {
+    for key in SESSION_KEYS {
+        secrets::delete_secret(secrets::NS_AUTH, &session_key(account, key))
+            .map_err(|e| AuthError::Keyring(e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Save the session as the active one, and remember it in the account list
+pub fn save_session(auth_state: &AuthState) -> Result<(), AuthError> {
+    store_session(None, auth_state)?;
+
+    if let Some(ref user) = auth_state.user {
+        store_session(Some(&user.email), auth_state)?;
+        remember_account(user)?;
+    }
+
+    Ok(())
+}
+
+/// Load the active session (token, refresh token and expiry)
+pub fn load_session() -> Result<AuthState, AuthError> {
+    restore_session(None)
+}
+
+/// Clear the active session
 pub fn clear_token() -> Result<(), AuthError> {
+    delete_session(None)
+}
+
+// Saved accounts
+
+/// Account entry shown in the account switcher, with its current usage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountSummary {
+    pub email: String,
+    pub name: Option<String>,
+    pub is_active: bool,
+    pub session_valid: bool,
+    pub plan: Option<String>,
+    pub jobs_limit: Option<i32>,
+    pub jobs_used: Option<i32>,
+    pub jobs_remaining: Option<i32>,
+}
+
+fn remember_account(user: &User) -> Result<(), AuthError> {
     let mut cfg = config::load_config().map_err(|e| AuthError::Keyring(e.to_string()))?;
+    let auth = cfg.auth.get_or_insert_with(AuthConfig::default);
+
+    match auth.accounts.iter_mut().find(|a| a.email.eq_ignore_ascii_case(&user.email)) {
+        Some(account) => account.name = user.name.clone(),
+        None => auth.accounts.push(SavedAccount {
+            email: user.email.clone(),
+            name: user.name.clone(),
+        }),
+    }
+    auth.active_account = Some(user.email.clone());
+
+    config::save_config(&cfg).map_err(|e| AuthError::Keyring(e.to_string()))?;
+    Ok(())
+}
 
+/// Remove a saved account and its stored session
+pub fn forget_account(email: &str) -> Result<(), AuthError> {
+    delete_session(Some(email))?;
+
+    let mut cfg = config::load_config().map_err(|e| AuthError::Keyring(e.to_string()))?;
     if let Some(ref mut auth) = cfg.auth {
-        auth.token = None;
+        auth.accounts.retain(|a| !a.email.eq_ignore_ascii_case(email));
+        if auth.active_account.as_deref().is_some_and(|a| a.eq_ignore_ascii_case(email)) {
+            auth.active_account = None;
+        }
     }
+    config::save_config(&cfg).map_err(|e| AuthError::Keyring(e.to_string()))?;
+    Ok(())
+}
+
+/// List saved accounts with their usage status
+pub async fn list_accounts() -> Result<Vec<AccountSummary>, AuthError> {
+    let cfg = config::load_config().map_err(|e| AuthError::Keyring(e.to_string()))?;
+    let auth = cfg.auth.unwrap_or_default();
+
+    let mut summaries = Vec::new();
+    for account in auth.accounts {
+        let is_active = auth
+            .active_account
+            .as_deref()
+            .is_some_and(|a| a.eq_ignore_ascii_case(&account.email));
+
+        let mut summary = AccountSummary {
+            email: account.email.clone(),
+            name: account.name.clone(),
+            is_active,
+            session_valid: false,
+            plan: None,
+            jobs_limit: None,
+            jobs_used: None,
+            jobs_remaining: None,
+        };
+
+        if let Ok(session) = restore_session(Some(&account.email)) {
+            let client = PdfDkClient::new(session.token);
+            if let Ok(usage) = client.get_usage_status().await {
+                summary.session_valid = true;
+                summary.plan = Some(usage.plan);
+                summary.jobs_limit = Some(usage.limit);
+                summary.jobs_used = Some(usage.used);
+                summary.jobs_remaining = Some(usage.limit - usage.used);
+            }
+        }
+
+        summaries.push(summary);
+    }
+
+    Ok(summaries)
+}
+
+/// Make a saved account the active one
+pub async fn switch_account(email: &str) -> Result<AuthState, AuthError> {
+    let saved = restore_session(Some(email))?;
+    let result = resume_session(&saved).await?;
+    save_session(&result)?;
+    Ok(result)
+}
 
+// Guest mode
+
+/// Start processing as a guest, keeping the guest session ID across restarts
+pub async fn start_guest_session() -> Result<AuthState, AuthError> {
+    let mut cfg = config::load_config().map_err(|e| AuthError::Keyring(e.to_string()))?;
+    let auth = cfg.auth.get_or_insert_with(AuthConfig::default);
+    let session_id = auth
+        .guest_session_id
+        .get_or_insert_with(|| uuid::Uuid::new_v4().to_string())
+        .clone();
+    auth.guest_mode = true;
+    config::save_config(&cfg).map_err(|e| AuthError::Keyring(e.to_string()))?;
+
+    crate::api::set_session_id(&session_id);
+
+    let mut result = AuthState {
+        is_guest: true,
+        plan: Some("guest".to_string()),
+        ..Default::default()
+    };
+    apply_usage_status(&mut result).await;
+    Ok(result)
+}
+
+/// Check whether the user chose guest mode last time
+pub fn is_guest_mode() -> bool {
+    config::load_config()
+        .ok()
+        .and_then(|c| c.auth)
+        .is_some_and(|a| a.guest_mode)
+}
+
+/// Leave guest mode (on login or logout)
+pub fn end_guest_mode() -> Result<(), AuthError> {
+    let mut cfg = config::load_config().map_err(|e| AuthError::Keyring(e.to_string()))?;
+    if let Some(ref mut auth) = cfg.auth {
+        if !auth.guest_mode {
+            return Ok(());
+        }
+        auth.guest_mode = false;
+    }
     config::save_config(&cfg).map_err(|e| AuthError::Keyring(e.to_string()))?;
     Ok(())
 }
 
-/// Save credentials to config file (for "Remember me" feature)
+/// Check whether a guest has used up the guest quota
+pub fn guest_quota_exhausted(auth_state: &AuthState) -> bool {
+    auth_state.is_guest
+        && auth_state.is_unlimited != Some(true)
+        && auth_state.jobs_remaining.is_some_and(|r| r <= 0)
+}
+
+/// Save credentials for the "Remember me" feature
+/// The email is kept in the config file, the password in the secrets vault
 pub fn save_credentials(email: &str, password: &str) -> Result<(), AuthError> {
     let mut cfg = config::load_config().map_err(|e| AuthError::Keyring(e.to_string()))?;
 
@@ -275,33 +646,44 @@ pub fn save_credentials(email: &str, password: &str) -> Result<(), AuthError> {
     }
     if let Some(ref mut auth) = cfg.auth {
         auth.email = Some(email.to_string());
-        auth.password = Some(password.to_string());
     }
 
     config::save_config(&cfg).map_err(|e| AuthError::Keyring(e.to_string()))?;
+    secrets::set_secret(secrets::NS_AUTH, "password", password)
+        .map_err(|e| AuthError::Keyring(e.to_string()))?;
     Ok(())
 }
 
-/// Load saved credentials from config file
+/// Load saved credentials
 pub fn load_credentials() -> Result<(String, String), AuthError> {
     let cfg = config::load_config().map_err(|e| AuthError::Keyring(e.to_string()))?;
 
     let auth = cfg.auth.ok_or_else(|| AuthError::Keyring("No saved credentials".to_string()))?;
     let email = auth.email.ok_or_else(|| AuthError::Keyring("No saved email".to_string()))?;
-    let password = auth.password.ok_or_else(|| AuthError::Keyring("No saved password".to_string()))?;
+    let password = secrets::get_secret(secrets::NS_AUTH, "password")
+        .map_err(|e| AuthError::Keyring(e.to_string()))?
+        .ok_or_else(|| AuthError::Keyring("No saved password".to_string()))?;
 
     Ok((email, password))
 }
 
-/// Clear saved credentials from config file
+/// Clear saved credentials
 pub fn clear_credentials() -> Result<(), AuthError> {
     let mut cfg = config::load_config().map_err(|e| AuthError::Keyring(e.to_string()))?;
 
     if let Some(ref mut auth) = cfg.auth {
         auth.email = None;
-        auth.password = None;
     }
 
     config::save_config(&cfg).map_err(|e| AuthError::Keyring(e.to_string()))?;
+    secrets::delete_secret(secrets::NS_AUTH, "password")
+        .map_err(|e| AuthError::Keyring(e.to_string()))?;
     Ok(())
 }
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
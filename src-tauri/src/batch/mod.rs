@@ -0,0 +1,197 @@
+// Night batch module for PDF.dk Desktop
+// Runs the current queue to completion, writes a report and optionally sleeps or quits
+
+use crate::config;
+use crate::processor::{Job, JobStatus};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+/// How often the batch checks whether it was cancelled
+const BATCH_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+const REPORTS_DIR: &str = "reports";
+
+#[derive(Error, Debug)]
+pub enum BatchError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Config error: {0}")]
+    Config(#[from] config::ConfigError),
+    #[error("Could not put the computer to sleep: {0}")]
+    Sleep(String),
+}
+
+/// What to do once the batch has finished
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AfterBatch {
+    #[default]
+    Nothing,
+    Exit,
+    Sleep,
+}
+
+/// A running night batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NightBatch {
+    pub id: String,
+    pub job_ids: Vec<String>,
+    pub after: AfterBatch,
+    pub started_at: String,
+}
+
+impl NightBatch {
+    pub fn new(job_ids: Vec<String>, after: AfterBatch) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            job_ids,
+            after,
+            started_at: chrono::Local::now().to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedFile {
+    pub file: String,
+    pub error: String,
+}
+
+/// Summary of a finished night batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchReport {
+    pub started_at: String,
+    pub finished_at: String,
+    pub total: usize,
+    pub completed: usize,
+    pub failed: Vec<FailedFile>,
+    pub output_files: Vec<String>,
+    pub report_path: Option<String>,
+}
+
+/// Wait until every job in the batch has finished and summarize the results.
+/// `finished` gets each job as it finishes, so jobs that have dropped out of
+/// the history by the end of a long batch are still counted.
+/// Returns None if `still_running` reports the batch was cancelled.
+pub async fn wait_for_batch(
+    mut finished: mpsc::UnboundedReceiver<Job>,
+    batch: &NightBatch,
+    still_running: impl Fn() -> bool,
+) -> Option<BatchReport> {
+    let mut completed = 0;
+    let mut failed = Vec::new();
+    let mut output_files = Vec::new();
+    let mut remaining: HashSet<String> = batch.job_ids.iter().cloned().collect();
+
+    while !remaining.is_empty() {
+        let job = match tokio::time::timeout(BATCH_POLL_INTERVAL, finished.recv()).await {
+            Ok(Some(job)) => job,
+            // The job tracker is gone, so nothing more finishes
+            Ok(None) => break,
+            Err(_) => {
+                if !still_running() {
+                    return None;
+                }
+                continue;
+            }
+        };
+        if !remaining.remove(&job.id) {
+            continue;
+        }
+        match job.status {
+            JobStatus::Completed => {
+                completed += 1;
+                output_files.extend(job.output_file);
+            }
+            JobStatus::Failed => failed.push(FailedFile {
+                file: job.input_file,
+                error: job.error.unwrap_or_default(),
+            }),
+            _ => {}
+        }
+    }
+    if !still_running() {
+        return None;
+    }
+
+    Some(BatchReport {
+        started_at: batch.started_at.clone(),
+        finished_at: chrono::Local::now().to_rfc3339(),
+        total: batch.job_ids.len(),
+        completed,
+        failed,
+        output_files,
+        report_path: None,
+    })
+}
+
+/// Write the report as a text file in the reports folder and return its path
+pub fn write_report(report: &BatchReport) -> Result<PathBuf, BatchError> {
+    let dir = config::get_config_dir()?.join(REPORTS_DIR);
+    fs::create_dir_all(&dir)?;
+
+    let path = dir.join(format!(
+        "batch-{}.txt",
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    ));
+
+    let mut text = String::new();
+    text.push_str("PDF.dk Desktop - Night batch report\n\n");
+    text.push_str(&format!("Started:   {}\n", report.started_at));
+    text.push_str(&format!("Finished:  {}\n", report.finished_at));
+    text.push_str(&format!("Files:     {}\n", report.total));
+    text.push_str(&format!("Completed: {}\n", report.completed));
+    text.push_str(&format!("Failed:    {}\n", report.failed.len()));
+
+    if !report.failed.is_empty() {
+        text.push_str("\nFailed files:\n");
+        for f in &report.failed {
+            text.push_str(&format!("  {} - {}\n", f.file, f.error));
+        }
+    }
+    if !report.output_files.is_empty() {
+        text.push_str("\nOutput files:\n");
+        for f in &report.output_files {
+            text.push_str(&format!("  {}\n", f));
+        }
+    }
+
+    fs::write(&path, text)?;
+    Ok(path)
+}
+
+/// Put the computer to sleep
+pub fn sleep_system() -> Result<(), BatchError> {
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut c = std::process::Command::new("rundll32.exe");
+        c.args(["powrprof.dll,SetSuspendState", "0,1,0"]);
+        c
+    };
+    #[cfg(target_os = "macos")]
+    let mut command = {
+        let mut c = std::process::Command::new("pmset");
+        c.arg("sleepnow");
+        c
+    };
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let mut command = {
+        let mut c = std::process::Command::new("systemctl");
+        c.arg("suspend");
+        c
+    };
+
+    let status = command.status().map_err(|e| BatchError::Sleep(e.to_string()))?;
+    if !status.success() {
+        return Err(BatchError::Sleep(format!("exited with {}", status)));
+    }
+    Ok(())
+}
@@ -0,0 +1,173 @@
+// Calendar module for PDF.dk Desktop
+// Public holidays and company closure dates, entered by hand or imported from ICS files
+
+use crate::config::{ClosedDay, UploadWindow, WorkCalendar};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+
+/// Longest event imported from an ICS file, to skip e.g. year-long "busy" blocks
+const MAX_EVENT_DAYS: i64 = 31;
+
+#[derive(Error, Debug)]
+pub enum CalendarError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("No all-day events found in the calendar file")]
+    NoEvents,
+}
+
+/// Why a day is closed, or None on working days
+pub fn closed_reason(calendar: &WorkCalendar, date: NaiveDate) -> Option<String> {
+    if let Some(day) = calendar.closed_dates.iter().find(|d| d.date == date) {
+        return Some(day.name.clone().unwrap_or_else(|| "Closed".to_string()));
+    }
+    if calendar.weekends_closed && matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+        return Some("Weekend".to_string());
+    }
+    None
+}
+
+/// First working day after `date`
+pub fn next_working_day(calendar: &WorkCalendar, date: NaiveDate) -> NaiveDate {
+    let mut day = date;
+    // A year of closed days means the calendar is wrong, not that nothing may run
+    for _ in 0..366 {
+        let Some(next) = day.succ_opt() else {
+            break;
+        };
+        day = next;
+        if closed_reason(calendar, day).is_none() {
+            return day;
+        }
+    }
+    date
+}
+
+/// Time between `from` and `to` that falls on working days
+pub fn working_time(calendar: &WorkCalendar, from: NaiveDateTime, to: NaiveDateTime) -> Duration {
+    let mut total = Duration::zero();
+    let mut day = from.date();
+    while day <= to.date() {
+        let next = day.succ_opt();
+        if closed_reason(calendar, day).is_none() {
+            let start = from.max(day.and_time(NaiveTime::MIN));
+            let end = next.map_or(to, |next| to.min(next.and_time(NaiveTime::MIN)));
+            if end > start {
+                total += end - start;
+            }
+        }
+        let Some(next) = next else {
+            break;
+        };
+        day = next;
+    }
+    total
+}
+
+/// Whether `now` is inside an upload window. Windows with times that can't
+/// be read are always open, so a typo doesn't hold files forever.
+pub fn window_open(window: &UploadWindow, now: NaiveTime) -> bool {
+    let (Ok(start), Ok(end)) = (
+        NaiveTime::parse_from_str(&window.start, "%H:%M"),
+        NaiveTime::parse_from_str(&window.end, "%H:%M"),
+    ) else {
+        return true;
+    };
+    if start <= end {
+        start <= now && now < end
+    } else {
+        now >= start || now < end
+    }
+}
+
+/// Read the all-day events of an ICS file as closed days
+pub fn import_ics(path: &Path) -> Result<Vec<ClosedDay>, CalendarError> {
+    let days = parse_ics(&fs::read_to_string(path)?);
+    if days.is_empty() {
+        return Err(CalendarError::NoEvents);
+    }
+    Ok(days)
+}
+
+/// Add imported days to the calendar, skipping dates it already has.
+/// Returns how many were added.
+pub fn merge_days(calendar: &mut WorkCalendar, days: Vec<ClosedDay>) -> usize {
+    let before = calendar.closed_dates.len();
+    for day in days {
+        if !calendar.closed_dates.iter().any(|d| d.date == day.date) {
+            calendar.closed_dates.push(day);
+        }
+    }
+    calendar.closed_dates.sort_by_key(|d| d.date);
+    calendar.closed_dates.len() - before
+}
+
+fn parse_ics(text: &str) -> Vec<ClosedDay> {
+    let mut days = Vec::new();
+    let mut start: Option<NaiveDate> = None;
+    let mut end: Option<NaiveDate> = None;
+    let mut summary: Option<String> = None;
+    let mut all_day = false;
+
+    for line in unfold(text) {
+        let Some((name, value)) = line.trim_end().split_once(':') else {
+            continue;
+        };
+        // Drop parameters such as DTSTART;VALUE=DATE
+        let name = name.split(';').next().unwrap_or_default().to_ascii_uppercase();
+        match name.as_str() {
+            "BEGIN" if value.eq_ignore_ascii_case("VEVENT") => {
+                start = None;
+                end = None;
+                summary = None;
+                all_day = false;
+            }
+            "DTSTART" => {
+                // All-day events have a plain date, timed events a date-time
+                all_day = value.trim().len() == 8;
+                start = parse_date(value);
+            }
+            "DTEND" => end = parse_date(value),
+            "SUMMARY" => summary = Some(value.replace("\\,", ",").replace("\\;", ";").trim().to_string()),
+            "END" if value.eq_ignore_ascii_case("VEVENT") => {
+                let Some(first) = start.filter(|_| all_day) else { continue };
+                // DTEND is exclusive; single-day events may leave it out
+                let last = end.map(|e| e - Duration::days(1)).unwrap_or(first).max(first);
+                if (last - first).num_days() >= MAX_EVENT_DAYS {
+                    continue;
+                }
+                let mut date = first;
+                while date <= last {
+                    days.push(ClosedDay {
+                        date,
+                        name: summary.clone().filter(|s| !s.is_empty()),
+                    });
+                    date += Duration::days(1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    days
+}
+
+/// Join folded lines (continuations start with a space or tab). Lines are
+/// kept as they are, as a line may be folded right after a space.
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// The date part of an ICS DATE or DATE-TIME value
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.get(..8)?, "%Y%m%d").ok()
+}
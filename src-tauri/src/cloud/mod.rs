@@ -0,0 +1,388 @@
+// Cloud module for PDF.dk Desktop
+// Uploads finished outputs to S3 buckets, Google Drive folders and Dropbox,
+// and connects Google Drive and Dropbox destinations to an account with OAuth
+
+use crate::cloud_headers::{amz_date, ascii_json, uri_encode, S3Signer};
+use crate::config::{Destination, DestinationTarget};
+use crate::secrets::{self, NS_DESTINATIONS};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use once_cell::sync::Lazy;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// The OAuth redirect goes to http://localhost:<this port>/, which has to be
+/// registered as a redirect URI of the Google client or Dropbox app
+pub const OAUTH_REDIRECT_PORT: u16 = 53682;
+/// How long the browser has to finish connecting an account
+const OAUTH_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// Renew access tokens this long before they expire
+const TOKEN_MARGIN: Duration = Duration::from_secs(60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+/// Largest file Dropbox takes in a single upload
+const DROPBOX_MAX_UPLOAD: u64 = 150 * 1024 * 1024;
+
+const GOOGLE_AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const GOOGLE_SCOPE: &str = "https://www.googleapis.com/auth/drive";
+const DROPBOX_AUTH_URL: &str = "https://www.dropbox.com/oauth2/authorize";
+const DROPBOX_TOKEN_URL: &str = "https://api.dropboxapi.com/oauth2/token";
+
+// Destination id -> access token and when it expires
+static ACCESS_TOKENS: Lazy<Mutex<HashMap<String, (String, Instant)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Error, Debug)]
+pub enum CloudError {
+    #[error("Network error: {0}")]
+    Network(#[from] reqwest::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Keyring error: {0}")]
+    Keyring(#[from] secrets::SecretsError),
+    #[error("{0}")]
+    NotConnected(&'static str),
+    #[error("Could not connect the account: {0}")]
+    OAuth(String),
+    #[error("Server error ({0}): {1}")]
+    Server(StatusCode, String),
+    #[error("{0}")]
+    Unsupported(String),
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<u64>,
+    #[serde(default)]
+    refresh_token: Option<String>,
+}
+
+/// Check the destination's credentials and that its bucket or folder exists
+pub async fn check(destination: &Destination) -> Result<(), CloudError> {
+    let client = client()?;
+    let request = match &destination.target {
+        DestinationTarget::S3 { .. } => s3_request(&client, destination, reqwest::Method::HEAD, "", Vec::new())?,
+        DestinationTarget::GoogleDrive { folder_id, .. } => client
+            .get(format!("https://www.googleapis.com/drive/v3/files/{}", folder_id))
+            .query(&[("fields", "id"), ("supportsAllDrives", "true")])
+            .bearer_auth(access_token(&client, destination).await?),
+        DestinationTarget::Dropbox { .. } => client
+            .post("https://api.dropboxapi.com/2/users/get_current_account")
+            .bearer_auth(access_token(&client, destination).await?),
+        _ => return Err(CloudError::Unsupported("Not a cloud destination".to_string())),
+    };
+    expect_success(request.send().await?).await.map(|_| ())
+}
+
+/// Upload an output. Returns where it went, for the log.
+pub async fn upload(destination: &Destination, file: &Path) -> Result<String, CloudError> {
+    if file.is_dir() {
+        return Err(CloudError::Unsupported(
+            "Unpacked results can't be uploaded - turn off unpacking for this tool".to_string(),
+        ));
+    }
+    let name = file.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let body = tokio::fs::read(file).await?;
+    let client = client()?;
+
+    match &destination.target {
+        DestinationTarget::S3 { bucket, prefix, .. } => {
+            let key = match prefix.trim_matches('/') {
+                "" => name,
+                prefix => format!("{}/{}", prefix, name),
+            };
+            let request = s3_request(&client, destination, reqwest::Method::PUT, &key, body)?;
+            expect_success(request.send().await?).await?;
+            Ok(format!("s3://{}/{}", bucket, key))
+        }
+        DestinationTarget::GoogleDrive { folder_id, .. } => {
+            // A resumable session carries the name and folder, then takes the content
+            let token = access_token(&client, destination).await?;
+            let session = client
+                .post("https://www.googleapis.com/upload/drive/v3/files")
+                .query(&[("uploadType", "resumable"), ("supportsAllDrives", "true")])
+                .bearer_auth(&token)
+                .json(&serde_json::json!({ "name": name, "parents": [folder_id] }))
+                .send()
+                .await?;
+            let session = expect_success(session).await?;
+            let location = session
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|l| l.to_str().ok())
+                .ok_or_else(|| CloudError::Server(session.status(), "No upload location".to_string()))?
+                .to_string();
+            expect_success(client.put(location).bearer_auth(&token).body(body).send().await?).await?;
+            Ok(format!("Google Drive folder {}/{}", folder_id, name))
+        }
+        DestinationTarget::Dropbox { path, .. } => {
+            if body.len() as u64 > DROPBOX_MAX_UPLOAD {
+                return Err(CloudError::Unsupported(format!(
+                    "{} is larger than the {} MB Dropbox takes in one upload",
+                    name,
+                    DROPBOX_MAX_UPLOAD / 1024 / 1024
+                )));
+            }
+            let path = format!("{}/{}", path.trim_end_matches('/'), name);
+            let path = if path.starts_with('/') { path } else { format!("/{}", path) };
+            let arg = serde_json::json!({ "path": path, "mode": "overwrite", "mute": true });
+            let request = client
+                .post("https://content.dropboxapi.com/2/files/upload")
+                .bearer_auth(access_token(&client, destination).await?)
+                .header("Dropbox-API-Arg", ascii_json(&arg.to_string()))
+                .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+                .body(body);
+            expect_success(request.send().await?).await?;
+            Ok(format!("Dropbox {}", path))
+        }
+        _ => Err(CloudError::Unsupported("Not a cloud destination".to_string())),
+    }
+}
+
+/// Connect a Google Drive or Dropbox destination to an account. `open` shows
+/// the provider's sign-in page in the browser; the refresh token it hands back
+/// is saved in the keyring.
+pub async fn connect(destination: &Destination, open: impl FnOnce(&str) -> Result<(), String>) -> Result<(), CloudError> {
+    let redirect_uri = format!("http://localhost:{}/", OAUTH_REDIRECT_PORT);
+    let verifier = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+    let state = uuid::Uuid::new_v4().simple().to_string();
+
+    let (auth_url, query) = match &destination.target {
+        DestinationTarget::GoogleDrive { client_id, .. } => (
+            GOOGLE_AUTH_URL,
+            vec![
+                ("client_id", client_id.as_str()),
+                ("scope", GOOGLE_SCOPE),
+                ("access_type", "offline"),
+                // Google only hands out a refresh token on the first consent otherwise
+                ("prompt", "consent"),
+            ],
+        ),
+        DestinationTarget::Dropbox { app_key, .. } => (
+            DROPBOX_AUTH_URL,
+            vec![("client_id", app_key.as_str()), ("token_access_type", "offline")],
+        ),
+        _ => return Err(not_oauth()),
+    };
+    let url = reqwest::Url::parse_with_params(
+        auth_url,
+        query.into_iter().chain([
+            ("response_type", "code"),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("code_challenge", challenge.as_str()),
+            ("code_challenge_method", "S256"),
+            ("state", state.as_str()),
+        ]),
+    )
+    .map_err(|e| CloudError::OAuth(e.to_string()))?;
+
+    let listener = tokio::net::TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, OAUTH_REDIRECT_PORT))).await?;
+    open(url.as_str()).map_err(CloudError::OAuth)?;
+    let code = tokio::time::timeout(OAUTH_TIMEOUT, wait_for_code(&listener, &state))
+        .await
+        .map_err(|_| CloudError::OAuth("timed out waiting for the browser".to_string()))??;
+
+    let (token_url, mut form) = oauth_client(&destination.target).ok_or_else(not_oauth)?;
+    form.extend([
+        ("grant_type", "authorization_code"),
+        ("code", code.as_str()),
+        ("code_verifier", verifier.as_str()),
+        ("redirect_uri", redirect_uri.as_str()),
+    ]);
+    let client = client()?;
+    let response = expect_success(client.post(token_url).form(&form).send().await?).await?;
+    let token: TokenResponse = response.json().await?;
+    let refresh_token = token
+        .refresh_token
+        .ok_or_else(|| CloudError::OAuth("no refresh token was handed out".to_string()))?;
+    secrets::set_secret(NS_DESTINATIONS, &destination.id, &refresh_token)?;
+    cache_token(&destination.id, token.access_token, token.expires_in);
+    Ok(())
+}
+
+/// Forget the cached access token, e.g. after the refresh token changed
+pub fn forget_token(destination_id: &str) {
+    if let Ok(mut tokens) = ACCESS_TOKENS.lock() {
+        tokens.remove(destination_id);
+    }
+}
+
+/// Accept the browser's redirect and take the authorization code from it
+async fn wait_for_code(listener: &tokio::net::TcpListener, state: &str) -> Result<String, CloudError> {
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let mut request = Vec::new();
+        let mut buffer = [0u8; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 16 * 1024 {
+            match stream.read(&mut buffer).await {
+                Ok(read) if read > 0 => request.extend_from_slice(&buffer[..read]),
+                _ => break,
+            }
+        }
+        let request_line = String::from_utf8_lossy(&request);
+        let target = request_line.split_whitespace().nth(1).unwrap_or_default();
+        let Ok(url) = reqwest::Url::parse(&format!("http://localhost{}", target)) else {
+            continue;
+        };
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        // Browsers also ask for a favicon
+        if !params.contains_key("code") && !params.contains_key("error") {
+            let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\nConnection: close\r\n\r\n").await;
+            continue;
+        }
+
+        let result = if params.get("state").map(String::as_str) != Some(state) {
+            Err(CloudError::OAuth("the sign-in answer doesn't belong to this request".to_string()))
+        } else if let Some(code) = params.get("code") {
+            Ok(code.clone())
+        } else {
+            Err(CloudError::OAuth(params.get("error").cloned().unwrap_or_default()))
+        };
+        let message = match &result {
+            Ok(_) => "PDF.dk Desktop is connected. You can close this window.",
+            Err(_) => "PDF.dk Desktop could not be connected. Please try again from the app.",
+        };
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            message.len(),
+            message
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+        let _ = stream.shutdown().await;
+        return result;
+    }
+}
+
+/// A current access token for a Google Drive or Dropbox destination,
+/// renewed from the refresh token in the keyring when needed
+async fn access_token(client: &Client, destination: &Destination) -> Result<String, CloudError> {
+    if let Some((token, expires)) = ACCESS_TOKENS.lock().ok().and_then(|t| t.get(&destination.id).cloned()) {
+        if Instant::now() + TOKEN_MARGIN < expires {
+            return Ok(token);
+        }
+    }
+    let refresh_token = secrets::get_secret(NS_DESTINATIONS, &destination.id)?
+        .ok_or(CloudError::NotConnected("This destination isn't connected to an account yet"))?;
+    let (token_url, mut form) = oauth_client(&destination.target).ok_or_else(not_oauth)?;
+    form.extend([("grant_type", "refresh_token"), ("refresh_token", refresh_token.as_str())]);
+    let response = expect_success(client.post(token_url).form(&form).send().await?).await?;
+    let token: TokenResponse = response.json().await?;
+    cache_token(&destination.id, token.access_token.clone(), token.expires_in);
+    Ok(token.access_token)
+}
+
+/// Token endpoint of a destination's OAuth provider, and the form fields
+/// identifying the client
+fn oauth_client(target: &DestinationTarget) -> Option<(&'static str, Vec<(&'static str, &str)>)> {
+    match target {
+        DestinationTarget::GoogleDrive {
+            client_id,
+            client_secret,
+            ..
+        } => Some((
+            GOOGLE_TOKEN_URL,
+            vec![("client_id", client_id.as_str()), ("client_secret", client_secret.as_str())],
+        )),
+        DestinationTarget::Dropbox { app_key, .. } => Some((DROPBOX_TOKEN_URL, vec![("client_id", app_key.as_str())])),
+        _ => None,
+    }
+}
+
+fn not_oauth() -> CloudError {
+    CloudError::Unsupported("Only Google Drive and Dropbox destinations connect to an account".to_string())
+}
+
+fn cache_token(destination_id: &str, token: String, expires_in: Option<u64>) {
+    // Tokens without a lifetime are renewed after an hour to be safe
+    let expires = Instant::now() + Duration::from_secs(expires_in.unwrap_or(3600));
+    if let Ok(mut tokens) = ACCESS_TOKENS.lock() {
+        tokens.insert(destination_id.to_string(), (token, expires));
+    }
+}
+
+/// Build a request to an S3 bucket, signed with AWS Signature Version 4.
+/// An empty `key` addresses the bucket itself.
+fn s3_request(
+    client: &Client,
+    destination: &Destination,
+    method: reqwest::Method,
+    key: &str,
+    body: Vec<u8>,
+) -> Result<reqwest::RequestBuilder, CloudError> {
+    let DestinationTarget::S3 {
+        bucket,
+        region,
+        endpoint,
+        access_key_id,
+        ..
+    } = &destination.target
+    else {
+        return Err(CloudError::Unsupported("Not an S3 destination".to_string()));
+    };
+    let secret_key = secrets::get_secret(NS_DESTINATIONS, &destination.id)?
+        .ok_or(CloudError::NotConnected("No secret access key saved for this destination"))?;
+
+    // Amazon S3 is addressed by bucket host name, other S3 services by path
+    let (base, host, mut path) = match endpoint {
+        Some(endpoint) => {
+            let endpoint = endpoint.trim_end_matches('/');
+            let host = endpoint.split("://").last().unwrap_or(endpoint).to_string();
+            (endpoint.to_string(), host, format!("/{}", uri_encode(bucket, false)))
+        }
+        None => {
+            let host = format!("{}.s3.{}.amazonaws.com", bucket, region);
+            (format!("https://{}", host), host, String::new())
+        }
+    };
+    path.push('/');
+    path.push_str(&uri_encode(key, true));
+
+    let now = chrono::Utc::now();
+    let amz_date = amz_date(now);
+    let payload_hash = format!("{:x}", Sha256::digest(&body));
+    let signer = S3Signer {
+        access_key_id,
+        secret_key: &secret_key,
+        region,
+    };
+    let authorization = signer.authorization(
+        method.as_str(),
+        &path,
+        &[
+            ("host", host.as_str()),
+            ("x-amz-content-sha256", payload_hash.as_str()),
+            ("x-amz-date", amz_date.as_str()),
+        ],
+        &payload_hash,
+        now,
+    );
+
+    Ok(client
+        .request(method, format!("{}{}", base, path))
+        .header("x-amz-content-sha256", payload_hash)
+        .header("x-amz-date", amz_date)
+        .header(reqwest::header::AUTHORIZATION, authorization)
+        .body(body))
+}
+
+async fn expect_success(response: reqwest::Response) -> Result<reqwest::Response, CloudError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(CloudError::Server(status, crate::log_redact::error_excerpt(&body)))
+}
+
+fn client() -> Result<Client, CloudError> {
+    Ok(crate::proxy::client_builder().timeout(REQUEST_TIMEOUT).build()?)
+}
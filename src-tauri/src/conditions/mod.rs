@@ -0,0 +1,77 @@
+// Conditions module for PDF.dk Desktop
+// Inspects inputs locally so files that don't need a tool aren't uploaded
+
+use crate::config::Condition;
+use std::path::Path;
+
+const BYTES_PER_MEGABYTE: f64 = 1024.0 * 1024.0;
+
+/// What the PDF-based conditions need to know about a file
+struct Inspection {
+    pages: u32,
+    has_text: bool,
+}
+
+/// The first condition `path` doesn't meet, described for the job history.
+/// Files that can't be inspected are processed - the server reports what's wrong with them.
+pub async fn unmet(conditions: &[Condition], path: &Path) -> Option<String> {
+    if conditions.is_empty() {
+        return None;
+    }
+    let size = tokio::fs::metadata(path).await.ok()?.len() as f64 / BYTES_PER_MEGABYTE;
+
+    // Parsing is the slow part, so only do it if a condition looks inside the PDF
+    let is_pdf = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("pdf"));
+    let needs_inspection = is_pdf
+        && conditions
+            .iter()
+            .any(|c| !matches!(c, Condition::LargerThan { .. } | Condition::SmallerThan { .. }));
+    let inspection = if needs_inspection {
+        let path = path.to_path_buf();
+        crate::local_work::run(move || inspect(&path)).await.ok().flatten()
+    } else {
+        None
+    };
+
+    conditions.iter().find_map(|condition| {
+        let met = match condition {
+            Condition::LargerThan { megabytes } => size > *megabytes,
+            Condition::SmallerThan { megabytes } => size < *megabytes,
+            Condition::MorePagesThan { pages } => inspection.as_ref().is_none_or(|i| i.pages > *pages),
+            Condition::FewerPagesThan { pages } => inspection.as_ref().is_none_or(|i| i.pages < *pages),
+            Condition::NoTextLayer => inspection.as_ref().is_none_or(|i| !i.has_text),
+        };
+        (!met).then(|| describe(condition))
+    })
+}
+
+fn describe(condition: &Condition) -> String {
+    match condition {
+        Condition::LargerThan { megabytes } => format!("not larger than {} MB", megabytes),
+        Condition::SmallerThan { megabytes } => format!("not smaller than {} MB", megabytes),
+        Condition::MorePagesThan { pages } => format!("not more than {} pages", pages),
+        Condition::FewerPagesThan { pages } => format!("not fewer than {} pages", pages),
+        Condition::NoTextLayer => "already has a text layer".to_string(),
+    }
+}
+
+fn inspect(path: &Path) -> Option<Inspection> {
+    let document = match lopdf::Document::load(path) {
+        Ok(document) => document,
+        Err(e) => {
+            crate::add_log(&format!("Could not inspect {:?}, processing it anyway: {}", path, e));
+            return None;
+        }
+    };
+    let page_numbers: Vec<u32> = document.get_pages().keys().copied().collect();
+    // Scans often carry a few stray characters, e.g. a scanner's footer
+    let has_text = page_numbers.iter().any(|page| {
+        document
+            .extract_text(&[*page])
+            .is_ok_and(|text| text.chars().filter(|c| c.is_alphanumeric()).count() > 20)
+    });
+    Some(Inspection {
+        pages: page_numbers.len() as u32,
+        has_text,
+    })
+}
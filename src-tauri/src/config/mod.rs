@@ -1,10 +1,15 @@
 // Configuration management for PDF.dk Desktop
 
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
+use std::sync::RwLock;
 use thiserror::Error;
 
+// Tool catalog fetched from the API, if the fetch succeeded
+static TOOL_CATALOG: Lazy<RwLock<Option<Vec<ToolDefinition>>>> = Lazy::new(|| RwLock::new(None));
+
 #[derive(Error, Debug)]
 pub enum ConfigError {
     #[error("IO error: {0}")]
@@ -15,15 +20,41 @@ pub enum ConfigError {
     NoConfigDir,
     #[error("Tool not found: {0}")]
     ToolNotFound(String),
+    #[error("Config migration failed: {0}")]
+    Migration(#[from] crate::migrations::MigrationError),
 }
 
 /// Saved authentication credentials
+/// Token and password live in the secrets vault - the fields here are only
+/// read from older configs so they can be migrated
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct AuthConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub token: Option<String>,
     pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub password: Option<String>,
+    /// Accounts that have logged in on this machine
+    #[serde(default)]
+    pub accounts: Vec<SavedAccount>,
+    /// Email of the account used by the watchers
+    #[serde(default)]
+    pub active_account: Option<String>,
+    /// Process files without logging in, using the guest quota
+    #[serde(default)]
+    pub guest_mode: bool,
+    /// Session ID the guest quota is tracked against
+    #[serde(default)]
+    pub guest_session_id: Option<String>,
+}
+
+/// An account that can be switched to without logging in again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedAccount {
+    pub email: String,
+    pub name: Option<String>,
 }
 
 /// Main application configuration
@@ -35,6 +66,364 @@ pub struct AppConfig {
     pub tools: Vec<ToolConfig>,
     #[serde(default)]
     pub auth: Option<AuthConfig>,
+    /// Remembered window geometry - written by the window_state module
+    #[serde(default)]
+    pub windows: WindowStates,
+    #[serde(default)]
+    pub log_shipping: LogShipping,
+    /// Backup destinations tools can copy their outputs to
+    #[serde(default)]
+    pub destinations: Vec<Destination>,
+    #[serde(default)]
+    pub calendar: WorkCalendar,
+    #[serde(default)]
+    pub self_test: SelfTestSettings,
+    /// How long processed inputs are kept in the Originals folders
+    #[serde(default)]
+    pub retention: RetentionSettings,
+    /// Mail server tools email their outputs through
+    #[serde(default)]
+    pub smtp: SmtpSettings,
+    /// Inbox folders whose files go to a tool chosen by file name
+    #[serde(default)]
+    pub rules: Vec<RoutingRule>,
+    /// Folder holding a subfolder per tool, named after the tool
+    #[serde(default)]
+    pub base_folder: Option<String>,
+    /// Name of the profile the tools, rules and base folder were switched to
+    #[serde(default)]
+    pub active_profile: Option<String>,
+}
+
+/// Sends files in an inbox folder whose name matches `pattern` to a tool.
+/// The first matching rule of the folder wins.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RoutingRule {
+    pub folder_path: String,
+    /// Glob on the file name (`invoice_*.pdf`), or a regex wrapped in slashes
+    pub pattern: String,
+    pub tool_id: String,
+}
+
+/// Nightly run of a sample PDF through every enabled tool
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfTestSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Local time of day to run at, "HH:MM"
+    #[serde(default = "default_self_test_time")]
+    pub time: String,
+}
+
+impl Default for SelfTestSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            time: default_self_test_time(),
+        }
+    }
+}
+
+fn default_self_test_time() -> String {
+    "05:00".to_string()
+}
+
+/// Limits for each Originals folder; the oldest files beyond any of them are
+/// deleted. Unset limits don't apply, so by default everything is kept.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionSettings {
+    #[serde(default)]
+    pub max_age_days: Option<u32>,
+    #[serde(default)]
+    pub max_files: Option<usize>,
+    #[serde(default)]
+    pub max_megabytes: Option<u64>,
+}
+
+impl RetentionSettings {
+    pub fn is_enabled(&self) -> bool {
+        self.max_age_days.is_some() || self.max_files.is_some() || self.max_megabytes.is_some()
+    }
+}
+
+/// Mail server for emailing outputs. The password is kept in the keyring.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SmtpSettings {
+    pub host: String,
+    /// Defaults to 587 for STARTTLS, 465 for TLS and 25 without encryption
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    /// Leave empty for servers that don't need a login
+    #[serde(default)]
+    pub username: String,
+    /// Sender address, e.g. "PDF.dk <scanner@example.com>"
+    pub from: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SmtpSecurity {
+    #[default]
+    StartTls,
+    Tls,
+    /// Only for relays on the local network
+    None,
+}
+
+/// Email each final output of a tool, e.g. to a bookkeeping address.
+/// Templates can use {name}, {file}, {tool}, {folder}, {date} and {time}.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailAction {
+    /// Recipients, separated by commas
+    pub to: String,
+    #[serde(default = "default_email_subject")]
+    pub subject: String,
+    #[serde(default)]
+    pub body: String,
+}
+
+fn default_email_subject() -> String {
+    "{file}".to_string()
+}
+
+/// Send each final output to a printer, making the folder a print queue
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PrintAction {
+    /// Name as listed by the system; the default printer when unset
+    #[serde(default)]
+    pub printer: Option<String>,
+    #[serde(default = "default_copies")]
+    pub copies: u32,
+}
+
+fn default_copies() -> u32 {
+    1
+}
+
+/// A local command or script run on each final output, e.g. to file it in
+/// another system. It also gets PDFDK_OUTPUT, PDFDK_INPUT, PDFDK_TOOL,
+/// PDFDK_FOLDER and PDFDK_JOB_ID in its environment.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PostCommand {
+    pub program: String,
+    /// "{output}" and "{input}" are replaced by the paths. Without either,
+    /// the output path is passed as the last argument.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// The command is stopped after this long
+    #[serde(default = "default_post_command_timeout")]
+    pub timeout_seconds: u64,
+}
+
+fn default_post_command_timeout() -> u64 {
+    60
+}
+
+/// Public holidays and closure dates
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkCalendar {
+    /// Pause processing on closed days
+    #[serde(default)]
+    pub pause_on_closed_days: bool,
+    #[serde(default = "default_true")]
+    pub weekends_closed: bool,
+    #[serde(default)]
+    pub closed_dates: Vec<ClosedDay>,
+    /// Only upload in these hours; folders can set their own. Windows don't
+    /// open on closed days.
+    #[serde(default)]
+    pub upload_window: Option<UploadWindow>,
+    /// Warn about files still waiting after this many hours, not counting closed days
+    #[serde(default)]
+    pub overdue_after_hours: Option<u32>,
+}
+
+impl Default for WorkCalendar {
+    fn default() -> Self {
+        Self {
+            pause_on_closed_days: false,
+            weekends_closed: true,
+            closed_dates: vec![],
+            upload_window: None,
+            overdue_after_hours: None,
+        }
+    }
+}
+
+/// Hours in which files are uploaded, as "HH:MM". A window ending before it
+/// starts runs past midnight, e.g. 18:00-06:00 for large print files.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadWindow {
+    pub start: String,
+    pub end: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ClosedDay {
+    pub date: chrono::NaiveDate,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Where a tool delivers a copy of each final output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DestinationSpec {
+    /// A folder like the tool's output folder, e.g. the Processed subfolder
+    Output(OutputMode),
+    /// The first of these destinations (ids) that takes the copy, in failover order
+    Failover(Vec<String>),
+}
+
+/// A place finished outputs are copied to, e.g. a mirror folder on a backup share
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Destination {
+    pub id: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub target: DestinationTarget,
+    /// Extra attempts after a failed delivery before trying the next
+    /// destination, once the delivery is queued
+    #[serde(default)]
+    pub retries: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum DestinationTarget {
+    Folder { path: String },
+    /// Upload over SFTP, e.g. to a RIP's hot folder. The password is kept in
+    /// the keyring under the destination's id.
+    Sftp {
+        host: String,
+        /// Defaults to 22
+        #[serde(default)]
+        port: Option<u16>,
+        username: String,
+        /// Remote folder, relative to the login folder unless it starts with /
+        #[serde(default)]
+        path: String,
+        /// SHA-256 of the server's host key, base64 as `ssh-keygen -l` shows it.
+        /// When set, servers with another key are refused. Otherwise the key
+        /// seen on the first connection is pinned, and a changed key is refused.
+        #[serde(default)]
+        fingerprint: Option<String>,
+    },
+    /// Upload over FTP with explicit TLS. The password is kept in the keyring
+    /// under the destination's id.
+    Ftps {
+        host: String,
+        /// Defaults to 21
+        #[serde(default)]
+        port: Option<u16>,
+        username: String,
+        #[serde(default)]
+        path: String,
+    },
+    /// Upload to an S3 bucket, or to S3-compatible storage at `endpoint`.
+    /// The secret access key is kept in the keyring under the destination's id.
+    S3 {
+        bucket: String,
+        region: String,
+        /// e.g. "https://minio.example.com" - addressed path-style. Amazon S3 when unset.
+        #[serde(default)]
+        endpoint: Option<String>,
+        #[serde(rename = "accessKeyId")]
+        access_key_id: String,
+        /// Key prefix, e.g. "scans/processed"
+        #[serde(default)]
+        prefix: String,
+    },
+    /// Upload to a Google Drive folder. The refresh token from connecting the
+    /// destination is kept in the keyring under its id.
+    GoogleDrive {
+        #[serde(rename = "folderId")]
+        folder_id: String,
+        /// OAuth client of the organisation's own Google Cloud project
+        #[serde(rename = "clientId")]
+        client_id: String,
+        /// Google doesn't treat the secret of a desktop client as confidential
+        #[serde(rename = "clientSecret")]
+        client_secret: String,
+    },
+    /// Upload to a Dropbox folder. The refresh token from connecting the
+    /// destination is kept in the keyring under its id.
+    Dropbox {
+        /// e.g. "/Scans/Processed"
+        #[serde(default)]
+        path: String,
+        /// App key of the organisation's own Dropbox app
+        #[serde(rename = "appKey")]
+        app_key: String,
+    },
+}
+
+/// Optional forwarding of logs to a central collector
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LogShipping {
+    pub enabled: bool,
+    pub transport: LogTransport,
+    /// host:port for syslog, URL for HTTP
+    pub endpoint: String,
+    /// Least severe level to ship: error, warn, info, debug or trace
+    #[serde(default = "default_log_level")]
+    pub min_level: String,
+    /// Only ship events whose target starts with one of these (empty = all)
+    #[serde(default)]
+    pub targets: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogTransport {
+    #[default]
+    SyslogUdp,
+    SyslogTcp,
+    /// JSON batches POSTed to the endpoint
+    Http,
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+/// Saved geometry of a window, in physical pixels
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub maximized: bool,
+    /// Name of the monitor the window was on
+    #[serde(default)]
+    pub monitor: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct WindowStates {
+    pub main: Option<WindowGeometry>,
+    pub mini: Option<WindowGeometry>,
+    /// Whether the mini status window was open when the app last ran
+    #[serde(default)]
+    pub mini_open: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -44,6 +433,84 @@ pub struct GeneralSettings {
     pub start_minimized: bool,
     pub show_notifications: bool,
     pub language: String,
+    /// Run local CPU-heavy work (hashing, zipping, previews) at low priority
+    #[serde(default = "default_true")]
+    pub background_priority: bool,
+    /// How many local operations may run at once
+    #[serde(default = "default_max_local_operations")]
+    pub max_local_operations: usize,
+    /// Keep a receipt signed by pdf.dk for every completed job
+    #[serde(default)]
+    pub job_receipts: bool,
+    /// Keep an index of processed files in each watched folder
+    #[serde(default = "default_true")]
+    pub folder_index: bool,
+    /// Upload a copy of each input taken when its job starts
+    #[serde(default = "default_true")]
+    pub snapshot_inputs: bool,
+    /// Monitor only: status, history and stats are shown, but settings and
+    /// accounts can't be changed. Turned off by editing the config file.
+    #[serde(default)]
+    pub viewer_mode: bool,
+    /// Serve a JSON health report on this localhost port for monitoring
+    /// tools; off if unset. Read at startup.
+    #[serde(default)]
+    pub health_port: Option<u16>,
+    /// Seconds a file must go unchanged before it is processed; raise it for
+    /// large files copied over slow networks. Folders can override it.
+    #[serde(default = "default_debounce_seconds")]
+    pub debounce_seconds: u64,
+    /// How often the watcher checks whether waiting files are ready, in milliseconds
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+    /// Checks in a row a file's size must stay unchanged before it is processed
+    #[serde(default = "default_stable_size_checks")]
+    pub stable_size_checks: u32,
+    /// Process files already in a folder when it is enabled, without asking first
+    #[serde(default)]
+    pub process_existing_files: bool,
+    /// Hold uploads while the internet connection is metered, e.g. a phone hotspot
+    #[serde(default)]
+    pub pause_on_metered: bool,
+    /// Hold uploads while the computer runs on battery
+    #[serde(default)]
+    pub pause_on_battery: bool,
+    /// Proxy for the app's HTTP traffic, e.g. "http://proxy.example.com:8080";
+    /// the system's proxy is used if unset
+    #[serde(default)]
+    pub proxy: Option<String>,
+}
+
+pub const DEFAULT_MAX_LOCAL_OPERATIONS: usize = 2;
+pub const DEFAULT_DEBOUNCE_SECONDS: u64 = 2;
+pub const DEFAULT_POLL_INTERVAL_MS: u64 = 500;
+pub const DEFAULT_STABLE_SIZE_CHECKS: u32 = 2;
+
+fn default_debounce_seconds() -> u64 {
+    DEFAULT_DEBOUNCE_SECONDS
+}
+
+fn default_poll_interval_ms() -> u64 {
+    DEFAULT_POLL_INTERVAL_MS
+}
+
+fn default_stable_size_checks() -> u32 {
+    DEFAULT_STABLE_SIZE_CHECKS
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_local_operations() -> usize {
+    DEFAULT_MAX_LOCAL_OPERATIONS
+}
+
+/// How many subfolder levels a recursive watch reaches by default
+pub const DEFAULT_MAX_DEPTH: u32 = 5;
+
+fn default_max_depth() -> u32 {
+    DEFAULT_MAX_DEPTH
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -51,15 +518,292 @@ pub struct GeneralSettings {
 pub struct ToolConfig {
     pub id: String,
     pub enabled: bool,
+    /// The folders the tool watches, each with its own output and watch settings.
+    /// The first is the one the tool was set up with.
+    #[serde(default)]
+    pub folders: Vec<WatchFolder>,
+    // The settings of one of `folders`, only set on the configs the watcher
+    // gets from watch_folders()
+    #[serde(skip)]
     pub folder_path: Option<String>,
+    #[serde(skip)]
     pub output_mode: OutputMode,
+    #[serde(skip)]
+    pub recursive: bool,
+    #[serde(skip, default = "default_max_depth")]
+    pub max_depth: u32,
+    #[serde(skip)]
+    pub debounce_seconds: Option<u64>,
+    #[serde(skip)]
+    pub backlog_max_age_days: Option<u32>,
+    #[serde(skip)]
+    pub min_size_kb: Option<u64>,
+    #[serde(skip)]
+    pub max_size_mb: Option<u64>,
+    #[serde(skip)]
+    pub on_failure: FailurePolicy,
+    #[serde(skip)]
+    pub duplicates: DuplicatePolicy,
+    #[serde(skip)]
+    pub watch_mode: WatchMode,
+    #[serde(skip)]
+    pub polling_interval_seconds: Option<u64>,
+    #[serde(skip)]
+    pub upload_window: Option<UploadWindow>,
+    #[serde(skip)]
+    pub priority: Priority,
+    #[serde(skip)]
+    pub originals: OriginalsHandling,
     pub options: serde_json::Value,
+    #[serde(default)]
+    pub review: ReviewStage,
+    #[serde(default)]
+    pub decoration: OutputDecoration,
+    /// Output file name without its extension, e.g. "{date}_{name}".
+    /// Defaults to "{name}_{tool}".
+    #[serde(default)]
+    pub output_template: Option<String>,
+    #[serde(default)]
+    pub on_conflict: ConflictPolicy,
+    /// Unpack zip results into a subfolder named after the document and delete
+    /// the zip, e.g. the images of "PDF to JPG"; defaults to the tool's own setting
+    #[serde(default)]
+    pub extract_zip: Option<bool>,
+    /// Run files the tool can't read through the repair tool and try again
+    #[serde(default)]
+    pub auto_repair: bool,
+    /// Where each final output is copied to besides the output folder, e.g.
+    /// the Processed subfolder and an archive share. Each gets its own copy.
+    #[serde(default)]
+    pub destinations: Vec<DestinationSpec>,
+    /// Email each final output
+    #[serde(default)]
+    pub email: Option<EmailAction>,
+    /// Write the job's metadata to `<output>.pdfdk.json` next to each output
+    #[serde(default)]
+    pub sidecar: bool,
+    /// Print each final output
+    #[serde(default)]
+    pub print: Option<PrintAction>,
+    /// Local command run after each successful job
+    #[serde(default)]
+    pub post_command: Option<PostCommand>,
+    /// Tools run after this one, in order, each on the previous output.
+    /// Only the output of the last one is delivered.
+    #[serde(default)]
+    pub chain: Vec<ChainStep>,
+    /// Only run the tool on files meeting all of these; others are delivered unchanged
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    /// For the config of a chained step, the tool whose chain it is in, which
+    /// its secret options are stored under
+    #[serde(skip)]
+    pub chain_owner: Option<String>,
 }
 
+/// A folder watched by a tool, e.g. a local folder or a network share
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchFolder {
+    pub path: String,
+    #[serde(default)]
+    pub output_mode: OutputMode,
+    /// Also pick up files in subfolders, e.g. one per client, down to `max_depth` levels
+    #[serde(default)]
+    pub recursive: bool,
+    #[serde(default = "default_max_depth")]
+    pub max_depth: u32,
+    /// Overrides the general debounce for this folder
+    #[serde(default)]
+    pub debounce_seconds: Option<u64>,
+    /// Only offer to process files already in the folder if they were
+    /// modified within this many days
+    #[serde(default)]
+    pub backlog_max_age_days: Option<u32>,
+    /// Files smaller or larger than this are left in the folder, not uploaded
+    #[serde(default)]
+    pub min_size_kb: Option<u64>,
+    #[serde(default)]
+    pub max_size_mb: Option<u64>,
+    #[serde(default)]
+    pub on_failure: FailurePolicy,
+    #[serde(default)]
+    pub duplicates: DuplicatePolicy,
+    #[serde(default)]
+    pub watch_mode: WatchMode,
+    /// How often a polled folder is scanned, instead of the default
+    #[serde(default)]
+    pub polling_interval_seconds: Option<u64>,
+    /// Files detected outside these hours wait for them; overrides the calendar's window
+    #[serde(default)]
+    pub upload_window: Option<UploadWindow>,
+    /// Queued files of more urgent folders are processed first
+    #[serde(default)]
+    pub priority: Priority,
+    /// What happens to inputs once they are processed
+    #[serde(default)]
+    pub originals: OriginalsHandling,
+}
+
+impl WatchFolder {
+    pub fn new(path: &str, output_mode: OutputMode) -> Self {
+        Self {
+            path: path.to_string(),
+            output_mode,
+            recursive: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            debounce_seconds: None,
+            backlog_max_age_days: None,
+            min_size_kb: None,
+            max_size_mb: None,
+            on_failure: FailurePolicy::default(),
+            duplicates: DuplicatePolicy::default(),
+            watch_mode: WatchMode::default(),
+            polling_interval_seconds: None,
+            upload_window: None,
+            priority: Priority::default(),
+            originals: OriginalsHandling::default(),
+        }
+    }
+}
+
+/// How urgent a folder's files are, least urgent first
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    /// E.g. a "Rush jobs" folder
+    Urgent,
+}
+
+/// How changes in a folder are noticed
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WatchMode {
+    /// Polling for network shares, where the system's notifications often
+    /// miss changes, otherwise the system's notifications
+    #[default]
+    Auto,
+    Native,
+    /// Scan the folder for changes at an interval
+    Polling,
+}
+
+/// How often a polled folder is scanned by default
+pub const DEFAULT_POLLING_INTERVAL_SECONDS: u64 = 10;
+
+/// What happens to an exact copy of an input the tool has processed before
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicatePolicy {
+    /// Move it to Originals without a job; its job points to the earlier output
+    #[default]
+    Skip,
+    /// Process it, but flag the job
+    Warn,
+    Process,
+}
+
+/// What happens to an input once it is processed, or skipped as a duplicate
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OriginalsHandling {
+    /// Move it to the Originals subfolder
+    #[default]
+    Move,
+    Delete,
+    /// Send it to the system's trash (Recycle Bin)
+    Trash,
+    /// Leave it where it is, e.g. for another system watching the folder.
+    /// It isn't picked up again unless it changes.
+    Leave,
+}
+
+/// What happens when a file with the output's name is already there
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    #[default]
+    Overwrite,
+    /// Leave the existing file and don't process the input; it is moved to Originals
+    Skip,
+    /// Add a number to the new output's name, e.g. "invoice_compress_2.pdf"
+    Rename,
+}
+
+/// What happens to an input whose job failed
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailurePolicy {
+    /// Leave it for another try; it is only moved to Failed after failing repeatedly
+    #[default]
+    KeepInPlace,
+    /// Move it to a Failed subfolder right away if the input itself is at fault,
+    /// with an error report next to it
+    MoveToFailed,
+}
+
+/// A check on an input, made locally before it is uploaded
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Condition {
+    LargerThan { megabytes: f64 },
+    SmallerThan { megabytes: f64 },
+    MorePagesThan { pages: u32 },
+    FewerPagesThan { pages: u32 },
+    /// The PDF has no text to select or search, e.g. a scan
+    NoTextLayer,
+}
+
+/// A tool run on the output of the step before it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainStep {
+    pub tool_id: String,
+    #[serde(default = "empty_options")]
+    pub options: serde_json::Value,
+}
+
+fn empty_options() -> serde_json::Value {
+    serde_json::json!({})
+}
+
+/// Id of the built-in tool that fixes damaged PDFs
+pub const REPAIR_TOOL_ID: &str = "repair";
+
+/// How outputs are marked so they are recognizable at a glance in shared folders
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(tag = "mode", rename_all = "kebab-case")]
+pub enum OutputDecoration {
+    #[default]
+    None,
+    /// Prefix output file names with a marker, e.g. "✔ " or "[C] "
+    Prefix { marker: String },
+    /// Tag outputs for the icon overlay shown by the PDF.dk Explorer helper (Windows only)
+    Overlay,
+}
+
+/// Optional human check between processing and delivery:
+/// outputs land in the review folder and are delivered to the normal
+/// output location once someone moves them into the approved folder
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewStage {
+    pub enabled: bool,
+    /// Defaults to "Review" inside the watched folder
+    pub review_folder: Option<String>,
+    /// Defaults to "Approved" inside the watched folder
+    pub approved_folder: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum OutputMode {
     SameFolder,
+    #[default]
     Subfolder,
     Custom(String),
 }
@@ -76,20 +820,192 @@ pub struct ToolDefinition {
     pub api_endpoint: String,
     pub icon: String,
     pub has_options: bool,
+    /// Schema of the tool's options, for tools defined by the API
+    #[serde(default)]
+    pub options_schema: Option<serde_json::Value>,
+    /// Extension of the file the tool produces
+    #[serde(default = "default_output_extension")]
+    pub output_extension: String,
+    /// The watched folder collects files that are processed together as one job
+    #[serde(default)]
+    pub multi_input: bool,
+    /// The result is a report about the input. The original is delivered
+    /// unchanged with the report next to it, instead of being replaced.
+    #[serde(default)]
+    pub report_output: bool,
+    /// The result is a zip, unpacked into a subfolder named after the document
+    #[serde(default)]
+    pub extract_zip: bool,
+    /// Two versions of a document are matched up and processed together as one job
+    #[serde(default)]
+    pub paired_input: bool,
+    /// Extensions of the files the watched folder picks up, lowercase without the dot
+    #[serde(default = "default_input_extensions")]
+    pub input_extensions: Vec<String>,
+}
+
+fn default_output_extension() -> String {
+    "pdf".to_string()
+}
+
+pub fn default_input_extensions() -> Vec<String> {
+    vec!["pdf".to_string()]
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
-            version: 1,
+            version: crate::migrations::CONFIG_VERSION,
             general: GeneralSettings {
                 start_on_login: true,
                 start_minimized: true,
                 show_notifications: true,
                 language: "da".to_string(),
+                background_priority: true,
+                max_local_operations: DEFAULT_MAX_LOCAL_OPERATIONS,
+                job_receipts: false,
+                folder_index: true,
+                snapshot_inputs: true,
+                viewer_mode: false,
+                health_port: None,
+                debounce_seconds: DEFAULT_DEBOUNCE_SECONDS,
+                poll_interval_ms: DEFAULT_POLL_INTERVAL_MS,
+                stable_size_checks: DEFAULT_STABLE_SIZE_CHECKS,
+                process_existing_files: false,
+                pause_on_metered: false,
+                pause_on_battery: false,
+                proxy: None,
             },
             tools: vec![],
             auth: None,
+            windows: WindowStates::default(),
+            log_shipping: LogShipping::default(),
+            destinations: vec![],
+            calendar: WorkCalendar::default(),
+            self_test: SelfTestSettings::default(),
+            retention: RetentionSettings::default(),
+            smtp: SmtpSettings::default(),
+            rules: vec![],
+            base_folder: None,
+            active_profile: None,
+        }
+    }
+}
+
+impl ToolConfig {
+    /// Enabled config with default settings for a tool watching `folder_path`
+    pub fn new(tool_id: &str, folder_path: &str) -> Self {
+        let folder = WatchFolder::new(folder_path, OutputMode::Subfolder);
+        Self {
+            id: tool_id.to_string(),
+            enabled: true,
+            folders: vec![],
+            folder_path: None,
+            output_mode: OutputMode::Subfolder,
+            recursive: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            debounce_seconds: None,
+            backlog_max_age_days: None,
+            min_size_kb: None,
+            max_size_mb: None,
+            on_failure: FailurePolicy::default(),
+            duplicates: DuplicatePolicy::default(),
+            watch_mode: WatchMode::default(),
+            polling_interval_seconds: None,
+            upload_window: None,
+            priority: Priority::default(),
+            originals: OriginalsHandling::default(),
+            options: serde_json::json!({}),
+            review: ReviewStage::default(),
+            decoration: OutputDecoration::default(),
+            output_template: None,
+            on_conflict: ConflictPolicy::default(),
+            extract_zip: None,
+            auto_repair: false,
+            destinations: vec![],
+            email: None,
+            sidecar: false,
+            print: None,
+            post_command: None,
+            chain: vec![],
+            conditions: vec![],
+            chain_owner: None,
+        }
+        .for_folder(&folder)
+    }
+
+    /// Folder where outputs wait for review, if the review stage is enabled
+    pub fn review_folder(&self) -> Option<PathBuf> {
+        self.review_stage_folder(&self.review.review_folder, "Review")
+    }
+
+    /// Folder that triggers delivery when a reviewed output is moved into it
+    pub fn approved_folder(&self) -> Option<PathBuf> {
+        self.review_stage_folder(&self.review.approved_folder, "Approved")
+    }
+
+    /// The folder's tools in the order they run: this tool, then its chain.
+    /// Chained steps share the folder's output settings.
+    pub fn steps(&self) -> Vec<ToolConfig> {
+        let mut steps = vec![self.clone()];
+        steps.extend(self.chain.iter().map(|step| ToolConfig {
+            id: step.tool_id.clone(),
+            options: step.options.clone(),
+            chain: vec![],
+            chain_owner: Some(self.id.clone()),
+            ..self.clone()
+        }));
+        steps
+    }
+
+    /// One config per watched folder, in the order of `folders`
+    pub fn watch_folders(&self) -> Vec<ToolConfig> {
+        self.folders.iter().map(|folder| self.for_folder(folder)).collect()
+    }
+
+    /// The folder the tool was set up with, which merges are run in
+    pub fn main_folder(&self) -> Option<&str> {
+        self.folders.first().map(|folder| folder.path.as_str())
+    }
+
+    /// The config the watcher gets for one of the tool's folders
+    fn for_folder(&self, folder: &WatchFolder) -> ToolConfig {
+        ToolConfig {
+            folder_path: Some(folder.path.clone()),
+            output_mode: folder.output_mode.clone(),
+            recursive: folder.recursive,
+            max_depth: folder.max_depth,
+            debounce_seconds: folder.debounce_seconds,
+            backlog_max_age_days: folder.backlog_max_age_days,
+            min_size_kb: folder.min_size_kb,
+            max_size_mb: folder.max_size_mb,
+            on_failure: folder.on_failure,
+            duplicates: folder.duplicates,
+            watch_mode: folder.watch_mode,
+            polling_interval_seconds: folder.polling_interval_seconds,
+            upload_window: folder.upload_window.clone(),
+            priority: folder.priority,
+            originals: folder.originals,
+            folders: vec![folder.clone()],
+            ..self.clone()
+        }
+    }
+
+    /// Tool the secret options of this config are stored under, if not its own
+    pub fn secrets_owner(&self) -> Option<&str> {
+        self.chain_owner.as_deref()
+    }
+
+    fn review_stage_folder(&self, custom: &Option<String>, default_name: &str) -> Option<PathBuf> {
+        if !self.review.enabled {
+            return None;
+        }
+        match custom {
+            Some(path) => Some(PathBuf::from(path)),
+            None => self
+                .folder_path
+                .as_ref()
+                .map(|folder| PathBuf::from(folder).join(default_name)),
         }
     }
 }
@@ -114,23 +1030,35 @@ impl AppConfig {
             fs::create_dir_all(&processed_path)?;
         }
 
-        // Update or add tool config
+        // Update or add tool config. A new folder replaces the first one and
+        // keeps its settings.
         if let Some(tool) = self.tools.iter_mut().find(|t| t.id == tool_id) {
             tool.enabled = true;
-            tool.folder_path = Some(folder_path.to_string());
+            tool.folders.retain(|f| f.path != folder_path);
+            match tool.folders.first_mut() {
+                Some(first) => first.path = folder_path.to_string(),
+                None => tool.folders.push(WatchFolder::new(folder_path, OutputMode::Subfolder)),
+            }
         } else {
-            self.tools.push(ToolConfig {
-                id: tool_id.to_string(),
-                enabled: true,
-                folder_path: Some(folder_path.to_string()),
-                output_mode: OutputMode::Subfolder,
-                options: serde_json::json!({}),
-            });
+            self.tools.push(ToolConfig::new(tool_id, folder_path));
         }
 
         Ok(())
     }
 
+    /// Folder a tool watches in base folder mode, e.g. `PDF.dk/Compress PDF`
+    pub fn tool_subfolder(&self, tool_id: &str) -> Result<PathBuf, ConfigError> {
+        let tool = get_tool_definition(tool_id).ok_or_else(|| ConfigError::ToolNotFound(tool_id.to_string()))?;
+        let base = self.base_folder.as_ref().map(PathBuf::from).unwrap_or_else(get_default_base_folder);
+        // Names like "PDF to PDF/A" must stay a single folder
+        let name: String = tool
+            .name
+            .chars()
+            .map(|c| if matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*') { '-' } else { c })
+            .collect();
+        Ok(base.join(name.trim()))
+    }
+
     pub fn disable_tool(&mut self, tool_id: &str) {
         if let Some(tool) = self.tools.iter_mut().find(|t| t.id == tool_id) {
             tool.enabled = false;
@@ -142,39 +1070,100 @@ impl AppConfig {
     }
 }
 
-/// Get the config file path
-fn get_config_path() -> Result<PathBuf, ConfigError> {
-    let config_dir = dirs::config_dir().ok_or(ConfigError::NoConfigDir)?;
-    let app_config_dir = config_dir.join("dk.pdf.desktop");
+/// Get the app config directory, creating it if needed.
+/// When running portable this is the data folder beside the executable.
+pub fn get_config_dir() -> Result<PathBuf, ConfigError> {
+    match crate::portable::data_dir() {
+        Some(dir) => {
+            if !dir.exists() {
+                fs::create_dir_all(dir)?;
+            }
+            Ok(dir.to_path_buf())
+        }
+        None => get_user_config_dir(),
+    }
+}
+
+/// Get the app config directory in the user profile, creating it if needed.
+/// Unlike get_config_dir this stays on the PC when running portable.
+pub fn get_user_config_dir() -> Result<PathBuf, ConfigError> {
+    let app_config_dir = dirs::config_dir().ok_or(ConfigError::NoConfigDir)?.join("dk.pdf.desktop");
 
     if !app_config_dir.exists() {
         fs::create_dir_all(&app_config_dir)?;
     }
 
-    Ok(app_config_dir.join("config.json"))
+    Ok(app_config_dir)
 }
 
-/// Load configuration from disk
+/// Get the config file path
+pub fn get_config_path() -> Result<PathBuf, ConfigError> {
+    Ok(get_config_dir()?.join("config.json"))
+}
+
+/// Load configuration from disk, with the machine's policy laid over it
 pub fn load_config() -> Result<AppConfig, ConfigError> {
     let path = get_config_path()?;
 
-    if path.exists() {
-        let content = fs::read_to_string(&path)?;
-        let config: AppConfig = serde_json::from_str(&content)?;
-        Ok(config)
-    } else {
-        Ok(AppConfig::default())
+    if !path.exists() {
+        let mut value = serde_json::to_value(AppConfig::default())?;
+        crate::policy::apply(&mut value, true);
+        return Ok(serde_json::from_value(value)?);
+    }
+
+    let content = fs::read_to_string(&path)?;
+    let mut value: serde_json::Value = serde_json::from_str(&content)?;
+    match crate::migrations::migrate(&mut value) {
+        Ok(Some(from)) => {
+            // Keep the file as the older version wrote it, in case it has to go back
+            let backup = path.with_file_name(format!("config.v{}.backup.json", from));
+            if !backup.exists() {
+                fs::write(&backup, &content)?;
+            }
+            fs::write(&path, serde_json::to_string_pretty(&value)?)?;
+            tracing::info!("Migrated the config from schema version {}, the old file is {:?}", from, backup);
+        }
+        Ok(None) => {}
+        // Fields a newer version added are ignored, so the config is still read
+        Err(e @ crate::migrations::MigrationError::TooNew(..)) => tracing::warn!("{}", e),
+        Err(e) => return Err(e.into()),
     }
+    crate::policy::apply(&mut value, false);
+    Ok(serde_json::from_value(value)?)
 }
 
-/// Save configuration to disk
+/// Save configuration to disk. What the machine's policy sets stays out of
+/// the file, which keeps the user's own values.
 pub fn save_config(config: &AppConfig) -> Result<(), ConfigError> {
     let path = get_config_path()?;
-    let content = serde_json::to_string_pretty(config)?;
+    let content = match crate::policy::current() {
+        None => serde_json::to_string_pretty(config)?,
+        Some(_) => {
+            let mut value = serde_json::to_value(config)?;
+            let user: serde_json::Value = fs::read_to_string(&path)
+                .ok()
+                .and_then(|text| serde_json::from_str(&text).ok())
+                .unwrap_or_default();
+            crate::policy::restore(&mut value, &user);
+            serde_json::to_string_pretty(&value)?
+        }
+    };
     fs::write(&path, content)?;
     Ok(())
 }
 
+/// Save configuration from the app state without touching the auth and window
+/// sections, which the auth and window_state modules manage directly on disk
+pub fn save_app_config(config: &AppConfig) -> Result<(), ConfigError> {
+    let mut merged = config.clone();
+    let on_disk = load_config().ok();
+    merged.auth = on_disk.as_ref().and_then(|c| c.auth.clone());
+    if let Some(on_disk) = on_disk {
+        merged.windows = on_disk.windows;
+    }
+    save_config(&merged)
+}
+
 /// Get the default base folder path
 pub fn get_default_base_folder() -> PathBuf {
     dirs::document_dir()
@@ -183,8 +1172,37 @@ pub fn get_default_base_folder() -> PathBuf {
 }
 
 /// Get list of available tools
-/// Starting with just Compress and Outline as requested
+/// Uses the catalog fetched from the API, or the built-in list when offline
 pub fn get_available_tools() -> Vec<ToolDefinition> {
+    TOOL_CATALOG
+        .read()
+        .ok()
+        .and_then(|catalog| catalog.clone())
+        .unwrap_or_else(builtin_tools)
+}
+
+/// Look up a single tool definition
+pub fn get_tool_definition(tool_id: &str) -> Option<ToolDefinition> {
+    get_available_tools().into_iter().find(|t| t.id == tool_id)
+}
+
+/// Lay the definitions fetched from the API over the built-in list. Built-in
+/// tools the API doesn't list are kept, so folders set up with them keep working.
+pub fn set_tool_catalog(tools: Vec<ToolDefinition>) {
+    let mut merged = builtin_tools();
+    for tool in tools {
+        match merged.iter_mut().find(|t| t.id == tool.id) {
+            Some(builtin) => *builtin = tool,
+            None => merged.push(tool),
+        }
+    }
+    if let Ok(mut catalog) = TOOL_CATALOG.write() {
+        *catalog = Some(merged);
+    }
+}
+
+/// Tools known to this app version, used when the catalog can't be fetched
+fn builtin_tools() -> Vec<ToolDefinition> {
     vec![
         ToolDefinition {
             id: "compress".to_string(),
@@ -195,6 +1213,18 @@ pub fn get_available_tools() -> Vec<ToolDefinition> {
             api_endpoint: "compress".to_string(),
             icon: "compress".to_string(),
             has_options: true,
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "quality": { "type": "string", "enum": ["low", "default", "high", "maximum"] }
+                }
+            })),
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
         },
         ToolDefinition {
             id: "outline".to_string(),
@@ -205,6 +1235,75 @@ pub fn get_available_tools() -> Vec<ToolDefinition> {
             api_endpoint: "outline".to_string(),
             icon: "text".to_string(),
             has_options: false,
+            options_schema: None,
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
+        },
+        ToolDefinition {
+            id: "doc-to-pdf".to_string(),
+            name: "Office to PDF".to_string(),
+            name_da: "Office til PDF".to_string(),
+            description: "Convert Word, Excel and PowerPoint files to PDF".to_string(),
+            description_da: "Konverter Word-, Excel- og PowerPoint-filer til PDF".to_string(),
+            api_endpoint: "doc-to-pdf".to_string(),
+            icon: "file-word".to_string(),
+            has_options: false,
+            options_schema: None,
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: vec!["docx".to_string(), "xlsx".to_string(), "pptx".to_string()],
+        },
+        ToolDefinition {
+            id: "image-to-pdf".to_string(),
+            name: "Image to PDF".to_string(),
+            name_da: "Billede til PDF".to_string(),
+            description: "Convert scanned JPG, PNG and TIFF images to PDF".to_string(),
+            description_da: "Konverter scannede JPG-, PNG- og TIFF-billeder til PDF".to_string(),
+            api_endpoint: "image-to-pdf".to_string(),
+            icon: "image".to_string(),
+            has_options: true,
+            // With all images in one PDF the folder collects images like merge
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "pageSize": {
+                        "type": "string",
+                        "title": "Page size",
+                        "enum": ["fit", "a4", "a3", "letter"],
+                        "x-enum-labels": ["Same as image", "A4", "A3", "Letter"],
+                        "default": "a4"
+                    },
+                    "grouping": {
+                        "type": "string",
+                        "title": "Images per PDF",
+                        "enum": ["single", "combine"],
+                        "x-enum-labels": ["One PDF per image", "All images in one PDF"],
+                        "x-multi-input": "combine",
+                        "default": "single"
+                    },
+                    "idleTimeoutSecs": {
+                        "type": "integer",
+                        "title": "Combine after this many idle seconds (0 = on merge.now only)",
+                        "minimum": 0,
+                        "maximum": 86400,
+                        "default": 30
+                    },
+                    "order": { "type": "string", "enum": ["name", "added"], "default": "name" }
+                }
+            })),
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: ["jpg", "jpeg", "png", "tif", "tiff"].iter().map(|e| e.to_string()).collect(),
         },
         ToolDefinition {
             id: "pdf-to-word".to_string(),
@@ -215,6 +1314,13 @@ pub fn get_available_tools() -> Vec<ToolDefinition> {
             api_endpoint: "pdf-to-word".to_string(),
             icon: "file-word".to_string(),
             has_options: false,
+            options_schema: None,
+            output_extension: "docx".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
         },
         ToolDefinition {
             id: "pdf-to-excel".to_string(),
@@ -225,6 +1331,13 @@ pub fn get_available_tools() -> Vec<ToolDefinition> {
             api_endpoint: "pdf-to-excel".to_string(),
             icon: "file-excel".to_string(),
             has_options: false,
+            options_schema: None,
+            output_extension: "xlsx".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
         },
         ToolDefinition {
             id: "pdf-to-jpg".to_string(),
@@ -235,6 +1348,104 @@ pub fn get_available_tools() -> Vec<ToolDefinition> {
             api_endpoint: "pdf-to-jpg".to_string(),
             icon: "image".to_string(),
             has_options: false,
+            options_schema: None,
+            output_extension: "zip".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
+        },
+        ToolDefinition {
+            id: "pdf-to-pdfa".to_string(),
+            name: "PDF to PDF/A".to_string(),
+            name_da: "PDF til PDF/A".to_string(),
+            description: "Convert to PDF/A for long-term archiving".to_string(),
+            description_da: "Konverter til PDF/A til langtidsarkivering".to_string(),
+            api_endpoint: "pdf-to-pdfa".to_string(),
+            icon: "archive".to_string(),
+            has_options: true,
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "conformance": { "type": "string", "enum": ["1b", "2b", "2u", "3b"] },
+                    "validationReport": { "type": "boolean" }
+                }
+            })),
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
+        },
+        ToolDefinition {
+            id: "extract-text".to_string(),
+            name: "Extract Text".to_string(),
+            name_da: "Udtræk tekst".to_string(),
+            description: "Save the text of a PDF as a text or Markdown file".to_string(),
+            description_da: "Gem teksten fra en PDF som tekst- eller Markdown-fil".to_string(),
+            api_endpoint: "extract-text".to_string(),
+            icon: "text".to_string(),
+            has_options: true,
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "format": {
+                        "type": "string",
+                        "title": "Output format",
+                        "enum": ["txt", "md"],
+                        "x-enum-labels": ["Plain text (.txt)", "Markdown (.md)"],
+                        "x-output-extension": true,
+                        "default": "txt"
+                    },
+                    "pageBreaks": {
+                        "type": "boolean",
+                        "title": "Mark page breaks",
+                        "default": false
+                    }
+                }
+            })),
+            output_extension: "txt".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
+        },
+        ToolDefinition {
+            id: "extract-images".to_string(),
+            name: "Extract Images".to_string(),
+            name_da: "Udtræk billeder".to_string(),
+            description: "Save the images embedded in a PDF to a folder".to_string(),
+            description_da: "Gem de indlejrede billeder fra en PDF i en mappe".to_string(),
+            api_endpoint: "extract-images".to_string(),
+            icon: "image".to_string(),
+            has_options: true,
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "format": {
+                        "type": "string",
+                        "title": "Image format",
+                        "enum": ["original", "png", "jpg"],
+                        "x-enum-labels": ["Keep original format", "PNG", "JPG"],
+                        "default": "original"
+                    },
+                    "minSize": {
+                        "type": "integer",
+                        "title": "Skip images smaller than (pixels)",
+                        "minimum": 0,
+                        "default": 32
+                    }
+                }
+            })),
+            output_extension: "zip".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: true,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
         },
         ToolDefinition {
             id: "rotate".to_string(),
@@ -245,6 +1456,18 @@ pub fn get_available_tools() -> Vec<ToolDefinition> {
             api_endpoint: "rotate".to_string(),
             icon: "rotate".to_string(),
             has_options: true,
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "degrees": { "type": "integer", "enum": [90, 180, 270] }
+                }
+            })),
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
         },
         ToolDefinition {
             id: "unlock".to_string(),
@@ -255,6 +1478,177 @@ pub fn get_available_tools() -> Vec<ToolDefinition> {
             api_endpoint: "unlock".to_string(),
             icon: "unlock".to_string(),
             has_options: false,
+            options_schema: None,
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
+        },
+        ToolDefinition {
+            id: "metadata".to_string(),
+            name: "Edit Metadata".to_string(),
+            name_da: "Rediger Metadata".to_string(),
+            description: "Set title, author, subject and keywords".to_string(),
+            description_da: "Angiv titel, forfatter, emne og nøgleord".to_string(),
+            api_endpoint: "metadata".to_string(),
+            icon: "tag".to_string(),
+            has_options: true,
+            // Values may use {filename}, {folder}, {date} and {time}
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "title": { "type": "string", "maxLength": 500, "x-template": true },
+                    "author": { "type": "string", "maxLength": 200, "x-template": true },
+                    "subject": { "type": "string", "maxLength": 500, "x-template": true },
+                    "keywords": { "type": "string", "maxLength": 1000, "x-template": true }
+                }
+            })),
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
+        },
+        ToolDefinition {
+            id: "protect".to_string(),
+            name: "Protect PDF".to_string(),
+            name_da: "Beskyt PDF".to_string(),
+            description: "Add a password and restrict printing or copying".to_string(),
+            description_da: "Tilføj adgangskode og begræns udskrivning eller kopiering".to_string(),
+            api_endpoint: "protect".to_string(),
+            icon: "lock".to_string(),
+            has_options: true,
+            // The password is kept in the secrets vault, per folder
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "password": { "type": "string", "minLength": 1, "maxLength": 128 },
+                    "allowPrinting": { "type": "boolean" },
+                    "allowCopying": { "type": "boolean" },
+                    "allowEditing": { "type": "boolean" }
+                },
+                "required": ["password"]
+            })),
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
+        },
+        ToolDefinition {
+            id: "sign".to_string(),
+            name: "Sign PDF".to_string(),
+            name_da: "Underskriv PDF".to_string(),
+            description: "Add a visible digital signature with your certificate".to_string(),
+            description_da: "Tilføj en synlig digital signatur med dit certifikat".to_string(),
+            api_endpoint: "sign".to_string(),
+            icon: "signature".to_string(),
+            has_options: true,
+            // The .p12 is uploaded with each job and signing happens on the
+            // server; its password is kept in the secrets vault, per folder
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "certificatePath": {
+                        "type": "string",
+                        "format": "file-path",
+                        "x-file-extensions": ["p12", "pfx"],
+                        "x-upload-as": "certificate",
+                        "minLength": 1
+                    },
+                    "certificatePassword": { "type": "string", "maxLength": 256 },
+                    "page": { "type": "string", "enum": ["first", "last"], "default": "last" },
+                    "position": {
+                        "type": "string",
+                        "enum": ["bottom-right", "bottom-left", "top-right", "top-left"],
+                        "default": "bottom-right"
+                    },
+                    "reason": { "type": "string", "maxLength": 200, "x-template": true },
+                    "location": { "type": "string", "maxLength": 200 },
+                    "showDate": { "type": "boolean", "default": true }
+                },
+                "required": ["certificatePath"]
+            })),
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
+        },
+        ToolDefinition {
+            id: "redact".to_string(),
+            name: "Redact PDF".to_string(),
+            name_da: "Anonymiser PDF".to_string(),
+            description: "Black out keywords and personal data such as CPR numbers".to_string(),
+            description_da: "Sværter nøgleord og personoplysninger som CPR-numre".to_string(),
+            api_endpoint: "redact".to_string(),
+            icon: "eye-off".to_string(),
+            has_options: true,
+            // Presets are expanded into patterns before upload, so the server
+            // gets one list of keywords and /regexes/
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "patterns": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "format": "pattern-list",
+                        "default": []
+                    },
+                    "presets": {
+                        "type": "array",
+                        "items": { "type": "string", "enum": ["cpr", "email", "phone"] },
+                        "x-presets-for": "patterns",
+                        "default": ["cpr", "email"]
+                    },
+                    "matchCase": { "type": "boolean", "default": false }
+                }
+            })),
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
+        },
+        ToolDefinition {
+            id: REPAIR_TOOL_ID.to_string(),
+            name: "Repair PDF".to_string(),
+            name_da: "Reparer PDF".to_string(),
+            description: "Fix damaged PDFs that won't open".to_string(),
+            description_da: "Reparer beskadigede PDF'er der ikke kan åbnes".to_string(),
+            api_endpoint: "repair".to_string(),
+            icon: "wrench".to_string(),
+            has_options: false,
+            options_schema: None,
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
+        },
+        ToolDefinition {
+            id: "flatten".to_string(),
+            name: "Flatten PDF".to_string(),
+            name_da: "Flad PDF".to_string(),
+            description: "Bake form fields and annotations into the pages".to_string(),
+            description_da: "Indbræn formularfelter og kommentarer i siderne".to_string(),
+            api_endpoint: "flatten".to_string(),
+            icon: "layers".to_string(),
+            has_options: false,
+            options_schema: None,
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
         },
         ToolDefinition {
             id: "ocr".to_string(),
@@ -265,6 +1659,18 @@ pub fn get_available_tools() -> Vec<ToolDefinition> {
             api_endpoint: "ocr".to_string(),
             icon: "scan".to_string(),
             has_options: true,
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "language": { "type": "string", "enum": ["da", "en"] }
+                }
+            })),
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
         },
         ToolDefinition {
             id: "bleed".to_string(),
@@ -275,6 +1681,243 @@ pub fn get_available_tools() -> Vec<ToolDefinition> {
             api_endpoint: "bleed".to_string(),
             icon: "expand".to_string(),
             has_options: true,
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "amount": { "type": "number", "minimum": 0, "maximum": 20 }
+                }
+            })),
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
+        },
+        ToolDefinition {
+            id: "cmyk-convert".to_string(),
+            name: "Convert to CMYK".to_string(),
+            name_da: "Konverter til CMYK".to_string(),
+            description: "Convert RGB colors to CMYK with an ICC profile".to_string(),
+            description_da: "Konverter RGB-farver til CMYK med en ICC-profil".to_string(),
+            api_endpoint: "cmyk-convert".to_string(),
+            icon: "palette".to_string(),
+            has_options: true,
+            // Titled properties are rendered from the schema by the options UI
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "profile": {
+                        "type": "string",
+                        "title": "ICC profile",
+                        "enum": ["fogra39", "fogra51", "gracol2013", "swop", "japan-color-2011"],
+                        "x-enum-labels": [
+                            "Coated FOGRA39 (ISO 12647-2:2004)",
+                            "PSO Coated v3 (FOGRA51)",
+                            "GRACoL 2013 (CRPC6)",
+                            "U.S. Web Coated (SWOP) v2",
+                            "Japan Color 2011 Coated"
+                        ],
+                        "default": "fogra39"
+                    },
+                    "customProfilePath": {
+                        "type": "string",
+                        "title": "Custom ICC profile (optional, replaces the above)",
+                        "format": "file-path",
+                        "x-upload-as": "icc_profile"
+                    },
+                    "renderingIntent": {
+                        "type": "string",
+                        "title": "Rendering intent",
+                        "enum": ["perceptual", "relative-colorimetric", "saturation", "absolute-colorimetric"],
+                        "x-enum-labels": ["Perceptual", "Relative colorimetric", "Saturation", "Absolute colorimetric"],
+                        "default": "relative-colorimetric"
+                    },
+                    "blackPointCompensation": {
+                        "type": "boolean",
+                        "title": "Black point compensation",
+                        "default": true
+                    },
+                    "preservePureBlack": {
+                        "type": "boolean",
+                        "title": "Keep black text as pure K",
+                        "default": true
+                    }
+                }
+            })),
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
+        },
+        ToolDefinition {
+            id: "preflight".to_string(),
+            name: "Preflight".to_string(),
+            name_da: "Preflight".to_string(),
+            description: "Check PDFs against a print profile and write a report".to_string(),
+            description_da: "Kontroller PDF'er mod en trykprofil og skriv en rapport".to_string(),
+            api_endpoint: "preflight".to_string(),
+            icon: "clipboard-check".to_string(),
+            has_options: true,
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "profile": { "type": "string", "enum": ["pdf-x-4", "pdf-x-1a", "pdf-x-3"] },
+                    "maxInkCoverage": { "type": "integer", "minimum": 200, "maximum": 400 },
+                    "minImageResolution": { "type": "integer", "minimum": 72, "maximum": 1200 }
+                }
+            })),
+            output_extension: "json".to_string(),
+            multi_input: false,
+            report_output: true,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
+        },
+        ToolDefinition {
+            id: "n-up".to_string(),
+            name: "N-up Imposition".to_string(),
+            name_da: "Flere sider pr. ark".to_string(),
+            description: "Place several pages on each printed sheet".to_string(),
+            description_da: "Placer flere sider på hvert trykark".to_string(),
+            api_endpoint: "n-up".to_string(),
+            icon: "grid".to_string(),
+            has_options: true,
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "sheetSize": { "type": "string", "enum": ["a4", "a3", "sra3", "letter", "tabloid"] },
+                    "pagesPerSheet": { "type": "integer", "enum": [2, 4, 6, 8, 9, 16] },
+                    "margin": { "type": "number", "minimum": 0, "maximum": 50 }
+                }
+            })),
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
+        },
+        ToolDefinition {
+            id: "watermark".to_string(),
+            name: "Watermark".to_string(),
+            name_da: "Vandmærke".to_string(),
+            description: "Stamp a text or image watermark on every page".to_string(),
+            description_da: "Stempl et tekst- eller billedvandmærke på hver side".to_string(),
+            api_endpoint: "watermark".to_string(),
+            icon: "stamp".to_string(),
+            has_options: true,
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "text": { "type": "string", "maxLength": 200 },
+                    "imagePath": { "type": "string", "format": "file-path", "x-upload-as": "image" },
+                    "opacity": { "type": "number", "minimum": 0, "maximum": 1 },
+                    "position": {
+                        "type": "string",
+                        "enum": ["center", "diagonal", "top-left", "top-right", "bottom-left", "bottom-right"]
+                    },
+                    "fontSize": { "type": "integer", "minimum": 6, "maximum": 200 }
+                }
+            })),
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
+        },
+        ToolDefinition {
+            id: "page-numbers".to_string(),
+            name: "Page Numbers".to_string(),
+            name_da: "Sidetal".to_string(),
+            description: "Add page numbers to every page".to_string(),
+            description_da: "Tilføj sidetal på hver side".to_string(),
+            api_endpoint: "page-numbers".to_string(),
+            icon: "hash".to_string(),
+            has_options: true,
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "position": {
+                        "type": "string",
+                        "enum": ["bottom-center", "bottom-left", "bottom-right", "top-center", "top-left", "top-right"]
+                    },
+                    "startNumber": { "type": "integer", "minimum": 0, "maximum": 100000 },
+                    "format": { "type": "string", "format": "page-label", "maxLength": 100 },
+                    "pages": { "type": "string", "format": "page-ranges", "maxLength": 200 }
+                }
+            })),
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
+        },
+        ToolDefinition {
+            id: "compare".to_string(),
+            name: "Compare PDFs".to_string(),
+            name_da: "Sammenlign PDF'er".to_string(),
+            description: "Mark the differences between two versions of a document".to_string(),
+            description_da: "Markér forskellene mellem to versioner af et dokument".to_string(),
+            api_endpoint: "compare".to_string(),
+            icon: "diff".to_string(),
+            has_options: true,
+            // Versions are matched by name suffix (x_v1.pdf + x_v2.pdf) or by
+            // the same name in the A/ and B/ subfolders
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "pairing": {
+                        "type": "string",
+                        "title": "Match versions by",
+                        "enum": ["suffix", "folders"],
+                        "x-enum-labels": ["Name suffix (x_v1.pdf + x_v2.pdf)", "Same name in A/ and B/"],
+                        "default": "suffix"
+                    },
+                    "oldSuffix": { "type": "string", "title": "Suffix of the old version", "minLength": 1, "maxLength": 50, "default": "_v1" },
+                    "newSuffix": { "type": "string", "title": "Suffix of the new version", "minLength": 1, "maxLength": 50, "default": "_v2" },
+                    "highlight": {
+                        "type": "string",
+                        "title": "Mark",
+                        "enum": ["text", "visual", "both"],
+                        "x-enum-labels": ["Text changes", "Visual changes", "Both"],
+                        "default": "both"
+                    }
+                }
+            })),
+            output_extension: "pdf".to_string(),
+            multi_input: false,
+            report_output: false,
+            extract_zip: false,
+            paired_input: true,
+            input_extensions: default_input_extensions(),
+        },
+        ToolDefinition {
+            id: "merge".to_string(),
+            name: "Merge PDFs".to_string(),
+            name_da: "Saml PDF'er".to_string(),
+            description: "Collect PDFs in the folder and merge them into one file".to_string(),
+            description_da: "Saml PDF'er i mappen og flet dem til én fil".to_string(),
+            api_endpoint: "merge".to_string(),
+            icon: "merge".to_string(),
+            has_options: true,
+            options_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "idleTimeoutSecs": { "type": "integer", "minimum": 0, "maximum": 86400 },
+                    "order": { "type": "string", "enum": ["name", "added"] }
+                }
+            })),
+            output_extension: "pdf".to_string(),
+            multi_input: true,
+            report_output: false,
+            extract_zip: false,
+            paired_input: false,
+            input_extensions: default_input_extensions(),
         },
     ]
 }
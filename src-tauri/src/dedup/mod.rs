@@ -0,0 +1,147 @@
+// Deduplication module for PDF.dk Desktop
+// Remembers the content hash of every input each tool has processed, so the
+// same PDF dropped twice (or restored from Originals) isn't billed again
+
+use crate::config;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LEDGER_FILE: &str = "processed-inputs.json";
+/// Inputs remembered per tool; the oldest are forgotten first
+const MAX_ENTRIES_PER_TOOL: usize = 10_000;
+
+/// An input a tool has processed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessedInput {
+    pub file: String,
+    pub output: String,
+    /// Unix seconds
+    pub processed_at: u64,
+}
+
+impl ProcessedInput {
+    /// Why a copy of this input is a duplicate, for the job history
+    pub fn describe(&self) -> String {
+        let date = chrono::DateTime::from_timestamp(self.processed_at as i64, 0)
+            .map(|d| d.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        format!("same content as {}, processed {}", self.file, date)
+    }
+}
+
+// Tool id -> content hash -> when and where it was processed, loaded on first use
+static LEDGER: Lazy<Mutex<HashMap<String, HashMap<String, ProcessedInput>>>> = Lazy::new(|| Mutex::new(load()));
+
+/// SHA-256 of a file, hashed in the background
+pub async fn hash(path: &Path) -> Option<String> {
+    let path = path.to_path_buf();
+    crate::local_work::run(move || crate::receipts::sha256_file(&path))
+        .await
+        .ok()?
+        .ok()
+}
+
+/// The earlier run of `tool_id` on an input with this hash, if there was one.
+/// Falls back to the index in the input's folder, which outlives a cleared
+/// ledger and also covers runs from other PCs watching the same folder.
+pub async fn find(tool_id: &str, sha256: &str, folder: Option<&Path>) -> Option<ProcessedInput> {
+    let known = LEDGER
+        .lock()
+        .ok()
+        .and_then(|ledger| ledger.get(tool_id)?.get(sha256).cloned());
+    if known.is_some() {
+        return known;
+    }
+    let folder = folder?;
+    let index = crate::folder_index::load(folder).await.ok()?;
+    let entry = index.entries.get(sha256).filter(|e| e.tool_id == tool_id)?;
+    Some(ProcessedInput {
+        file: folder.join(&entry.file_name).to_string_lossy().into_owned(),
+        output: entry.output.clone(),
+        processed_at: chrono::DateTime::parse_from_rfc3339(&entry.processed_at)
+            .map(|d| d.timestamp().max(0) as u64)
+            .unwrap_or(0),
+    })
+}
+
+/// Whether `tool_id` processed the file at `path` since it was last modified,
+/// so an input left in its folder isn't picked up again
+pub fn processed_since(tool_id: &str, path: &Path, modified: SystemTime) -> bool {
+    let modified = modified.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let path = path.to_string_lossy();
+    LEDGER.lock().is_ok_and(|ledger| {
+        ledger
+            .get(tool_id)
+            .is_some_and(|processed| processed.values().any(|p| p.file == path && p.processed_at >= modified))
+    })
+}
+
+/// Remember that `tool_id` processed an input with this hash
+pub fn record(tool_id: &str, sha256: &str, input: &Path, output: &Path) {
+    let Ok(mut ledger) = LEDGER.lock() else {
+        return;
+    };
+    let processed = ledger.entry(tool_id.to_string()).or_default();
+    processed.insert(
+        sha256.to_string(),
+        ProcessedInput {
+            file: input.to_string_lossy().into_owned(),
+            output: output.to_string_lossy().into_owned(),
+            processed_at: now_secs(),
+        },
+    );
+    if processed.len() > MAX_ENTRIES_PER_TOOL {
+        let mut by_age: Vec<(String, u64)> = processed.iter().map(|(h, p)| (h.clone(), p.processed_at)).collect();
+        by_age.sort_by_key(|(_, at)| *at);
+        for (hash, _) in by_age.into_iter().take(processed.len() - MAX_ENTRIES_PER_TOOL) {
+            processed.remove(&hash);
+        }
+    }
+    save(&ledger);
+}
+
+/// Forget the inputs of one tool, or of every tool. Returns how many were forgotten.
+pub fn clear(tool_id: Option<&str>) -> usize {
+    let Ok(mut ledger) = LEDGER.lock() else {
+        return 0;
+    };
+    let cleared = match tool_id {
+        Some(tool_id) => ledger.remove(tool_id).map(|p| p.len()).unwrap_or(0),
+        None => ledger.drain().map(|(_, p)| p.len()).sum(),
+    };
+    save(&ledger);
+    cleared
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn load() -> HashMap<String, HashMap<String, ProcessedInput>> {
+    config::get_config_dir()
+        .ok()
+        .and_then(|dir| fs::read_to_string(dir.join(LEDGER_FILE)).ok())
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save(ledger: &HashMap<String, HashMap<String, ProcessedInput>>) {
+    let result = config::get_config_dir()
+        .map_err(|e| e.to_string())
+        .and_then(|dir| {
+            let text = serde_json::to_string(ledger).map_err(|e| e.to_string())?;
+            fs::write(dir.join(LEDGER_FILE), text).map_err(|e| e.to_string())
+        });
+    if let Err(e) = result {
+        tracing::warn!("Could not save the processed inputs ledger: {}", e);
+    }
+}
@@ -0,0 +1,568 @@
+// Delivery module for PDF.dk Desktop
+// Copies finished outputs to further output folders and to backup destinations in failover order,
+// queueing them while they can't be delivered, and uploads them to SFTP/FTPS destinations such as
+// a RIP's hot folder or to cloud storage
+
+use crate::config::{self, Destination, DestinationSpec, DestinationTarget};
+use crate::secrets::{self, NS_DESTINATIONS};
+use base64::{engine::general_purpose::STANDARD_NO_PAD as BASE64, Engine};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+use thiserror::Error;
+use tracing::info;
+
+pub const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const HEALTH_PROBE: &str = ".pdfdk-health-check";
+const QUEUE_FILE: &str = "delivery-queue.json";
+/// "host:port" -> SHA-256 of the host key seen on the first connection
+const KNOWN_HOSTS_FILE: &str = "sftp-known-hosts.json";
+const SFTP_PORT: u16 = 22;
+const FTPS_PORT: u16 = 21;
+/// Connecting, and each read or write once connected
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Wait before a destination's first retry, doubled for each one after
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+/// Extra attempts at copying a queued output into an output folder
+const OUTPUT_FOLDER_RETRIES: u32 = 2;
+
+static DESTINATIONS: Lazy<RwLock<Vec<Destination>>> = Lazy::new(|| RwLock::new(Vec::new()));
+static HEALTH: Lazy<RwLock<HashMap<String, Health>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+// Serializes access to the queue file
+static QUEUE_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+static QUEUED: AtomicUsize = AtomicUsize::new(0);
+// Serializes access to the known hosts file
+static KNOWN_HOSTS_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+#[derive(Error, Debug)]
+pub enum DeliveryError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Config error: {0}")]
+    Config(#[from] config::ConfigError),
+    #[error("Invalid delivery queue: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Default)]
+struct Health {
+    healthy: Option<bool>,
+    last_checked: Option<String>,
+    last_error: Option<String>,
+}
+
+/// Health of one destination for diagnostics and the tray
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DestinationStatus {
+    pub id: String,
+    pub name: String,
+    /// None until the destination has been checked or used
+    pub healthy: Option<bool>,
+    pub last_checked: Option<String>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveryStatus {
+    pub destinations: Vec<DestinationStatus>,
+    /// Outputs waiting for a destination to come back
+    pub queued: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeliveryOutcome {
+    /// Copied to the destination with this id, or into this output folder
+    Delivered(String),
+    /// Every destination is down, or the output folder can't be written to -
+    /// retried after each health check
+    Queued,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct QueuedDelivery {
+    file: PathBuf,
+    #[serde(default)]
+    destinations: Vec<String>,
+    /// Output folder the copy goes to, in place of destinations
+    #[serde(default)]
+    folder: Option<PathBuf>,
+    queued_at: String,
+}
+
+/// Use the destinations of a new or changed config
+pub fn configure(destinations: &[Destination]) {
+    if let Ok(mut current) = DESTINATIONS.write() {
+        *current = destinations.to_vec();
+    }
+    if let Ok(mut health) = HEALTH.write() {
+        health.retain(|id, _| destinations.iter().any(|d| &d.id == id));
+    }
+    QUEUED.store(load_queue().map(|q| q.len()).unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Current health of every destination
+pub fn status() -> DeliveryStatus {
+    let destinations = DESTINATIONS.read().map(|d| d.clone()).unwrap_or_default();
+    let health = HEALTH.read().map(|h| h.clone()).unwrap_or_default();
+    DeliveryStatus {
+        destinations: destinations
+            .into_iter()
+            .map(|d| {
+                let h = health.get(&d.id).cloned().unwrap_or_default();
+                DestinationStatus {
+                    id: d.id,
+                    name: d.name,
+                    healthy: h.healthy,
+                    last_checked: h.last_checked,
+                    last_error: h.last_error,
+                }
+            })
+            .collect(),
+        queued: QUEUED.load(Ordering::Relaxed),
+    }
+}
+
+/// Copy an output to where `spec` says, or queue the copy if it can't be
+/// made now. Output folders are placed relative to `source_folder`.
+/// Each place is tried once, so the job worker isn't held up by a place that
+/// is down; the retries happen when the queue is retried.
+pub async fn deliver(spec: &DestinationSpec, source_folder: &Path, file: &Path) -> Result<DeliveryOutcome, DeliveryError> {
+    let (delivered, destinations, folder) = match spec {
+        DestinationSpec::Output(mode) => {
+            let folder = crate::watcher::output_dir(source_folder, mode);
+            // Already there as the tool's own output
+            if file.parent() == Some(folder.as_path()) {
+                return Ok(DeliveryOutcome::Delivered(folder.to_string_lossy().into_owned()));
+            }
+            (deliver_to_folder(&folder, file, 0).await, vec![], Some(folder))
+        }
+        DestinationSpec::Failover(order) => (deliver_in_order(order, file, false).await, order.clone(), None),
+    };
+    if let Some(delivered) = delivered {
+        return Ok(DeliveryOutcome::Delivered(delivered));
+    }
+
+    let _lock = QUEUE_LOCK.lock().await;
+    let mut queue = load_queue()?;
+    match &folder {
+        Some(folder) => crate::add_log(&format!("Could not copy {:?} to {:?} - queued it", file, folder)),
+        None => crate::add_log(&format!("All destinations are down - queued {:?} for delivery", file)),
+    }
+    queue.push(QueuedDelivery {
+        file: file.to_path_buf(),
+        destinations,
+        folder,
+        queued_at: chrono::Local::now().to_rfc3339(),
+    });
+    save_queue(&queue)?;
+    Ok(DeliveryOutcome::Queued)
+}
+
+/// Check every destination, then retry queued deliveries
+pub async fn check_all() {
+    let destinations = DESTINATIONS.read().map(|d| d.clone()).unwrap_or_default();
+    for destination in &destinations {
+        let result = check(destination).await;
+        if let Err(e) = &result {
+            if is_healthy(&destination.id) != Some(false) {
+                crate::add_log(&format!("Destination {} is down: {}", destination.name, e));
+            }
+        } else if is_healthy(&destination.id) == Some(false) {
+            crate::add_log(&format!("Destination {} is back up", destination.name));
+        }
+        record(&destination.id, result);
+    }
+
+    if let Err(e) = retry_queue().await {
+        crate::add_log(&format!("Failed to retry queued deliveries: {}", e));
+    }
+}
+
+/// Copy an output to the first destination in `order` that takes it, giving
+/// each its retries if `retry` is set
+async fn deliver_in_order(order: &[String], file: &Path, retry: bool) -> Option<String> {
+    let destinations = DESTINATIONS.read().map(|d| d.clone()).unwrap_or_default();
+    for id in order {
+        let Some(destination) = destinations.iter().find(|d| &d.id == id) else {
+            crate::add_log(&format!("Unknown destination {} - skipping it", id));
+            continue;
+        };
+        // Skip destinations the last check found down - the next check retries them
+        if is_healthy(id) == Some(false) {
+            continue;
+        }
+        let retries = if retry { destination.retries } else { 0 };
+        let mut attempt = 0;
+        loop {
+            match copy_to(destination, file).await {
+                Ok(copied) => {
+                    record(id, Ok(()));
+                    info!("Delivered {:?} to {:?}", file, copied);
+                    return Some(id.clone());
+                }
+                Err(e) if attempt < retries => {
+                    let delay = RETRY_DELAY * 2u32.saturating_pow(attempt.min(6));
+                    attempt += 1;
+                    crate::add_log(&format!(
+                        "Could not deliver to {}: {} - retry {} of {} in {}s",
+                        destination.name,
+                        e,
+                        attempt,
+                        retries,
+                        delay.as_secs()
+                    ));
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => {
+                    crate::add_log(&format!("Could not deliver to {}: {} - trying the next destination", destination.name, e));
+                    record(id, Err(e));
+                    break;
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Copy an output into an output folder, created if missing, with up to
+/// `retries` more attempts. Returns the folder if the copy was made.
+async fn deliver_to_folder(folder: &Path, file: &Path, retries: u32) -> Option<String> {
+    let mut attempt = 0;
+    loop {
+        let copied = match tokio::fs::create_dir_all(folder).await {
+            Ok(()) => copy_into(folder, file).await,
+            Err(e) => Err(e.to_string()),
+        };
+        match copied {
+            Ok(target) => {
+                info!("Copied output to: {:?}", target);
+                return Some(folder.to_string_lossy().into_owned());
+            }
+            Err(e) if attempt < retries => {
+                attempt += 1;
+                crate::add_log(&format!("Could not copy {:?} to {:?}: {} - retry {}", file, folder, e, attempt));
+                tokio::time::sleep(RETRY_DELAY * attempt).await;
+            }
+            Err(e) => {
+                crate::add_log(&format!("Could not copy {:?} to {:?}: {}", file, folder, e));
+                return None;
+            }
+        }
+    }
+}
+
+async fn retry_queue() -> Result<(), DeliveryError> {
+    // Uploads can take long, so the queue isn't locked while they run and
+    // deliveries queued meanwhile are kept
+    let queue = {
+        let _lock = QUEUE_LOCK.lock().await;
+        load_queue()?
+    };
+    if queue.is_empty() {
+        return Ok(());
+    }
+
+    let mut done = Vec::new();
+    for item in queue {
+        if !item.file.exists() {
+            crate::add_log(&format!("Queued output {:?} no longer exists - dropping it", item.file));
+            done.push(item);
+            continue;
+        }
+        let delivered = match &item.folder {
+            Some(folder) => deliver_to_folder(folder, &item.file, OUTPUT_FOLDER_RETRIES).await,
+            None => deliver_in_order(&item.destinations, &item.file, true).await,
+        };
+        if delivered.is_some() {
+            crate::add_log(&format!("Delivered queued output {:?}", item.file));
+            done.push(item);
+        }
+    }
+
+    let _lock = QUEUE_LOCK.lock().await;
+    let mut queue = load_queue()?;
+    queue.retain(|item| !done.contains(item));
+    save_queue(&queue)
+}
+
+/// A destination is healthy if something can be written to it
+async fn check(destination: &Destination) -> Result<(), String> {
+    match &destination.target {
+        DestinationTarget::Folder { path } => {
+            // A missing root usually means an unmounted share, so don't create it
+            let probe = Path::new(path).join(HEALTH_PROBE);
+            tokio::fs::write(&probe, b"").await.map_err(|e| e.to_string())?;
+            let _ = tokio::fs::remove_file(&probe).await;
+            Ok(())
+        }
+        DestinationTarget::Sftp { .. } | DestinationTarget::Ftps { .. } => upload(destination, None).await.map(|_| ()),
+        DestinationTarget::S3 { .. } | DestinationTarget::GoogleDrive { .. } | DestinationTarget::Dropbox { .. } => {
+            crate::cloud::check(destination).await.map_err(|e| e.to_string())
+        }
+    }
+}
+
+async fn copy_to(destination: &Destination, file: &Path) -> Result<PathBuf, String> {
+    match &destination.target {
+        DestinationTarget::Folder { path } => copy_into(Path::new(path), file).await,
+        DestinationTarget::Sftp { .. } | DestinationTarget::Ftps { .. } => {
+            if file.is_dir() {
+                return Err("Unpacked results can't be uploaded - turn off unpacking for this tool".to_string());
+            }
+            upload(destination, Some(file)).await.map(PathBuf::from)
+        }
+        DestinationTarget::S3 { .. } | DestinationTarget::GoogleDrive { .. } | DestinationTarget::Dropbox { .. } => {
+            crate::cloud::upload(destination, file).await.map(PathBuf::from).map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Save the password of an SFTP/FTPS destination, or the secret access key
+/// of an S3 one, in the keyring. An empty password removes it.
+pub fn set_password(destination_id: &str, password: &str) -> Result<(), secrets::SecretsError> {
+    if password.is_empty() {
+        secrets::delete_secret(NS_DESTINATIONS, destination_id)
+    } else {
+        secrets::set_secret(NS_DESTINATIONS, destination_id, password)
+    }
+}
+
+/// Log in to an SFTP/FTPS destination and upload `file` into its folder.
+/// Without a file this only checks the folder can be opened.
+/// Returns the URL of the upload.
+async fn upload(destination: &Destination, file: Option<&Path>) -> Result<String, String> {
+    let password = secrets::get_secret(NS_DESTINATIONS, &destination.id)
+        .map_err(|e| e.to_string())?
+        .ok_or("No password saved for this destination")?;
+    let target = destination.target.clone();
+    let file = file.map(Path::to_path_buf);
+    tokio::task::spawn_blocking(move || {
+        let result = match &target {
+            DestinationTarget::Sftp {
+                host,
+                port,
+                username,
+                path,
+                fingerprint,
+            } => {
+                let login = Login {
+                    host,
+                    port: port.unwrap_or(SFTP_PORT),
+                    username,
+                    password: &password,
+                };
+                upload_sftp(&login, fingerprint.as_deref(), path, file.as_deref())
+            }
+            DestinationTarget::Ftps {
+                host,
+                port,
+                username,
+                path,
+            } => {
+                let login = Login {
+                    host,
+                    port: port.unwrap_or(FTPS_PORT),
+                    username,
+                    password: &password,
+                };
+                upload_ftps(&login, path, file.as_deref())
+            }
+            _ => Err("Not an SFTP/FTPS destination".into()),
+        };
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+struct Login<'a> {
+    host: &'a str,
+    port: u16,
+    username: &'a str,
+    password: &'a str,
+}
+
+type RemoteResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+fn connect(login: &Login) -> RemoteResult<SocketAddr> {
+    (login.host, login.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| format!("Could not resolve {}", login.host).into())
+}
+
+fn upload_sftp(login: &Login, fingerprint: Option<&str>, folder: &str, file: Option<&Path>) -> RemoteResult<String> {
+    let tcp = TcpStream::connect_timeout(&connect(login)?, REMOTE_TIMEOUT)?;
+    let mut session = ssh2::Session::new()?;
+    session.set_tcp_stream(tcp);
+    session.set_timeout(REMOTE_TIMEOUT.as_millis() as u32);
+    session.handshake()?;
+    // Before the password is sent
+    check_host_key(&session, login, fingerprint)?;
+    session.userauth_password(login.username, login.password)?;
+    let sftp = session.sftp()?;
+
+    let folder = if folder.is_empty() { "." } else { folder.trim_end_matches('/') };
+    let Some(file) = file else {
+        sftp.stat(Path::new(folder))?;
+        return Ok(String::new());
+    };
+    // Hot folders pick up whatever appears, so upload under a hidden name
+    // and only rename it once it is complete
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let partial = format!("{}/.{}.part", folder, name);
+    let target = format!("{}/{}", folder, name);
+    {
+        let mut source = std::fs::File::open(file)?;
+        let mut remote = sftp.create(Path::new(&partial))?;
+        std::io::copy(&mut source, &mut remote)?;
+    }
+    // SFTP rename doesn't replace an existing file on most servers
+    let _ = sftp.unlink(Path::new(&target));
+    sftp.rename(Path::new(&partial), Path::new(&target), None)?;
+    Ok(format!("sftp://{}/{}", login.host, target.trim_start_matches("./")))
+}
+
+/// Check the server's host key against the configured fingerprint or, without
+/// one, against the key pinned on the first connection to the server
+fn check_host_key(session: &ssh2::Session, login: &Login, fingerprint: Option<&str>) -> RemoteResult<()> {
+    let actual = session
+        .host_key_hash(ssh2::HashType::Sha256)
+        .map(|hash| BASE64.encode(hash))
+        .ok_or("The server sent no host key")?;
+    let host = format!("{}:{}", login.host, login.port);
+    let _lock = KNOWN_HOSTS_LOCK.lock().map_err(|_| "Known hosts lock poisoned")?;
+    let path = config::get_config_dir()?.join(KNOWN_HOSTS_FILE);
+    let mut known: HashMap<String, String> = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default();
+
+    match fingerprint {
+        Some(expected) => {
+            let expected = expected.trim().trim_start_matches("SHA256:").trim_end_matches('=');
+            if actual != expected {
+                return Err(format!("The server's host key SHA256:{} is not the expected one", actual).into());
+            }
+        }
+        None => match known.get(&host) {
+            Some(pinned) if *pinned == actual => return Ok(()),
+            Some(pinned) => {
+                return Err(format!(
+                    "The host key of {} changed from SHA256:{} to SHA256:{}. If the server was \
+                     reinstalled, enter its new fingerprint in the destination.",
+                    host, pinned, actual
+                )
+                .into())
+            }
+            None => info!("Pinned host key SHA256:{} of {}", actual, host),
+        },
+    }
+    // A configured fingerprint also replaces the pin, so it holds if the fingerprint is removed
+    if known.get(&host) != Some(&actual) {
+        known.insert(host, actual);
+        std::fs::write(&path, serde_json::to_string_pretty(&known)?)?;
+    }
+    Ok(())
+}
+
+fn upload_ftps(login: &Login, folder: &str, file: Option<&Path>) -> RemoteResult<String> {
+    use suppaftp::native_tls::TlsConnector;
+    use suppaftp::{NativeTlsConnector, NativeTlsFtpStream};
+
+    let stream = NativeTlsFtpStream::connect_timeout(connect(login)?, REMOTE_TIMEOUT)?;
+    stream.get_ref().set_read_timeout(Some(REMOTE_TIMEOUT))?;
+    stream.get_ref().set_write_timeout(Some(REMOTE_TIMEOUT))?;
+    let mut ftp = stream.into_secure(NativeTlsConnector::from(TlsConnector::new()?), login.host)?;
+    ftp.login(login.username, login.password)?;
+    if !folder.is_empty() {
+        ftp.cwd(folder)?;
+    }
+    let Some(file) = file else {
+        let _ = ftp.quit();
+        return Ok(String::new());
+    };
+    ftp.transfer_type(suppaftp::types::FileType::Binary)?;
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let partial = format!(".{}.part", name);
+    let mut source = std::fs::File::open(file)?;
+    ftp.put_file(&partial, &mut source)?;
+    let _ = ftp.rm(&*name);
+    ftp.rename(&partial, &*name)?;
+    let _ = ftp.quit();
+    let target = [folder.trim_matches('/'), &*name].join("/");
+    Ok(format!("ftps://{}/{}", login.host, target.trim_start_matches('/')))
+}
+
+/// Copy an output into a folder, noting the copy as the app's own output so a
+/// watched folder doesn't pick it up as a new input
+async fn copy_into(dir: &Path, file: &Path) -> Result<PathBuf, String> {
+    let target = dir.join(file.file_name().unwrap_or_default());
+    crate::watcher::record_output(&target);
+    copy_path(file, &target).await.map_err(|e| e.to_string())?;
+    Ok(target)
+}
+
+/// Copy a file, or a folder of unpacked results with everything in it
+async fn copy_path(source: &Path, target: &Path) -> Result<(), std::io::Error> {
+    if !source.is_dir() {
+        return tokio::fs::copy(source, target).await.map(|_| ());
+    }
+    let mut pending = vec![(source.to_path_buf(), target.to_path_buf())];
+    while let Some((from, to)) = pending.pop() {
+        tokio::fs::create_dir_all(&to).await?;
+        let mut entries = tokio::fs::read_dir(&from).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let dest = to.join(entry.file_name());
+            if entry.file_type().await?.is_dir() {
+                pending.push((entry.path(), dest));
+            } else {
+                tokio::fs::copy(entry.path(), dest).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_healthy(id: &str) -> Option<bool> {
+    HEALTH.read().ok().and_then(|h| h.get(id).and_then(|h| h.healthy))
+}
+
+fn record(id: &str, result: Result<(), String>) {
+    if let Ok(mut health) = HEALTH.write() {
+        health.insert(
+            id.to_string(),
+            Health {
+                healthy: Some(result.is_ok()),
+                last_checked: Some(chrono::Local::now().to_rfc3339()),
+                last_error: result.err(),
+            },
+        );
+    }
+}
+
+fn queue_path() -> Result<PathBuf, DeliveryError> {
+    Ok(config::get_config_dir()?.join(QUEUE_FILE))
+}
+
+fn load_queue() -> Result<Vec<QueuedDelivery>, DeliveryError> {
+    let path = queue_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+fn save_queue(queue: &[QueuedDelivery]) -> Result<(), DeliveryError> {
+    std::fs::write(queue_path()?, serde_json::to_string_pretty(queue)?)?;
+    QUEUED.store(queue.len(), Ordering::Relaxed);
+    Ok(())
+}
@@ -0,0 +1,435 @@
+// Diagnostics module for PDF.dk Desktop
+// Collects environment and folder status for troubleshooting, and turns known
+// failure patterns into suggestions
+
+use crate::config::{AppConfig, DestinationSpec, DestinationTarget, ToolConfig};
+use crate::delivery;
+use crate::processor::{Job, JobStatus};
+use crate::watcher::{self, FolderAccess};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Access status of a folder used by a tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderStatus {
+    pub tool_id: String,
+    pub folder: String,
+    pub access: FolderAccess,
+    /// Where the user can grant access, if it is denied
+    pub settings_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostics {
+    pub app_version: String,
+    pub os: String,
+    pub arch: String,
+    pub folders: Vec<FolderStatus>,
+    pub delivery: delivery::DeliveryStatus,
+}
+
+/// Collect diagnostics for the current config
+pub fn collect(app_version: &str, config: &AppConfig) -> Diagnostics {
+    Diagnostics {
+        app_version: app_version.to_string(),
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        folders: folder_statuses(config),
+        delivery: delivery::status(),
+    }
+}
+
+/// Access status of the watch and approved folders of every enabled tool
+pub fn folder_statuses(config: &AppConfig) -> Vec<FolderStatus> {
+    let mut statuses = Vec::new();
+
+    for tool in config.tools.iter().filter(|t| t.enabled).flat_map(|t| t.watch_folders()) {
+        let folders = tool
+            .folder_path
+            .as_ref()
+            .map(PathBuf::from)
+            .into_iter()
+            .chain(tool.approved_folder());
+
+        for folder in folders {
+            let access = watcher::check_folder_access(&folder);
+            let settings_url = match access {
+                FolderAccess::Denied => watcher::permission_settings_url(&folder).map(String::from),
+                _ => None,
+            };
+            statuses.push(FolderStatus {
+                tool_id: tool.id.clone(),
+                folder: folder.to_string_lossy().to_string(),
+                access,
+                settings_url,
+            });
+        }
+    }
+
+    statuses
+}
+
+/// How much a config problem matters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IssueSeverity {
+    /// Files are still processed, but maybe not as intended
+    Warning,
+    /// Files in the folder won't be processed, or end up in the wrong place
+    Error,
+}
+
+/// A problem with a folder of an enabled tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigIssue {
+    /// "missing-folder", "folder-unavailable", "access-denied", "not-writable",
+    /// "same-folder", "overlapping-folders" or "output-in-watched-folder"
+    pub code: String,
+    pub severity: IssueSeverity,
+    pub tool_id: String,
+    pub folder: String,
+    /// The tool and folder the problem is with, for problems between two folders
+    pub other_tool_id: Option<String>,
+    pub other_folder: Option<String>,
+    pub message: String,
+    /// What the user can do to fix it
+    pub advice: String,
+}
+
+/// Check the folders of every enabled tool: that they exist and can be read
+/// and written, and that no folder picks up the files or outputs of another
+pub fn validate_config(config: &AppConfig) -> Vec<ConfigIssue> {
+    let folders: Vec<(ToolConfig, PathBuf)> = config
+        .tools
+        .iter()
+        .filter(|t| t.enabled)
+        .flat_map(|t| t.watch_folders())
+        .filter_map(|tool| {
+            let folder = PathBuf::from(tool.folder_path.as_ref()?);
+            Some((tool, folder))
+        })
+        .collect();
+
+    let mut issues = Vec::new();
+    for (tool, folder) in &folders {
+        check_folder(tool, folder, &mut issues);
+    }
+
+    let normalized: Vec<PathBuf> = folders.iter().map(|(_, folder)| normalize(folder)).collect();
+    for (i, (tool, folder)) in folders.iter().enumerate() {
+        let outputs: Vec<PathBuf> = output_dirs(config, tool, folder).into_iter().map(|dir| normalize(&dir)).collect();
+        for (j, (other, other_folder)) in folders.iter().enumerate() {
+            if i == j {
+                continue;
+            }
+            if normalized[i] == normalized[j] {
+                // Reported once per pair
+                if i < j {
+                    issues.push(pair_issue(
+                        "same-folder",
+                        (tool, folder),
+                        (other, other_folder),
+                        format!("{} and {} both watch {}", tool.id, other.id, folder.display()),
+                        "Each file is picked up by both tools. Give each tool its own folder, \
+                         or use a chain to run one after the other.",
+                    ));
+                }
+                continue;
+            }
+            if other.recursive && normalized[i].starts_with(&normalized[j]) {
+                issues.push(pair_issue(
+                    "overlapping-folders",
+                    (tool, folder),
+                    (other, other_folder),
+                    format!(
+                        "{} is inside {}, which {} watches with its subfolders",
+                        folder.display(),
+                        other_folder.display(),
+                        other.id
+                    ),
+                    "Files in the inner folder are picked up by both tools. Move the folder out, \
+                     or stop watching the subfolders of the outer one.",
+                ));
+            }
+            let in_other = outputs
+                .iter()
+                .find(|output| **output == normalized[j] || (other.recursive && output.starts_with(&normalized[j])));
+            if let Some(output) = in_other {
+                issues.push(pair_issue(
+                    "output-in-watched-folder",
+                    (tool, folder),
+                    (other, other_folder),
+                    format!(
+                        "Outputs of {} are written to {}, which {} watches",
+                        tool.id,
+                        output.display(),
+                        other.id
+                    ),
+                    "Every output is processed again by the other tool. Choose another output \
+                     folder, or use a chain if the outputs should go through both tools.",
+                ));
+            }
+        }
+    }
+
+    issues
+}
+
+/// Folders the outputs of a tool's folder are written to: its output folder,
+/// then the output folders and folder destinations it delivers copies to
+fn output_dirs(config: &AppConfig, tool: &ToolConfig, folder: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![watcher::get_output_dir(folder, tool)];
+    for spec in &tool.destinations {
+        match spec {
+            DestinationSpec::Output(mode) => dirs.push(watcher::output_dir(folder, mode)),
+            DestinationSpec::Failover(order) => dirs.extend(
+                config
+                    .destinations
+                    .iter()
+                    .filter(|d| order.contains(&d.id))
+                    .filter_map(|d| match &d.target {
+                        DestinationTarget::Folder { path } => Some(PathBuf::from(path)),
+                        _ => None,
+                    }),
+            ),
+        }
+    }
+    dirs
+}
+
+/// Problems of a single watch folder and its output folder
+fn check_folder(tool: &ToolConfig, folder: &Path, issues: &mut Vec<ConfigIssue>) {
+    let mut issue = |code: &str, severity, folder: &Path, message: String, advice: &str| {
+        issues.push(ConfigIssue {
+            code: code.to_string(),
+            severity,
+            tool_id: tool.id.clone(),
+            folder: folder.to_string_lossy().to_string(),
+            other_tool_id: None,
+            other_folder: None,
+            message,
+            advice: advice.to_string(),
+        })
+    };
+
+    match watcher::check_folder_access(folder) {
+        FolderAccess::Granted => {}
+        FolderAccess::Missing if folder.parent().is_some_and(Path::exists) => {
+            issue(
+                "missing-folder",
+                IssueSeverity::Warning,
+                folder,
+                format!("{} doesn't exist", folder.display()),
+                "It is created when watching starts. Check the path if you expected the folder to be there.",
+            );
+            return;
+        }
+        FolderAccess::Missing | FolderAccess::Unavailable => {
+            issue(
+                "folder-unavailable",
+                IssueSeverity::Error,
+                folder,
+                format!("{} can't be reached", folder.display()),
+                "Connect the drive or network share the folder is on, or choose another folder.",
+            );
+            return;
+        }
+        FolderAccess::Denied => {
+            let advice = match watcher::permission_settings_url(folder) {
+                Some(_) => "Allow PDF.dk Desktop to access the folder in the privacy settings of your system.",
+                None => "Give your user permission to read the folder, or choose another folder.",
+            };
+            issue(
+                "access-denied",
+                IssueSeverity::Error,
+                folder,
+                format!("PDF.dk Desktop isn't allowed to read {}", folder.display()),
+                advice,
+            );
+            return;
+        }
+    }
+
+    if !is_writable(folder) {
+        issue(
+            "not-writable",
+            IssueSeverity::Error,
+            folder,
+            format!("PDF.dk Desktop can't write to {}", folder.display()),
+            "Processed files are moved out of the watched folder. Give your user permission \
+             to write to it, or choose another folder.",
+        );
+    }
+
+    let output = watcher::get_output_dir(folder, tool);
+    if output == folder {
+        return;
+    }
+    // The output folder is created when needed, so check the part of its path that exists
+    if let Some(existing) = output.ancestors().find(|dir| dir.exists()) {
+        if !is_writable(existing) {
+            issue(
+                "not-writable",
+                IssueSeverity::Error,
+                &output,
+                format!("Outputs of {} can't be written to {}", tool.id, output.display()),
+                "Give your user permission to write to the output folder, or choose another one.",
+            );
+        }
+    }
+}
+
+fn pair_issue(
+    code: &str,
+    (tool, folder): (&ToolConfig, &Path),
+    (other, other_folder): (&ToolConfig, &Path),
+    message: String,
+    advice: &str,
+) -> ConfigIssue {
+    ConfigIssue {
+        code: code.to_string(),
+        severity: IssueSeverity::Error,
+        tool_id: tool.id.clone(),
+        folder: folder.to_string_lossy().to_string(),
+        other_tool_id: Some(other.id.clone()),
+        other_folder: Some(other_folder.to_string_lossy().to_string()),
+        message,
+        advice: advice.to_string(),
+    }
+}
+
+/// Whether a file can be created in `dir`. Permission bits and ACLs don't
+/// tell reliably, so this writes and removes a file.
+fn is_writable(dir: &Path) -> bool {
+    let probe = dir.join(format!(".pdfdk-write-test-{}", uuid::Uuid::new_v4()));
+    match std::fs::File::create(&probe) {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&probe);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Path with links and `..` resolved where it exists, for comparing folders
+fn normalize(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// A known problem found in recent failures or logs, with what to do about it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthSuggestion {
+    pub id: String,
+    pub title: String,
+    /// What the user can do to fix it
+    pub advice: String,
+    /// How many recent failures and log lines matched
+    pub occurrences: usize,
+}
+
+struct HealthPattern {
+    id: &'static str,
+    title: &'static str,
+    advice: &'static str,
+    /// Lowercase fragments, any of which identifies the problem
+    needles: &'static [&'static str],
+}
+
+// Checked in order, so the watch limit comes before the full disk it shares an
+// error code with on Linux
+const HEALTH_PATTERNS: [HealthPattern; 5] = [
+    HealthPattern {
+        id: "inotify-limit",
+        title: "Too many watched folders",
+        advice: "The system limit for watched files is reached. Raise fs.inotify.max_user_watches \
+                 (e.g. `sudo sysctl fs.inotify.max_user_watches=524288`) or watch fewer folders.",
+        needles: &["inotify", "max_user_watches", "too many open files"],
+    },
+    HealthPattern {
+        id: "expired-token",
+        title: "Sign-in expired",
+        advice: "Your PDF.dk session has expired. Sign in again to resume processing.",
+        needles: &["unauthorized", "token expired", "session expired", "silent re-login failed"],
+    },
+    HealthPattern {
+        id: "disk-full",
+        title: "Disk is full",
+        advice: "There is no space left for output files. Free up space on the output drive \
+                 or choose another output folder.",
+        needles: &["no space left", "not enough space", "disk full", "os error 28)", "os error 112)"],
+    },
+    HealthPattern {
+        id: "proxy-unreachable",
+        title: "Proxy can't be reached",
+        advice: "PDF.dk can't connect through the configured proxy. Check the proxy settings \
+                 of your system or network, or ask IT whether api.pdf.dk is allowed.",
+        needles: &["proxy"],
+    },
+    HealthPattern {
+        id: "cloud-placeholder",
+        title: "Files are only in the cloud",
+        advice: "Some files are online-only placeholders from OneDrive, iCloud or Dropbox. \
+                 Mark the watched folder as \"Always keep on this device\" so the files are downloaded.",
+        needles: &["cloud file provider", "cloud operation", ".icloud", "os error 362)", "os error 389)"],
+    },
+];
+
+/// Suggestion for a single error message, if it matches a known problem
+pub fn suggestion_for(error: &str) -> Option<HealthSuggestion> {
+    match_pattern(error).map(|pattern| to_suggestion(pattern, 1))
+}
+
+/// Suggestions for the known problems in recent failed jobs and log lines,
+/// most frequent first
+pub fn health_suggestions(jobs: &[Job], logs: &[String]) -> Vec<HealthSuggestion> {
+    let failures = jobs
+        .iter()
+        .filter(|j| j.status == JobStatus::Failed)
+        .filter_map(|j| j.error.as_deref());
+    let log_errors = logs
+        .iter()
+        .map(String::as_str)
+        .filter(|l| l.contains("ERROR") || l.to_lowercase().contains("failed"));
+
+    let mut counts = [0usize; HEALTH_PATTERNS.len()];
+    for text in failures.chain(log_errors) {
+        if let Some(i) = HEALTH_PATTERNS.iter().position(|p| matches_pattern(p, text)) {
+            counts[i] += 1;
+        }
+    }
+
+    let mut suggestions: Vec<HealthSuggestion> = HEALTH_PATTERNS
+        .iter()
+        .zip(counts)
+        .filter(|(_, count)| *count > 0)
+        .map(|(pattern, count)| to_suggestion(pattern, count))
+        .collect();
+    suggestions.sort_by_key(|s| std::cmp::Reverse(s.occurrences));
+    suggestions
+}
+
+fn match_pattern(text: &str) -> Option<&'static HealthPattern> {
+    HEALTH_PATTERNS.iter().find(|p| matches_pattern(p, text))
+}
+
+fn matches_pattern(pattern: &HealthPattern, text: &str) -> bool {
+    let text = text.to_lowercase();
+    // On Linux a full watch table also reports "no space left" (ENOSPC)
+    if pattern.id == "inotify-limit" && text.contains("notify error") && text.contains("os error 28)") {
+        return true;
+    }
+    pattern.needles.iter().any(|n| text.contains(n))
+}
+
+fn to_suggestion(pattern: &HealthPattern, occurrences: usize) -> HealthSuggestion {
+    HealthSuggestion {
+        id: pattern.id.to_string(),
+        title: pattern.title.to_string(),
+        advice: pattern.advice.to_string(),
+        occurrences,
+    }
+}
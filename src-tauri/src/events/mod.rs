@@ -0,0 +1,175 @@
+// Events module for PDF.dk Desktop
+// Sends backend events to the webviews, filtered by what each window subscribed to
+// and with frequent progress events throttled
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, EventTarget, Manager};
+
+/// Least time between two throttled events of the same name; the latest payload wins
+const THROTTLE_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventCategory {
+    Jobs,
+    Usage,
+    Config,
+    Connectivity,
+    Folders,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// What a window wants to receive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventFilter {
+    /// Empty means every category
+    #[serde(default)]
+    pub categories: Vec<EventCategory>,
+    #[serde(default = "default_severity")]
+    pub min_severity: Severity,
+}
+
+fn default_severity() -> Severity {
+    Severity::Info
+}
+
+struct EventKind {
+    name: &'static str,
+    category: EventCategory,
+    severity: Severity,
+    /// Sent at most every THROTTLE_INTERVAL
+    throttled: bool,
+    /// Waits for a reply from the UI, so it is never filtered out
+    always: bool,
+}
+
+const EVENTS: [EventKind; 14] = [
+    EventKind { name: "queue-status", category: EventCategory::Jobs, severity: Severity::Info, throttled: true, always: false },
+    EventKind { name: "pause-state-changed", category: EventCategory::Jobs, severity: Severity::Info, throttled: false, always: false },
+    EventKind { name: "night-batch-finished", category: EventCategory::Jobs, severity: Severity::Info, throttled: false, always: false },
+    EventKind { name: "self-test-finished", category: EventCategory::Jobs, severity: Severity::Info, throttled: false, always: false },
+    EventKind { name: "queue-rebind-requested", category: EventCategory::Jobs, severity: Severity::Warning, throttled: false, always: true },
+    EventKind { name: "auth-state-changed", category: EventCategory::Usage, severity: Severity::Info, throttled: false, always: false },
+    EventKind { name: "tools-updated", category: EventCategory::Config, severity: Severity::Info, throttled: false, always: false },
+    EventKind { name: "profile-switched", category: EventCategory::Config, severity: Severity::Info, throttled: false, always: false },
+    EventKind { name: "config-reloaded", category: EventCategory::Config, severity: Severity::Info, throttled: false, always: false },
+    EventKind { name: "destination-status", category: EventCategory::Connectivity, severity: Severity::Info, throttled: false, always: false },
+    EventKind { name: "folder-permission-denied", category: EventCategory::Folders, severity: Severity::Warning, throttled: false, always: false },
+    EventKind { name: "folder-backlog-found", category: EventCategory::Folders, severity: Severity::Info, throttled: false, always: false },
+    EventKind { name: "folder-watch-lost", category: EventCategory::Folders, severity: Severity::Warning, throttled: false, always: false },
+    EventKind { name: "folder-watch-restored", category: EventCategory::Folders, severity: Severity::Info, throttled: false, always: false },
+];
+
+// Window label -> its filter. Windows that haven't set one get everything.
+static FILTERS: Lazy<Mutex<HashMap<String, EventFilter>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+// Throttled events: when each was last sent, and the payload waiting to be sent
+static LAST_SENT: Lazy<Mutex<HashMap<&'static str, Instant>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static PENDING: Lazy<Mutex<HashMap<&'static str, serde_json::Value>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Set what a window receives. The window has to listen on itself, not
+/// globally, for the filter to apply.
+pub fn set_filter(window: &str, filter: EventFilter) {
+    if let Ok(mut filters) = FILTERS.lock() {
+        filters.insert(window.to_string(), filter);
+    }
+}
+
+/// Forget the filter of a closed window
+pub fn clear_filter(window: &str) {
+    if let Ok(mut filters) = FILTERS.lock() {
+        filters.remove(window);
+    }
+}
+
+/// Send an event to the windows that want it
+pub fn emit<S: Serialize + Clone>(app: &AppHandle, name: &'static str, payload: S) {
+    let kind = EVENTS.iter().find(|e| e.name == name);
+    if let Some(kind) = kind {
+        if !app.webview_windows().keys().any(|label| is_wanted_by(kind, label)) {
+            return;
+        }
+        if kind.throttled {
+            match serde_json::to_value(payload) {
+                Ok(value) => emit_throttled(app, name, value),
+                Err(e) => tracing::warn!("Could not serialize {} event: {}", name, e),
+            }
+            return;
+        }
+    }
+    send(app, name, payload);
+}
+
+/// Send to the listeners of the windows whose filter lets the event through,
+/// and to global listeners
+fn send<S: Serialize + Clone>(app: &AppHandle, name: &str, payload: S) {
+    let Some(kind) = EVENTS.iter().find(|e| e.name == name).filter(|kind| !kind.always) else {
+        let _ = app.emit(name, payload);
+        return;
+    };
+    let _ = app.emit_filter(name, payload, |target| match target {
+        EventTarget::Window { label } | EventTarget::Webview { label } | EventTarget::WebviewWindow { label } => {
+            is_wanted_by(kind, label)
+        }
+        _ => true,
+    });
+}
+
+fn is_wanted_by(kind: &EventKind, window: &str) -> bool {
+    let Ok(filters) = FILTERS.lock() else {
+        return true;
+    };
+    kind.always
+        || filters.get(window).is_none_or(|f| {
+            kind.severity >= f.min_severity && (f.categories.is_empty() || f.categories.contains(&kind.category))
+        })
+}
+
+/// Send now if the last one went out long enough ago, otherwise once the
+/// interval has passed, with whatever payload is latest by then
+fn emit_throttled(app: &AppHandle, name: &'static str, payload: serde_json::Value) {
+    let already_scheduled = match PENDING.lock() {
+        Ok(mut pending) => pending.insert(name, payload).is_some(),
+        Err(_) => return,
+    };
+    if already_scheduled {
+        return;
+    }
+
+    let wait = LAST_SENT
+        .lock()
+        .ok()
+        .and_then(|sent| sent.get(name).map(|at| THROTTLE_INTERVAL.saturating_sub(at.elapsed())))
+        .unwrap_or(Duration::ZERO);
+    if wait.is_zero() {
+        flush(app, name);
+        return;
+    }
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(wait).await;
+        flush(&app, name);
+    });
+}
+
+fn flush(app: &AppHandle, name: &'static str) {
+    let Some(payload) = PENDING.lock().ok().and_then(|mut pending| pending.remove(name)) else {
+        return;
+    };
+    if let Ok(mut sent) = LAST_SENT.lock() {
+        sent.insert(name, Instant::now());
+    }
+    send(app, name, payload);
+}
@@ -0,0 +1,150 @@
+// Folder index module for PDF.dk Desktop
+// Keeps a small index of processed files in each watched folder, so a folder
+// still shows what was done to it, and duplicates are still recognised, after the
+// app's own history is gone
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use thiserror::Error;
+
+pub const INDEX_FILE: &str = ".pdfdk-index.json";
+const INDEX_VERSION: u32 = 1;
+/// Entries kept per folder; the oldest are dropped first
+const MAX_ENTRIES: usize = 5000;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+// Serializes read-modify-write of index files
+static INDEX_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+#[derive(Error, Debug)]
+pub enum IndexError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid folder index: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Local task failed: {0}")]
+    LocalWork(#[from] crate::local_work::LocalWorkError),
+}
+
+/// One processed input, keyed by its SHA-256 in the index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexEntry {
+    pub job_id: String,
+    pub tool_id: String,
+    pub file_name: String,
+    pub output: String,
+    pub processed_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderIndex {
+    pub version: u32,
+    /// Input SHA-256 -> latest job that processed it
+    pub entries: HashMap<String, IndexEntry>,
+}
+
+impl Default for FolderIndex {
+    fn default() -> Self {
+        Self {
+            version: INDEX_VERSION,
+            entries: HashMap::new(),
+        }
+    }
+}
+
+/// Turn the index for new jobs on or off (general setting `folderIndex`)
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Read the index of a folder. A folder without one has an empty index.
+pub async fn load(folder: &Path) -> Result<FolderIndex, IndexError> {
+    match tokio::fs::read_to_string(folder.join(INDEX_FILE)).await {
+        Ok(text) => Ok(serde_json::from_str(&text)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(FolderIndex::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Record that a job processed `inputs` from `folder` into `output`
+pub async fn record(
+    folder: &Path,
+    inputs: &[PathBuf],
+    job_id: &str,
+    tool_id: &str,
+    output: &Path,
+) -> Result<(), IndexError> {
+    let files = inputs.to_vec();
+    let hashes = crate::local_work::run(move || {
+        files
+            .iter()
+            .map(|p| crate::receipts::sha256_file(p).map(|hash| (hash, p.clone())))
+            .collect::<Result<Vec<_>, std::io::Error>>()
+    })
+    .await??;
+
+    let _lock = INDEX_LOCK.lock().await;
+    let mut index = load(folder).await.unwrap_or_else(|e| {
+        tracing::warn!("Replacing unreadable folder index in {:?}: {}", folder, e);
+        FolderIndex::default()
+    });
+
+    let processed_at = chrono::Local::now().to_rfc3339();
+    for (hash, input) in hashes {
+        index.entries.insert(
+            hash,
+            IndexEntry {
+                job_id: job_id.to_string(),
+                tool_id: tool_id.to_string(),
+                file_name: input.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                output: output.to_string_lossy().to_string(),
+                processed_at: processed_at.clone(),
+            },
+        );
+    }
+
+    if index.entries.len() > MAX_ENTRIES {
+        // RFC 3339 times in the same offset sort as text
+        let mut by_age: Vec<(String, String)> = index
+            .entries
+            .iter()
+            .map(|(hash, e)| (e.processed_at.clone(), hash.clone()))
+            .collect();
+        by_age.sort();
+        let excess = index.entries.len() - MAX_ENTRIES;
+        for (_, hash) in by_age.into_iter().take(excess) {
+            index.entries.remove(&hash);
+        }
+    }
+
+    // Write next to the index and rename, so a crash never leaves half a file
+    let path = folder.join(INDEX_FILE);
+    let temp = folder.join(format!("{}.tmp", INDEX_FILE));
+    tokio::fs::write(&temp, serde_json::to_string_pretty(&index)?).await?;
+    tokio::fs::rename(&temp, &path).await?;
+    hide(&path);
+    Ok(())
+}
+
+/// Mark the index hidden in Explorer (dot files are already hidden elsewhere)
+#[cfg(target_os = "windows")]
+fn hide(path: &Path) {
+    use std::os::windows::ffi::OsStrExt;
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    unsafe {
+        windows_sys::Win32::Storage::FileSystem::SetFileAttributesW(wide.as_ptr(), FILE_ATTRIBUTE_HIDDEN);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn hide(_path: &Path) {}
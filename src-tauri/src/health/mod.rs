@@ -0,0 +1,212 @@
+// Health module for PDF.dk Desktop
+// Localhost health endpoint and --healthcheck for monitoring tools (Zabbix, Nagios, NSClient++)
+
+use crate::config::{self, AppConfig};
+use crate::diagnostics;
+use crate::processor::JobTracker;
+use crate::watcher::{FolderAccess, FolderWatcher};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::RwLock;
+
+const HEALTH_PATH: &str = "/health";
+/// How long --healthcheck waits for the running app
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest request the endpoint reads
+const MAX_REQUEST: usize = 8 * 1024;
+/// Wait after a failed accept, doubled on each failure in a row up to the max
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+const MAX_ACCEPT_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+// Exit codes of --healthcheck, following the Nagios plugin convention
+const EXIT_OK: i32 = 0;
+const EXIT_CRITICAL: i32 = 2;
+const EXIT_UNKNOWN: i32 = 3;
+
+// When the last error was logged (unix seconds, 0 if none yet)
+static LAST_ERROR: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthReport {
+    pub up: bool,
+    /// False if the watcher isn't running or a watched folder can't be read
+    pub watchers_ok: bool,
+    /// Watched folders that can't be read
+    pub failing_folders: Vec<String>,
+    /// Jobs waiting or running
+    pub queue_depth: usize,
+    pub last_error_at: Option<String>,
+}
+
+impl HealthReport {
+    pub fn is_healthy(&self) -> bool {
+        self.up && self.watchers_ok
+    }
+}
+
+/// Remember that an error was logged, for `lastErrorAt`
+pub fn note_error() {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    LAST_ERROR.store(now, Ordering::Relaxed);
+}
+
+pub async fn report(
+    config: &RwLock<AppConfig>,
+    watcher: &RwLock<Option<FolderWatcher>>,
+    jobs: &JobTracker,
+) -> HealthReport {
+    let failing_folders: Vec<String> = diagnostics::folder_statuses(&*config.read().await)
+        .into_iter()
+        .filter(|s| s.access != FolderAccess::Granted)
+        .map(|s| s.folder)
+        .collect();
+    let watcher_running = watcher.read().await.is_some();
+    let eta = jobs.queue_eta();
+
+    let last_error = LAST_ERROR.load(Ordering::Relaxed);
+    let last_error_at = (last_error > 0)
+        .then(|| chrono::DateTime::from_timestamp(last_error as i64, 0))
+        .flatten()
+        .map(|t| t.with_timezone(&chrono::Local).to_rfc3339());
+
+    HealthReport {
+        up: true,
+        watchers_ok: watcher_running && failing_folders.is_empty(),
+        failing_folders,
+        queue_depth: eta.queued + eta.active,
+        last_error_at,
+    }
+}
+
+/// Answer GET /health on 127.0.0.1:`port` until the app exits. Returns 200
+/// when healthy and 503 otherwise, with the report as JSON either way.
+pub async fn serve(
+    port: u16,
+    config: Arc<RwLock<AppConfig>>,
+    watcher: Arc<RwLock<Option<FolderWatcher>>>,
+    jobs: JobTracker,
+) {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let listener = match tokio::net::TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            crate::add_log(&format!("ERROR: Could not start the health endpoint on {}: {}", addr, e));
+            return;
+        }
+    };
+    crate::add_log(&format!("Health endpoint listening on http://{}{}", addr, HEALTH_PATH));
+
+    let mut retry_delay = ACCEPT_RETRY_DELAY;
+    loop {
+        // Errors like running out of sockets repeat at once, so back off instead of spinning
+        let mut stream = match listener.accept().await {
+            Ok((stream, _)) => {
+                retry_delay = ACCEPT_RETRY_DELAY;
+                stream
+            }
+            Err(e) => {
+                tracing::warn!("Health endpoint could not accept a connection: {}", e);
+                tokio::time::sleep(retry_delay).await;
+                retry_delay = (retry_delay * 2).min(MAX_ACCEPT_RETRY_DELAY);
+                continue;
+            }
+        };
+        let (config, watcher, jobs) = (config.clone(), watcher.clone(), jobs.clone());
+        tokio::spawn(async move {
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 1024];
+            // Only the request line matters, so stop at the end of the headers
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+                match tokio::time::timeout(CHECK_TIMEOUT, stream.read(&mut buffer)).await {
+                    Ok(Ok(read)) if read > 0 => request.extend_from_slice(&buffer[..read]),
+                    _ => break,
+                }
+            }
+
+            let request_line = String::from_utf8_lossy(&request);
+            let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+            let response = if path == HEALTH_PATH || path == "/" {
+                let report = report(&config, &watcher, &jobs).await;
+                let status = if report.is_healthy() { "200 OK" } else { "503 Service Unavailable" };
+                http_response(status, &serde_json::to_string(&report).unwrap_or_default())
+            } else {
+                http_response("404 Not Found", "{\"error\":\"not found\"}")
+            };
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        });
+    }
+}
+
+fn http_response(status: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+/// Ask the running app for its health, print the report and return the exit
+/// code: 0 healthy, 2 unhealthy or not running, 3 health endpoint turned off
+pub fn check_endpoint() -> i32 {
+    attach_console();
+    let Some(port) = config::load_config().ok().and_then(|c| c.general.health_port) else {
+        eprintln!("UNKNOWN - the health endpoint is off (set general.healthPort in the config)");
+        return EXIT_UNKNOWN;
+    };
+
+    match fetch_health(port) {
+        Ok((status, body)) => {
+            println!("{}", body);
+            if status == 200 {
+                EXIT_OK
+            } else {
+                EXIT_CRITICAL
+            }
+        }
+        Err(e) => {
+            println!("CRITICAL - PDF.dk Desktop is not responding on port {}: {}", port, e);
+            EXIT_CRITICAL
+        }
+    }
+}
+
+/// Release builds are GUI programs without a console, so print to the one
+/// --healthcheck was started from. Redirected output is inherited either way.
+#[cfg(target_os = "windows")]
+fn attach_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn attach_console() {}
+
+fn fetch_health(port: u16) -> Result<(u16, String), std::io::Error> {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let mut stream = TcpStream::connect_timeout(&addr, CHECK_TIMEOUT)?;
+    stream.set_read_timeout(Some(CHECK_TIMEOUT))?;
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", HEALTH_PATH)?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let status = response
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "not an HTTP response"))?;
+    let body = response.split_once("\r\n\r\n").map(|(_, b)| b).unwrap_or_default();
+    Ok((status, body.to_string()))
+}
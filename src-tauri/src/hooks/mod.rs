@@ -0,0 +1,97 @@
+// Hooks module for PDF.dk Desktop
+// Runs a tool's post-processing command on each final output, so users can
+// plug results into their own scripts
+
+use crate::config::PostCommand;
+use crate::watcher::FileEvent;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+/// Most of a command's output kept in the job history
+const MAX_OUTPUT_CHARS: usize = 8 * 1024;
+
+/// Run the post-processing command on `output` and wait for it, up to its
+/// timeout. Returns how it exited and what it printed, for the job history.
+pub async fn run(command: &PostCommand, event: &FileEvent, output: &Path, job_id: &str) -> String {
+    let output_path = output.to_string_lossy();
+    let input_path = event.path.to_string_lossy();
+    let mut args: Vec<String> = command
+        .args
+        .iter()
+        .map(|arg| arg.replace("{output}", &output_path).replace("{input}", &input_path))
+        .collect();
+    if !command.args.iter().any(|arg| arg.contains("{output}") || arg.contains("{input}")) {
+        args.push(output_path.to_string());
+    }
+
+    let mut process = tokio::process::Command::new(&command.program);
+    process
+        .args(&args)
+        .env("PDFDK_OUTPUT", output)
+        .env("PDFDK_INPUT", &event.path)
+        .env("PDFDK_TOOL", &event.tool_id)
+        .env("PDFDK_FOLDER", event.tool_config.folder_path.as_deref().unwrap_or_default())
+        .env("PDFDK_JOB_ID", job_id)
+        .current_dir(output.parent().unwrap_or(Path::new(".")))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    #[cfg(target_os = "windows")]
+    {
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        process.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    let child = match process.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            let message = format!("Could not start {}: {}", command.program, e);
+            crate::add_log(&format!("Post-processing command for {:?} failed: {}", output, message));
+            return message;
+        }
+    };
+    // Dropping the child on timeout kills it
+    let timeout = Duration::from_secs(command.timeout_seconds);
+    let result = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(Ok(finished)) => {
+            let printed = format!(
+                "{}{}",
+                String::from_utf8_lossy(&finished.stdout),
+                String::from_utf8_lossy(&finished.stderr)
+            );
+            let status = match finished.status.code() {
+                Some(code) => format!("Exited with code {}", code),
+                None => "Stopped by a signal".to_string(),
+            };
+            if !finished.status.success() {
+                crate::add_log(&format!("Post-processing command for {:?} failed: {}", output, status));
+            }
+            match printed.trim() {
+                "" => status,
+                printed => format!("{}\n{}", status, truncate(printed)),
+            }
+        }
+        Ok(Err(e)) => format!("Could not run {}: {}", command.program, e),
+        Err(_) => {
+            crate::add_log(&format!(
+                "Post-processing command for {:?} timed out after {}s",
+                output, command.timeout_seconds
+            ));
+            format!("Stopped after {}s", command.timeout_seconds)
+        }
+    };
+    tracing::info!("Post-processing command for {:?}: {}", output, result);
+    result
+}
+
+/// The end of a long output, where errors usually are
+fn truncate(printed: &str) -> String {
+    let count = printed.chars().count();
+    if count <= MAX_OUTPUT_CHARS {
+        return printed.to_string();
+    }
+    let tail: String = printed.chars().skip(count - MAX_OUTPUT_CHARS).collect();
+    format!("...{}", tail)
+}
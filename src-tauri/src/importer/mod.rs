@@ -0,0 +1,332 @@
+// Importer module for PDF.dk Desktop
+// Converts folder-to-action exports from other hot-folder products into tool configs
+
+use crate::config::{self, AppConfig, OutputMode, WatchFolder};
+use quick_xml::events::{BytesStart, Event};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+
+// Column, attribute and element names other products use for each field
+const FOLDER_FIELDS: [&str; 8] = ["folder", "path", "input", "inputfolder", "hotfolder", "source", "watch", "watchfolder"];
+const ACTION_FIELDS: [&str; 6] = ["action", "operation", "workflow", "task", "job", "process"];
+const OUTPUT_FIELDS: [&str; 5] = ["output", "outputfolder", "target", "destination", "out"];
+
+// Words in an action name that identify a tool, checked in order
+const ACTION_KEYWORDS: [(&str, &[&str]); 20] = [
+    ("pdf-to-pdfa", &["pdf/a", "pdfa", "archive"]),
+    // Before the PDF-to-X tools, which "word to pdf" or "image to pdf" would also match
+    ("image-to-pdf", &["image to pdf", "jpg to pdf", "tiff to pdf", "scan to pdf"]),
+    // Named by the document type, as any tool's name could end in "to PDF", e.g. "OCR to PDF"
+    (
+        "doc-to-pdf",
+        &["word to pdf", "doc to pdf", "docx to pdf", "excel to pdf", "xlsx to pdf", "powerpoint to pdf", "pptx to pdf", "office to pdf"],
+    ),
+    ("pdf-to-word", &["word", "docx"]),
+    ("pdf-to-excel", &["excel", "xlsx"]),
+    ("extract-images", &["extract image", "image extraction"]),
+    ("pdf-to-jpg", &["jpg", "jpeg", "image"]),
+    ("page-numbers", &["page number", "pagenumber", "numbering"]),
+    ("n-up", &["n-up", "nup", "imposition", "impose"]),
+    ("compress", &["compress", "optimize", "optimise", "reduce"]),
+    ("ocr", &["ocr", "searchable", "text recognition"]),
+    ("merge", &["merge", "combine", "join"]),
+    ("rotate", &["rotate"]),
+    ("watermark", &["watermark", "stamp"]),
+    ("unlock", &["decrypt", "unlock", "remove password"]),
+    ("protect", &["encrypt", "protect", "password"]),
+    ("flatten", &["flatten"]),
+    ("sign", &["signature", "sign pdf", "digital sign", "e-sign"]),
+    ("repair", &["repair", "fix"]),
+    ("bleed", &["bleed"]),
+];
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Config error: {0}")]
+    Config(#[from] config::ConfigError),
+    #[error("Invalid XML: {0}")]
+    Xml(#[from] quick_xml::Error),
+    #[error("Unsupported export format: {0} (use a .csv or .xml file)")]
+    UnsupportedFormat(String),
+    #[error("No folder mappings found - the export needs folder and action fields")]
+    NoMappings,
+}
+
+/// What importing one mapping does to the config
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImportAction {
+    /// Enables the tool with this folder
+    Add,
+    /// Moves an already configured tool to this folder
+    Replace,
+    /// Adds this folder to a tool given another folder earlier in the import
+    AddFolder,
+    /// Not imported, see the note
+    Skip,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportEntry {
+    pub folder: String,
+    /// The action as named in the export
+    pub source_action: String,
+    pub tool_id: Option<String>,
+    pub output_folder: Option<String>,
+    pub action: ImportAction,
+    pub note: Option<String>,
+}
+
+/// Preview of an import. Applying it makes exactly these changes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportPreview {
+    pub entries: Vec<ImportEntry>,
+}
+
+#[derive(Debug, Clone)]
+struct Mapping {
+    folder: String,
+    action: String,
+    output: Option<String>,
+}
+
+/// Work out what importing an export file would change, without changing anything
+pub fn preview(path: &Path, config: &AppConfig) -> Result<ImportPreview, ImportError> {
+    let mappings = read_mappings(path)?;
+    if mappings.is_empty() {
+        return Err(ImportError::NoMappings);
+    }
+
+    let tools = config::get_available_tools();
+    // Tool id and folder of each mapping imported so far
+    let mut claimed: Vec<(String, String)> = Vec::new();
+    let entries = mappings
+        .into_iter()
+        .map(|m| {
+            let tool_id = match_tool(&m.action, &tools);
+            let (action, note) = match &tool_id {
+                None => (ImportAction::Skip, Some("No matching PDF.dk tool".to_string())),
+                Some(id) if claimed.iter().any(|(tool, folder)| tool == id && folder == &m.folder) => {
+                    (ImportAction::Skip, Some("Same folder and tool as an earlier mapping".to_string()))
+                }
+                Some(id) if claimed.iter().any(|(tool, _)| tool == id) => {
+                    claimed.push((id.clone(), m.folder.clone()));
+                    (
+                        ImportAction::AddFolder,
+                        Some(format!("Watched by {} besides its first folder from this import", id)),
+                    )
+                }
+                Some(id) => {
+                    claimed.push((id.clone(), m.folder.clone()));
+                    match config.tools.iter().find(|t| &t.id == id && t.enabled) {
+                        Some(existing) => (
+                            ImportAction::Replace,
+                            existing.main_folder().map(|f| format!("Currently watches {}", f)),
+                        ),
+                        None => (ImportAction::Add, None),
+                    }
+                }
+            };
+            ImportEntry {
+                folder: m.folder,
+                source_action: m.action,
+                tool_id,
+                output_folder: m.output,
+                action,
+                note,
+            }
+        })
+        .collect();
+
+    Ok(ImportPreview { entries })
+}
+
+/// Apply a preview to the config. Returns how many folders were set up.
+pub fn apply(preview: &ImportPreview, config: &mut AppConfig) -> Result<usize, ImportError> {
+    let mut applied = 0;
+    for entry in &preview.entries {
+        let Some(tool_id) = entry.tool_id.as_deref().filter(|_| entry.action != ImportAction::Skip) else {
+            continue;
+        };
+        if entry.action == ImportAction::AddFolder {
+            let Some(tool) = config.tools.iter_mut().find(|t| t.id == tool_id) else {
+                continue;
+            };
+            fs::create_dir_all(&entry.folder)?;
+            let output_mode = entry.output_folder.clone().map_or(OutputMode::Subfolder, OutputMode::Custom);
+            tool.folders.push(WatchFolder::new(&entry.folder, output_mode));
+            applied += 1;
+            continue;
+        }
+        config.enable_tool(tool_id, &entry.folder)?;
+        let folder = config
+            .tools
+            .iter_mut()
+            .filter(|t| t.id == tool_id)
+            .flat_map(|t| t.folders.iter_mut())
+            .find(|f| f.path == entry.folder);
+        if let (Some(output), Some(folder)) = (&entry.output_folder, folder) {
+            folder.output_mode = OutputMode::Custom(output.clone());
+        }
+        applied += 1;
+    }
+    Ok(applied)
+}
+
+fn read_mappings(path: &Path) -> Result<Vec<Mapping>, ImportError> {
+    let text = fs::read_to_string(path)?;
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    match extension.as_str() {
+        "csv" | "txt" => Ok(parse_csv(&text)),
+        "xml" => parse_xml(&text),
+        other => Err(ImportError::UnsupportedFormat(other.to_string())),
+    }
+}
+
+fn match_tool(action: &str, tools: &[config::ToolDefinition]) -> Option<String> {
+    let action = action.trim().to_lowercase();
+    if let Some(tool) = tools
+        .iter()
+        .find(|t| t.id == action || t.name.to_lowercase() == action || t.name_da.to_lowercase() == action)
+    {
+        return Some(tool.id.clone());
+    }
+    ACTION_KEYWORDS
+        .iter()
+        .find(|(_, words)| words.iter().any(|w| action.contains(w)))
+        .map(|(id, _)| id.to_string())
+        .filter(|id| tools.iter().any(|t| &t.id == id))
+}
+
+/// Build a mapping from named fields, if it has a folder and an action
+fn to_mapping(fields: &HashMap<String, String>) -> Option<Mapping> {
+    let find = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|n| fields.get(*n))
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    Some(Mapping {
+        folder: find(&FOLDER_FIELDS)?,
+        action: find(&ACTION_FIELDS)?,
+        output: find(&OUTPUT_FIELDS),
+    })
+}
+
+fn normalize_name(name: &str) -> String {
+    name.trim()
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '_' | '-'))
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// CSV with a header row; comma or semicolon separated
+fn parse_csv(text: &str) -> Vec<Mapping> {
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+    let Some(header) = lines.next() else {
+        return Vec::new();
+    };
+    let separator = if header.matches(';').count() > header.matches(',').count() { ';' } else { ',' };
+    let columns: Vec<String> = split_csv_line(header, separator).iter().map(|c| normalize_name(c)).collect();
+
+    lines
+        .filter_map(|line| {
+            let fields = columns
+                .iter()
+                .cloned()
+                .zip(split_csv_line(line, separator))
+                .collect::<HashMap<_, _>>();
+            to_mapping(&fields)
+        })
+        .collect()
+}
+
+fn split_csv_line(line: &str, separator: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Any XML layout where one element per mapping carries the fields as
+/// attributes or as child elements
+fn parse_xml(text: &str) -> Result<Vec<Mapping>, ImportError> {
+    let mut reader = quick_xml::Reader::from_str(text);
+    let mut mappings = Vec::new();
+    // Fields collected for each open element
+    let mut stack: Vec<HashMap<String, String>> = Vec::new();
+    let mut text_value = String::new();
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                stack.push(attributes(&e));
+                text_value.clear();
+            }
+            Event::Empty(e) => {
+                let fields = attributes(&e);
+                if let Some(mapping) = to_mapping(&fields) {
+                    mappings.push(mapping);
+                }
+            }
+            Event::Text(t) => {
+                text_value = t.unescape().map(|t| t.into_owned()).unwrap_or_default();
+            }
+            Event::End(e) => {
+                let fields = stack.pop().unwrap_or_default();
+                if let Some(mapping) = to_mapping(&fields) {
+                    mappings.push(mapping);
+                } else if let Some(parent) = stack.last_mut() {
+                    // A field given as a child element, e.g. <Path>C:\In</Path>
+                    let name = normalize_name(&String::from_utf8_lossy(e.local_name().as_ref()));
+                    if !text_value.trim().is_empty() {
+                        parent.entry(name).or_insert_with(|| text_value.trim().to_string());
+                    }
+                }
+                text_value.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(mappings)
+}
+
+fn attributes(element: &BytesStart) -> HashMap<String, String> {
+    element
+        .attributes()
+        .flatten()
+        .filter_map(|a| {
+            let name = normalize_name(&String::from_utf8_lossy(a.key.local_name().as_ref()));
+            let value = a.unescape_value().ok()?.into_owned();
+            Some((name, value))
+        })
+        .collect()
+}
@@ -1,33 +1,91 @@
 // PDF.dk Desktop - Main library
 // Watched folders for automatic PDF processing
 
-mod api;
+pub mod api;
 mod auth;
-mod config;
+mod batch;
+pub mod calendar;
+mod cloud;
+pub mod cloud_headers;
+mod conditions;
+pub mod config;
+mod dedup;
+mod delivery;
+mod diagnostics;
+mod events;
+mod folder_index;
+mod health;
+mod hooks;
+mod importer;
+mod local_work;
+mod log_redact;
+mod log_shipper;
+mod mail;
+pub mod migrations;
+pub mod naming;
+mod notified;
+mod options;
+mod poison;
+pub mod policy;
+mod portable;
+mod power;
+mod printing;
 mod processor;
+mod profiles;
+mod proxy;
+mod receipts;
+pub mod reload;
+mod retention;
+pub mod routing;
+mod secrets;
+mod selftest;
+mod sidecar;
+mod staging;
+mod stats;
 mod watcher;
+mod window_state;
 
 use config::AppConfig;
 use std::sync::{Arc, Mutex};
 use tauri::{
     tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
-    Manager, Runtime, AppHandle,
+    Manager, AppHandle,
 };
 use tauri_plugin_notification::NotificationExt;
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc, RwLock};
 use tracing::{error, info};
 use once_cell::sync::Lazy;
 
+/// How long an account change waits for the running upload before going ahead
+const ACCOUNT_CHANGE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(120);
+
+/// How often the background task checks whether the access token needs refreshing
+const TOKEN_REFRESH_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How often to check whether today is a closed day in the work calendar,
+/// and whether held files' upload windows have opened
+const CALENDAR_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
 // Global log buffer for debug viewing in the app
 static LOG_BUFFER: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(Vec::new()));
 
 /// Add a log message to the buffer (callable from anywhere)
 pub fn add_log(message: &str) {
+    if message.starts_with("ERROR") {
+        health::note_error();
+    }
+    log_shipper::ship_app_log(message);
+    record_log(message);
+}
+
+/// Add to the in-app log without shipping it, for content that must stay on this machine
+pub(crate) fn record_log(message: &str) {
     let timestamp = chrono::Local::now().format("%H:%M:%S").to_string();
     let log_entry = format!("[{}] {}", timestamp, message);
 
-    // Also print to console
+    // Also print to console, and to the log file when running portable
     println!("{}", log_entry);
+    portable::append_log(&log_entry);
 
     if let Ok(mut logs) = LOG_BUFFER.lock() {
         logs.push(log_entry);
@@ -43,6 +101,10 @@ pub struct AppState {
     pub config: Arc<RwLock<AppConfig>>,
     pub auth: Arc<RwLock<auth::AuthState>>,
     pub watcher: Arc<RwLock<Option<watcher::FolderWatcher>>>,
+    pub capabilities: Arc<RwLock<api::ApiCapabilities>>,
+    pub jobs: processor::JobTracker,
+    pub pause: processor::PauseControl,
+    pub night_batch: Arc<RwLock<Option<batch::NightBatch>>>,
 }
 
 // Tauri commands exposed to the frontend
@@ -53,23 +115,250 @@ async fn get_config(state: tauri::State<'_, AppState>) -> Result<AppConfig, Stri
     Ok(config.clone())
 }
 
+const VIEWER_MODE_ERROR: &str = "This computer is in viewer mode - settings and accounts can't be changed here";
+
+/// Viewer mode machines only monitor - refuse changes to config and credentials
+async fn ensure_not_viewer(state: &AppState) -> Result<(), String> {
+    if state.config.read().await.general.viewer_mode {
+        return Err(VIEWER_MODE_ERROR.to_string());
+    }
+    Ok(())
+}
+
+/// Tools the machine's policy disables or sets up can't be changed by the user
+fn ensure_tool_not_locked(tool_id: &str) -> Result<(), String> {
+    policy::tool_locked(tool_id).map_or(Ok(()), Err)
+}
+
+/// The machine's policy, so the settings it locks can be shown as such
+#[tauri::command]
+async fn get_policy() -> Result<Option<policy::Policy>, String> {
+    Ok(policy::current().cloned())
+}
+
 #[tauri::command]
 async fn save_config(
+    app: AppHandle,
     state: tauri::State<'_, AppState>,
-    new_config: AppConfig,
+    mut new_config: AppConfig,
 ) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    // Settings the machine's policy locks keep their values
+    policy::enforce(&mut new_config);
+    check_config(&new_config)?;
+    // Passwords typed into tool options go to the vault, not config.json
+    secrets::store_config_secrets(&mut new_config).map_err(|e| e.to_string())?;
+    {
+        let mut config = state.config.write().await;
+        *config = new_config.clone();
+        config::save_app_config(&new_config).map_err(|e| e.to_string())?;
+    }
+    apply_settings(&new_config);
+
+    // Only folders whose tools were added, removed or changed are touched
+    reconcile_watchers(&app, &state, &new_config).await;
+    Ok(())
+}
+
+/// Refuse configs with routing rules or output names that can't be used
+fn check_config(config: &AppConfig) -> Result<(), String> {
+    routing::routes(config).map_err(|e| e.to_string())?;
+    for tool in &config.tools {
+        if let Some(template) = &tool.output_template {
+            naming::validate(template).map_err(|e| format!("Output name of {}: {}", tool.id, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Hand the settings the modules keep for themselves to them
+fn apply_settings(config: &AppConfig) {
+    proxy::configure(&config.general);
+    local_work::configure(&config.general);
+    watcher::configure(&config.general);
+    receipts::set_enabled(config.general.job_receipts);
+    folder_index::set_enabled(config.general.folder_index);
+    staging::set_enabled(config.general.snapshot_inputs);
+    log_shipper::configure(&config.log_shipping);
+    delivery::configure(&config.destinations);
+    mail::configure(&config.smtp);
+}
+
+/// Use config.json again after it was changed outside the app. Folders of
+/// tools that were added or changed are watched, those of removed tools
+/// dropped, and the rest keep watching.
+async fn reload_config(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    let new_config = match config::load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            add_log(&format!("ERROR: config.json changed but can't be read - keeping the current settings: {}", e));
+            return;
+        }
+    };
+    let old_config = state.config.read().await.clone();
+    // The app's own saves land here too
+    if reload::same_settings(&old_config, &new_config) {
+        return;
+    }
+    if let Err(e) = check_config(&new_config) {
+        add_log(&format!("ERROR: config.json changed but is not valid - keeping the current settings: {}", e));
+        return;
+    }
+
+    let changes = reload::tool_changes(&old_config, &new_config);
+    *state.config.write().await = new_config.clone();
+    apply_settings(&new_config);
+    if changes.is_empty() {
+        add_log("config.json changed on disk - reloaded");
+    } else {
+        add_log(&format!(
+            "config.json changed on disk - reloaded (tools added: {:?}, removed: {:?}, changed: {:?})",
+            changes.added, changes.removed, changes.changed
+        ));
+    }
+
+    reconcile_watchers(app, &state, &new_config).await;
+    refresh_profiles_menu(app, new_config.active_profile.as_deref());
+    events::emit(app, "config-reloaded", new_config);
+}
+
+/// Bring the running watcher in line with the tools and routing rules of
+/// `config`, starting it if nothing was watched yet. Folders that didn't
+/// change keep watching, so files arriving meanwhile aren't missed.
+async fn reconcile_watchers(app: &AppHandle, state: &AppState, config: &AppConfig) {
+    let folders: Vec<config::ToolConfig> = config.tools.iter()
+        .filter(|t| t.enabled)
+        .flat_map(|t| t.watch_folders())
+        .collect();
+    let routes = routing::routes(config).unwrap_or_else(|e| {
+        add_log(&format!("ERROR: Routing rules ignored: {}", e));
+        routing::Routes::new()
+    });
+
+    let mut watcher_guard = state.watcher.write().await;
+    let Some(watcher) = watcher_guard.as_mut() else {
+        drop(watcher_guard);
+        if let Err(e) = start_watchers(app.clone(), app.state::<AppState>()).await {
+            add_log(&format!("ERROR: {}", e));
+        }
+        return;
+    };
+    let reconciled = watcher.reconcile(folders).await;
+    if let Err(e) = watcher.set_routes(routes).await {
+        add_log(&format!("ERROR: Failed to watch inbox folders: {}", e));
+    }
+    drop(watcher_guard);
+
+    for folder in &reconciled.removed {
+        add_log(&format!("Stopped watching {:?}", folder));
+    }
+    for (folder, result) in reconciled.added.into_iter().chain(reconciled.changed) {
+        match result {
+            Ok(backlog) => {
+                add_log(&format!("Watching {:?} for tool {}", folder.folder_path.as_deref().unwrap_or_default(), folder.id));
+                report_backlog(app, backlog);
+            }
+            Err(e) => {
+                add_log(&format!("ERROR: Failed to add folder for tool {}: {}", folder.id, e));
+                report_folder_error(app, &folder.id, &e);
+            }
+        }
+    }
+}
+
+/// Show what importing another product's hot-folder export would change
+#[tauri::command]
+async fn preview_config_import(
+    state: tauri::State<'_, AppState>,
+    path: String,
+) -> Result<importer::ImportPreview, String> {
+    let config = state.config.read().await;
+    importer::preview(std::path::Path::new(&path), &config).map_err(|e| e.to_string())
+}
+
+/// Import another product's hot-folder export into the tool config
+#[tauri::command]
+async fn apply_config_import(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    path: String,
+) -> Result<importer::ImportPreview, String> {
+    ensure_not_viewer(&state).await?;
+    let (preview, imported) = {
+        let mut config = state.config.write().await;
+        let preview = importer::preview(std::path::Path::new(&path), &config).map_err(|e| e.to_string())?;
+        let applied = importer::apply(&preview, &mut config).map_err(|e| e.to_string())?;
+        policy::enforce(&mut config);
+        config::save_app_config(&config).map_err(|e| e.to_string())?;
+        add_log(&format!("Imported {} folder mapping(s) from {}", applied, path));
+        (preview, config.clone())
+    };
+
+    // Watch the imported folders
+    reconcile_watchers(&app, &state, &imported).await;
+    Ok(preview)
+}
+
+/// Names of the saved configuration profiles
+#[tauri::command]
+async fn list_profiles() -> Result<Vec<String>, String> {
+    profiles::list().map_err(|e| e.to_string())
+}
+
+/// Save the current tool folders and routing rules as a profile
+#[tauri::command]
+async fn save_profile(app: AppHandle, state: tauri::State<'_, AppState>, name: String) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
     let mut config = state.config.write().await;
-    *config = new_config.clone();
-    config::save_config(&new_config).map_err(|e| e.to_string())?;
+    profiles::save_current(&mut config, &name).map_err(|e| e.to_string())?;
+    config::save_app_config(&config).map_err(|e| e.to_string())?;
+    add_log(&format!("Saved profile {}", name.trim()));
+    refresh_profiles_menu(&app, config.active_profile.as_deref());
+    Ok(())
+}
+
+#[tauri::command]
+async fn delete_profile(app: AppHandle, state: tauri::State<'_, AppState>, name: String) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    let mut config = state.config.write().await;
+    profiles::delete(&mut config, &name).map_err(|e| e.to_string())?;
+    config::save_app_config(&config).map_err(|e| e.to_string())?;
+    refresh_profiles_menu(&app, config.active_profile.as_deref());
+    Ok(())
+}
+
+/// Switch to the tool folders and routing rules of another profile and watch
+/// its folders instead
+#[tauri::command]
+async fn switch_profile(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    name: String,
+) -> Result<AppConfig, String> {
+    ensure_not_viewer(&state).await?;
+    let switched = {
+        let mut config = state.config.write().await;
+        let mut switched = config.clone();
+        profiles::switch(&mut switched, &name).map_err(|e| e.to_string())?;
+        policy::enforce(&mut switched);
+        config::save_app_config(&switched).map_err(|e| e.to_string())?;
+        *config = switched.clone();
+        switched
+    };
+    add_log(&format!("Switched to profile {}", name));
 
-    // Restart watcher with new config
+    // Tear down the old profile's watchers before watching the new folders
     let mut watcher = state.watcher.write().await;
     if let Some(w) = watcher.take() {
         drop(w);
     }
-    // Will be restarted by the watcher manager
+    drop(watcher);
+    start_watchers(app.clone(), state).await?;
 
-    Ok(())
+    refresh_profiles_menu(&app, switched.active_profile.as_deref());
+    events::emit(&app, "profile-switched", switched.clone());
+    Ok(switched)
 }
 
 #[tauri::command]
@@ -80,33 +369,29 @@ async fn get_auth_state(state: tauri::State<'_, AppState>) -> Result<auth::AuthS
 
 #[tauri::command]
 async fn login(
+    app: AppHandle,
     state: tauri::State<'_, AppState>,
     email: String,
     password: String,
     remember: Option<bool>,
 ) -> Result<auth::AuthState, String> {
+    ensure_not_viewer(&state).await?;
     let mut result = auth::login(&email, &password).await.map_err(|e| e.to_string())?;
 
     // All users can login - plan limits are enforced per-file
     // Fetch usage status to get plan limits
-    if let Some(ref token) = result.token {
-        let client = api::PdfDkClient::new(Some(token.clone()));
-        if let Ok(usage) = client.get_usage_status().await {
-            result.plan = Some(usage.plan);
-            result.jobs_limit = Some(usage.limit);
-            result.jobs_used = Some(usage.used);
-            result.jobs_remaining = Some(usage.limit - usage.used);
-            result.max_file_size_mb = usage.max_file_size_mb.or(Some(100)); // From API, fallback to 100MB
-            result.is_unlimited = Some(usage.is_unlimited);
-        }
-    }
+    auth::apply_usage_status(&mut result).await;
 
     let mut auth_state = state.auth.write().await;
     *auth_state = result.clone();
+    drop(auth_state);
 
     // Save token securely
-    auth::save_token(&result.token.clone().unwrap_or_default())
-        .map_err(|e| e.to_string())?;
+    auth::save_session(&result).map_err(|e| e.to_string())?;
+    if let Err(e) = auth::end_guest_mode() {
+        error!("Failed to leave guest mode: {}", e);
+    }
+    session_restored(&app, &state, &result);
 
     // Save credentials if "Remember me" is checked
     info!("Remember me: {:?}", remember);
@@ -125,7 +410,8 @@ async fn login(
 }
 
 #[tauri::command]
-async fn get_saved_credentials() -> Result<Option<serde_json::Value>, String> {
+async fn get_saved_credentials(state: tauri::State<'_, AppState>) -> Result<Option<serde_json::Value>, String> {
+    ensure_not_viewer(&state).await?;
     match auth::load_credentials() {
         Ok((email, password)) => Ok(Some(serde_json::json!({
             "email": email,
@@ -136,37 +422,377 @@ async fn get_saved_credentials() -> Result<Option<serde_json::Value>, String> {
 }
 
 #[tauri::command]
-async fn logout(state: tauri::State<'_, AppState>) -> Result<(), String> {
+async fn logout(app: AppHandle, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    begin_account_change(&app, &state).await;
+
     let mut auth_state = state.auth.write().await;
+    // Signing out also removes the account from the account switcher
+    if let Some(ref user) = auth_state.user {
+        auth::forget_account(&user.email).map_err(|e| e.to_string())?;
+    }
     *auth_state = auth::AuthState::default();
     auth::clear_token().map_err(|e| e.to_string())?;
+    auth::end_guest_mode().map_err(|e| e.to_string())?;
+    events::emit(&app, "auth-state-changed", auth_state.clone());
+    drop(auth_state);
+
+    finish_account_change(&app, &state, None);
     Ok(())
 }
 
 #[tauri::command]
-async fn check_auth(state: tauri::State<'_, AppState>) -> Result<auth::AuthState, String> {
-    // Try to load saved token and validate it
-    if let Ok(token) = auth::load_token() {
-        if let Ok(mut auth_result) = auth::validate_token(&token).await {
-            // Fetch usage status to get plan limits
-            let client = api::PdfDkClient::new(Some(token.clone()));
-            if let Ok(usage) = client.get_usage_status().await {
-                auth_result.plan = Some(usage.plan);
-                auth_result.jobs_limit = Some(usage.limit);
-                auth_result.jobs_used = Some(usage.used);
-                auth_result.jobs_remaining = Some(usage.limit - usage.used);
-                auth_result.max_file_size_mb = usage.max_file_size_mb.or(Some(100)); // From API, fallback to 100MB
-                auth_result.is_unlimited = Some(usage.is_unlimited);
+async fn check_auth(app: AppHandle, state: tauri::State<'_, AppState>) -> Result<auth::AuthState, String> {
+    // Try to load saved session and validate it (refreshing the token if needed)
+    if let Ok(saved) = auth::load_session() {
+        if let Ok(auth_result) = auth::resume_session(&saved).await {
+            if let Err(e) = auth::save_session(&auth_result) {
+                error!("Failed to save session: {}", e);
             }
 
             let mut auth_state = state.auth.write().await;
             *auth_state = auth_result.clone();
+            drop(auth_state);
+            session_restored(&app, &state, &auth_result);
             return Ok(auth_result);
         }
     }
+
+    // No account session - carry on as a guest if that was chosen last time
+    if auth::is_guest_mode() {
+        return start_guest_session(app, state).await;
+    }
     Ok(auth::AuthState::default())
 }
 
+#[tauri::command]
+async fn start_guest_session(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<auth::AuthState, String> {
+    ensure_not_viewer(&state).await?;
+    let result = auth::start_guest_session().await.map_err(|e| e.to_string())?;
+    *state.auth.write().await = result.clone();
+    add_log(&format!(
+        "Processing as guest ({} of {} free jobs left)",
+        result.jobs_remaining.unwrap_or(0),
+        result.jobs_limit.unwrap_or(0)
+    ));
+
+    if auth::guest_quota_exhausted(&result) {
+        guest_limit_reached(&app, &state.pause);
+    }
+    events::emit(&app, "auth-state-changed", result.clone());
+    Ok(result)
+}
+
+#[tauri::command]
+async fn get_accounts() -> Result<Vec<auth::AccountSummary>, String> {
+    auth::list_accounts().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn switch_account(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    email: String,
+) -> Result<auth::AuthState, String> {
+    ensure_not_viewer(&state).await?;
+    begin_account_change(&app, &state).await;
+    let result = match auth::switch_account(&email).await {
+        Ok(result) => result,
+        Err(e) => {
+            // Still on the old account - carry on with the queue
+            state.pause.resume(processor::PauseReason::AccountChange);
+            publish_pause_state(&app, &state.pause);
+            return Err(e.to_string());
+        }
+    };
+
+    // The watcher pipeline reads the token from AppState, so new jobs use this account
+    let mut auth_state = state.auth.write().await;
+    *auth_state = result.clone();
+    drop(auth_state);
+    add_log(&format!("Switched to account {}", email));
+    session_restored(&app, &state, &result);
+
+    Ok(result)
+}
+
+#[tauri::command]
+async fn remove_account(state: tauri::State<'_, AppState>, email: String) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    let is_active = {
+        let auth_state = state.auth.read().await;
+        auth_state.user.as_ref().is_some_and(|u| u.email.eq_ignore_ascii_case(&email))
+    };
+    if is_active {
+        return Err("Cannot remove the active account - switch account or log out first".to_string());
+    }
+    auth::forget_account(&email).map_err(|e| e.to_string())
+}
+
+/// Queued files waiting for the user to confirm they may go to another account
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct QueueRebindRequest {
+    queued: usize,
+    /// The account the files would be processed with, None when signed out
+    account: Option<String>,
+}
+
+/// Stop new uploads and give the running job time to finish with the current account
+async fn begin_account_change(app: &AppHandle, state: &AppState) {
+    state.pause.pause(processor::PauseReason::AccountChange);
+    publish_pause_state(app, &state.pause);
+    if !state.pause.wait_for_running_job(ACCOUNT_CHANGE_TIMEOUT).await {
+        add_log("Upload still running - changing account anyway, it finishes with the previous session");
+    }
+}
+
+/// Resume the queue after an account change, asking the user first if queued
+/// files would now be billed to another account
+fn finish_account_change(app: &AppHandle, state: &AppState, account: Option<String>) {
+    let queued = state.jobs.pending_count();
+    if queued == 0 {
+        state.pause.resume(processor::PauseReason::AccountChange);
+        publish_pause_state(app, &state.pause);
+        return;
+    }
+    add_log(&format!("{} queued files waiting for confirmation after account change", queued));
+    events::emit(app, "queue-rebind-requested", QueueRebindRequest { queued, account });
+}
+
+/// Answer to "queue-rebind-requested": process the queued files with the
+/// current account, or cancel them
+#[tauri::command]
+async fn confirm_queue_rebind(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    keep: bool,
+) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    if !keep {
+        let cancelled = state.jobs.cancel_pending("Cancelled - account changed");
+        add_log(&format!("Cancelled {} queued files after account change", cancelled));
+        publish_queue_status(&app, &state.jobs);
+    }
+    state.pause.resume(processor::PauseReason::AccountChange);
+    publish_pause_state(&app, &state.pause);
+    Ok(())
+}
+
+/// Resume uploads paused by an expired session and tell the frontend we are signed in
+fn session_restored(app: &AppHandle, state: &AppState, auth_state: &auth::AuthState) {
+    let waiting_for_login = [
+        processor::PauseReason::SessionExpired,
+        processor::PauseReason::GuestLimitReached,
+    ];
+    if waiting_for_login.iter().any(|r| state.pause.is_paused_for(*r)) {
+        for reason in waiting_for_login {
+            state.pause.resume(reason);
+        }
+        add_log("Signed in - resuming uploads");
+        publish_pause_state(app, &state.pause);
+    }
+    events::emit(app, "auth-state-changed", auth_state.clone());
+
+    if state.pause.is_paused_for(processor::PauseReason::AccountChange) {
+        let account = auth_state.user.as_ref().map(|u| u.email.clone());
+        finish_account_change(app, state, account);
+    }
+}
+
+/// Mark the session as expired, pause uploads and ask the user to sign in again
+async fn session_expired(
+    app: &AppHandle,
+    auth_state: &Arc<RwLock<auth::AuthState>>,
+    pause: &processor::PauseControl,
+) {
+    if pause.is_paused_for(processor::PauseReason::SessionExpired) {
+        return;
+    }
+    pause.pause(processor::PauseReason::SessionExpired);
+    add_log("Session expired - uploads paused until you sign in again");
+
+    let snapshot = {
+        let mut auth = auth_state.write().await;
+        auth.is_authenticated = false;
+        auth.session_expired = true;
+        auth.clone()
+    };
+    events::emit(app, "auth-state-changed", snapshot);
+    publish_pause_state(app, pause);
+
+    let _ = app.notification()
+        .builder()
+        .title("PDF.dk - Session Expired")
+        .body("Session expired — click to sign in. Uploads are paused until then.")
+        .show();
+}
+
+/// Pause uploads once the guest quota is used up and ask the user to sign in
+fn guest_limit_reached(app: &AppHandle, pause: &processor::PauseControl) {
+    if pause.is_paused_for(processor::PauseReason::GuestLimitReached) {
+        return;
+    }
+    pause.pause(processor::PauseReason::GuestLimitReached);
+    add_log("Guest limit reached - uploads paused until you sign in");
+    publish_pause_state(app, pause);
+
+    let _ = app.notification()
+        .builder()
+        .title("PDF.dk - Guest Limit Reached")
+        .body("You have used all free guest jobs. Sign in to keep processing files.")
+        .show();
+}
+
+/// Push the pause state to the frontend and keep the tray menu label in sync
+fn publish_pause_state(app: &AppHandle, pause: &processor::PauseControl) {
+    events::emit(app, "pause-state-changed", pause.reasons());
+
+    let label = if pause.is_paused_for(processor::PauseReason::User) {
+        "Resume Processing"
+    } else {
+        "Pause Processing"
+    };
+    if let Some(item) = app.try_state::<TrayPauseItem>() {
+        let _ = item.0.set_text(label);
+    }
+}
+
+/// Push backup destination health to the frontend and the tray menu
+fn publish_destination_status(app: &AppHandle) {
+    let status = delivery::status();
+    if let Some(item) = app.try_state::<TrayDestinationsItem>() {
+        let _ = item.0.set_text(destinations_label(&status));
+    }
+    events::emit(app, "destination-status", status);
+}
+
+fn destinations_label(status: &delivery::DeliveryStatus) -> String {
+    let down = status.destinations.iter().filter(|d| d.healthy == Some(false)).count();
+    let label = match (status.destinations.len(), down) {
+        (0, _) => "No backup destinations".to_string(),
+        (_, 0) => "Destinations: all OK".to_string(),
+        (total, down) => format!("Destinations: {} of {} down", down, total),
+    };
+    match status.queued {
+        0 => label,
+        queued => format!("{}, {} queued", label, queued),
+    }
+}
+
+/// Add the all-day events of an ICS file to the work calendar.
+/// Returns how many new closed days were added.
+#[tauri::command]
+async fn import_holiday_calendar(state: tauri::State<'_, AppState>, path: String) -> Result<usize, String> {
+    ensure_not_viewer(&state).await?;
+    let days = calendar::import_ics(std::path::Path::new(&path)).map_err(|e| e.to_string())?;
+
+    let mut config = state.config.write().await;
+    let added = calendar::merge_days(&mut config.calendar, days);
+    config::save_app_config(&config).map_err(|e| e.to_string())?;
+    add_log(&format!("Imported {} closed day(s) from {}", added, path));
+    Ok(added)
+}
+
+#[tauri::command]
+async fn get_destination_status() -> Result<delivery::DeliveryStatus, String> {
+    Ok(delivery::status())
+}
+
+/// Save the password an SFTP/FTPS destination logs in with
+#[tauri::command]
+async fn set_destination_password(
+    state: tauri::State<'_, AppState>,
+    destination_id: String,
+    password: String,
+) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    delivery::set_password(&destination_id, &password).map_err(|e| e.to_string())?;
+    add_log(&format!("Saved the password of destination {}", destination_id));
+    Ok(())
+}
+
+/// Connect a Google Drive or Dropbox destination to an account in the browser
+#[tauri::command]
+async fn connect_destination(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    destination_id: String,
+) -> Result<(), String> {
+    use tauri_plugin_opener::OpenerExt;
+
+    ensure_not_viewer(&state).await?;
+    let destination = state
+        .config
+        .read()
+        .await
+        .destinations
+        .iter()
+        .find(|d| d.id == destination_id)
+        .cloned()
+        .ok_or("Unknown destination")?;
+    cloud::connect(&destination, |url| {
+        app.opener().open_url(url, None::<&str>).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    add_log(&format!("Connected destination {} to its account", destination.name));
+    Ok(())
+}
+
+/// Save the password of the mail server outputs are emailed through
+#[tauri::command]
+async fn set_smtp_password(state: tauri::State<'_, AppState>, password: String) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    mail::set_password(&password).map_err(|e| e.to_string())?;
+    add_log("Saved the mail server password");
+    Ok(())
+}
+
+/// Printers tools can print their outputs on
+#[tauri::command]
+async fn list_printers() -> Result<Vec<printing::Printer>, String> {
+    tauri::async_runtime::spawn_blocking(printing::list)
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Per-tool job statistics rolled up by day, week or month (the default),
+/// and the space compression saved
+#[tauri::command]
+async fn get_statistics(period: Option<stats::StatsPeriod>) -> Result<stats::Statistics, String> {
+    Ok(stats::statistics(period.unwrap_or_default()))
+}
+
+#[tauri::command]
+async fn get_pause_state(state: tauri::State<'_, AppState>) -> Result<Vec<processor::PauseReason>, String> {
+    Ok(state.pause.reasons())
+}
+
+#[tauri::command]
+async fn set_paused(app: AppHandle, state: tauri::State<'_, AppState>, paused: bool) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    if paused {
+        state.pause.pause(processor::PauseReason::User);
+        add_log("Processing paused");
+    } else {
+        state.pause.resume(processor::PauseReason::User);
+        add_log("Processing resumed");
+    }
+    publish_pause_state(&app, &state.pause);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_api_capabilities(state: tauri::State<'_, AppState>) -> Result<api::ApiCapabilities, String> {
+    let capabilities = state.capabilities.read().await;
+    Ok(capabilities.clone())
+}
+
 #[tauri::command]
 async fn get_available_tools() -> Result<Vec<config::ToolDefinition>, String> {
     Ok(config::get_available_tools())
@@ -174,122 +800,790 @@ async fn get_available_tools() -> Result<Vec<config::ToolDefinition>, String> {
 
 #[tauri::command]
 async fn enable_tool(
+    app: AppHandle,
     state: tauri::State<'_, AppState>,
     tool_id: String,
     folder_path: String,
 ) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    ensure_tool_not_locked(&tool_id)?;
     // Update config
     let tool_config = {
         let mut config = state.config.write().await;
         config.enable_tool(&tool_id, &folder_path).map_err(|e| e.to_string())?;
-        config::save_config(&config).map_err(|e| e.to_string())?;
+        config::save_app_config(&config).map_err(|e| e.to_string())?;
         config.tools.iter().find(|t| t.id == tool_id).cloned()
     };
 
     // Start/update watcher for this tool
-    if let Some(tc) = tool_config {
+    match tool_config {
+        Some(tc) => watch_tool(&app, &state, tc).await,
+        None => Ok(()),
+    }
+}
+
+/// Watch the folders of an enabled tool, creating the watcher if needed
+async fn watch_tool(app: &AppHandle, state: &AppState, tool_config: config::ToolConfig) -> Result<(), String> {
+    let mut watcher_guard = state.watcher.write().await;
+
+    // Create watcher if it doesn't exist
+    if watcher_guard.is_none() {
+        match watcher::FolderWatcher::new() {
+            Ok((watcher, rx, removed_rx)) => {
+                spawn_event_processor(app.clone(), state, rx, removed_rx);
+                *watcher_guard = Some(watcher);
+            }
+            Err(e) => {
+                error!("Failed to create watcher: {}", e);
+                return Err(format!("Failed to create file watcher: {}", e));
+            }
+        }
+    }
+
+    // Add the tool's folders to watcher
+    if let Some(watcher) = watcher_guard.as_mut() {
+        for folder in tool_config.watch_folders() {
+            match watcher.add_folder(folder).await {
+                Ok(backlog) => report_backlog(app, backlog),
+                Err(e) => {
+                    report_folder_error(app, &tool_config.id, &e);
+                    return Err(e.to_string());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Base folder mode: each tool watches a subfolder named after it in
+/// `base_folder` (Documents/PDF.dk if not given), created if missing
+#[tauri::command]
+async fn use_base_folder(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    base_folder: Option<String>,
+    tool_ids: Vec<String>,
+) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    tool_ids.iter().try_for_each(|id| ensure_tool_not_locked(id))?;
+    let (tool_configs, old_folders) = {
+        let mut config = state.config.write().await;
+        config.base_folder = base_folder;
+
+        let mut old_folders = vec![];
+        for tool_id in &tool_ids {
+            let folder = config.tool_subfolder(tool_id).map_err(|e| e.to_string())?;
+            let previous = config.tools.iter().find(|t| &t.id == tool_id && t.enabled);
+            if let Some(previous) = previous.and_then(|t| t.main_folder()) {
+                if std::path::Path::new(previous) != folder {
+                    old_folders.push(std::path::PathBuf::from(previous));
+                }
+            }
+            config.enable_tool(tool_id, &folder.to_string_lossy()).map_err(|e| e.to_string())?;
+        }
+        config::save_app_config(&config).map_err(|e| e.to_string())?;
+
+        let tool_configs: Vec<config::ToolConfig> = config.tools.iter()
+            .filter(|t| tool_ids.contains(&t.id))
+            .cloned()
+            .collect();
+        (tool_configs, old_folders)
+    };
+    add_log(&format!("Using base folder mode for {} tools", tool_configs.len()));
+
+    // Stop watching the folders the tools used before
+    if let Some(watcher) = state.watcher.write().await.as_mut() {
+        for folder in old_folders {
+            let _ = watcher.remove_folder(&folder).await;
+        }
+    }
+    for tool_config in tool_configs {
+        watch_tool(&app, &state, tool_config).await?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn disable_tool(state: tauri::State<'_, AppState>, tool_id: String) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    ensure_tool_not_locked(&tool_id)?;
+    // Get the folder paths before disabling
+    let folder_paths: Vec<std::path::PathBuf> = {
+        let config = state.config.read().await;
+        config.tools.iter()
+            .filter(|t| t.id == tool_id)
+            .flat_map(|t| t.watch_folders())
+            .filter_map(|t| t.folder_path)
+            .map(std::path::PathBuf::from)
+            .collect()
+    };
+
+    // Update config
+    {
+        let mut config = state.config.write().await;
+        config.disable_tool(&tool_id);
+        config::save_app_config(&config).map_err(|e| e.to_string())?;
+    }
+
+    // Remove folders from watcher
+    let mut watcher_guard = state.watcher.write().await;
+    if let Some(watcher) = watcher_guard.as_mut() {
+        for path in folder_paths {
+            let _ = watcher.remove_folder(&path).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Watch another folder with an enabled tool, keeping its options
+#[tauri::command]
+async fn add_tool_folder(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    tool_id: String,
+    folder_path: String,
+    output_mode: config::OutputMode,
+) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    ensure_tool_not_locked(&tool_id)?;
+    let folder_config = {
+        let mut config = state.config.write().await;
+        let tool = config
+            .tools
+            .iter_mut()
+            .find(|t| t.id == tool_id && !t.folders.is_empty())
+            .ok_or_else(|| format!("Tool {} has no watched folder", tool_id))?;
+        if tool.folders.iter().any(|f| f.path == folder_path) {
+            return Err(format!("{} already watches {}", tool_id, folder_path));
+        }
+        std::fs::create_dir_all(&folder_path).map_err(|e| e.to_string())?;
+
+        tool.folders.push(config::WatchFolder::new(&folder_path, output_mode));
+        let folder_config = tool.watch_folders().pop().filter(|t| t.enabled);
+        config::save_app_config(&config).map_err(|e| e.to_string())?;
+        folder_config
+    };
+    info!("Tool {} now also watches {}", tool_id, folder_path);
+
+    if let Some(folder_config) = folder_config {
         let mut watcher_guard = state.watcher.write().await;
+        if let Some(watcher) = watcher_guard.as_mut() {
+            match watcher.add_folder(folder_config).await {
+                Ok(backlog) => report_backlog(&app, backlog),
+                Err(e) => {
+                    report_folder_error(&app, &tool_id, &e);
+                    return Err(e.to_string());
+                }
+            }
+        }
+    }
+    Ok(())
+}
 
-        // Create watcher if it doesn't exist
-        if watcher_guard.is_none() {
-            match watcher::FolderWatcher::new() {
-                Ok((watcher, mut rx)) => {
-                    // Spawn event processor - notifications handled in start_watchers
-                    let auth_state = state.auth.clone();
-                    tokio::spawn(async move {
-                        while let Ok(event) = rx.recv().await {
-                            let file_name = event.path.file_name()
-                                .and_then(|n| n.to_str())
-                                .unwrap_or("file")
-                                .to_string();
-                            info!("Processing file: {}", file_name);
-                            let token = {
-                                let auth = auth_state.read().await;
-                                auth.token.clone()
-                            };
-
-                            match watcher::process_file_event(event.clone(), token).await {
-                                Ok(output_path) => {
-                                    add_log(&format!("SUCCESS: {} processed to {:?}", file_name, output_path));
-                                }
-                                Err(e) => {
-                                    add_log(&format!("ERROR: {} failed: {}", file_name, e));
-                                }
-                            }
+/// Pick up files in subfolders of one of a tool's folders, down to `max_depth` levels
+#[tauri::command]
+async fn set_folder_recursion(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    tool_id: String,
+    folder_path: String,
+    recursive: bool,
+    max_depth: Option<u32>,
+) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    ensure_tool_not_locked(&tool_id)?;
+    let max_depth = max_depth.unwrap_or(config::DEFAULT_MAX_DEPTH);
+    let folder_config = {
+        let mut config = state.config.write().await;
+        let tool = config
+            .tools
+            .iter_mut()
+            .find(|t| t.id == tool_id)
+            .ok_or_else(|| format!("Tool not found: {}", tool_id))?;
+        let folder = tool
+            .folders
+            .iter_mut()
+            .find(|f| f.path == folder_path)
+            .ok_or_else(|| format!("{} doesn't watch {}", tool_id, folder_path))?;
+        folder.recursive = recursive;
+        folder.max_depth = max_depth;
+        let folder_config = tool
+            .watch_folders()
+            .into_iter()
+            .find(|t| t.folder_path.as_deref() == Some(folder_path.as_str()) && t.enabled);
+        config::save_app_config(&config).map_err(|e| e.to_string())?;
+        folder_config
+    };
+    info!("Recursive watching of {} for {}: {} (depth {})", folder_path, tool_id, recursive, max_depth);
+
+    // Watch the folder again in the new mode
+    if let Some(folder_config) = folder_config {
+        let mut watcher_guard = state.watcher.write().await;
+        if let Some(watcher) = watcher_guard.as_mut() {
+            let _ = watcher.remove_folder(std::path::Path::new(&folder_path)).await;
+            match watcher.add_folder(folder_config).await {
+                Ok(backlog) => report_backlog(&app, backlog),
+                Err(e) => {
+                    report_folder_error(&app, &tool_id, &e);
+                    return Err(e.to_string());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Stop watching one of a tool's folders. The last one stays; disable the tool instead.
+#[tauri::command]
+async fn remove_tool_folder(
+    state: tauri::State<'_, AppState>,
+    tool_id: String,
+    folder_path: String,
+) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    ensure_tool_not_locked(&tool_id)?;
+    {
+        let mut config = state.config.write().await;
+        let tool = config
+            .tools
+            .iter_mut()
+            .find(|t| t.id == tool_id)
+            .ok_or_else(|| format!("Tool not found: {}", tool_id))?;
+        if !tool.folders.iter().any(|f| f.path == folder_path) {
+            return Err(format!("{} doesn't watch {}", tool_id, folder_path));
+        }
+        if tool.folders.len() == 1 {
+            return Err(format!("{} is the only folder of {} - disable the tool instead", folder_path, tool_id));
+        }
+        tool.folders.retain(|f| f.path != folder_path);
+        config::save_app_config(&config).map_err(|e| e.to_string())?;
+    }
+    info!("Tool {} no longer watches {}", tool_id, folder_path);
+
+    let mut watcher_guard = state.watcher.write().await;
+    if let Some(watcher) = watcher_guard.as_mut() {
+        let _ = watcher.remove_folder(std::path::Path::new(&folder_path)).await;
+    }
+    Ok(())
+}
+
+/// Merge the files collected so far in a merge tool's folder
+#[tauri::command]
+async fn merge_now(state: tauri::State<'_, AppState>, tool_id: String) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    let folder = {
+        let config = state.config.read().await;
+        config.tools.iter()
+            .find(|t| t.id == tool_id && t.enabled)
+            .and_then(|t| t.main_folder().map(String::from))
+            .ok_or_else(|| format!("Tool {} has no watched folder", tool_id))?
+    };
+
+    let watcher_guard = state.watcher.read().await;
+    let watcher = watcher_guard.as_ref().ok_or("Watchers are not running")?;
+    watcher.trigger_merge(std::path::Path::new(&folder)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_jobs(state: tauri::State<'_, AppState>) -> Result<Vec<processor::Job>, String> {
+    // Return queued, active and recent jobs, newest first
+    Ok(state.jobs.jobs())
+}
+
+#[tauri::command]
+async fn get_queue_eta(state: tauri::State<'_, AppState>) -> Result<processor::QueueEta, String> {
+    Ok(state.jobs.queue_eta())
+}
+
+/// Detected files not queued yet, e.g. still open in another application
+#[tauri::command]
+async fn get_waiting_files(state: tauri::State<'_, AppState>) -> Result<Vec<watcher::WaitingFile>, String> {
+    Ok(state
+        .watcher
+        .read()
+        .await
+        .as_ref()
+        .map(|w| w.waiting_files())
+        .unwrap_or_default())
+}
+
+/// What the retention limits would delete from the Originals folders now
+#[tauri::command]
+async fn preview_originals_cleanup(state: tauri::State<'_, AppState>) -> Result<retention::CleanupReport, String> {
+    let config = state.config.read().await.clone();
+    Ok(retention::cleanup(&config.tools, &config.retention, true).await)
+}
+
+/// Whether each watched folder is active, or offline with its drive unplugged
+#[tauri::command]
+async fn get_folder_status(state: tauri::State<'_, AppState>) -> Result<Vec<watcher::WatchedFolderStatus>, String> {
+    match state.watcher.read().await.as_ref() {
+        Some(watcher) => Ok(watcher.folder_status().await),
+        None => Ok(vec![]),
+    }
+}
+
+/// Whether the folder watcher is running and still picking up changes
+#[tauri::command]
+async fn get_watcher_status(state: tauri::State<'_, AppState>) -> Result<watcher::WatcherStatus, String> {
+    match state.watcher.read().await.as_ref() {
+        Some(watcher) => Ok(watcher.status()),
+        None => Ok(watcher::WatcherStatus::default()),
+    }
+}
+
+/// Watched folders that are missing, e.g. deleted or on a disconnected drive
+#[tauri::command]
+async fn get_lost_folders(state: tauri::State<'_, AppState>) -> Result<Vec<watcher::FolderWatchChange>, String> {
+    match state.watcher.read().await.as_ref() {
+        Some(watcher) => Ok(watcher.lost_folders().await),
+        None => Ok(vec![]),
+    }
+}
+
+#[tauri::command]
+async fn get_night_batch(state: tauri::State<'_, AppState>) -> Result<Option<batch::NightBatch>, String> {
+    Ok(state.night_batch.read().await.clone())
+}
+
+/// Process everything currently queued, then write a report and optionally sleep or quit.
+/// Like other scheduled work, the batch waits on closed days of the work calendar.
+#[tauri::command]
+async fn start_night_batch(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+    after: batch::AfterBatch,
+) -> Result<batch::NightBatch, String> {
+    ensure_not_viewer(&state).await?;
+    let mut night_batch = state.night_batch.write().await;
+    if night_batch.is_some() {
+        return Err("A night batch is already running".to_string());
+    }
+
+    let (job_ids, finished) = state.jobs.follow_unfinished();
+    if job_ids.is_empty() {
+        return Err("There are no queued files to process".to_string());
+    }
+
+    let new_batch = batch::NightBatch::new(job_ids, after);
+    *night_batch = Some(new_batch.clone());
+    add_log(&format!(
+        "Night batch started with {} file(s), then {:?}",
+        new_batch.job_ids.len(),
+        after
+    ));
+    let calendar = state.config.read().await.calendar.clone();
+    let today = chrono::Local::now().date_naive();
+    if let Some(reason) = calendar::closed_reason(&calendar, today) {
+        add_log(&format!(
+            "Closed today ({}) - the night batch waits until {}",
+            reason,
+            calendar::next_working_day(&calendar, today)
+        ));
+    }
+
+    let current = state.night_batch.clone();
+    let running = new_batch.clone();
+    tokio::spawn(async move {
+        let still_running = || {
+            current
+                .try_read()
+                .map(|b| b.as_ref().is_some_and(|b| b.id == running.id))
+                .unwrap_or(true)
+        };
+        let Some(mut report) = batch::wait_for_batch(finished, &running, still_running).await else {
+            add_log("Night batch cancelled");
+            return;
+        };
+        *current.write().await = None;
+
+        match batch::write_report(&report) {
+            Ok(path) => {
+                add_log(&format!("Night batch report written to {:?}", path));
+                report.report_path = Some(path.to_string_lossy().to_string());
+            }
+            Err(e) => add_log(&format!("ERROR: Failed to write night batch report: {}", e)),
+        }
+
+        add_log(&format!(
+            "Night batch finished: {} of {} completed, {} failed",
+            report.completed,
+            report.total,
+            report.failed.len()
+        ));
+        events::emit(&app, "night-batch-finished", report.clone());
+        let _ = app.notification()
+            .builder()
+            .title("PDF.dk - Night Batch Finished")
+            .body(format!(
+                "{} of {} files completed, {} failed",
+                report.completed,
+                report.total,
+                report.failed.len()
+            ))
+            .show();
+
+        // Give the notification a moment before the app goes away
+        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+        match running.after {
+            batch::AfterBatch::Nothing => {}
+            batch::AfterBatch::Exit => {
+                add_log("Night batch done - quitting");
+                app.exit(0);
+            }
+            batch::AfterBatch::Sleep => {
+                add_log("Night batch done - putting the computer to sleep");
+                if let Err(e) = batch::sleep_system() {
+                    add_log(&format!("ERROR: {}", e));
+                }
+            }
+        }
+    });
+
+    Ok(new_batch)
+}
+
+#[tauri::command]
+async fn cancel_night_batch(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    state.night_batch.write().await.take();
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_self_test_report() -> Result<Option<selftest::SelfTestReport>, String> {
+    Ok(selftest::last_report())
+}
+
+/// Run the self-test now instead of waiting for the night
+#[tauri::command]
+async fn run_self_test(app: AppHandle, state: tauri::State<'_, AppState>) -> Result<selftest::SelfTestReport, String> {
+    // Runs billed jobs
+    ensure_not_viewer(&state).await?;
+    self_test(&app, &state).await
+}
+
+/// Run the sample PDF through every enabled tool and alert the user if any failed
+async fn self_test(app: &AppHandle, state: &AppState) -> Result<selftest::SelfTestReport, String> {
+    let auth = state.auth.read().await.clone();
+    if !auth.is_authenticated || auth.is_guest {
+        return Err("Sign in to run the self-test".to_string());
+    }
+    let tools = state.config.read().await.tools.clone();
+    let test_jobs = state.capabilities.read().await.supports("test_jobs");
+    if !test_jobs {
+        add_log("Server can't flag test jobs - the self-test counts against the quota");
+    }
+
+    add_log("Self-test started");
+    let report = selftest::run(&tools, auth.token, test_jobs).await;
+    let failed = report.failed();
+    if failed.is_empty() {
+        add_log(&format!("Self-test passed ({} tool(s))", report.results.len()));
+    } else {
+        let names: Vec<&str> = failed.iter().map(|r| r.tool_id.as_str()).collect();
+        add_log(&format!("ERROR: Self-test failed for {}", names.join(", ")));
+        let _ = app.notification()
+            .builder()
+            .title("PDF.dk - Self-Test Failed")
+            .body(format!("{} tool(s) are not working: {}", failed.len(), names.join(", ")))
+            .show();
+    }
+    events::emit(app, "self-test-finished", report.clone());
+    Ok(report)
+}
+
+/// Spawn the tasks that queue and process file events from the watcher.
+/// Events are registered as pending jobs as soon as they arrive, so the queue
+/// and its ETA are visible, and are then processed one at a time.
+/// Queued jobs are cancelled when their file is removed from the folder.
+fn spawn_event_processor(
+    app: AppHandle,
+    state: &AppState,
+    mut rx: mpsc::UnboundedReceiver<watcher::FileEvent>,
+    mut removed_rx: mpsc::UnboundedReceiver<std::path::PathBuf>,
+) {
+    let (job_tx, mut job_rx) = mpsc::unbounded_channel::<(watcher::FileEvent, processor::JobRef)>();
+
+    // Receiver: queue every event as a pending job. Jobs outside their upload
+    // window are held here until it opens.
+    let jobs = state.jobs.clone();
+    let config_state = state.config.clone();
+    let app_handle = app.clone();
+    tokio::spawn(async move {
+        add_log("Event receiver task started - waiting for files...");
+        let mut held: Vec<(watcher::FileEvent, processor::JobRef)> = Vec::new();
+        // Outside the loop, so files arriving don't keep putting the check off
+        let mut release_check = tokio::time::interval(CALENDAR_CHECK_INTERVAL);
+        release_check.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                received = rx.recv() => {
+                    let Some(event) = received else {
+                        break;
+                    };
+                    add_log(&format!("Received file event: {:?} for tool: {}", event.path, event.tool_id));
+                    // E.g. found again by a rescan after the system dropped events
+                    if jobs.has_unfinished(&event.path.to_string_lossy()) {
+                        add_log(&format!("Already queued, skipping: {:?}", event.path));
+                        continue;
+                    }
+                    let job = jobs.enqueue(&event);
+                    publish_queue_status(&app_handle, &jobs);
+                    let closed = closed_upload_window(&event, &config_state.read().await);
+                    if let Some(opens) = closed {
+                        add_log(&format!("Holding {:?} until its upload window opens at {}", event.path, opens));
+                        job.update(|j| j.held_until = Some(opens));
+                        held.push((event, job));
+                        continue;
+                    }
+                    if job_tx.send((event, job)).is_err() {
+                        break;
+                    }
+                }
+                Some(path) = removed_rx.recv() => {
+                    let cancelled = jobs.cancel_input(&path.to_string_lossy(), "Cancelled - file was removed");
+                    if cancelled > 0 {
+                        add_log(&format!("File removed before it was processed, cancelled its job: {:?}", path));
+                        held.retain(|(_, job)| jobs.job(&job.id).is_some_and(|j| !j.is_finished()));
+                        publish_queue_status(&app_handle, &jobs);
+                    }
+                }
+                _ = release_check.tick(), if !held.is_empty() => {
+                    let config = config_state.read().await;
+                    let (waiting, released): (Vec<_>, Vec<_>) = held
+                        .drain(..)
+                        .partition(|(event, _)| closed_upload_window(event, &config).is_some());
+                    drop(config);
+                    held = waiting;
+                    if !released.is_empty() {
+                        add_log(&format!("Upload window open - releasing {} held file(s)", released.len()));
+                    }
+                    for (event, job) in released {
+                        job.update(|j| j.held_until = None);
+                        if job_tx.send((event, job)).is_err() {
+                            break;
                         }
+                    }
+                }
+            }
+        }
+        add_log("Event receiver task ended");
+    });
+
+    // Worker: process queued jobs by folder priority, otherwise in order
+    let auth_state = state.auth.clone();
+    let jobs = state.jobs.clone();
+    let pause = state.pause.clone();
+    tokio::spawn(async move {
+        let mut queue = processor::JobQueue::default();
+        loop {
+            // Take in everything that has arrived, so the most urgent job goes next
+            while let Ok((event, job)) = job_rx.try_recv() {
+                queue.push(event, job);
+            }
+            let (event, job) = match queue.pop() {
+                Some(next) => next,
+                None => match job_rx.recv().await {
+                    Some(next) => next,
+                    None => break,
+                },
+            };
+            let file_name = event.path.file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("file")
+                .to_string();
+            // Taken before processing moves the input away
+            let notice_key = notified::job_key(&event.tool_id, &event.path).await;
+            let last_step = event.tool_config.chain.last().map_or(event.tool_id.clone(), |step| step.tool_id.clone());
+
+            let result = loop {
+                if pause.is_paused() {
+                    add_log("Processing paused - waiting before next upload");
+                    pause.wait_until_resumed().await;
+                }
+
+                let _running = pause.job_started().await;
+                // Paused again while waiting, e.g. by an account change
+                if pause.is_paused() {
+                    continue;
+                }
+                // Cancelled while queued
+                if jobs.job(&job.id).is_none_or(|j| j.is_finished()) {
+                    break None;
+                }
+                // Removed while queued without the watcher noticing, e.g. on a polled share
+                if !event.input_paths().iter().all(|p| p.exists()) {
+                    add_log(&format!("File removed before it was processed, skipping: {:?}", event.path));
+                    job.update(|j| j.set_cancelled("Cancelled - file was removed"));
+                    publish_queue_status(&app, &jobs);
+                    break None;
+                }
+                job.update(|j| j.set_started());
+
+                add_log(&format!("Processing file with tool: {}", event.tool_id));
+                match processor::handle_file_event(event.clone(), &auth_state, &job).await {
+                    // Keep the job queued and retry it once the user has signed in
+                    Err(api::ApiError::Unauthorized | api::ApiError::JobLimitExceeded { .. })
+                        if auth_state.read().await.is_guest =>
+                    {
+                        job.update(|j| j.set_pending());
+                        guest_limit_reached(&app, &pause);
+                        publish_queue_status(&app, &jobs);
+                    }
+                    Err(api::ApiError::Unauthorized) => {
+                        job.update(|j| j.set_pending());
+                        session_expired(&app, &auth_state, &pause).await;
+                        publish_queue_status(&app, &jobs);
+                    }
+                    result => break Some(result),
+                }
+            };
+            let Some(result) = result else {
+                continue;
+            };
+
+            // Jobs resumed after a restart may have been announced already
+            let announce = notified::first_notice(&notice_key);
+            match result {
+                Ok(output_path) => {
+                    let output_size = std::fs::metadata(&output_path).ok().filter(|m| m.is_file()).map(|m| m.len());
+                    job.update(|j| {
+                        j.set_completed(&output_path.to_string_lossy());
+                        j.output_size_bytes = output_size;
                     });
-                    *watcher_guard = Some(watcher);
+                    if let Some(saved) = job.job().and_then(|j| stats::record(&j, &last_step)) {
+                        job.update(|j| j.saved_bytes = Some(saved));
+                    }
+                    poison::clear(&event.path);
+                    if auth_state.read().await.is_guest {
+                        update_guest_usage(&app, &auth_state, &pause).await;
+                    }
+                    if announce {
+                        add_log(&format!("SUCCESS: File processed to {:?}", output_path));
+                        // Send success notification
+                        let _ = app.notification()
+                            .builder()
+                            .title("PDF.dk - File Processed")
+                            .body(format!("{} completed successfully", file_name))
+                            .show();
+                    }
+                }
+                // Reading the input failed because it was removed while the job ran
+                Err(api::ApiError::Io(_)) if !event.input_paths().iter().all(|p| p.exists()) => {
+                    add_log(&format!("File removed while it was processed: {:?}", event.path));
+                    job.update(|j| j.set_cancelled("Cancelled - file was removed while it was processed"));
                 }
                 Err(e) => {
-                    error!("Failed to create watcher: {}", e);
-                    return Err(format!("Failed to create file watcher: {}", e));
+                    let error_msg = set_aside_failed_input(&event, &e, &job).await;
+                    job.update(|j| j.set_failed(&error_msg));
+                    if let Some(failed) = job.job() {
+                        stats::record(&failed, &last_step);
+                    }
+                    if announce {
+                        add_log(&format!("ERROR: Failed to process file: {}", error_msg));
+                        // Send error notification, with advice instead of the raw error when known
+                        let body = match diagnostics::suggestion_for(&error_msg) {
+                            Some(suggestion) => format!("{}: {} - {}", file_name, suggestion.title, suggestion.advice),
+                            None => format!("{}: {}", file_name, error_msg),
+                        };
+                        let _ = app.notification()
+                            .builder()
+                            .title("PDF.dk - Processing Failed")
+                            .body(body)
+                            .show();
+                    }
                 }
             }
-        }
 
-        // Add folder to watcher
-        if let Some(watcher) = watcher_guard.as_mut() {
-            watcher.add_folder(tc).await.map_err(|e| e.to_string())?;
+            publish_queue_status(&app, &jobs);
         }
-    }
-
-    Ok(())
+    });
 }
 
-#[tauri::command]
-async fn disable_tool(state: tauri::State<'_, AppState>, tool_id: String) -> Result<(), String> {
-    // Get the folder path before disabling
-    let folder_path = {
-        let config = state.config.read().await;
-        config.tools.iter()
-            .find(|t| t.id == tool_id)
-            .and_then(|t| t.folder_path.clone())
-            .map(std::path::PathBuf::from)
-    };
+/// Refresh the guest quota after a job and pause once it is used up
+async fn update_guest_usage(
+    app: &AppHandle,
+    auth_state: &Arc<RwLock<auth::AuthState>>,
+    pause: &processor::PauseControl,
+) {
+    let mut snapshot = auth_state.read().await.clone();
+    auth::apply_usage_status(&mut snapshot).await;
 
-    // Update config
     {
-        let mut config = state.config.write().await;
-        config.disable_tool(&tool_id);
-        config::save_config(&config).map_err(|e| e.to_string())?;
-    }
-
-    // Remove folder from watcher
-    if let Some(path) = folder_path {
-        let mut watcher_guard = state.watcher.write().await;
-        if let Some(watcher) = watcher_guard.as_mut() {
-            let _ = watcher.remove_folder(&path).await;
+        let mut auth = auth_state.write().await;
+        // Skip if the user signed in meanwhile
+        if !auth.is_guest {
+            return;
         }
+        *auth = snapshot.clone();
     }
+    events::emit(app, "auth-state-changed", snapshot.clone());
 
-    Ok(())
+    if auth::guest_quota_exhausted(&snapshot) {
+        guest_limit_reached(app, pause);
+    }
 }
 
-#[tauri::command]
-async fn get_jobs(_state: tauri::State<'_, AppState>) -> Result<Vec<processor::Job>, String> {
-    // Return recent jobs from processor
-    Ok(vec![]) // TODO: implement job tracking
+/// Push the queue ETA to the frontend and the tray tooltip, and announce long batches
+fn publish_queue_status(app: &AppHandle, jobs: &processor::JobTracker) {
+    let eta = jobs.queue_eta();
+    let remaining = eta.queued + eta.active;
+
+    events::emit(app, "queue-status", eta.clone());
+
+    let tooltip = if remaining == 0 {
+        "PDF.dk Desktop".to_string()
+    } else {
+        format!(
+            "PDF.dk Desktop - {} file(s) left, about {}",
+            remaining,
+            processor::format_eta(eta.total_seconds)
+        )
+    };
+    if let Some(tray) = app.tray_by_id("main") {
+        let _ = tray.set_tooltip(Some(tooltip));
+    }
+
+    if jobs.should_announce_long_batch(&eta) {
+        let _ = app.notification()
+            .builder()
+            .title("PDF.dk - Large Batch")
+            .body(format!(
+                "{} files queued - about {} remaining",
+                remaining,
+                processor::format_eta(eta.total_seconds)
+            ))
+            .show();
+    }
 }
 
 #[tauri::command]
 async fn start_watchers(app: AppHandle, state: tauri::State<'_, AppState>) -> Result<(), String> {
     info!("Starting watchers for enabled tools...");
 
-    // Get enabled tools from config
-    let enabled_tools: Vec<config::ToolConfig> = {
+    // Get enabled tools and routing rules from config
+    let (enabled_tools, routes) = {
         let config = state.config.read().await;
-        config.tools.iter()
-            .filter(|t| t.enabled && t.folder_path.is_some())
-            .cloned()
-            .collect()
+        let tools: Vec<config::ToolConfig> = config.tools.iter()
+            .filter(|t| t.enabled)
+            .flat_map(|t| t.watch_folders())
+            .collect();
+        (tools, routing::routes(&config))
     };
+    let routes = routes.unwrap_or_else(|e| {
+        add_log(&format!("ERROR: Routing rules ignored: {}", e));
+        routing::Routes::new()
+    });
 
-    if enabled_tools.is_empty() {
+    if enabled_tools.is_empty() && routes.is_empty() {
         add_log("No enabled tools to watch");
         return Ok(());
     }
 
-    add_log(&format!("Found {} enabled tools to watch", enabled_tools.len()));
+    add_log(&format!("Found {} enabled tool folders to watch", enabled_tools.len()));
 
     let mut watcher_guard = state.watcher.write().await;
 
@@ -297,49 +1591,9 @@ async fn start_watchers(app: AppHandle, state: tauri::State<'_, AppState>) -> Re
     if watcher_guard.is_none() {
         add_log("Creating new file watcher...");
         match watcher::FolderWatcher::new() {
-            Ok((watcher, mut rx)) => {
+            Ok((watcher, rx, removed_rx)) => {
                 add_log("File watcher created successfully");
-                // Spawn event processor
-                let auth_state = state.auth.clone();
-                let app_handle = app.clone();
-                tokio::spawn(async move {
-                    add_log("Event receiver task started - waiting for files...");
-                    while let Ok(event) = rx.recv().await {
-                        let file_name = event.path.file_name()
-                            .and_then(|n| n.to_str())
-                            .unwrap_or("file")
-                            .to_string();
-                        add_log(&format!("Received file event: {} for tool: {}", file_name, event.tool_id));
-                        let token = {
-                            let auth = auth_state.read().await;
-                            auth.token.clone()
-                        };
-
-                        add_log(&format!("Processing file with tool: {}", event.tool_id));
-                        match watcher::process_file_event(event.clone(), token).await {
-                            Ok(output_path) => {
-                                add_log(&format!("SUCCESS: File processed to {:?}", output_path));
-                                // Send success notification
-                                let _ = app_handle.notification()
-                                    .builder()
-                                    .title("PDF.dk - File Processed")
-                                    .body(&format!("{} completed successfully", file_name))
-                                    .show();
-                            }
-                            Err(e) => {
-                                let error_msg = format!("{}", e);
-                                add_log(&format!("ERROR: Failed to process file: {}", error_msg));
-                                // Send error notification
-                                let _ = app_handle.notification()
-                                    .builder()
-                                    .title("PDF.dk - Processing Failed")
-                                    .body(&format!("{}: {}", file_name, error_msg))
-                                    .show();
-                            }
-                        }
-                    }
-                    add_log("Event receiver task ended");
-                });
+                spawn_event_processor(app.clone(), &state, rx, removed_rx);
                 *watcher_guard = Some(watcher);
             }
             Err(e) => {
@@ -353,16 +1607,234 @@ async fn start_watchers(app: AppHandle, state: tauri::State<'_, AppState>) -> Re
     if let Some(watcher) = watcher_guard.as_mut() {
         for tool in enabled_tools {
             add_log(&format!("Adding watch folder for tool: {} at {:?}", tool.id, tool.folder_path));
-            if let Err(e) = watcher.add_folder(tool.clone()).await {
-                add_log(&format!("ERROR: Failed to add folder for tool {}: {}", tool.id, e));
+            match watcher.add_folder(tool.clone()).await {
+                Ok(backlog) => report_backlog(&app, backlog),
+                Err(e) => {
+                    add_log(&format!("ERROR: Failed to add folder for tool {}: {}", tool.id, e));
+                    report_folder_error(&app, &tool.id, &e);
+                }
             }
         }
+        if !routes.is_empty() {
+            add_log(&format!("Routing files in {} inbox folder(s)", routes.len()));
+        }
+        if let Err(e) = watcher.set_routes(routes).await {
+            add_log(&format!("ERROR: Failed to watch inbox folders: {}", e));
+        }
     }
 
     add_log("Watcher setup complete");
     Ok(())
 }
 
+/// Tell the user when a watched folder can't be read, e.g. blocked by macOS privacy settings
+fn report_folder_error(app: &AppHandle, tool_id: &str, error: &watcher::WatcherError) {
+    let watcher::WatcherError::PermissionDenied(folder) = error else {
+        return;
+    };
+
+    let status = diagnostics::FolderStatus {
+        tool_id: tool_id.to_string(),
+        folder: folder.to_string_lossy().to_string(),
+        access: watcher::FolderAccess::Denied,
+        settings_url: watcher::permission_settings_url(folder).map(String::from),
+    };
+    events::emit(app, "folder-permission-denied", status);
+
+    let _ = app.notification()
+        .builder()
+        .title("PDF.dk - Folder Access Needed")
+        .body(format!("PDF.dk Desktop can't read {}. Allow access to keep watching it.", folder.display()))
+        .show();
+}
+
+/// Move a failed input to the Failed folder if its folder wants that right away,
+/// or once it has failed too often. Returns the job's error message.
+/// Only errors down to the input move it; network, account, server and option
+/// errors would move every queued file during an outage or after a bad setting.
+async fn set_aside_failed_input(event: &watcher::FileEvent, error: &api::ApiError, job: &processor::JobRef) -> String {
+    let error_msg = error.to_string();
+    if event.stage != watcher::PipelineStage::Process || !poison::is_input_error(error) {
+        return error_msg;
+    }
+
+    let failures = poison::record_failure(&event.path, &error_msg).await;
+    let move_now = event.tool_config.on_failure == config::FailurePolicy::MoveToFailed;
+    if !move_now && failures < poison::MAX_FAILURES {
+        return error_msg;
+    }
+
+    match poison::quarantine(&event.path, &event.tool_id, &error_msg, failures.max(1)).await {
+        Ok(target) => {
+            add_log(&format!("Moved failed file {:?} to {:?}", event.path, target));
+            job.update(|j| j.failed_file = Some(target.to_string_lossy().into_owned()));
+            if move_now {
+                format!("{} - moved to {}", error_msg, poison::FAILED_FOLDER)
+            } else {
+                format!("{} - failed {} times, moved to {}", error_msg, failures, poison::FAILED_FOLDER)
+            }
+        }
+        Err(e) => {
+            add_log(&format!("ERROR: Could not move failed file {:?}: {}", event.path, e));
+            error_msg
+        }
+    }
+}
+
+/// Pause or resume for a condition checked in the background, logging changes
+fn set_pause_reason(app: &AppHandle, pause: &processor::PauseControl, reason: processor::PauseReason, active: bool, label: &str) {
+    let paused = pause.is_paused_for(reason);
+    if active && !paused {
+        pause.pause(reason);
+        add_log(&format!("Computer is {} - processing paused", label));
+        publish_pause_state(app, pause);
+    } else if !active && paused {
+        pause.resume(reason);
+        add_log(&format!("No longer {} - processing resumed", label));
+        publish_pause_state(app, pause);
+    }
+}
+
+/// When the upload window an event has to wait for opens, if it is closed
+/// right now: its folder's window, or else the calendar's. Windows don't open
+/// on closed days of the calendar. The folder's window is taken from
+/// `config`, as it may have changed since the event was held.
+fn closed_upload_window(event: &watcher::FileEvent, config: &AppConfig) -> Option<String> {
+    let folder_window = config
+        .tools
+        .iter()
+        .filter(|t| t.id == event.tool_id)
+        .flat_map(|t| t.watch_folders())
+        .find(|folder| folder.folder_path == event.tool_config.folder_path)
+        .map_or_else(|| event.tool_config.upload_window.clone(), |folder| folder.upload_window);
+    let window = folder_window.or_else(|| config.calendar.upload_window.clone())?;
+    let now = chrono::Local::now();
+    let today = now.date_naive();
+    if calendar::closed_reason(&config.calendar, today).is_some() {
+        let day = calendar::next_working_day(&config.calendar, today);
+        return Some(format!("{} {}", day.format("%Y-%m-%d"), window.start));
+    }
+    (!calendar::window_open(&window, now.time())).then_some(window.start)
+}
+
+/// Tell the user a watched folder went missing, or is watched again
+fn report_watch_change(app: &AppHandle, change: watcher::FolderWatchChange) {
+    if change.restored {
+        add_log(&format!("Watching {} again", change.folder));
+        events::emit(app, "folder-watch-restored", change);
+        return;
+    }
+    add_log(&format!("WARNING: Watched folder {} is missing", change.folder));
+    let (title, body) = if change.offline {
+        (
+            "PDF.dk - Drive Removed",
+            format!("{} is paused until its drive is connected again.", change.folder),
+        )
+    } else {
+        (
+            "PDF.dk - Watched Folder Missing",
+            format!("{} was deleted or is offline. It is watched again as soon as it is back.", change.folder),
+        )
+    };
+    let _ = app.notification().builder().title(title).body(body).show();
+    events::emit(app, "folder-watch-lost", change);
+}
+
+/// Ask whether to process the files found in a newly watched folder
+fn report_backlog(app: &AppHandle, backlog: Option<watcher::FolderBacklog>) {
+    let Some(backlog) = backlog else {
+        return;
+    };
+    let _ = app.notification()
+        .builder()
+        .title("PDF.dk - Files Already in Folder")
+        .body(format!(
+            "{} has {} file(s) waiting. Open PDF.dk Desktop to process them.",
+            backlog.folder,
+            backlog.files.len()
+        ))
+        .show();
+    events::emit(app, "folder-backlog-found", backlog);
+}
+
+/// Forget which inputs a tool (or every tool) has processed, so copies of them
+/// are processed again. Returns how many were forgotten.
+#[tauri::command]
+async fn clear_processed_inputs(state: tauri::State<'_, AppState>, tool_id: Option<String>) -> Result<usize, String> {
+    ensure_not_viewer(&state).await?;
+    let cleared = dedup::clear(tool_id.as_deref());
+    add_log(&format!("Forgot {} processed input(s)", cleared));
+    Ok(cleared)
+}
+
+/// Files found in watched folders that wait for confirmation
+#[tauri::command]
+async fn get_folder_backlogs(state: tauri::State<'_, AppState>) -> Result<Vec<watcher::FolderBacklog>, String> {
+    let watcher_guard = state.watcher.read().await;
+    Ok(watcher_guard.as_ref().map(|w| w.backlogs()).unwrap_or_default())
+}
+
+/// Queue the files that were already in a folder. Returns how many were queued.
+#[tauri::command]
+async fn process_folder_backlog(state: tauri::State<'_, AppState>, folder: String) -> Result<usize, String> {
+    ensure_not_viewer(&state).await?;
+    let mut watcher_guard = state.watcher.write().await;
+    let watcher = watcher_guard.as_mut().ok_or("Watchers are not running")?;
+    watcher.process_backlog(std::path::Path::new(&folder)).map_err(|e| e.to_string())
+}
+
+/// Leave the files that were already in a folder alone
+#[tauri::command]
+async fn dismiss_folder_backlog(state: tauri::State<'_, AppState>, folder: String) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    let mut watcher_guard = state.watcher.write().await;
+    if let Some(watcher) = watcher_guard.as_mut() {
+        watcher.dismiss_backlog(std::path::Path::new(&folder));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn open_permission_settings(app: AppHandle, folder: String) -> Result<(), String> {
+    use tauri_plugin_opener::OpenerExt;
+
+    let url = watcher::permission_settings_url(std::path::Path::new(&folder))
+        .ok_or("Folder permissions are managed by the file system on this platform")?;
+    app.opener().open_url(url, None::<&str>).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_diagnostics(
+    app: AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<diagnostics::Diagnostics, String> {
+    let config = state.config.read().await;
+    Ok(diagnostics::collect(&app.package_info().version.to_string(), &config))
+}
+
+/// Problems with the folders of the enabled tools, in `config` or the current config
+#[tauri::command]
+async fn validate_config(
+    state: tauri::State<'_, AppState>,
+    config: Option<AppConfig>,
+) -> Result<Vec<diagnostics::ConfigIssue>, String> {
+    let config = match config {
+        Some(config) => config,
+        None => state.config.read().await.clone(),
+    };
+    tauri::async_runtime::spawn_blocking(move || diagnostics::validate_config(&config))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_health_suggestions(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<diagnostics::HealthSuggestion>, String> {
+    let logs = LOG_BUFFER.lock().map(|logs| logs.clone()).unwrap_or_default();
+    Ok(diagnostics::health_suggestions(&state.jobs.jobs(), &logs))
+}
+
 #[tauri::command]
 async fn select_folder() -> Result<Option<String>, String> {
     // This will be handled by tauri-plugin-dialog on frontend
@@ -375,14 +1847,24 @@ async fn update_tool_options(
     tool_id: String,
     options: serde_json::Value,
 ) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    ensure_tool_not_locked(&tool_id)?;
     let mut config = state.config.write().await;
 
     // Find the tool index first
     let tool_idx = config.tools.iter().position(|t| t.id == tool_id);
 
     if let Some(idx) = tool_idx {
+        let errors = options::validate_tool_options(&tool_id, &options);
+        if !errors.is_empty() {
+            return Err(format!("Invalid options: {}", options::describe(&errors)));
+        }
+
+        let mut options = options;
+        secrets::store_tool_option_secrets(&tool_id, None, &mut options)
+            .map_err(|e| e.to_string())?;
         config.tools[idx].options = options.clone();
-        config::save_config(&config).map_err(|e| e.to_string())?;
+        config::save_app_config(&config).map_err(|e| e.to_string())?;
         info!("Updated options for tool {}: {:?}", tool_id, options);
     } else {
         return Err(format!("Tool not found: {}", tool_id));
@@ -391,11 +1873,112 @@ async fn update_tool_options(
     Ok(())
 }
 
+/// Set the tools run after a folder's own tool, in order
+#[tauri::command]
+async fn update_tool_chain(
+    state: tauri::State<'_, AppState>,
+    tool_id: String,
+    chain: Vec<config::ChainStep>,
+) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    ensure_tool_not_locked(&tool_id)?;
+    let mut config = state.config.write().await;
+    let idx = config
+        .tools
+        .iter()
+        .position(|t| t.id == tool_id)
+        .ok_or_else(|| format!("Tool not found: {}", tool_id))?;
+
+    let mut chain = chain;
+    for step in chain.iter_mut() {
+        if config::get_tool_definition(&step.tool_id).is_none() {
+            return Err(format!("Tool not found: {}", step.tool_id));
+        }
+        let errors = options::validate_tool_options(&step.tool_id, &step.options);
+        if !errors.is_empty() {
+            return Err(format!("Invalid options for {}: {}", step.tool_id, options::describe(&errors)));
+        }
+        secrets::store_tool_option_secrets(&step.tool_id, Some(&tool_id), &mut step.options)
+            .map_err(|e| e.to_string())?;
+    }
+
+    let names: Vec<&str> = chain.iter().map(|s| s.tool_id.as_str()).collect();
+    info!("Updated chain for tool {}: {:?}", tool_id, names);
+    config.tools[idx].chain = chain;
+    config::save_app_config(&config).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Set the conditions a file must meet for a folder's tool to run on it
+#[tauri::command]
+async fn update_tool_conditions(
+    state: tauri::State<'_, AppState>,
+    tool_id: String,
+    conditions: Vec<config::Condition>,
+) -> Result<(), String> {
+    ensure_not_viewer(&state).await?;
+    ensure_tool_not_locked(&tool_id)?;
+    let mut config = state.config.write().await;
+    let tool = config
+        .tools
+        .iter_mut()
+        .find(|t| t.id == tool_id)
+        .ok_or_else(|| format!("Tool not found: {}", tool_id))?;
+
+    info!("Updated conditions for tool {}: {:?}", tool_id, conditions);
+    tool.conditions = conditions;
+    config::save_app_config(&config).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Processed-file index of a watched folder: which inputs were processed,
+/// by which job and tool, and when
+#[tauri::command]
+async fn get_folder_index(folder: String) -> Result<folder_index::FolderIndex, String> {
+    folder_index::load(std::path::Path::new(&folder))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Check that an output was produced by the job in a receipt.
+/// `output_path` is only needed if the output has been moved since.
+#[tauri::command]
+async fn verify_receipt(
+    state: tauri::State<'_, AppState>,
+    path: String,
+    output_path: Option<String>,
+) -> Result<receipts::ReceiptVerification, String> {
+    let token = state.auth.read().await.token.clone();
+    let client = api::PdfDkClient::new(token);
+    receipts::verify_receipt(&client, std::path::Path::new(&path), output_path.map(std::path::PathBuf::from))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Check tool options without saving them, so the UI can show errors per field
+#[tauri::command]
+async fn validate_tool_options(
+    tool_id: String,
+    options: serde_json::Value,
+) -> Result<Vec<options::OptionError>, String> {
+    Ok(options::validate_tool_options(&tool_id, &options))
+}
+
 #[tauri::command]
 fn get_logs() -> Vec<String> {
     LOG_BUFFER.lock().map(|logs| logs.clone()).unwrap_or_default()
 }
 
+#[tauri::command]
+fn set_debug_capture(enabled: bool) {
+    log_redact::set_capture(enabled);
+}
+
+#[tauri::command]
+fn get_debug_capture() -> bool {
+    log_redact::is_capturing()
+}
+
 #[tauri::command]
 fn clear_logs() {
     if let Ok(mut logs) = LOG_BUFFER.lock() {
@@ -403,15 +1986,125 @@ fn clear_logs() {
     }
 }
 
-fn setup_tray<R: Runtime>(app: &tauri::App<R>) -> Result<(), Box<dyn std::error::Error>> {
+/// Choose which backend events the calling window receives
+#[tauri::command]
+fn set_event_filter(window: tauri::Window, filter: events::EventFilter) {
+    events::set_filter(window.label(), filter);
+}
+
+/// Show the compact status window, or close it if it is already open
+fn toggle_mini_window(app: &AppHandle) -> tauri::Result<()> {
+    if let Some(window) = app.get_webview_window(window_state::MINI_WINDOW) {
+        return window.close();
+    }
+
+    let window = tauri::WebviewWindowBuilder::new(
+        app,
+        window_state::MINI_WINDOW,
+        tauri::WebviewUrl::App("index.html?view=mini".into()),
+    )
+    .title("PDF.dk Status")
+    .inner_size(320.0, 150.0)
+    .resizable(false)
+    .maximizable(false)
+    .minimizable(false)
+    .always_on_top(true)
+    .skip_taskbar(true)
+    .build()?;
+
+    window_state::manage(&window);
+    // Closed from the tray or its own close button
+    window.on_window_event(|event| match event {
+        tauri::WindowEvent::CloseRequested { .. } => {
+            let _ = window_state::set_mini_open(false);
+        }
+        tauri::WindowEvent::Destroyed => events::clear_filter(window_state::MINI_WINDOW),
+        _ => {}
+    });
+    let _ = window_state::set_mini_open(true);
+
+    Ok(())
+}
+
+/// Forget remembered window positions and move the windows back on screen
+#[tauri::command]
+async fn reset_window_state(app: AppHandle) -> Result<(), String> {
+    let windows: Vec<_> = app.webview_windows().into_values().collect();
+    window_state::reset(&windows).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn toggle_status_window(app: AppHandle) -> Result<(), String> {
+    toggle_mini_window(&app).map_err(|e| e.to_string())
+}
+
+// Tray menu item for pausing, managed so its label can be updated
+struct TrayPauseItem(tauri::menu::MenuItem<tauri::Wry>);
+
+// Tray menu item showing backup destination health
+struct TrayDestinationsItem(tauri::menu::MenuItem<tauri::Wry>);
+
+// Tray submenu listing the profiles to switch to
+struct TrayProfilesMenu(tauri::menu::Submenu<tauri::Wry>);
+
+/// Menu item ids of the profiles are this followed by the profile name
+const PROFILE_MENU_PREFIX: &str = "profile:";
+
+/// List the saved profiles in the tray submenu, checking the active one
+fn refresh_profiles_menu(app: &AppHandle, active: Option<&str>) {
+    let Some(menu) = app.try_state::<TrayProfilesMenu>() else {
+        return;
+    };
+    let names = profiles::list().unwrap_or_else(|e| {
+        error!("Could not list profiles: {}", e);
+        Vec::new()
+    });
+
+    if let Err(e) = fill_profiles_menu(app, &menu.0, names, active) {
+        error!("Failed to update the profiles menu: {}", e);
+    }
+}
+
+fn fill_profiles_menu(
+    app: &AppHandle,
+    menu: &tauri::menu::Submenu<tauri::Wry>,
+    names: Vec<String>,
+    active: Option<&str>,
+) -> tauri::Result<()> {
+    for item in menu.items()? {
+        menu.remove(&item)?;
+    }
+    if names.is_empty() {
+        let none = tauri::menu::MenuItem::with_id(app, "no-profiles", "No saved profiles", false, None::<&str>)?;
+        return menu.append(&none);
+    }
+    for name in names {
+        let checked = active.is_some_and(|active| active.eq_ignore_ascii_case(&name));
+        let id = format!("{}{}", PROFILE_MENU_PREFIX, name);
+        let item = tauri::menu::CheckMenuItem::with_id(app, id, &name, true, checked, None::<&str>)?;
+        menu.append(&item)?;
+    }
+    Ok(())
+}
+
+fn setup_tray(app: &tauri::App, active_profile: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
     // Get the existing tray icon created by Tauri from tauri.conf.json
     let tray = app.tray_by_id("main").ok_or("Tray not found")?;
 
     // Create menu
     let show = tauri::menu::MenuItem::with_id(app, "show", "Show PDF.dk Desktop", true, None::<&str>)?;
+    let mini = tauri::menu::MenuItem::with_id(app, "mini", "Status Window", true, None::<&str>)?;
     let pause = tauri::menu::MenuItem::with_id(app, "pause", "Pause Processing", true, None::<&str>)?;
+    let destinations = tauri::menu::MenuItem::with_id(app, "destinations", destinations_label(&delivery::status()), false, None::<&str>)?;
+    let profiles = tauri::menu::Submenu::with_id(app, "profiles", "Profile", true)?;
     let quit = tauri::menu::MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
-    let menu = tauri::menu::Menu::with_items(app, &[&show, &pause, &quit])?;
+    let menu = tauri::menu::Menu::with_items(app, &[&show, &mini, &pause, &destinations, &profiles, &quit])?;
+
+    // Keep handles to the items whose labels follow the app state
+    app.manage(TrayPauseItem(pause.clone()));
+    app.manage(TrayDestinationsItem(destinations.clone()));
+    app.manage(TrayProfilesMenu(profiles));
+    refresh_profiles_menu(app.handle(), active_profile);
 
     // Set menu on existing tray
     tray.set_menu(Some(menu))?;
@@ -425,15 +2118,43 @@ fn setup_tray<R: Runtime>(app: &tauri::App<R>) -> Result<(), Box<dyn std::error:
                 let _ = window.set_focus();
             }
         }
+        "mini" => {
+            if let Err(e) = toggle_mini_window(app) {
+                error!("Failed to toggle status window: {}", e);
+            }
+        }
         "pause" => {
-            info!("Pause processing requested");
-            // TODO: Toggle pause state
+            let state = app.state::<AppState>();
+            if state.pause.is_paused_for(processor::PauseReason::User) {
+                info!("Resume processing requested");
+                state.pause.resume(processor::PauseReason::User);
+                add_log("Processing resumed");
+            } else {
+                info!("Pause processing requested");
+                state.pause.pause(processor::PauseReason::User);
+                add_log("Processing paused");
+            }
+            publish_pause_state(app, &state.pause);
         }
         "quit" => {
             info!("Quit requested");
             app.exit(0);
         }
-        _ => {}
+        id => {
+            let Some(name) = id.strip_prefix(PROFILE_MENU_PREFIX) else {
+                return;
+            };
+            let name = name.to_string();
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = switch_profile(app.clone(), app.state::<AppState>(), name.clone()).await {
+                    add_log(&format!("ERROR: Could not switch to profile {}: {}", name, e));
+                    // Put the check mark back on the active profile
+                    let active = app.state::<AppState>().config.read().await.active_profile.clone();
+                    refresh_profiles_menu(&app, active.as_deref());
+                }
+            });
+        }
     });
 
     // Set up click event handler
@@ -455,10 +2176,29 @@ fn setup_tray<R: Runtime>(app: &tauri::App<R>) -> Result<(), Box<dyn std::error:
     Ok(())
 }
 
+/// Check the health of the running app for `--healthcheck`, returning the exit code
+pub fn healthcheck() -> i32 {
+    health::check_endpoint()
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    // Initialize logging
-    tracing_subscriber::fmt::init();
+    // Initialize logging, with optional forwarding to a log collector and a
+    // log file beside the executable when running portable
+    {
+        use tracing_subscriber::layer::SubscriberExt;
+        use tracing_subscriber::util::SubscriberInitExt;
+        let log_file = portable::is_portable()
+            .then(|| tracing_subscriber::fmt::layer().with_ansi(false).with_writer(|| portable::LogWriter));
+        tracing_subscriber::registry()
+            .with(tracing_subscriber::fmt::layer())
+            .with(log_file)
+            .with(log_shipper::layer())
+            .init();
+    }
+    if let Some(dir) = portable::data_dir() {
+        info!("Running portable, keeping data in {:?}", dir);
+    }
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
@@ -470,24 +2210,323 @@ pub fn run() {
         .plugin(tauri_plugin_shell::init())
         .setup(|app| {
             // Load config
-            let config = config::load_config().unwrap_or_default();
+            let mut config = config::load_config().unwrap_or_default();
+            apply_settings(&config);
+
+            // Secrets don't travel on a portable install's data folder
+            match secrets::move_portable_vault() {
+                Ok(0) => {}
+                Ok(moved) => info!("Moved {} secrets from the portable data folder to this PC", moved),
+                Err(e) => error!("Failed to move secrets out of the portable data folder: {}", e),
+            }
+
+            // Move any plain-text secrets from older configs into the vault
+            match secrets::migrate_plaintext_secrets(&mut config) {
+                Ok(true) => {
+                    if let Err(e) = config::save_config(&config) {
+                        error!("Failed to save migrated config: {}", e);
+                    }
+                    info!("Migrated plain-text secrets to the secrets vault");
+                }
+                Ok(false) => {}
+                Err(e) => error!("Failed to migrate secrets: {}", e),
+            }
 
             // Initialize app state
+            let health_port = config.general.health_port;
+            let active_profile = config.active_profile.clone();
             let state = AppState {
+                pause: processor::PauseControl::new(),
+                night_batch: Arc::new(RwLock::new(None)),
                 config: Arc::new(RwLock::new(config)),
                 auth: Arc::new(RwLock::new(auth::AuthState::default())),
                 watcher: Arc::new(RwLock::new(None)),
+                capabilities: Arc::new(RwLock::new(api::ApiCapabilities::default())),
+                jobs: processor::JobTracker::new(),
             };
 
+            // Health endpoint for monitoring tools
+            if let Some(port) = health_port {
+                tauri::async_runtime::spawn(health::serve(
+                    port,
+                    state.config.clone(),
+                    state.watcher.clone(),
+                    state.jobs.clone(),
+                ));
+            }
+
+            // Refresh the access token before it expires so long watch sessions never 401
+            let auth_state = state.auth.clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    tokio::time::sleep(TOKEN_REFRESH_CHECK_INTERVAL).await;
+
+                    let current = auth_state.read().await.clone();
+                    if !auth::needs_refresh(&current) {
+                        continue;
+                    }
+
+                    match auth::refresh_session(&current).await {
+                        Ok(refreshed) => {
+                            let mut auth = auth_state.write().await;
+                            // Skip if the user logged out or switched account meanwhile
+                            if auth.token == current.token {
+                                if let Err(e) = auth::save_session(&refreshed) {
+                                    add_log(&format!("Failed to save refreshed token: {}", e));
+                                }
+                                *auth = refreshed;
+                                add_log("Access token refreshed");
+                            }
+                        }
+                        Err(e) => add_log(&format!("Failed to refresh access token: {}", e)),
+                    }
+                }
+            });
+
+            // Pause on closed days of the work calendar, and during night batches,
+            // which are scheduled work too. Warn about files waiting too long.
+            let config_state = state.config.clone();
+            let pause = state.pause.clone();
+            let night_batch = state.night_batch.clone();
+            let jobs = state.jobs.clone();
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut overdue_warned: std::collections::HashSet<String> = std::collections::HashSet::new();
+                loop {
+                    let calendar = config_state.read().await.calendar.clone();
+                    let now = chrono::Local::now();
+                    let today = now.date_naive();
+                    let batch_running = night_batch.read().await.is_some();
+                    let closed = (calendar.pause_on_closed_days || batch_running)
+                        .then(|| calendar::closed_reason(&calendar, today))
+                        .flatten();
+                    let paused = pause.is_paused_for(processor::PauseReason::ClosedDay);
+                    match closed {
+                        Some(reason) if !paused => {
+                            pause.pause(processor::PauseReason::ClosedDay);
+                            add_log(&format!("Closed today ({}) - processing paused", reason));
+                            publish_pause_state(&app_handle, &pause);
+                        }
+                        None if paused => {
+                            pause.resume(processor::PauseReason::ClosedDay);
+                            add_log("Working day - processing resumed");
+                            publish_pause_state(&app_handle, &pause);
+                        }
+                        _ => {}
+                    }
+
+                    if let Some(hours) = calendar.overdue_after_hours {
+                        let overdue: Vec<processor::Job> = jobs
+                            .jobs()
+                            .into_iter()
+                            .filter(|job| !job.is_finished() && !overdue_warned.contains(&job.id))
+                            .filter(|job| {
+                                chrono::DateTime::from_timestamp(job.created_at as i64, 0).is_some_and(|created| {
+                                    let created = created.with_timezone(&chrono::Local).naive_local();
+                                    calendar::working_time(&calendar, created, now.naive_local())
+                                        > chrono::Duration::hours(i64::from(hours))
+                                })
+                            })
+                            .collect();
+                        for job in &overdue {
+                            add_log(&format!(
+                                "WARNING: {} has waited more than {} working hours",
+                                job.input_file, hours
+                            ));
+                            overdue_warned.insert(job.id.clone());
+                        }
+                        if !overdue.is_empty() {
+                            let _ = app_handle.notification()
+                                .builder()
+                                .title("PDF.dk - Files Overdue")
+                                .body(format!(
+                                    "{} file(s) have waited more than {} working hours",
+                                    overdue.len(),
+                                    hours
+                                ))
+                                .show();
+                        }
+                    }
+                    overdue_warned.retain(|id| jobs.job(id).is_some_and(|job| !job.is_finished()));
+                    tokio::time::sleep(CALENDAR_CHECK_INTERVAL).await;
+                }
+            });
+
+            // Pause on metered connections and battery power, if the user wants that
+            let config_state = state.config.clone();
+            let pause = state.pause.clone();
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    let general = config_state.read().await.general.clone();
+                    let metered = general.pause_on_metered && power::on_metered_connection().await;
+                    let battery = general.pause_on_battery && power::on_battery().await;
+                    set_pause_reason(&app_handle, &pause, processor::PauseReason::MeteredConnection, metered, "on a metered connection");
+                    set_pause_reason(&app_handle, &pause, processor::PauseReason::OnBattery, battery, "on battery power");
+                    tokio::time::sleep(power::CHECK_INTERVAL).await;
+                }
+            });
+
+            // Nightly self-test of the enabled tools
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    tokio::time::sleep(selftest::CHECK_INTERVAL).await;
+                    let state = app_handle.state::<AppState>();
+                    let settings = state.config.read().await.self_test.clone();
+                    if !selftest::take_due(&settings, chrono::Local::now().naive_local()) {
+                        continue;
+                    }
+                    if let Err(e) = self_test(&app_handle, &state).await {
+                        add_log(&format!("Self-test skipped: {}", e));
+                    }
+                }
+            });
+
+            // Delete originals past the retention limits
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    let config = app_handle.state::<AppState>().config.read().await.clone();
+                    if config.retention.is_enabled() {
+                        let report = retention::cleanup(&config.tools, &config.retention, false).await;
+                        if !report.files.is_empty() {
+                            add_log(&format!(
+                                "Deleted {} original(s) past the retention limits ({} MB)",
+                                report.files.len(),
+                                report.total_bytes / (1024 * 1024)
+                            ));
+                        }
+                        for error in &report.errors {
+                            add_log(&format!("WARNING: Could not delete original {}", error));
+                        }
+                    }
+                    tokio::time::sleep(retention::CLEANUP_INTERVAL).await;
+                }
+            });
+
+            // Watch folders again once they are recreated or back online, and
+            // after the computer has slept
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut last_check = std::time::SystemTime::now();
+                loop {
+                    tokio::time::sleep(watcher::WATCHDOG_INTERVAL).await;
+                    // The wall clock keeps running during sleep, the timer doesn't
+                    let now = std::time::SystemTime::now();
+                    let woke = now
+                        .duration_since(last_check)
+                        .is_ok_and(|gap| gap > watcher::WATCHDOG_INTERVAL + watcher::SLEEP_THRESHOLD);
+                    let slept_since = last_check;
+                    last_check = now;
+
+                    let state = app_handle.state::<AppState>();
+                    let mut guard = state.watcher.write().await;
+                    let Some(watcher) = guard.as_mut() else {
+                        continue;
+                    };
+                    if woke {
+                        add_log("Woke from sleep - watching folders again");
+                        match watcher.rebuild(slept_since).await {
+                            Ok(0) => {}
+                            Ok(queued) => add_log(&format!("Queued {} file(s) that arrived during sleep", queued)),
+                            Err(e) => add_log(&format!("ERROR: Could not watch folders again after sleep: {}", e)),
+                        }
+                    }
+                    let changes = watcher.check_roots().await;
+                    drop(guard);
+                    for (change, backlog) in changes {
+                        report_watch_change(&app_handle, change);
+                        report_backlog(&app_handle, backlog);
+                    }
+                }
+            });
+
+            // Restart the watcher if its event processor died or it stopped
+            // reporting changes
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    tokio::time::sleep(watcher::HEALTH_CHECK_INTERVAL).await;
+                    let state = app_handle.state::<AppState>();
+                    let mut guard = state.watcher.write().await;
+                    if let Some(watcher) = guard.as_mut() {
+                        watcher.check_health().await;
+                    }
+                }
+            });
+
+            // Check backup destinations and retry queued deliveries
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    delivery::check_all().await;
+                    publish_destination_status(&app_handle);
+                    tokio::time::sleep(delivery::HEALTH_CHECK_INTERVAL).await;
+                }
+            });
+
+            // Negotiate optional API features in the background
+            let capabilities = state.capabilities.clone();
+            tauri::async_runtime::spawn(async move {
+                let client = api::PdfDkClient::new(None);
+                match client.get_capabilities().await {
+                    Ok(caps) => {
+                        add_log(&format!("API capabilities: {:?}", caps));
+                        *capabilities.write().await = caps;
+                    }
+                    Err(e) => add_log(&format!("Capability handshake failed, using basic features: {}", e)),
+                }
+            });
+
+            // Fetch the current tool catalog, keeping the built-in list if offline
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let client = api::PdfDkClient::new(None);
+                match client.get_tools().await {
+                    Ok(tools) if !tools.is_empty() => {
+                        add_log(&format!("Loaded {} tools from the API", tools.len()));
+                        config::set_tool_catalog(tools.into_iter().map(Into::into).collect());
+                        events::emit(&app_handle, "tools-updated", config::get_available_tools());
+                    }
+                    Ok(_) => add_log("API returned an empty tool catalog, using built-in tools"),
+                    Err(e) => add_log(&format!("Could not fetch tool catalog, using built-in tools: {}", e)),
+                }
+            });
+
             app.manage(state);
 
             // Setup system tray
-            if let Err(e) = setup_tray(app) {
+            if let Err(e) = setup_tray(app, active_profile.as_deref()) {
                 error!("Failed to setup tray: {}", e);
             }
 
+            // Apply config.json when it is changed outside the app
+            match reload::watch() {
+                Ok((config_watcher, mut changes)) => {
+                    let app_handle = app.handle().clone();
+                    tauri::async_runtime::spawn(async move {
+                        // Changes are only reported while the watcher lives
+                        let _config_watcher = config_watcher;
+                        while changes.recv().await.is_some() {
+                            // Read once, after the last of a burst of writes
+                            loop {
+                                match tokio::time::timeout(reload::SETTLE_TIME, changes.recv()).await {
+                                    Ok(Some(())) => continue,
+                                    Ok(None) => return,
+                                    Err(_) => break,
+                                }
+                            }
+                            reload_config(&app_handle).await;
+                        }
+                    });
+                }
+                Err(e) => error!("Failed to watch config.json for changes: {}", e),
+            }
+
             // Handle window close - hide to tray instead of quitting
-            if let Some(window) = app.get_webview_window("main") {
+            if let Some(window) = app.get_webview_window(window_state::MAIN_WINDOW) {
+                window_state::manage(&window);
                 let window_clone = window.clone();
                 window.on_window_event(move |event| {
                     if let tauri::WindowEvent::CloseRequested { api, .. } = event {
@@ -500,6 +2539,13 @@ pub fn run() {
                 });
             }
 
+            // Reopen the status window if it was open last time
+            if window_state::mini_was_open() {
+                if let Err(e) = toggle_mini_window(app.handle()) {
+                    error!("Failed to open status window: {}", e);
+                }
+            }
+
             // Start watching folders (will be done after auth check in frontend)
             info!("PDF.dk Desktop started");
 
@@ -508,21 +2554,85 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             get_config,
             save_config,
+            get_policy,
+            preview_config_import,
+            apply_config_import,
+            list_profiles,
+            save_profile,
+            delete_profile,
+            switch_profile,
             get_auth_state,
             login,
             logout,
             check_auth,
+            start_guest_session,
+            get_accounts,
+            switch_account,
+            remove_account,
+            confirm_queue_rebind,
+            verify_receipt,
+            get_folder_index,
+            get_api_capabilities,
             get_available_tools,
             enable_tool,
             disable_tool,
+            add_tool_folder,
+            remove_tool_folder,
+            set_folder_recursion,
+            get_folder_backlogs,
+            process_folder_backlog,
+            dismiss_folder_backlog,
+            clear_processed_inputs,
+            use_base_folder,
             get_jobs,
+            merge_now,
+            get_queue_eta,
+            get_waiting_files,
+            get_lost_folders,
+            get_watcher_status,
+            preview_originals_cleanup,
+            get_folder_status,
+            get_night_batch,
+            start_night_batch,
+            cancel_night_batch,
+            get_self_test_report,
+            run_self_test,
+            get_pause_state,
+            set_paused,
             select_folder,
+            open_permission_settings,
+            get_diagnostics,
+            validate_config,
+            get_health_suggestions,
+            get_destination_status,
+            set_destination_password,
+            connect_destination,
+            set_smtp_password,
+            list_printers,
+            get_statistics,
+            import_holiday_calendar,
+            toggle_status_window,
+            reset_window_state,
             start_watchers,
             get_saved_credentials,
             update_tool_options,
+            update_tool_chain,
+            update_tool_conditions,
+            validate_tool_options,
             get_logs,
             clear_logs,
+            set_debug_capture,
+            get_debug_capture,
+            set_event_filter,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, event| {
+            if let tauri::RunEvent::Exit = event {
+                // Keep window positions for the next start
+                if let Err(e) = window_state::save_all() {
+                    error!("Failed to save window state: {}", e);
+                }
+            }
+        });
 }
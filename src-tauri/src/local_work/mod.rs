@@ -0,0 +1,149 @@
+// Local work module for PDF.dk Desktop
+// Runs CPU-heavy local tasks at low priority and limits how many run at once
+
+use crate::config::GeneralSettings;
+use once_cell::sync::Lazy;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, RwLock};
+use thiserror::Error;
+use tokio::sync::{oneshot, Semaphore};
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// The low-priority pool. It has a thread per allowed local operation; when
+/// the limit is lowered the extra threads stay, idle.
+struct Pool {
+    tx: mpsc::Sender<Task>,
+    rx: Arc<Mutex<mpsc::Receiver<Task>>>,
+    threads: usize,
+}
+
+#[derive(Error, Debug)]
+pub enum LocalWorkError {
+    #[error("Local task was cancelled or panicked")]
+    Aborted,
+}
+
+// Limit and permits for concurrent local operations - replaced when the limit changes
+static PERMITS: Lazy<RwLock<(usize, Arc<Semaphore>)>> = Lazy::new(|| {
+    let limit = crate::config::DEFAULT_MAX_LOCAL_OPERATIONS;
+    RwLock::new((limit, Arc::new(Semaphore::new(limit))))
+});
+
+static BACKGROUND_PRIORITY: Lazy<RwLock<bool>> = Lazy::new(|| RwLock::new(true));
+
+// Queue feeding the low-priority pool, whose threads are started on first use
+static POOL: Lazy<Mutex<Pool>> = Lazy::new(|| {
+    let (tx, rx) = mpsc::channel::<Task>();
+    Mutex::new(Pool {
+        tx,
+        rx: Arc::new(Mutex::new(rx)),
+        threads: 0,
+    })
+});
+
+/// Apply the background priority and concurrency settings
+pub fn configure(settings: &GeneralSettings) {
+    if let Ok(mut background) = BACKGROUND_PRIORITY.write() {
+        *background = settings.background_priority;
+    }
+    let limit = settings.max_local_operations.max(1);
+    if let Ok(mut permits) = PERMITS.write() {
+        if permits.0 != limit {
+            // Tasks holding permits of the old semaphore finish normally
+            *permits = (limit, Arc::new(Semaphore::new(limit)));
+        }
+    }
+}
+
+/// Run a blocking, CPU- or disk-heavy task off the async runtime, waiting for
+/// a free slot first. With background priority on, the task runs on the
+/// low-priority pool so it doesn't compete with the user's own work.
+pub async fn run<T, F>(task: F) -> Result<T, LocalWorkError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let permits = PERMITS.read().map(|p| p.1.clone()).map_err(|_| LocalWorkError::Aborted)?;
+    let _permit = permits.acquire_owned().await.map_err(|_| LocalWorkError::Aborted)?;
+
+    let background = BACKGROUND_PRIORITY.read().map(|b| *b).unwrap_or(true);
+    if !background {
+        return tokio::task::spawn_blocking(task)
+            .await
+            .map_err(|_| LocalWorkError::Aborted);
+    }
+
+    let (tx, rx) = oneshot::channel();
+    let job: Task = Box::new(move || {
+        let _ = tx.send(task());
+    });
+    {
+        let mut pool = POOL.lock().map_err(|_| LocalWorkError::Aborted)?;
+        let limit = PERMITS.read().map(|p| p.0).unwrap_or(1);
+        grow_pool(&mut pool, limit);
+        pool.tx.send(job).map_err(|_| LocalWorkError::Aborted)?;
+    }
+
+    rx.await.map_err(|_| LocalWorkError::Aborted)
+}
+
+/// Start pool threads until there are `count`
+fn grow_pool(pool: &mut Pool, count: usize) {
+    while pool.threads < count {
+        let rx = pool.rx.clone();
+        let spawned = std::thread::Builder::new()
+            .name(format!("pdfdk-local-{}", pool.threads))
+            .spawn(move || {
+                lower_thread_priority();
+                loop {
+                    let task = match rx.lock() {
+                        Ok(rx) => rx.recv(),
+                        Err(_) => return,
+                    };
+                    match task {
+                        Ok(task) => task(),
+                        Err(_) => return,
+                    }
+                }
+            });
+        match spawned {
+            Ok(_) => pool.threads += 1,
+            Err(e) => {
+                crate::add_log(&format!("Failed to start local worker thread: {}", e));
+                return;
+            }
+        }
+    }
+}
+
+/// Lower the priority of the current thread for its lifetime
+#[cfg(target_os = "linux")]
+fn lower_thread_priority() {
+    // On Linux the nice value is per thread
+    unsafe {
+        let tid = libc::syscall(libc::SYS_gettid) as libc::id_t;
+        libc::setpriority(libc::PRIO_PROCESS, tid, 10);
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn lower_thread_priority() {
+    unsafe {
+        libc::pthread_set_qos_class_self_np(libc::qos_class_t::QOS_CLASS_UTILITY, 0);
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn lower_thread_priority() {
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentThread, SetThreadPriority, THREAD_MODE_BACKGROUND_BEGIN,
+    };
+    // Background mode also lowers the thread's disk and memory priority
+    unsafe {
+        SetThreadPriority(GetCurrentThread(), THREAD_MODE_BACKGROUND_BEGIN);
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn lower_thread_priority() {}
@@ -0,0 +1,151 @@
+// Log redaction module for PDF.dk Desktop
+// Keeps tokens, signed URLs and personal data out of logged API bodies
+
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::debug;
+
+/// Longest body written to the log outside capture mode (characters)
+const MAX_LOGGED_BODY: usize = 1000;
+/// Size of each in-app log entry when a full body is captured (characters)
+const CAPTURE_CHUNK: usize = 4000;
+/// Longest server response or message kept in an error (characters)
+const MAX_ERROR_CHARS: usize = 300;
+
+const REDACTED: &str = "[redacted]";
+
+// Keys whose values are never logged. Matched case-insensitively; the first
+// group anywhere in the key, the second only as the whole key.
+const SECRET_KEY_PARTS: [&str; 7] = ["token", "password", "secret", "authorization", "api_key", "signature", "cookie"];
+const PERSONAL_KEYS: [&str; 6] = ["email", "phone", "first_name", "last_name", "full_name", "address"];
+
+static CAPTURE: AtomicBool = AtomicBool::new(false);
+
+/// Turn debug capture on or off. While on, full bodies go to the in-app log.
+/// Not saved - capture always starts off.
+pub fn set_capture(enabled: bool) {
+    CAPTURE.store(enabled, Ordering::Relaxed);
+    crate::add_log(if enabled {
+        "Debug capture on - full API bodies are written to the log"
+    } else {
+        "Debug capture off"
+    });
+}
+
+pub fn is_capturing() -> bool {
+    CAPTURE.load(Ordering::Relaxed)
+}
+
+/// Log an API request or response body. Normally only a redacted, truncated
+/// copy goes to the debug log. In capture mode the whole body is written to
+/// the in-app log in chunks, and is never shipped off the machine.
+pub fn log_body(label: &str, body: &str) {
+    if !is_capturing() {
+        debug!("{}: {}", label, redact(body));
+        return;
+    }
+
+    let chunks = split_chars(body, CAPTURE_CHUNK);
+    let total = chunks.len();
+    for (i, chunk) in chunks.iter().enumerate() {
+        if total > 1 {
+            crate::record_log(&format!("{} [{}/{}]: {}", label, i + 1, total, chunk));
+        } else {
+            crate::record_log(&format!("{}: {}", label, chunk));
+        }
+    }
+}
+
+/// Redacted, truncated copy of a body for logs and error messages
+pub fn redact(body: &str) -> String {
+    let redacted = match serde_json::from_str::<Value>(body) {
+        Ok(mut json) => {
+            redact_value(&mut json);
+            json.to_string()
+        }
+        Err(_) => redact_text(body),
+    };
+    truncate(&redacted, MAX_LOGGED_BODY)
+}
+
+/// Redacted, shorter copy of a server's response or message for an error,
+/// which is logged and shown in the UI
+pub fn error_excerpt(text: &str) -> String {
+    truncate(&redact(text), MAX_ERROR_CHARS)
+}
+
+fn is_sensitive_key(key: &str) -> bool {
+    let key = key.to_lowercase();
+    SECRET_KEY_PARTS.iter().any(|part| key.contains(part)) || PERSONAL_KEYS.contains(&key.as_str())
+}
+
+fn redact_value(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_sensitive_key(key) && !value.is_null() {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_value(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_value),
+        Value::String(text) => *text = redact_text(text),
+        _ => {}
+    }
+}
+
+/// Strip query strings from URLs (signed URLs carry their signature there)
+/// and hide bearer tokens
+fn redact_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("http") {
+        let (before, url) = rest.split_at(start);
+        out.push_str(before);
+        let end = url
+            .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>'))
+            .unwrap_or(url.len());
+        match url[..end].find('?') {
+            Some(query) if url.starts_with("http://") || url.starts_with("https://") => {
+                out.push_str(&url[..=query]);
+                out.push_str(REDACTED);
+            }
+            _ => out.push_str(&url[..end]),
+        }
+        rest = &url[end..];
+    }
+    out.push_str(rest);
+
+    let mut text = out;
+    let mut from = 0;
+    while let Some(found) = text[from..].find("Bearer ") {
+        let token_start = from + found + "Bearer ".len();
+        let token_end = text[token_start..]
+            .find(|c: char| c.is_whitespace() || c == '"')
+            .map_or(text.len(), |i| token_start + i);
+        text.replace_range(token_start..token_end, REDACTED);
+        from = token_start + REDACTED.len();
+    }
+    text
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((cut, _)) => format!("{}... ({} more bytes)", &text[..cut], text.len() - cut),
+        None => text.to_string(),
+    }
+}
+
+fn split_chars(text: &str, size: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while let Some((cut, _)) = rest.char_indices().nth(size) {
+        let (chunk, tail) = rest.split_at(cut);
+        chunks.push(chunk);
+        rest = tail;
+    }
+    chunks.push(rest);
+    chunks
+}
@@ -0,0 +1,256 @@
+// Log shipper module for PDF.dk Desktop
+// Forwards logs to a syslog (UDP/TCP) or HTTP collector, buffering while it is unreachable
+
+use crate::config::{LogShipping, LogTransport};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+/// Records queued for the shipping task before new ones are dropped
+const CHANNEL_CAPACITY: usize = 1000;
+/// Records kept while the collector is unreachable (oldest are dropped first)
+const MAX_BUFFERED: usize = 5000;
+const BATCH_SIZE: usize = 100;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+// Logs from these targets are never shipped - the shipper's own network
+// traffic would otherwise feed back into the logs
+const IGNORED_TARGETS: [&str; 5] = ["pdfdk_desktop_lib::log_shipper", "reqwest", "hyper", "h2", "rustls"];
+
+static SETTINGS: Lazy<RwLock<LogShipping>> = Lazy::new(|| RwLock::new(LogShipping::default()));
+static SENDER: OnceCell<mpsc::Sender<LogRecord>> = OnceCell::new();
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LogRecord {
+    timestamp: String,
+    level: String,
+    target: String,
+    message: String,
+    host: String,
+    app_version: String,
+}
+
+/// `tracing` layer that hands events to the shipping task
+pub struct LogShipperLayer;
+
+pub fn layer() -> LogShipperLayer {
+    LogShipperLayer
+}
+
+impl<S: Subscriber> Layer<S> for LogShipperLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if !should_ship(metadata.level(), metadata.target()) {
+            return;
+        }
+
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        enqueue(metadata.level(), metadata.target(), visitor.0);
+    }
+}
+
+/// Ship a message from the in-app log
+pub fn ship_app_log(message: &str) {
+    if should_ship(&Level::INFO, "app") {
+        enqueue(&Level::INFO, "app", message.to_string());
+    }
+}
+
+/// Apply new settings, starting the shipping task the first time shipping is enabled
+pub fn configure(settings: &LogShipping) {
+    if let Ok(mut current) = SETTINGS.write() {
+        *current = settings.clone();
+    }
+    if settings.enabled && SENDER.get().is_none() {
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        if SENDER.set(tx).is_ok() {
+            tauri::async_runtime::spawn(run_shipper(rx));
+        }
+    }
+}
+
+fn should_ship(level: &Level, target: &str) -> bool {
+    let Ok(settings) = SETTINGS.read() else {
+        return false;
+    };
+    settings.enabled
+        && *level <= min_level(&settings.min_level)
+        && !IGNORED_TARGETS.iter().any(|t| target.starts_with(t))
+        && (settings.targets.is_empty() || settings.targets.iter().any(|t| target.starts_with(t.as_str())))
+}
+
+fn min_level(name: &str) -> Level {
+    match name.to_lowercase().as_str() {
+        "error" => Level::ERROR,
+        "warn" | "warning" => Level::WARN,
+        "debug" => Level::DEBUG,
+        "trace" => Level::TRACE,
+        _ => Level::INFO,
+    }
+}
+
+fn enqueue(level: &Level, target: &str, message: String) {
+    let Some(sender) = SENDER.get() else {
+        return;
+    };
+    // Never block the caller - drop logs if the shipper can't keep up
+    let _ = sender.try_send(LogRecord {
+        timestamp: chrono::Local::now().to_rfc3339(),
+        level: level.to_string(),
+        target: target.to_string(),
+        message,
+        host: hostname(),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+    });
+}
+
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0.push_str(value);
+        } else {
+            let _ = write!(self.0, " {}={}", field.name(), value);
+        }
+    }
+}
+
+/// Collect records into batches and send them, retrying with backoff
+async fn run_shipper(mut rx: mpsc::Receiver<LogRecord>) {
+    let client = crate::proxy::client_builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .unwrap_or_default();
+    let mut buffer: VecDeque<LogRecord> = VecDeque::new();
+    let mut retry_delay = FLUSH_INTERVAL;
+    let mut failing = false;
+    let mut next_flush = Instant::now() + FLUSH_INTERVAL;
+
+    loop {
+        let wait = next_flush.saturating_duration_since(Instant::now());
+        match tokio::time::timeout(wait, rx.recv()).await {
+            Ok(Some(record)) => {
+                buffer.push_back(record);
+                while buffer.len() > MAX_BUFFERED {
+                    buffer.pop_front();
+                }
+                // Send early once a batch is full, unless waiting to retry
+                if failing || buffer.len() < BATCH_SIZE {
+                    continue;
+                }
+            }
+            Ok(None) => return,
+            Err(_) => {}
+        }
+
+        let settings = SETTINGS.read().map(|s| s.clone()).unwrap_or_default();
+        if !settings.enabled {
+            buffer.clear();
+        }
+
+        failing = false;
+        while !buffer.is_empty() {
+            let count = buffer.len().min(BATCH_SIZE);
+            let batch: Vec<LogRecord> = buffer.iter().take(count).cloned().collect();
+            if let Err(e) = send_batch(&client, &settings, &batch).await {
+                // Report the first failure only, the log itself is being shipped
+                if retry_delay == FLUSH_INTERVAL {
+                    crate::add_log(&format!("Could not ship logs to {}: {}", settings.endpoint, e));
+                }
+                failing = true;
+                break;
+            }
+            buffer.drain(..count);
+        }
+
+        retry_delay = if failing {
+            (retry_delay * 2).min(MAX_RETRY_DELAY)
+        } else {
+            FLUSH_INTERVAL
+        };
+        next_flush = Instant::now() + retry_delay;
+    }
+}
+
+async fn send_batch(client: &reqwest::Client, settings: &LogShipping, batch: &[LogRecord]) -> Result<(), String> {
+    match settings.transport {
+        LogTransport::Http => {
+            let response = client
+                .post(&settings.endpoint)
+                .json(batch)
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+            if !response.status().is_success() {
+                return Err(format!("collector returned {}", response.status()));
+            }
+        }
+        LogTransport::SyslogUdp => {
+            let socket = tokio::net::UdpSocket::bind("0.0.0.0:0").await.map_err(|e| e.to_string())?;
+            socket.connect(&settings.endpoint).await.map_err(|e| e.to_string())?;
+            for record in batch {
+                socket.send(syslog_line(record).as_bytes()).await.map_err(|e| e.to_string())?;
+            }
+        }
+        LogTransport::SyslogTcp => {
+            let mut stream = tokio::net::TcpStream::connect(&settings.endpoint)
+                .await
+                .map_err(|e| e.to_string())?;
+            for record in batch {
+                // Octet-counting framing (RFC 6587)
+                let line = syslog_line(record);
+                let framed = format!("{} {}", line.len(), line);
+                stream.write_all(framed.as_bytes()).await.map_err(|e| e.to_string())?;
+            }
+            stream.flush().await.map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Format a record as an RFC 5424 syslog message (facility "user")
+fn syslog_line(record: &LogRecord) -> String {
+    let severity = match record.level.as_str() {
+        "ERROR" => 3,
+        "WARN" => 4,
+        "INFO" => 6,
+        _ => 7,
+    };
+    format!(
+        "<{}>1 {} {} pdfdk-desktop {} - - [{}] {}",
+        8 + severity,
+        record.timestamp,
+        record.host,
+        std::process::id(),
+        record.target,
+        record.message
+    )
+}
+
+fn hostname() -> String {
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_else(|_| "-".to_string())
+}
@@ -0,0 +1,146 @@
+// Mail module for PDF.dk Desktop
+// Emails finished outputs through the configured SMTP server, e.g. to a bookkeeping address
+
+use crate::config::{EmailAction, SmtpSecurity, SmtpSettings};
+use crate::secrets::{self, NS_SMTP};
+use crate::watcher::FileEvent;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use once_cell::sync::Lazy;
+use std::path::Path;
+use std::sync::RwLock;
+use std::time::Duration;
+use thiserror::Error;
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(60);
+
+static SETTINGS: Lazy<RwLock<SmtpSettings>> = Lazy::new(|| RwLock::new(SmtpSettings::default()));
+
+#[derive(Error, Debug)]
+pub enum MailError {
+    #[error("No mail server is set up")]
+    NotConfigured,
+    #[error("Invalid address: {0}")]
+    Address(#[from] lettre::address::AddressError),
+    #[error("Could not build the email: {0}")]
+    Message(#[from] lettre::error::Error),
+    #[error("Mail server error: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Keyring error: {0}")]
+    Keyring(#[from] secrets::SecretsError),
+    #[error("{0}")]
+    Unsupported(String),
+}
+
+/// Use the mail server of a new or changed config
+pub fn configure(settings: &SmtpSettings) {
+    if let Ok(mut current) = SETTINGS.write() {
+        *current = settings.clone();
+    }
+}
+
+/// Save the mail server's password in the keyring. An empty password removes it.
+pub fn set_password(password: &str) -> Result<(), secrets::SecretsError> {
+    if password.is_empty() {
+        secrets::delete_secret(NS_SMTP, "password")
+    } else {
+        secrets::set_secret(NS_SMTP, "password", password)
+    }
+}
+
+/// Email a final output if its tool asks for it. A failed email is logged;
+/// the output itself is fine.
+pub async fn send_output(event: &FileEvent, output: &Path) {
+    let Some(action) = &event.tool_config.email else {
+        return;
+    };
+    match send(action, event, output).await {
+        Ok(to) => crate::add_log(&format!("Emailed {:?} to {}", output.file_name().unwrap_or_default(), to)),
+        Err(e) => crate::add_log(&format!("Could not email {:?}: {}", output, e)),
+    }
+}
+
+async fn send(action: &EmailAction, event: &FileEvent, output: &Path) -> Result<String, MailError> {
+    let settings = SETTINGS.read().map(|s| s.clone()).unwrap_or_default();
+    if settings.host.trim().is_empty() {
+        return Err(MailError::NotConfigured);
+    }
+    if output.is_dir() {
+        return Err(MailError::Unsupported("Unpacked results can't be emailed".to_string()));
+    }
+
+    let to = fill(&action.to, event, output);
+    let mut message = Message::builder()
+        .from(settings.from.parse::<Mailbox>()?)
+        .subject(fill(&action.subject, event, output));
+    for address in to.split([',', ';']).map(str::trim).filter(|a| !a.is_empty()) {
+        message = message.to(address.parse::<Mailbox>()?);
+    }
+    let name = output.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let attachment = Attachment::new(name).body(tokio::fs::read(output).await?, content_type(output));
+    let message = message.multipart(
+        MultiPart::mixed()
+            .singlepart(SinglePart::plain(fill(&action.body, event, output)))
+            .singlepart(attachment),
+    )?;
+
+    let host = settings.host.trim();
+    let builder = match settings.security {
+        SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+        SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+    };
+    let mut builder = builder.timeout(Some(SEND_TIMEOUT));
+    if let Some(port) = settings.port {
+        builder = builder.port(port);
+    }
+    if !settings.username.is_empty() {
+        let password = secrets::get_secret(NS_SMTP, "password")?.unwrap_or_default();
+        builder = builder.credentials(Credentials::new(settings.username.clone(), password));
+    }
+    builder.build().send(message).await?;
+    Ok(to)
+}
+
+/// Fill in a recipient, subject or body template for an output
+fn fill(template: &str, event: &FileEvent, output: &Path) -> String {
+    let name = event.path.file_stem().unwrap_or_default().to_string_lossy();
+    let file = output.file_name().unwrap_or_default().to_string_lossy();
+    let folder = event
+        .tool_config
+        .folder_path
+        .as_deref()
+        .map(Path::new)
+        .or(event.path.parent())
+        .and_then(Path::file_name)
+        .unwrap_or_default()
+        .to_string_lossy();
+    let now = chrono::Local::now();
+    template
+        .replace("{name}", &name)
+        .replace("{file}", &file)
+        .replace("{tool}", &event.tool_id)
+        .replace("{folder}", &folder)
+        .replace("{date}", &now.format("%Y-%m-%d").to_string())
+        .replace("{time}", &now.format("%H:%M").to_string())
+}
+
+fn content_type(path: &Path) -> ContentType {
+    let extension = path.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+    let mime = match extension.as_str() {
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "txt" => "text/plain",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        _ => "application/octet-stream",
+    };
+    ContentType::parse(mime).unwrap_or(ContentType::TEXT_PLAIN)
+}
@@ -2,5 +2,10 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    // Query the running app for monitoring tools instead of starting it
+    if std::env::args().any(|arg| arg == "--healthcheck") {
+        std::process::exit(pdfdk_desktop_lib::healthcheck());
+    }
+
     pdfdk_desktop_lib::run()
 }
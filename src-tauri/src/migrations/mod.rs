@@ -0,0 +1,105 @@
+// Config migrations module for PDF.dk Desktop
+// Upgrades config files written by older versions of the app to the current
+// schema, one version at a time, before they are read
+
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+/// Schema version of the configs this version of the app writes
+pub const CONFIG_VERSION: u32 = 2;
+
+/// Every migration, oldest first. A change to the structure of the config
+/// bumps CONFIG_VERSION, adds the step from the previous version here and
+/// gets a test in tests/config_migrations.rs.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 1,
+        description: "move each tool's folder into a folder list",
+        apply: folders_to_list,
+    },
+];
+
+#[derive(Error, Debug)]
+pub enum MigrationError {
+    #[error("The config is not a JSON object")]
+    NotAnObject,
+    #[error("The config is from a newer version of the app (schema {0}, this version knows up to {1})")]
+    TooNew(u32, u32),
+    #[error("No migration from schema version {0}")]
+    MissingStep(u32),
+    #[error("Migrating from schema version {0} failed: {1}")]
+    Failed(u32, String),
+}
+
+/// Upgrades a config from schema version `from` to `from + 1`
+pub struct Migration {
+    pub from: u32,
+    /// What changed, for the log
+    pub description: &'static str,
+    pub apply: fn(&mut Value) -> Result<(), String>,
+}
+
+/// Upgrade a config to CONFIG_VERSION. Returns the version it had if it was migrated.
+pub fn migrate(config: &mut Value) -> Result<Option<u32>, MigrationError> {
+    migrate_to(config, CONFIG_VERSION, MIGRATIONS)
+}
+
+/// Upgrade a config to `target` with `migrations`
+pub fn migrate_to(config: &mut Value, target: u32, migrations: &[Migration]) -> Result<Option<u32>, MigrationError> {
+    let from = version_of(config)?;
+    if from > target {
+        return Err(MigrationError::TooNew(from, target));
+    }
+    if from == target {
+        return Ok(None);
+    }
+
+    for version in from..target {
+        let step = migrations
+            .iter()
+            .find(|m| m.from == version)
+            .ok_or(MigrationError::MissingStep(version))?;
+        (step.apply)(config).map_err(|e| MigrationError::Failed(version, e))?;
+        config["version"] = Value::from(version + 1);
+        tracing::info!("Migrated config to schema version {}: {}", version + 1, step.description);
+    }
+    Ok(Some(from))
+}
+
+/// Schema version of a config. Configs without one count as version 1, the
+/// first that was written.
+pub fn version_of(config: &Value) -> Result<u32, MigrationError> {
+    let object = config.as_object().ok_or(MigrationError::NotAnObject)?;
+    Ok(object
+        .get("version")
+        .and_then(Value::as_u64)
+        .and_then(|v| u32::try_from(v).ok())
+        .unwrap_or(1))
+}
+
+fn folders_to_list(config: &mut Value) -> Result<(), String> {
+    let Some(tools) = config.get_mut("tools") else {
+        return Ok(());
+    };
+    let tools = tools.as_array_mut().ok_or("tools is not a list")?;
+    tools.iter_mut().try_for_each(tool_folders_to_list)
+}
+
+/// Give a tool in the shape of schema 1, with a single folderPath and
+/// outputMode, a folder list holding that folder
+fn tool_folders_to_list(tool: &mut Value) -> Result<(), String> {
+    let tool = tool.as_object_mut().ok_or("a tool is not an object")?;
+    let output_mode = tool.remove("outputMode");
+    let mut folders = Vec::new();
+    // A tool without a folder had nothing its output mode applied to
+    if let Some(Value::String(path)) = tool.remove("folderPath") {
+        let mut folder = Map::new();
+        folder.insert("path".to_string(), Value::String(path));
+        if let Some(output_mode) = output_mode {
+            folder.insert("outputMode".to_string(), output_mode);
+        }
+        folders.push(Value::Object(folder));
+    }
+    tool.insert("folders".to_string(), Value::Array(folders));
+    Ok(())
+}
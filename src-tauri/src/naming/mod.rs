@@ -0,0 +1,168 @@
+// Naming module for PDF.dk Desktop
+// Builds output file names from a tool's template, e.g. "{date}_{name}"
+
+use regex::Regex;
+use thiserror::Error;
+
+/// Characters that are not allowed in file names on any platform
+const ILLEGAL_CHARACTERS: [char; 9] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum NamingError {
+    #[error("The template is empty")]
+    Empty,
+    #[error("Unknown placeholder {{{0}}} - use {{name}}, {{tool}}, {{date}}, {{time}} or {{counter}}")]
+    UnknownPlaceholder(String),
+    #[error("A {{ in the template is never closed")]
+    UnclosedBrace,
+    #[error("File names can't contain {0:?}")]
+    IllegalCharacter(char),
+    #[error("The template needs {{name}} or {{counter}}, or outputs would overwrite each other")]
+    NotUnique,
+    #[error("The template needs some text, {{tool}}, {{date}} or {{time}}, or outputs can't be told apart from new files")]
+    NotDistinguishable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placeholder {
+    /// The input's name without its extension
+    Name,
+    Tool,
+    /// Processing date, "2024-03-31"
+    Date,
+    /// Processing time, "14-05-09"
+    Time,
+    /// The lowest number from 1 that gives a name not taken yet
+    Counter,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+/// Check a template can be used to name outputs
+pub fn validate(template: &str) -> Result<(), NamingError> {
+    let segments = parse(template)?;
+    let unique = segments
+        .iter()
+        .any(|s| matches!(s, Segment::Placeholder(Placeholder::Name | Placeholder::Counter)));
+    if !unique {
+        return Err(NamingError::NotUnique);
+    }
+    if !distinguishable(&segments) {
+        return Err(NamingError::NotDistinguishable);
+    }
+    Ok(())
+}
+
+/// The output file name `template` gives an input called `name`, with
+/// `extension` appended. `taken` tells which names `{counter}` has to skip.
+/// Invalid templates fall back to the default "{name}_{tool}".
+pub fn render(template: &str, name: &str, tool_id: &str, extension: &str, taken: impl Fn(&str) -> bool) -> String {
+    let segments = match parse(template) {
+        Ok(segments) => segments,
+        Err(e) => {
+            crate::add_log(&format!("Invalid output name template {:?}, using the default: {}", template, e));
+            return format!("{}_{}.{}", name, tool_id, extension);
+        }
+    };
+    let now = chrono::Local::now();
+    let date = now.format("%Y-%m-%d").to_string();
+    let time = now.format("%H-%M-%S").to_string();
+    let fill = |counter: u32| {
+        let stem: String = segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.clone(),
+                Segment::Placeholder(Placeholder::Name) => name.to_string(),
+                Segment::Placeholder(Placeholder::Tool) => tool_id.to_string(),
+                Segment::Placeholder(Placeholder::Date) => date.clone(),
+                Segment::Placeholder(Placeholder::Time) => time.clone(),
+                Segment::Placeholder(Placeholder::Counter) => counter.to_string(),
+            })
+            .collect();
+        format!("{}.{}", stem.trim(), extension)
+    };
+
+    if !segments.contains(&Segment::Placeholder(Placeholder::Counter)) {
+        return fill(0);
+    }
+    (1..)
+        .map(fill)
+        .find(|file_name| !taken(file_name))
+        .unwrap_or_default()
+}
+
+/// Whether `file_name` could be an output named by `template`. Templates
+/// whose outputs look like any input, e.g. "{name}", match nothing.
+pub fn is_match(template: &str, tool_id: &str, extension: &str, file_name: &str) -> bool {
+    let Ok(segments) = parse(template) else {
+        return false;
+    };
+    if !distinguishable(&segments) {
+        return false;
+    }
+    let mut source = String::from("(?i)^");
+    for segment in &segments {
+        source.push_str(&match segment {
+            Segment::Text(text) => regex::escape(text),
+            Segment::Placeholder(Placeholder::Name) => ".+".to_string(),
+            Segment::Placeholder(Placeholder::Tool) => regex::escape(tool_id),
+            Segment::Placeholder(Placeholder::Date) => r"\d{4}-\d{2}-\d{2}".to_string(),
+            Segment::Placeholder(Placeholder::Time) => r"\d{2}-\d{2}-\d{2}".to_string(),
+            Segment::Placeholder(Placeholder::Counter) => r"\d+".to_string(),
+        });
+    }
+    source.push_str(&format!(r"\.{}$", regex::escape(extension)));
+    Regex::new(&source).is_ok_and(|matcher| matcher.is_match(file_name))
+}
+
+/// Whether names from a template differ from input names in something besides
+/// the input's name and a number
+fn distinguishable(segments: &[Segment]) -> bool {
+    segments.iter().any(|segment| match segment {
+        Segment::Text(text) => !text.trim().is_empty(),
+        Segment::Placeholder(placeholder) => {
+            matches!(placeholder, Placeholder::Tool | Placeholder::Date | Placeholder::Time)
+        }
+    })
+}
+
+fn parse(template: &str) -> Result<Vec<Segment>, NamingError> {
+    if template.trim().is_empty() {
+        return Err(NamingError::Empty);
+    }
+    let mut segments = Vec::new();
+    let mut rest = template;
+    while !rest.is_empty() {
+        let Some(open) = rest.find('{') else {
+            segments.push(Segment::Text(text(rest)?));
+            break;
+        };
+        if open > 0 {
+            segments.push(Segment::Text(text(&rest[..open])?));
+        }
+        let close = rest[open..].find('}').ok_or(NamingError::UnclosedBrace)? + open;
+        let placeholder = match rest[open + 1..close].trim().to_lowercase().as_str() {
+            "name" => Placeholder::Name,
+            "tool" => Placeholder::Tool,
+            "date" => Placeholder::Date,
+            "time" => Placeholder::Time,
+            "counter" => Placeholder::Counter,
+            other => return Err(NamingError::UnknownPlaceholder(other.to_string())),
+        };
+        segments.push(Segment::Placeholder(placeholder));
+        rest = &rest[close + 1..];
+    }
+    Ok(segments)
+}
+
+/// Literal text of a template, checked for characters file names can't contain
+fn text(text: &str) -> Result<String, NamingError> {
+    match text.chars().find(|c| c.is_control() || ILLEGAL_CHARACTERS.contains(c)) {
+        Some(c) => Err(NamingError::IllegalCharacter(c)),
+        None => Ok(text.to_string()),
+    }
+}
@@ -0,0 +1,67 @@
+// Notified jobs module for PDF.dk Desktop
+// Remembers which jobs were already announced, so a restart never repeats a toast
+
+use crate::config;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const NOTIFIED_FILE: &str = "notified-jobs.json";
+/// How long a job is remembered after it was announced
+const NOTIFIED_TTL_SECS: u64 = 24 * 60 * 60;
+
+// Job key -> when it was announced (unix seconds), loaded on first use
+static NOTIFIED: Lazy<Mutex<HashMap<String, u64>>> = Lazy::new(|| Mutex::new(load()));
+
+/// Key of a job that stays the same across restarts, unlike its id: the tool,
+/// the input's path and a hash of its content
+pub async fn job_key(tool_id: &str, path: &Path) -> String {
+    let sha256 = crate::dedup::hash(path).await.unwrap_or_default();
+    format!("{}|{}|{}", tool_id, path.to_string_lossy(), sha256)
+}
+
+/// Record that a job's completion is being announced. Returns false if it
+/// already was, in which case the caller should stay quiet.
+pub fn first_notice(key: &str) -> bool {
+    let Ok(mut notified) = NOTIFIED.lock() else {
+        return true;
+    };
+    let now = now_secs();
+    notified.retain(|_, at| now.saturating_sub(*at) < NOTIFIED_TTL_SECS);
+    if notified.contains_key(key) {
+        return false;
+    }
+    notified.insert(key.to_string(), now);
+    save(&notified);
+    true
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn load() -> HashMap<String, u64> {
+    config::get_config_dir()
+        .ok()
+        .and_then(|dir| fs::read_to_string(dir.join(NOTIFIED_FILE)).ok())
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save(notified: &HashMap<String, u64>) {
+    let result = config::get_config_dir()
+        .map_err(|e| e.to_string())
+        .and_then(|dir| {
+            let text = serde_json::to_string(notified).map_err(|e| e.to_string())?;
+            fs::write(dir.join(NOTIFIED_FILE), text).map_err(|e| e.to_string())
+        });
+    if let Err(e) = result {
+        tracing::warn!("Could not save notified jobs: {}", e);
+    }
+}
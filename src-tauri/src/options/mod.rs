@@ -0,0 +1,336 @@
+// Tool option validation for PDF.dk Desktop
+// Checks tool options against the tool's schema before they are saved or uploaded
+
+use crate::config;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// A single problem with a tool's options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OptionError {
+    pub option: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for OptionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.option, self.message)
+    }
+}
+
+/// Join validation errors into one line for logs and plain error messages
+pub fn describe(errors: &[OptionError]) -> String {
+    errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; ")
+}
+
+/// Validate options against the schema of `tool_id`.
+/// Tools without a schema accept any options.
+pub fn validate_tool_options(tool_id: &str, options: &Value) -> Vec<OptionError> {
+    match config::get_tool_definition(tool_id).and_then(|t| t.options_schema) {
+        Some(schema) => validate(&schema, options),
+        None => vec![],
+    }
+}
+
+/// Take the options that name local files to upload with the job (schema
+/// `"format": "file-path"`) out of `options`, returning (form field, path)
+/// pairs. The form field is the property's `x-upload-as`, else its name.
+pub fn take_file_options(tool_id: &str, options: &mut Value) -> Vec<(String, PathBuf)> {
+    let Some(schema) = config::get_tool_definition(tool_id).and_then(|t| t.options_schema) else {
+        return vec![];
+    };
+    let (Some(properties), Some(options)) = (
+        schema.get("properties").and_then(|p| p.as_object()),
+        options.as_object_mut(),
+    ) else {
+        return vec![];
+    };
+
+    let mut files = Vec::new();
+    for (name, spec) in properties {
+        if spec.get("format").and_then(|f| f.as_str()) != Some("file-path") {
+            continue;
+        }
+        match options.remove(name) {
+            Some(Value::String(path)) if !path.trim().is_empty() => {
+                let field = spec.get("x-upload-as").and_then(|f| f.as_str()).unwrap_or(name);
+                files.push((field.to_string(), PathBuf::from(path)));
+            }
+            _ => {}
+        }
+    }
+    files
+}
+
+/// Fill in placeholders in the options marked `"x-template": true` in the
+/// tool's schema, for the file being processed: {filename} (without
+/// extension), {folder}, {date} (YYYY-MM-DD) and {time} (HH:MM)
+pub fn expand_templates(tool_id: &str, options: &mut Value, input: &Path) {
+    let Some(schema) = config::get_tool_definition(tool_id).and_then(|t| t.options_schema) else {
+        return;
+    };
+    let (Some(properties), Some(options)) = (
+        schema.get("properties").and_then(|p| p.as_object()),
+        options.as_object_mut(),
+    ) else {
+        return;
+    };
+
+    let now = chrono::Local::now();
+    let name_of = |p: Option<&Path>| {
+        p.and_then(|p| p.file_name())
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let filename = input
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let folder = name_of(input.parent());
+    let values = [
+        ("{filename}", filename),
+        ("{folder}", folder),
+        ("{date}", now.format("%Y-%m-%d").to_string()),
+        ("{time}", now.format("%H:%M").to_string()),
+    ];
+
+    for (name, spec) in properties {
+        if spec.get("x-template").and_then(|t| t.as_bool()) != Some(true) {
+            continue;
+        }
+        if let Some(Value::String(text)) = options.get_mut(name) {
+            for (placeholder, value) in &values {
+                *text = text.replace(placeholder, value);
+            }
+        }
+    }
+}
+
+// Named patterns offered as presets next to a pattern list
+const PATTERN_PRESETS: [(&str, &str); 3] = [
+    ("cpr", r"/\b[0-3]\d[01]\d{3}-?\d{4}\b/"),
+    ("email", r"/[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}/"),
+    ("phone", r"/(\+45[ -]?)?\b(\d{2}[ -]?){3}\d{2}\b/"),
+];
+
+/// Replace the presets chosen in options marked `"x-presets-for": "<list>"`
+/// with their patterns, appended to that pattern list. Entries of the list
+/// wrapped in slashes are regexes, anything else is a keyword.
+pub fn expand_pattern_presets(tool_id: &str, options: &mut Value) {
+    let Some(schema) = config::get_tool_definition(tool_id).and_then(|t| t.options_schema) else {
+        return;
+    };
+    let (Some(properties), Some(options)) = (
+        schema.get("properties").and_then(|p| p.as_object()),
+        options.as_object_mut(),
+    ) else {
+        return;
+    };
+
+    for (name, spec) in properties {
+        let Some(target) = spec.get("x-presets-for").and_then(|t| t.as_str()) else {
+            continue;
+        };
+        let Some(Value::Array(chosen)) = options.remove(name) else {
+            continue;
+        };
+        let patterns = chosen
+            .iter()
+            .filter_map(|c| c.as_str())
+            .filter_map(|c| PATTERN_PRESETS.iter().find(|(id, _)| *id == c))
+            .map(|(_, pattern)| Value::String(pattern.to_string()));
+        match options.entry(target).or_insert_with(|| Value::Array(vec![])) {
+            Value::Array(list) => list.extend(patterns),
+            other => *other = Value::Array(patterns.collect()),
+        }
+    }
+}
+
+/// Validate options against a JSON Schema style object schema.
+/// Supports properties with type, enum, items, minimum, maximum, minLength,
+/// maxLength and the file-path (the file must exist, with one of its
+/// x-file-extensions), page-ranges, page-label and pattern-list (regexes must
+/// compile) formats, the required list and additionalProperties (unknown
+/// options are rejected unless it is true, so typos are caught).
+pub fn validate(schema: &Value, options: &Value) -> Vec<OptionError> {
+    let mut errors = Vec::new();
+
+    let empty = serde_json::Map::new();
+    let options = match options {
+        Value::Object(obj) => obj,
+        Value::Null => &empty,
+        _ => {
+            errors.push(error("options", "must be an object"));
+            return errors;
+        }
+    };
+    let properties = schema.get("properties").and_then(|p| p.as_object()).unwrap_or(&empty);
+
+    if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+        for name in required.iter().filter_map(|n| n.as_str()) {
+            if !options.contains_key(name) {
+                errors.push(error(name, "is required"));
+            }
+        }
+    }
+
+    let allow_unknown = schema.get("additionalProperties").and_then(|a| a.as_bool()) == Some(true);
+
+    for (name, value) in options {
+        match properties.get(name) {
+            Some(spec) => {
+                if let Some(message) = check_value(spec, value) {
+                    errors.push(error(name, &message));
+                }
+            }
+            None if !allow_unknown => {
+                let known: Vec<&str> = properties.keys().map(|k| k.as_str()).collect();
+                let message = if known.is_empty() {
+                    "is not an option of this tool".to_string()
+                } else {
+                    format!("is not an option of this tool (expected one of: {})", known.join(", "))
+                };
+                errors.push(error(name, &message));
+            }
+            None => {}
+        }
+    }
+
+    errors
+}
+
+fn error(option: &str, message: &str) -> OptionError {
+    OptionError {
+        option: option.to_string(),
+        message: message.to_string(),
+    }
+}
+
+/// Check one value against its property schema, returning the problem if any
+fn check_value(spec: &Value, value: &Value) -> Option<String> {
+    // Options are sent as form fields, so numbers given as strings are fine
+    let number = value.as_f64().or_else(|| value.as_str().and_then(|s| s.trim().parse().ok()));
+
+    match spec.get("type").and_then(|t| t.as_str()) {
+        Some("string") if !value.is_string() => return Some("must be text".to_string()),
+        Some("boolean") if !value.is_boolean() => return Some("must be true or false".to_string()),
+        Some("number") if number.is_none() => return Some("must be a number".to_string()),
+        Some("integer") if number.is_none_or(|n| n.fract() != 0.0) => {
+            return Some("must be a whole number".to_string())
+        }
+        Some("array") if !value.is_array() => return Some("must be a list".to_string()),
+        _ => {}
+    }
+
+    if let (Some(items), Some(spec)) = (value.as_array(), spec.get("items")) {
+        if let Some((i, message)) = items
+            .iter()
+            .enumerate()
+            .find_map(|(i, item)| check_value(spec, item).map(|m| (i, m)))
+        {
+            return Some(format!("item {} {}", i + 1, message));
+        }
+    }
+
+    if let Some(allowed) = spec.get("enum").and_then(|e| e.as_array()) {
+        let matches = allowed.iter().any(|a| {
+            a == value || (a.as_f64().is_some() && a.as_f64() == number)
+        });
+        if !matches {
+            let list: Vec<String> = allowed
+                .iter()
+                .map(|a| a.as_str().map(String::from).unwrap_or_else(|| a.to_string()))
+                .collect();
+            return Some(format!("must be one of: {}", list.join(", ")));
+        }
+    }
+
+    if let Some(n) = number {
+        if let Some(min) = spec.get("minimum").and_then(|m| m.as_f64()) {
+            if n < min {
+                return Some(format!("must be at least {}", min));
+            }
+        }
+        if let Some(max) = spec.get("maximum").and_then(|m| m.as_f64()) {
+            if n > max {
+                return Some(format!("must be at most {}", max));
+            }
+        }
+    }
+
+    if let (Some(s), Some(min_len)) = (value.as_str(), spec.get("minLength").and_then(|m| m.as_u64())) {
+        if (s.chars().count() as u64) < min_len {
+            return Some(if min_len == 1 {
+                "must not be empty".to_string()
+            } else {
+                format!("must be at least {} characters", min_len)
+            });
+        }
+    }
+
+    if let (Some(s), Some(max_len)) = (value.as_str(), spec.get("maxLength").and_then(|m| m.as_u64())) {
+        if s.chars().count() as u64 > max_len {
+            return Some(format!("must be at most {} characters", max_len));
+        }
+    }
+
+    let text = value.as_str().filter(|s| !s.trim().is_empty());
+    match (spec.get("format").and_then(|f| f.as_str()), text) {
+        (Some("file-path"), Some(path)) if !std::path::Path::new(path).is_file() => {
+            return Some(format!("file not found: {}", path))
+        }
+        (Some("file-path"), Some(path)) if !has_allowed_extension(spec, path) => {
+            return Some(format!("must be a {} file", allowed_extensions(spec).join(" or ")))
+        }
+        (Some("page-ranges"), Some(ranges)) if !is_page_ranges(ranges) => {
+            return Some("must be page ranges like 1-3,5,8-".to_string())
+        }
+        (Some("page-label"), Some(label)) if !label.contains("{n}") => {
+            return Some("must contain {n} for the page number".to_string())
+        }
+        _ => {}
+    }
+
+    if spec.get("format").and_then(|f| f.as_str()) == Some("pattern-list") {
+        for pattern in value.as_array().into_iter().flatten().filter_map(|p| p.as_str()) {
+            let Some(regex) = pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/')) else {
+                continue;
+            };
+            if let Err(e) = regex::Regex::new(regex) {
+                return Some(format!("has an invalid pattern {}: {}", pattern, e));
+            }
+        }
+    }
+
+    None
+}
+
+/// Extensions a file-path option accepts (`x-file-extensions`), e.g. [".p12", ".pfx"]
+fn allowed_extensions(spec: &Value) -> Vec<String> {
+    spec.get("x-file-extensions")
+        .and_then(|e| e.as_array())
+        .map(|exts| exts.iter().filter_map(|e| e.as_str()).map(|e| format!(".{}", e)).collect())
+        .unwrap_or_default()
+}
+
+fn has_allowed_extension(spec: &Value, path: &str) -> bool {
+    let allowed = allowed_extensions(spec);
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or_default();
+    allowed.is_empty() || allowed.iter().any(|a| a[1..].eq_ignore_ascii_case(extension))
+}
+
+/// Check a page range list such as "1-3,5,8-" (an open end means the last page)
+fn is_page_ranges(ranges: &str) -> bool {
+    let page = |s: &str| s.trim().parse::<u32>().is_ok_and(|n| n > 0);
+
+    ranges.split(',').all(|range| match range.split_once('-') {
+        Some((from, to)) => {
+            page(from)
+                && (to.trim().is_empty()
+                    || (page(to) && from.trim().parse::<u32>().ok() <= to.trim().parse::<u32>().ok()))
+        }
+        None => page(range),
+    })
+}
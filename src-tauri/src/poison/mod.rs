@@ -0,0 +1,145 @@
+// Poison files module for PDF.dk Desktop
+// Counts how often each input has failed, so a file that can never be processed
+// is moved aside instead of using quota every time its folder is touched, and
+// moves failed files to a Failed folder with a report of what went wrong
+
+use crate::api::ApiError;
+use crate::config;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const FAILURES_FILE: &str = "file-failures.json";
+/// Failures after which a file is moved to the Failed folder
+pub const MAX_FAILURES: u32 = 3;
+/// Subfolder next to the input that failed files are moved to
+pub const FAILED_FOLDER: &str = "Failed";
+
+/// What went wrong with a failed file, saved next to it as `<file>.error.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorReport {
+    pub file: String,
+    pub tool_id: String,
+    pub failed_at: String,
+    pub error: String,
+    pub failures: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Failures {
+    path: String,
+    sha256: String,
+    count: u32,
+    last_error: String,
+}
+
+// "<path>|<sha256>" -> failures of that version of the file, loaded on first use
+static FAILURES: Lazy<Mutex<HashMap<String, Failures>>> = Lazy::new(|| Mutex::new(load()));
+
+/// Whether an error is down to the input itself, rather than the network,
+/// the account or the tool's settings - only those make a file poison
+pub fn is_input_error(error: &ApiError) -> bool {
+    matches!(error, ApiError::JobFailed(_) | ApiError::DamagedPdf(_) | ApiError::FileTooLarge(_))
+}
+
+/// Count a failure of `path`. Returns how often this version of it has failed.
+pub async fn record_failure(path: &Path, error: &str) -> u32 {
+    let Some(sha256) = crate::dedup::hash(path).await else {
+        return 0;
+    };
+    let Ok(mut failures) = FAILURES.lock() else {
+        return 0;
+    };
+    let entry = failures.entry(key(path, &sha256)).or_insert_with(|| Failures {
+        path: path.to_string_lossy().into_owned(),
+        sha256,
+        count: 0,
+        last_error: String::new(),
+    });
+    entry.count += 1;
+    entry.last_error = error.to_string();
+    let count = entry.count;
+    save(&failures);
+    count
+}
+
+/// Forget the failures of a file, e.g. once it was processed or moved aside
+pub fn clear(path: &Path) {
+    let Ok(mut failures) = FAILURES.lock() else {
+        return;
+    };
+    let path = path.to_string_lossy();
+    let before = failures.len();
+    failures.retain(|_, f| f.path != path);
+    if failures.len() != before {
+        save(&failures);
+    }
+}
+
+/// Move a failed file into the Failed folder next to it, with its error report.
+/// Returns where it went.
+pub async fn quarantine(path: &Path, tool_id: &str, error: &str, failures: u32) -> Result<PathBuf, std::io::Error> {
+    let folder = path.parent().unwrap_or(Path::new(".")).join(FAILED_FOLDER);
+    tokio::fs::create_dir_all(&folder).await?;
+    let file_name = path.file_name().unwrap_or_default();
+    let mut target = folder.join(file_name);
+    if target.exists() {
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("file");
+        let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("pdf");
+        target = folder.join(format!("{}_{}.{}", stem, chrono::Local::now().format("%Y%m%d-%H%M%S"), ext));
+    }
+    // Rename fails across drives, so fall back to copy and delete
+    if tokio::fs::rename(path, &target).await.is_err() {
+        tokio::fs::copy(path, &target).await?;
+        tokio::fs::remove_file(path).await?;
+    }
+    clear(path);
+
+    // The file is safe either way, so a missing report doesn't fail the move
+    let report = ErrorReport {
+        file: path.to_string_lossy().into_owned(),
+        tool_id: tool_id.to_string(),
+        failed_at: chrono::Local::now().to_rfc3339(),
+        error: error.to_string(),
+        failures,
+    };
+    let report_path = folder.join(format!("{}.error.json", target.file_name().unwrap_or_default().to_string_lossy()));
+    match serde_json::to_vec_pretty(&report) {
+        Ok(json) => {
+            if let Err(e) = tokio::fs::write(&report_path, json).await {
+                tracing::warn!("Could not write error report {:?}: {}", report_path, e);
+            }
+        }
+        Err(e) => tracing::warn!("Could not write error report {:?}: {}", report_path, e),
+    }
+    Ok(target)
+}
+
+fn key(path: &Path, sha256: &str) -> String {
+    format!("{}|{}", path.to_string_lossy(), sha256)
+}
+
+fn load() -> HashMap<String, Failures> {
+    config::get_config_dir()
+        .ok()
+        .and_then(|dir| fs::read_to_string(dir.join(FAILURES_FILE)).ok())
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save(failures: &HashMap<String, Failures>) {
+    let result = config::get_config_dir()
+        .map_err(|e| e.to_string())
+        .and_then(|dir| {
+            let text = serde_json::to_string(failures).map_err(|e| e.to_string())?;
+            fs::write(dir.join(FAILURES_FILE), text).map_err(|e| e.to_string())
+        });
+    if let Err(e) = result {
+        tracing::warn!("Could not save file failures: {}", e);
+    }
+}
@@ -0,0 +1,246 @@
+// Policy module for PDF.dk Desktop
+// Reads the machine-wide policy file IT deploys through MDM or GPO and lays it
+// over the user's config: defaults the user can change, and locked settings,
+// forced folders and disabled tools the user can't
+
+use crate::config::{AppConfig, ToolConfig};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::PathBuf;
+
+const POLICY_FILE: &str = "policy.json";
+
+/// What IT decides for every user of the machine. Settings use the shape and
+/// names of config.json, e.g. `{"general": {"proxy": "http://proxy:8080"}}`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Policy {
+    /// Used where the user's config doesn't set a value, e.g. on first start
+    #[serde(default)]
+    pub defaults: Map<String, Value>,
+    /// Used whatever the user's config says
+    #[serde(default)]
+    pub locked: Map<String, Value>,
+    /// Tools that always watch these folders, in place of the user's config of the tool
+    #[serde(default)]
+    pub forced_tools: Vec<ToolConfig>,
+    /// Tools that can't be enabled
+    #[serde(default)]
+    pub disabled_tools: Vec<String>,
+}
+
+// Read once; a changed policy applies when the app next starts
+static POLICY: Lazy<Option<Policy>> = Lazy::new(load);
+
+/// The machine's policy, if it has one
+pub fn current() -> Option<&'static Policy> {
+    POLICY.as_ref()
+}
+
+/// Why the policy doesn't let the user change a tool, if it doesn't
+pub fn tool_locked(tool_id: &str) -> Option<String> {
+    let policy = current()?;
+    if policy.disabled_tools.iter().any(|id| id == tool_id) {
+        return Some(format!("{} is disabled by your organization's policy", tool_id));
+    }
+    if policy.forced_tools.iter().any(|tool| tool.id == tool_id) {
+        return Some(format!("{} is set up by your organization's policy and can't be changed", tool_id));
+    }
+    None
+}
+
+/// Lay the machine's policy over a config in config.json form, see [`Policy::apply`]
+pub fn apply(config: &mut Value, fresh: bool) {
+    if let Some(policy) = current() {
+        policy.apply(config, fresh);
+    }
+}
+
+/// Apply the machine's policy to a config the user changed, so locked settings win
+pub fn enforce(config: &mut AppConfig) {
+    if let Some(policy) = current() {
+        policy.enforce(config);
+    }
+}
+
+/// Take the machine's policy back out of a config about to be written to the
+/// user's file, see [`Policy::restore`]
+pub fn restore(config: &mut Value, user: &Value) {
+    if let Some(policy) = current() {
+        policy.restore(config, user);
+    }
+}
+
+impl Policy {
+    /// Lay the policy over a config in config.json form. `fresh` is for users
+    /// without a config yet, where the policy's defaults replace the app's.
+    pub fn apply(&self, config: &mut Value, fresh: bool) {
+        if fresh {
+            merge_over(config, &self.defaults);
+        } else {
+            merge_missing(config, &self.defaults);
+        }
+        merge_over(config, &self.locked);
+
+        let Some(tools) = config.get_mut("tools").and_then(Value::as_array_mut) else {
+            return;
+        };
+        for forced in &self.forced_tools {
+            let Ok(mut value) = serde_json::to_value(forced) else {
+                continue;
+            };
+            value["enabled"] = Value::Bool(true);
+            match tools.iter_mut().find(|tool| tool["id"] == forced.id.as_str()) {
+                Some(tool) => *tool = value,
+                None => tools.push(value),
+            }
+        }
+        for tool in tools.iter_mut() {
+            if tool["id"].as_str().is_some_and(|id| self.disabled_tools.iter().any(|d| d == id)) {
+                tool["enabled"] = Value::Bool(false);
+            }
+        }
+    }
+
+    /// Apply the policy to a config the user changed, so locked settings win
+    pub fn enforce(&self, config: &mut AppConfig) {
+        let Ok(mut value) = serde_json::to_value(&*config) else {
+            return;
+        };
+        self.apply(&mut value, false);
+        match serde_json::from_value(value) {
+            Ok(enforced) => *config = enforced,
+            Err(e) => tracing::error!("Could not apply the policy: {}", e),
+        }
+    }
+
+    /// Take the policy back out of a config about to be written to the user's
+    /// file, so the file keeps the user's own settings: locked settings and the
+    /// policy's tools go back to what `user`, the file as it is, has. Removing
+    /// the policy then gives the user their settings back.
+    pub fn restore(&self, config: &mut Value, user: &Value) {
+        let app_defaults = serde_json::to_value(AppConfig::default()).unwrap_or_default();
+        restore_locked(config, user, &app_defaults, &self.locked);
+
+        let user_tools = user.get("tools").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+        let user_tool = |id: &str| user_tools.iter().find(|tool| tool["id"] == id);
+        let Some(tools) = config.get_mut("tools").and_then(Value::as_array_mut) else {
+            return;
+        };
+        tools.retain_mut(|tool| {
+            let Some(id) = tool["id"].as_str().map(String::from) else {
+                return true;
+            };
+            if self.forced_tools.iter().any(|forced| forced.id == id) {
+                return match user_tool(&id) {
+                    Some(own) => {
+                        *tool = own.clone();
+                        true
+                    }
+                    None => false,
+                };
+            }
+            if self.disabled_tools.contains(&id) {
+                if let Some(enabled) = user_tool(&id).map(|own| own["enabled"].clone()) {
+                    tool["enabled"] = enabled;
+                }
+            }
+            true
+        });
+    }
+}
+
+/// Set every value of `patch` in `config`
+fn merge_over(config: &mut Value, patch: &Map<String, Value>) {
+    let Some(config) = config.as_object_mut() else {
+        return;
+    };
+    for (key, value) in patch {
+        match (config.get_mut(key), value) {
+            (Some(existing), Value::Object(patch)) if existing.is_object() => merge_over(existing, patch),
+            _ => {
+                config.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Set the values of `patch` that `config` doesn't have
+fn merge_missing(config: &mut Value, patch: &Map<String, Value>) {
+    let Some(config) = config.as_object_mut() else {
+        return;
+    };
+    for (key, value) in patch {
+        match (config.get_mut(key), value) {
+            (Some(existing), Value::Object(patch)) if existing.is_object() => merge_missing(existing, patch),
+            (Some(_), _) => {}
+            (None, _) => {
+                config.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Put back the user's values of the locked settings, or the app's defaults
+/// where the user has none
+fn restore_locked(config: &mut Value, user: &Value, app_defaults: &Value, locked: &Map<String, Value>) {
+    let Some(config) = config.as_object_mut() else {
+        return;
+    };
+    for (key, locked_value) in locked {
+        let user = user.get(key).unwrap_or(&Value::Null);
+        let app_default = app_defaults.get(key).unwrap_or(&Value::Null);
+        match (config.get_mut(key), locked_value) {
+            (Some(existing), Value::Object(locked)) if existing.is_object() => {
+                restore_locked(existing, user, app_default, locked)
+            }
+            _ if !user.is_null() => {
+                config.insert(key.clone(), user.clone());
+            }
+            _ if !app_default.is_null() => {
+                config.insert(key.clone(), app_default.clone());
+            }
+            _ => {
+                config.remove(key);
+            }
+        }
+    }
+}
+
+fn load() -> Option<Policy> {
+    let path = machine_dir()?.join(POLICY_FILE);
+    if !path.exists() {
+        return None;
+    }
+    let policy = fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| serde_json::from_str::<Policy>(&text).map_err(|e| e.to_string()));
+    match policy {
+        Ok(policy) => {
+            tracing::info!("Applying the policy in {:?}", path);
+            Some(policy)
+        }
+        Err(e) => {
+            tracing::error!("Ignoring the policy in {:?}, it can't be read: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Machine-wide folder only administrators can write to
+#[cfg(target_os = "windows")]
+fn machine_dir() -> Option<PathBuf> {
+    std::env::var_os("ProgramData").map(|dir| PathBuf::from(dir).join("PDF.dk"))
+}
+
+#[cfg(target_os = "macos")]
+fn machine_dir() -> Option<PathBuf> {
+    Some(PathBuf::from("/Library/Application Support/PDF.dk"))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn machine_dir() -> Option<PathBuf> {
+    Some(PathBuf::from("/etc/pdfdk"))
+}
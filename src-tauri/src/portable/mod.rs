@@ -0,0 +1,87 @@
+// Portable mode for PDF.dk Desktop
+// Keeps config, logs and job data in a folder beside the executable instead of
+// the user profile, so the app can run from a USB stick on locked-down machines.
+// Passwords and tokens are not kept there (see the secrets module): they stay on
+// each PC and have to be entered again on a new one.
+
+use once_cell::sync::Lazy;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// File beside the executable that turns portable mode on
+pub const PORTABLE_FLAG: &str = "portable.flag";
+/// Command line switch that turns portable mode on
+pub const PORTABLE_ARG: &str = "--portable";
+/// Folder beside the executable holding everything the app stores
+const DATA_DIR: &str = "data";
+const LOGS_DIR: &str = "logs";
+const LOG_FILE: &str = "pdfdk-desktop.log";
+/// A log file larger than this at startup is moved to `.old`, replacing the previous one
+const MAX_LOG_BYTES: u64 = 10 * 1024 * 1024;
+
+// Data folder when running portable, decided once at startup
+static PORTABLE_DIR: Lazy<Option<PathBuf>> = Lazy::new(detect);
+
+static LOG: Lazy<Option<Mutex<File>>> = Lazy::new(|| {
+    let dir = PORTABLE_DIR.as_ref()?.join(LOGS_DIR);
+    match open_log(&dir) {
+        Ok(file) => Some(Mutex::new(file)),
+        Err(e) => {
+            eprintln!("Could not open the log file in {:?}: {}", dir, e);
+            None
+        }
+    }
+});
+
+/// Folder the app keeps its data in when running portable
+pub fn data_dir() -> Option<&'static Path> {
+    PORTABLE_DIR.as_deref()
+}
+
+pub fn is_portable() -> bool {
+    PORTABLE_DIR.is_some()
+}
+
+/// Add a line of the in-app log to the log file, when running portable
+pub fn append_log(line: &str) {
+    let _ = writeln!(LogWriter, "{}", line);
+}
+
+/// Writes to the log file beside the executable; writes go nowhere when not
+/// running portable
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match LOG.as_ref() {
+            Some(file) => file.lock().map_err(|_| io::Error::other("Log file lock poisoned"))?.write(buf),
+            None => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match LOG.as_ref() {
+            Some(file) => file.lock().map_err(|_| io::Error::other("Log file lock poisoned"))?.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Portable when started with --portable or with portable.flag beside the executable
+fn detect() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    let exe_dir = exe.parent()?;
+    let requested = std::env::args().any(|arg| arg == PORTABLE_ARG) || exe_dir.join(PORTABLE_FLAG).exists();
+    requested.then(|| exe_dir.join(DATA_DIR))
+}
+
+fn open_log(dir: &Path) -> io::Result<File> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(LOG_FILE);
+    if fs::metadata(&path).is_ok_and(|m| m.len() > MAX_LOG_BYTES) {
+        fs::rename(&path, path.with_extension("log.old"))?;
+    }
+    OpenOptions::new().create(true).append(true).open(path)
+}
@@ -0,0 +1,97 @@
+// Power module for PDF.dk Desktop
+// Tells when the computer runs on battery or uses a metered connection, so
+// large uploads can wait for mains power and an unmetered network
+
+use std::process::Command;
+use std::time::Duration;
+
+/// How often the power source and connection are checked
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Whether the computer runs on battery rather than mains power
+pub async fn on_battery() -> bool {
+    tokio::task::spawn_blocking(battery_power).await.unwrap_or(false)
+}
+
+/// Whether the internet connection is metered, e.g. a phone hotspot
+pub async fn on_metered_connection() -> bool {
+    tokio::task::spawn_blocking(metered_connection).await.unwrap_or(false)
+}
+
+#[cfg(target_os = "windows")]
+fn battery_power() -> bool {
+    use windows_sys::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+    const AC_OFFLINE: u8 = 0;
+
+    let mut status: SYSTEM_POWER_STATUS = unsafe { std::mem::zeroed() };
+    unsafe { GetSystemPowerStatus(&mut status) != 0 && status.ACLineStatus == AC_OFFLINE }
+}
+
+#[cfg(target_os = "macos")]
+fn battery_power() -> bool {
+    output_of(Command::new("pmset").args(["-g", "batt"])).is_some_and(|out| out.contains("'Battery Power'"))
+}
+
+/// On battery if no mains supply is online and a battery is discharging
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn battery_power() -> bool {
+    let Ok(supplies) = std::fs::read_dir("/sys/class/power_supply") else {
+        return false;
+    };
+    let read = |dir: &std::path::Path, name: &str| {
+        std::fs::read_to_string(dir.join(name)).map(|s| s.trim().to_string()).unwrap_or_default()
+    };
+    let mut discharging = false;
+    for dir in supplies.flatten().map(|entry| entry.path()) {
+        match read(&dir, "type").as_str() {
+            "Mains" | "USB" if read(&dir, "online") == "1" => return false,
+            "Battery" if read(&dir, "status") == "Discharging" => discharging = true,
+            _ => {}
+        }
+    }
+    discharging
+}
+
+/// Asks the Windows connection profile what the connection costs
+#[cfg(target_os = "windows")]
+fn metered_connection() -> bool {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    const SCRIPT: &str = "[Windows.Networking.Connectivity.NetworkInformation,Windows.Networking.Connectivity,ContentType=WindowsRuntime] | Out-Null; \
+        $profile = [Windows.Networking.Connectivity.NetworkInformation]::GetInternetConnectionProfile(); \
+        if ($profile) { $profile.GetConnectionCost().NetworkCostType }";
+
+    output_of(
+        Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command", SCRIPT])
+            .creation_flags(CREATE_NO_WINDOW),
+    )
+    .is_some_and(|cost| matches!(cost.trim(), "Fixed" | "Variable"))
+}
+
+/// macOS doesn't tell command line tools whether a connection is metered
+#[cfg(target_os = "macos")]
+fn metered_connection() -> bool {
+    false
+}
+
+/// Asks NetworkManager, which reports 1 for metered and 3 for probably metered
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn metered_connection() -> bool {
+    output_of(Command::new("busctl").args([
+        "get-property",
+        "org.freedesktop.NetworkManager",
+        "/org/freedesktop/NetworkManager",
+        "org.freedesktop.NetworkManager",
+        "Metered",
+    ]))
+    .is_some_and(|out| matches!(out.trim(), "u 1" | "u 3"))
+}
+
+fn output_of(command: &mut Command) -> Option<String> {
+    let output = command.output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
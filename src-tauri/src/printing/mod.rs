@@ -0,0 +1,143 @@
+// Printing module for PDF.dk Desktop
+// Sends finished outputs to a system printer, so a watched folder can print directly
+
+use crate::config::PrintAction;
+use crate::watcher::FileEvent;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Command;
+use thiserror::Error;
+
+#[cfg(target_os = "windows")]
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+#[derive(Error, Debug)]
+pub enum PrintError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Failed(String),
+}
+
+/// A printer the system knows about
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Printer {
+    pub name: String,
+    pub is_default: bool,
+}
+
+/// Print a final output if its tool asks for it. A failed print is logged;
+/// the output itself is fine.
+pub async fn print_output(event: &FileEvent, output: &Path) {
+    let Some(action) = event.tool_config.print.clone() else {
+        return;
+    };
+    if output.is_dir() {
+        crate::add_log(&format!("Unpacked results can't be printed - skipping {:?}", output));
+        return;
+    }
+    let printer = action.printer.clone();
+    let file = output.to_path_buf();
+    let result = tokio::task::spawn_blocking(move || print(&file, &action))
+        .await
+        .unwrap_or_else(|e| Err(PrintError::Failed(e.to_string())));
+    match result {
+        Ok(()) => crate::add_log(&format!(
+            "Sent {:?} to {}",
+            output.file_name().unwrap_or_default(),
+            printer.as_deref().unwrap_or("the default printer")
+        )),
+        Err(e) => crate::add_log(&format!("Could not print {:?}: {}", output, e)),
+    }
+}
+
+/// Printers installed on this computer
+#[cfg(target_os = "windows")]
+pub fn list() -> Result<Vec<Printer>, PrintError> {
+    use std::os::windows::process::CommandExt;
+
+    let output = run(Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-NonInteractive",
+            "-Command",
+            "Get-CimInstance Win32_Printer | ForEach-Object { \"$($_.Default)|$($_.Name)\" }",
+        ])
+        .creation_flags(CREATE_NO_WINDOW))?;
+    Ok(output
+        .lines()
+        .filter_map(|line| line.trim().split_once('|'))
+        .map(|(default, name)| Printer {
+            name: name.to_string(),
+            is_default: default.eq_ignore_ascii_case("true"),
+        })
+        .collect())
+}
+
+/// Printers set up in CUPS
+#[cfg(not(target_os = "windows"))]
+pub fn list() -> Result<Vec<Printer>, PrintError> {
+    let names = run(Command::new("lpstat").arg("-e"))?;
+    // "system default destination: Office"; fails when there is no default
+    let default = run(Command::new("lpstat").arg("-d"))
+        .ok()
+        .and_then(|out| out.split_once(':').map(|(_, name)| name.trim().to_string()));
+    Ok(names
+        .lines()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| Printer {
+            name: name.to_string(),
+            is_default: default.as_deref() == Some(name),
+        })
+        .collect())
+}
+
+/// Print through the app registered for the file type, with its PrintTo verb
+#[cfg(target_os = "windows")]
+fn print(path: &Path, action: &PrintAction) -> Result<(), PrintError> {
+    use std::os::windows::process::CommandExt;
+
+    // Paths and names go in through the environment, so quotes in them are harmless
+    let script = match action.printer {
+        Some(_) => "Start-Process -FilePath $env:PDFDK_PRINT_FILE -Verb PrintTo -ArgumentList ('\"' + $env:PDFDK_PRINTER + '\"') -WindowStyle Hidden",
+        None => "Start-Process -FilePath $env:PDFDK_PRINT_FILE -Verb Print -WindowStyle Hidden",
+    };
+    for _ in 0..action.copies.max(1) {
+        run(Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command", script])
+            .env("PDFDK_PRINT_FILE", path)
+            .env("PDFDK_PRINTER", action.printer.as_deref().unwrap_or_default())
+            .creation_flags(CREATE_NO_WINDOW))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn print(path: &Path, action: &PrintAction) -> Result<(), PrintError> {
+    let mut command = Command::new("lp");
+    if let Some(printer) = &action.printer {
+        command.args(["-d", printer.as_str()]);
+    }
+    command
+        .args(["-n", &action.copies.max(1).to_string()])
+        .arg("-t")
+        .arg(path.file_name().unwrap_or_default())
+        .arg("--")
+        .arg(path);
+    run(&mut command).map(|_| ())
+}
+
+fn run(command: &mut Command) -> Result<String, PrintError> {
+    let output = command.output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(PrintError::Failed(if message.is_empty() {
+            format!("exited with {}", output.status)
+        } else {
+            message
+        }));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
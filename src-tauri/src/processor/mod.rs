@@ -1,8 +1,30 @@
 // Job processor module for PDF.dk Desktop
 // Manages the job queue and processing state
 
+use crate::api::ApiError;
+use crate::auth::{self, AuthState};
+use crate::watcher::{self, FileEvent, JobOutput, PipelineStage};
 use serde::{Deserialize, Serialize};
-use std::time::SystemTime;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{mpsc, watch, OwnedRwLockReadGuard, RwLock};
+
+/// Number of finished jobs kept in the history
+const MAX_JOB_HISTORY: usize = 200;
+
+// Estimates used until real timings have been measured
+const DEFAULT_UPLOAD_BYTES_PER_SEC: f64 = 1_000_000.0;
+const DEFAULT_PROCESSING_SECS: f64 = 15.0;
+const DEFAULT_DOWNLOAD_SECS: f64 = 2.0;
+
+/// Weight of the newest sample in the rolling averages
+const ROLLING_AVERAGE_WEIGHT: f64 = 0.3;
+
+/// Queues with more than this much work left are announced with a notification
+pub const LONG_BATCH_SECS: u64 = 300;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -15,7 +37,49 @@ pub struct Job {
     pub progress: Option<u8>,
     pub error: Option<String>,
     pub created_at: u64,
+    /// When the worker picked the job up
+    #[serde(default)]
+    pub started_at: Option<u64>,
     pub completed_at: Option<u64>,
+    #[serde(default)]
+    pub size_bytes: Option<u64>,
+    /// Size of the output file, once completed
+    #[serde(default)]
+    pub output_size_bytes: Option<u64>,
+    /// Bytes the compress tool saved; negative if the output came out larger
+    #[serde(default)]
+    pub saved_bytes: Option<i64>,
+    /// Estimated seconds until this job is finished, counting the jobs ahead of it
+    #[serde(default)]
+    pub eta_seconds: Option<u64>,
+    /// How many redactions the server applied, for tools that redact
+    #[serde(default)]
+    pub redactions: Option<u64>,
+    /// The input was changed while the job ran; the result is from the snapshot taken at the start
+    #[serde(default)]
+    pub source_changed: bool,
+    /// Why the tool wasn't run: the input didn't meet the folder's conditions and
+    /// was delivered unchanged, or it was a copy of an input processed before
+    #[serde(default)]
+    pub skipped: Option<String>,
+    /// The input was processed although the tool had processed a copy of it before
+    #[serde(default)]
+    pub duplicate_of: Option<String>,
+    /// Where the input was moved after failing too often
+    #[serde(default)]
+    pub failed_file: Option<String>,
+    /// Start ("HH:MM") of the upload window the job is waiting for
+    #[serde(default)]
+    pub held_until: Option<String>,
+    /// How the tool's post-processing command exited, and what it printed
+    #[serde(default)]
+    pub post_command_output: Option<String>,
+    /// The worker has picked the job up, so it is no longer just queued
+    #[serde(skip)]
+    pub started: bool,
+    /// When the current status was entered (for ETA of the running phase)
+    #[serde(skip)]
+    pub phase_started: Option<Instant>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -27,6 +91,8 @@ pub enum JobStatus {
     Downloading,
     Completed,
     Failed,
+    /// The input was removed before the job could finish
+    Cancelled,
 }
 
 impl Job {
@@ -43,27 +109,67 @@ impl Job {
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .unwrap()
                 .as_secs(),
+            started_at: None,
             completed_at: None,
+            size_bytes: None,
+            output_size_bytes: None,
+            saved_bytes: None,
+            eta_seconds: None,
+            redactions: None,
+            source_changed: false,
+            skipped: None,
+            duplicate_of: None,
+            failed_file: None,
+            held_until: None,
+            post_command_output: None,
+            started: false,
+            phase_started: Some(Instant::now()),
         }
     }
 
+    /// Put the job back in the queue, e.g. while uploads are paused
+    pub fn set_pending(&mut self) {
+        self.status = JobStatus::Pending;
+        self.progress = None;
+        self.phase_started = Some(Instant::now());
+    }
+
     pub fn set_uploading(&mut self) {
         self.status = JobStatus::Uploading;
         self.progress = Some(10);
+        self.phase_started = Some(Instant::now());
     }
 
     pub fn set_processing(&mut self) {
         self.status = JobStatus::Processing;
         self.progress = Some(50);
+        self.phase_started = Some(Instant::now());
     }
 
     pub fn set_downloading(&mut self) {
         self.status = JobStatus::Downloading;
         self.progress = Some(80);
+        self.phase_started = Some(Instant::now());
+    }
+
+    /// The worker picked the job up
+    pub fn set_started(&mut self) {
+        self.started = true;
+        self.started_at = Some(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        );
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.status, JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled)
     }
 
     pub fn set_completed(&mut self, output_file: &str) {
         self.status = JobStatus::Completed;
+        self.eta_seconds = None;
         self.progress = Some(100);
         self.output_file = Some(output_file.to_string());
         self.completed_at = Some(
@@ -76,6 +182,7 @@ impl Job {
 
     pub fn set_failed(&mut self, error: &str) {
         self.status = JobStatus::Failed;
+        self.eta_seconds = None;
         self.error = Some(error.to_string());
         self.completed_at = Some(
             SystemTime::now()
@@ -84,4 +191,602 @@ impl Job {
                 .as_secs(),
         );
     }
+
+    /// Drop the job because its input was removed, e.g. deleted by the user
+    pub fn set_cancelled(&mut self, reason: &str) {
+        self.set_failed(reason);
+        self.status = JobStatus::Cancelled;
+    }
+}
+
+/// Estimated time remaining for the whole queue
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueEta {
+    pub queued: usize,
+    pub active: usize,
+    pub total_seconds: u64,
+    pub jobs: Vec<JobEta>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobEta {
+    pub id: String,
+    pub tool_id: String,
+    pub input_file: String,
+    pub status: JobStatus,
+    pub eta_seconds: u64,
+}
+
+/// Rolling averages of measured upload speed and per-tool server time
+#[derive(Debug, Default)]
+struct EtaModel {
+    upload_bytes_per_sec: Option<f64>,
+    processing_secs: HashMap<String, f64>,
+    download_secs: HashMap<String, f64>,
+}
+
+impl EtaModel {
+    fn rolling(current: Option<f64>, sample: f64) -> f64 {
+        match current {
+            Some(avg) => avg + ROLLING_AVERAGE_WEIGHT * (sample - avg),
+            None => sample,
+        }
+    }
+
+    fn record(&mut self, tool_id: &str, timings: &JobTimings) {
+        let upload_secs = timings.upload.as_secs_f64();
+        if timings.upload_bytes > 0 && upload_secs > 0.0 {
+            let bps = timings.upload_bytes as f64 / upload_secs;
+            self.upload_bytes_per_sec = Some(Self::rolling(self.upload_bytes_per_sec, bps));
+        }
+
+        let processing = Self::rolling(
+            self.processing_secs.get(tool_id).copied(),
+            timings.processing.as_secs_f64(),
+        );
+        self.processing_secs.insert(tool_id.to_string(), processing);
+
+        let download = Self::rolling(
+            self.download_secs.get(tool_id).copied(),
+            timings.download.as_secs_f64(),
+        );
+        self.download_secs.insert(tool_id.to_string(), download);
+    }
+
+    /// Seconds left for a single job, ignoring the jobs ahead of it
+    fn remaining_secs(&self, job: &Job) -> f64 {
+        let upload = job
+            .size_bytes
+            .map(|bytes| bytes as f64 / self.upload_bytes_per_sec.unwrap_or(DEFAULT_UPLOAD_BYTES_PER_SEC))
+            .unwrap_or(0.0);
+        let processing = self
+            .processing_secs
+            .get(&job.tool_id)
+            .copied()
+            .unwrap_or(DEFAULT_PROCESSING_SECS);
+        let download = self
+            .download_secs
+            .get(&job.tool_id)
+            .copied()
+            .unwrap_or(DEFAULT_DOWNLOAD_SECS);
+        let elapsed = job
+            .phase_started
+            .map(|t| t.elapsed().as_secs_f64())
+            .unwrap_or(0.0);
+
+        match job.status {
+            JobStatus::Pending => upload + processing + download,
+            JobStatus::Uploading => (upload - elapsed).max(0.0) + processing + download,
+            JobStatus::Processing => (processing - elapsed).max(0.0) + download,
+            JobStatus::Downloading => (download - elapsed).max(0.0),
+            JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled => 0.0,
+        }
+    }
+}
+
+/// Measured durations of the phases of a finished job
+#[derive(Debug, Clone, Default)]
+pub struct JobTimings {
+    pub upload_bytes: u64,
+    pub upload: Duration,
+    pub processing: Duration,
+    pub download: Duration,
+}
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    jobs: VecDeque<Job>,
+    eta: EtaModel,
+    long_batch_notified: bool,
+    /// Sent each job as it finishes, e.g. for a night batch
+    finished_listeners: Vec<mpsc::UnboundedSender<Job>>,
+}
+
+impl TrackerState {
+    fn notify_finished(&mut self, job: &Job) {
+        self.finished_listeners.retain(|listener| listener.send(job.clone()).is_ok());
+    }
+}
+
+/// Shared, cheaply clonable tracker of queued, active and recent jobs
+#[derive(Clone, Default)]
+pub struct JobTracker {
+    inner: Arc<Mutex<TrackerState>>,
+}
+
+/// Jobs waiting for the worker: those of the most urgent folders first, and
+/// in the order they arrived within a priority
+#[derive(Default)]
+pub struct JobQueue {
+    heap: BinaryHeap<QueuedJob>,
+    next_seq: u64,
+}
+
+struct QueuedJob {
+    priority: crate::config::Priority,
+    seq: u64,
+    event: FileEvent,
+    job: JobRef,
+}
+
+impl Ord for QueuedJob {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // The heap pops its greatest item, so earlier arrivals compare greater
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedJob {}
+
+impl JobQueue {
+    pub fn push(&mut self, event: FileEvent, job: JobRef) {
+        self.heap.push(QueuedJob {
+            priority: event.tool_config.priority,
+            seq: self.next_seq,
+            event,
+            job,
+        });
+        self.next_seq += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<(FileEvent, JobRef)> {
+        self.heap.pop().map(|queued| (queued.event, queued.job))
+    }
+}
+
+/// Handle to a tracked job, used to report progress while it runs
+#[derive(Clone)]
+pub struct JobRef {
+    tracker: JobTracker,
+    pub id: String,
+}
+
+impl JobRef {
+    /// Update the tracked job
+    pub fn update(&self, f: impl FnOnce(&mut Job)) {
+        self.tracker.update(&self.id, f);
+    }
+
+    /// The tracked job as it is now
+    pub fn job(&self) -> Option<Job> {
+        self.tracker.job(&self.id)
+    }
+
+    /// Feed the measured phase durations into the ETA averages
+    pub fn record_timings(&self, tool_id: &str, timings: &JobTimings) {
+        if let Ok(mut state) = self.tracker.inner.lock() {
+            state.eta.record(tool_id, timings);
+        }
+    }
+}
+
+impl JobTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a new pending job for a file event
+    pub fn enqueue(&self, event: &FileEvent) -> JobRef {
+        let mut job = Job::new(&event.tool_id, &event.path.to_string_lossy());
+        job.size_bytes = Some(
+            event
+                .input_paths()
+                .iter()
+                .filter_map(|p| std::fs::metadata(p).ok())
+                .map(|m| m.len())
+                .sum(),
+        );
+        let id = job.id.clone();
+
+        if let Ok(mut state) = self.inner.lock() {
+            state.jobs.push_back(job);
+
+            // Trim finished jobs beyond the history limit (oldest first)
+            while state.jobs.iter().filter(|j| j.is_finished()).count() > MAX_JOB_HISTORY {
+                match state.jobs.iter().position(|j| j.is_finished()) {
+                    Some(idx) => {
+                        state.jobs.remove(idx);
+                    }
+                    None => break,
+                }
+            }
+        }
+
+        JobRef {
+            tracker: self.clone(),
+            id,
+        }
+    }
+
+    pub fn update(&self, id: &str, f: impl FnOnce(&mut Job)) {
+        if let Ok(mut state) = self.inner.lock() {
+            let Some(job) = state.jobs.iter_mut().find(|j| j.id == id) else {
+                return;
+            };
+            let was_finished = job.is_finished();
+            f(job);
+            if !was_finished && job.is_finished() {
+                let job = job.clone();
+                state.notify_finished(&job);
+            }
+        }
+    }
+
+    /// A single tracked job
+    pub fn job(&self, id: &str) -> Option<Job> {
+        let state = self.inner.lock().ok()?;
+        state.jobs.iter().find(|j| j.id == id).cloned()
+    }
+
+    /// Whether a job for this input is queued or running
+    pub fn has_unfinished(&self, input_file: &str) -> bool {
+        self.inner
+            .lock()
+            .is_ok_and(|state| state.jobs.iter().any(|j| j.input_file == input_file && !j.is_finished()))
+    }
+
+    /// Number of jobs waiting in the queue
+    pub fn pending_count(&self) -> usize {
+        match self.inner.lock() {
+            Ok(state) => state.jobs.iter().filter(|j| j.status == JobStatus::Pending).count(),
+            Err(_) => 0,
+        }
+    }
+
+    /// Cancel every queued job with `reason`, so the worker skips them.
+    /// Returns how many were cancelled.
+    pub fn cancel_pending(&self, reason: &str) -> usize {
+        let Ok(mut state) = self.inner.lock() else {
+            return 0;
+        };
+        let mut cancelled = Vec::new();
+        for job in state.jobs.iter_mut().filter(|j| j.status == JobStatus::Pending) {
+            job.set_cancelled(reason);
+            cancelled.push(job.clone());
+        }
+        cancelled.iter().for_each(|job| state.notify_finished(job));
+        cancelled.len()
+    }
+
+    /// Cancel the queued jobs for an input that was removed, unless the worker
+    /// has picked them up already. Returns how many were cancelled.
+    pub fn cancel_input(&self, input_file: &str, reason: &str) -> usize {
+        let Ok(mut state) = self.inner.lock() else {
+            return 0;
+        };
+        let mut cancelled = Vec::new();
+        for job in state
+            .jobs
+            .iter_mut()
+            .filter(|j| j.input_file == input_file && j.status == JobStatus::Pending && !j.started)
+        {
+            job.set_cancelled(reason);
+            cancelled.push(job.clone());
+        }
+        cancelled.iter().for_each(|job| state.notify_finished(job));
+        cancelled.len()
+    }
+
+    /// IDs of queued and running jobs, oldest first, and a receiver that gets
+    /// each job as it finishes from now on. Taken together, so none of the
+    /// jobs can finish unnoticed in between.
+    pub fn follow_unfinished(&self) -> (Vec<String>, mpsc::UnboundedReceiver<Job>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let Ok(mut state) = self.inner.lock() else {
+            return (vec![], rx);
+        };
+        state.finished_listeners.push(tx);
+        let ids = state.jobs.iter().filter(|j| !j.is_finished()).map(|j| j.id.clone()).collect();
+        (ids, rx)
+    }
+
+    /// All tracked jobs, newest first, with ETAs filled in
+    pub fn jobs(&self) -> Vec<Job> {
+        let eta = self.queue_eta();
+        let etas: HashMap<String, u64> = eta.jobs.into_iter().map(|j| (j.id, j.eta_seconds)).collect();
+
+        let state = match self.inner.lock() {
+            Ok(state) => state,
+            Err(_) => return vec![],
+        };
+        state
+            .jobs
+            .iter()
+            .rev()
+            .cloned()
+            .map(|mut job| {
+                job.eta_seconds = etas.get(&job.id).copied();
+                job
+            })
+            .collect()
+    }
+
+    /// ETA per unfinished job and for the whole queue.
+    /// Jobs run one at a time, so each job's ETA includes the jobs ahead of it.
+    pub fn queue_eta(&self) -> QueueEta {
+        let state = match self.inner.lock() {
+            Ok(state) => state,
+            Err(_) => return QueueEta::default(),
+        };
+
+        let mut eta = QueueEta::default();
+        let mut cumulative = 0.0;
+
+        // Active jobs first, then pending ones in arrival order
+        let unfinished = state
+            .jobs
+            .iter()
+            .filter(|j| !j.is_finished() && j.status != JobStatus::Pending)
+            .chain(state.jobs.iter().filter(|j| j.status == JobStatus::Pending));
+
+        for job in unfinished {
+            if job.status == JobStatus::Pending {
+                eta.queued += 1;
+            } else {
+                eta.active += 1;
+            }
+            cumulative += state.eta.remaining_secs(job);
+            eta.jobs.push(JobEta {
+                id: job.id.clone(),
+                tool_id: job.tool_id.clone(),
+                input_file: job.input_file.clone(),
+                status: job.status.clone(),
+                eta_seconds: cumulative.ceil() as u64,
+            });
+        }
+
+        eta.total_seconds = cumulative.ceil() as u64;
+        eta
+    }
+
+    /// Returns true once per batch when the queue becomes long enough to announce.
+    /// The flag resets when the queue is empty again.
+    pub fn should_announce_long_batch(&self, eta: &QueueEta) -> bool {
+        let mut state = match self.inner.lock() {
+            Ok(state) => state,
+            Err(_) => return false,
+        };
+
+        if eta.queued + eta.active == 0 {
+            state.long_batch_notified = false;
+            return false;
+        }
+        if eta.total_seconds >= LONG_BATCH_SECS && !state.long_batch_notified {
+            state.long_batch_notified = true;
+            return true;
+        }
+        false
+    }
+}
+
+/// Why processing is paused. Uploads resume once no reasons are left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PauseReason {
+    /// Paused from the tray or the UI
+    User,
+    /// Token expired and could not be renewed - waiting for the user to sign in
+    SessionExpired,
+    /// Guest quota used up - waiting for the user to sign in
+    GuestLimitReached,
+    /// Signing out or switching account - waiting for running uploads and
+    /// for the user to confirm queued files may go to the new account
+    AccountChange,
+    /// Public holiday or closure date in the work calendar
+    ClosedDay,
+    /// The internet connection is metered and the user doesn't want uploads on it
+    MeteredConnection,
+    /// The computer runs on battery and the user doesn't want uploads then
+    OnBattery,
+}
+
+/// Global pause switch shared by the job worker, tray and commands
+#[derive(Clone)]
+pub struct PauseControl {
+    reasons: Arc<watch::Sender<BTreeSet<PauseReason>>>,
+    // Held (shared) by the worker while a job runs, so changes that must not
+    // overlap a running job can wait for it
+    running: Arc<RwLock<()>>,
+}
+
+impl Default for PauseControl {
+    fn default() -> Self {
+        let (tx, _rx) = watch::channel(BTreeSet::new());
+        Self {
+            reasons: Arc::new(tx),
+            running: Arc::new(RwLock::new(())),
+        }
+    }
+}
+
+impl PauseControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pause(&self, reason: PauseReason) {
+        self.reasons.send_modify(|reasons| {
+            reasons.insert(reason);
+        });
+    }
+
+    pub fn resume(&self, reason: PauseReason) {
+        self.reasons.send_modify(|reasons| {
+            reasons.remove(&reason);
+        });
+    }
+
+    pub fn is_paused(&self) -> bool {
+        !self.reasons.borrow().is_empty()
+    }
+
+    pub fn is_paused_for(&self, reason: PauseReason) -> bool {
+        self.reasons.borrow().contains(&reason)
+    }
+
+    pub fn reasons(&self) -> Vec<PauseReason> {
+        self.reasons.borrow().iter().copied().collect()
+    }
+
+    /// Wait until every pause reason has been cleared
+    pub async fn wait_until_resumed(&self) {
+        let mut rx = self.reasons.subscribe();
+        let _ = rx.wait_for(|reasons| reasons.is_empty()).await;
+    }
+
+    /// Mark a job as running until the returned guard is dropped
+    pub async fn job_started(&self) -> OwnedRwLockReadGuard<()> {
+        self.running.clone().read_owned().await
+    }
+
+    /// Wait for the running job to finish. Pause first so no new job starts.
+    /// Returns false if the job was still running after `timeout`.
+    pub async fn wait_for_running_job(&self, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, self.running.write()).await.is_ok()
+    }
+}
+
+/// Format a number of seconds for tray tooltips and notifications
+pub fn format_eta(seconds: u64) -> String {
+    if seconds < 60 {
+        format!("{} sec", seconds)
+    } else if seconds < 3600 {
+        format!("{} min", seconds.div_ceil(60))
+    } else {
+        format!("{} h {} min", seconds / 3600, (seconds % 3600) / 60)
+    }
+}
+
+/// Run the pipeline step a file event triggers
+pub async fn handle_file_event(
+    event: FileEvent,
+    auth_state: &Arc<RwLock<AuthState>>,
+    job: &JobRef,
+) -> Result<PathBuf, ApiError> {
+    // Outputs are final once delivered from review, or right away without a review stage
+    let is_final = event.stage == PipelineStage::Deliver || event.tool_config.review_folder().is_none();
+
+    let output = match &event.stage {
+        PipelineStage::Process | PipelineStage::Merge(_) | PipelineStage::Compare { .. } => {
+            process_with_relogin(event.clone(), auth_state, job).await?
+        }
+        PipelineStage::Deliver => JobOutput::Written(watcher::deliver_approved_file(&event).await?),
+    };
+    // The output actions only handle new outputs, once each
+    let output = match output {
+        JobOutput::Written(output) => output,
+        JobOutput::Kept(output) => return Ok(output),
+    };
+
+    if is_final {
+        crate::mail::send_output(&event, &output).await;
+        crate::printing::print_output(&event, &output).await;
+        if let Some(command) = &event.tool_config.post_command {
+            let result = crate::hooks::run(command, &event, &output, &job.id).await;
+            job.update(|j| j.post_command_output = Some(result));
+        }
+    }
+
+    if is_final && !event.tool_config.destinations.is_empty() {
+        // The output itself is fine, so a failed copy doesn't fail the job
+        let source_folder = watcher::source_folder(&event);
+        for spec in &event.tool_config.destinations {
+            if let Err(e) = crate::delivery::deliver(spec, &source_folder, &output).await {
+                crate::add_log(&format!("Could not deliver {:?} to its destinations: {}", output, e));
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+/// Process a file event with the current token.
+/// If the token of a signed-in session has expired, renew it silently (refresh
+/// token first, then saved "Remember me" credentials), update the shared auth
+/// state and retry the job once.
+pub async fn process_with_relogin(
+    event: FileEvent,
+    auth_state: &Arc<RwLock<AuthState>>,
+    job: &JobRef,
+) -> Result<JobOutput, ApiError> {
+    let token = auth_state.read().await.token.clone();
+
+    match watcher::process_file_event(event.clone(), token.clone(), job).await {
+        Err(ApiError::Unauthorized) => {
+            // Another job may already have refreshed the token - only log in again if not
+            let current_state = auth_state.read().await.clone();
+            let retry_token = if current_state.token != token {
+                current_state.token
+            } else if !current_state.is_authenticated || current_state.is_guest {
+                // Without a signed-in session there is nothing to renew, and
+                // saved credentials must not turn a guest into an account
+                return Err(ApiError::Unauthorized);
+            } else {
+                crate::add_log("Token expired - trying silent re-login");
+                let new_state = match auth::renew_session(&current_state).await {
+                    Ok(new_state) => new_state,
+                    Err(e) => {
+                        crate::add_log(&format!("Silent re-login failed: {}", e));
+                        return Err(ApiError::Unauthorized);
+                    }
+                };
+                let mut shared = auth_state.write().await;
+                // The user may have signed out or switched account while we renewed
+                if shared.token != token {
+                    crate::add_log("Account changed during re-login - using the current session");
+                    shared.token.clone()
+                } else {
+                    if let Err(e) = auth::save_session(&new_state) {
+                        crate::add_log(&format!("Failed to save renewed session: {}", e));
+                    }
+                    *shared = new_state;
+                    crate::add_log("Silent re-login successful");
+                    shared.token.clone()
+                }
+            };
+            if retry_token.is_none() {
+                return Err(ApiError::Unauthorized);
+            }
+
+            crate::add_log(&format!("Retrying {:?} after re-login", event.path));
+            watcher::process_file_event(event, retry_token, job).await
+        }
+        result => result,
+    }
 }
@@ -0,0 +1,161 @@
+// Profiles module for PDF.dk Desktop
+// Named sets of tool folders and routing rules, so one install can switch
+// between setups, e.g. one per client
+
+use crate::config::{self, AppConfig, ConfigError, RoutingRule, ToolConfig};
+use crate::secrets::{self, SecretsError};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use thiserror::Error;
+
+const PROFILES_DIR: &str = "profiles";
+/// Name the current setup is kept under when switching before any profile was saved
+pub const DEFAULT_PROFILE: &str = "Default";
+
+#[derive(Error, Debug)]
+pub enum ProfileError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Config(#[from] ConfigError),
+    #[error("Secrets error: {0}")]
+    Secrets(#[from] SecretsError),
+    #[error("Profile not found: {0}")]
+    NotFound(String),
+    #[error("A profile needs a name")]
+    EmptyName,
+}
+
+/// The part of the config that changes with the profile
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    pub name: String,
+    #[serde(default)]
+    pub tools: Vec<ToolConfig>,
+    #[serde(default)]
+    pub rules: Vec<RoutingRule>,
+    #[serde(default)]
+    pub base_folder: Option<String>,
+}
+
+impl Profile {
+    fn from_config(name: &str, config: &AppConfig) -> Self {
+        Self {
+            name: name.to_string(),
+            tools: config.tools.clone(),
+            rules: config.rules.clone(),
+            base_folder: config.base_folder.clone(),
+        }
+    }
+}
+
+/// Names of the saved profiles, sorted
+pub fn list() -> Result<Vec<String>, ProfileError> {
+    let dir = profiles_dir()?;
+    let mut names = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        match fs::read_to_string(&path)
+            .map_err(ProfileError::from)
+            .and_then(|text| serde_json::from_str::<Profile>(&text).map_err(ProfileError::from))
+        {
+            Ok(profile) => names.push(profile.name),
+            Err(e) => tracing::warn!("Skipping unreadable profile {:?}: {}", path, e),
+        }
+    }
+    names.sort_by_key(|name| name.to_lowercase());
+    Ok(names)
+}
+
+/// Save the current tools, rules and base folder as profile `name`, replacing
+/// a profile of that name, and make it the active one. The secrets of the
+/// tools' options are kept with the profile, as they differ per client.
+pub fn save_current(config: &mut AppConfig, name: &str) -> Result<(), ProfileError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ProfileError::EmptyName);
+    }
+    write(&Profile::from_config(name, config))?;
+    secrets::save_profile_secrets(name, &config.tools)?;
+    config.active_profile = Some(name.to_string());
+    Ok(())
+}
+
+/// Switch the config to profile `name`. The setup being left is saved to its
+/// profile first, so changes made since switching to it are kept.
+pub fn switch(config: &mut AppConfig, name: &str) -> Result<(), ProfileError> {
+    let profile = read(name)?;
+    let current = config.active_profile.clone().unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+    if !current.eq_ignore_ascii_case(&profile.name) {
+        write(&Profile::from_config(&current, config))?;
+        secrets::save_profile_secrets(&current, &config.tools)?;
+    }
+    secrets::load_profile_secrets(&profile.name, &profile.tools)?;
+
+    config.tools = profile.tools;
+    config.rules = profile.rules;
+    config.base_folder = profile.base_folder;
+    config.active_profile = Some(profile.name);
+    Ok(())
+}
+
+/// Forget profile `name`. The active setup stays as it is.
+pub fn delete(config: &mut AppConfig, name: &str) -> Result<(), ProfileError> {
+    let path = path_for(name)?;
+    if !path.exists() {
+        return Err(ProfileError::NotFound(name.to_string()));
+    }
+    // A profile that can't be read can still be deleted
+    if let Ok(profile) = read(name) {
+        secrets::delete_profile_secrets(&profile.name, &profile.tools)?;
+    }
+    fs::remove_file(&path)?;
+    if config.active_profile.as_deref().is_some_and(|active| active.eq_ignore_ascii_case(name)) {
+        config.active_profile = None;
+    }
+    Ok(())
+}
+
+fn read(name: &str) -> Result<Profile, ProfileError> {
+    let path = path_for(name)?;
+    if !path.exists() {
+        return Err(ProfileError::NotFound(name.to_string()));
+    }
+    Ok(serde_json::from_str(&fs::read_to_string(&path)?)?)
+}
+
+fn write(profile: &Profile) -> Result<(), ProfileError> {
+    let path = path_for(&profile.name)?;
+    fs::write(&path, serde_json::to_string_pretty(profile)?)?;
+    Ok(())
+}
+
+/// File of a profile. Names differing only in case share a file, like they
+/// would on Windows anyway.
+fn path_for(name: &str) -> Result<PathBuf, ProfileError> {
+    let file: String = name
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | ' ') { c } else { '_' })
+        .collect();
+    if file.trim().is_empty() {
+        return Err(ProfileError::EmptyName);
+    }
+    Ok(profiles_dir()?.join(format!("{}.json", file.trim())))
+}
+
+fn profiles_dir() -> Result<PathBuf, ProfileError> {
+    let dir = config::get_config_dir()?.join(PROFILES_DIR);
+    if !dir.exists() {
+        fs::create_dir_all(&dir)?;
+    }
+    Ok(dir)
+}
@@ -0,0 +1,32 @@
+// Proxy module for PDF.dk Desktop
+// Sends the app's HTTP traffic through the proxy in the general settings, or
+// the system's proxy when none is set
+
+use crate::config::GeneralSettings;
+use once_cell::sync::Lazy;
+use std::sync::RwLock;
+
+static PROXY: Lazy<RwLock<Option<String>>> = Lazy::new(|| RwLock::new(None));
+
+/// Apply the proxy setting to HTTP clients created from now on
+pub fn configure(general: &GeneralSettings) {
+    let proxy = general.proxy.as_deref().map(str::trim).filter(|p| !p.is_empty()).map(String::from);
+    if let Ok(mut current) = PROXY.write() {
+        *current = proxy;
+    }
+}
+
+/// Builder for an HTTP client that goes through the configured proxy
+pub fn client_builder() -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder();
+    let Some(url) = PROXY.read().ok().and_then(|p| p.clone()) else {
+        return builder;
+    };
+    match reqwest::Proxy::all(&url) {
+        Ok(proxy) => builder.proxy(proxy),
+        Err(e) => {
+            tracing::warn!("Ignoring invalid proxy {}: {}", url, e);
+            builder
+        }
+    }
+}
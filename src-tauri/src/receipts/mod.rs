@@ -0,0 +1,176 @@
+// Job receipts module for PDF.dk Desktop
+// Records which job produced an output, signed by pdf.dk so it can be proven later
+
+use crate::api::{PdfDkClient, ReceiptClaims, ReceiptSignature};
+use crate::config;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use thiserror::Error;
+
+const RECEIPTS_DIR: &str = "receipts";
+const RECEIPT_VERSION: u32 = 1;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Error, Debug)]
+pub enum ReceiptError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Config error: {0}")]
+    Config(#[from] config::ConfigError),
+    #[error("Invalid receipt: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("API error: {0}")]
+    Api(#[from] crate::api::ApiError),
+    #[error("Local task failed: {0}")]
+    LocalWork(#[from] crate::local_work::LocalWorkError),
+}
+
+/// Receipt for one completed job, stored as JSON in the receipts folder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Receipt {
+    pub version: u32,
+    pub tool_id: String,
+    pub input_files: Vec<String>,
+    pub output_path: String,
+    pub completed_at: String,
+    pub claims: ReceiptClaims,
+    /// None if the server could not sign it (e.g. an older server)
+    pub signature: Option<ReceiptSignature>,
+}
+
+/// Result of checking a receipt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptVerification {
+    pub job_uuid: String,
+    /// Whether pdf.dk confirmed the signature. None for unsigned receipts.
+    pub signature_valid: Option<bool>,
+    /// Whether the output file still matches the receipt. None if it is gone.
+    pub output_matches: Option<bool>,
+    pub message: Option<String>,
+}
+
+/// Turn receipts for new jobs on or off (general setting `jobReceipts`)
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// SHA-256 of a file as lowercase hex
+pub fn sha256_file(path: &Path) -> Result<String, std::io::Error> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Hash of the job's input. Jobs with several inputs (merge) hash the list of
+/// per-file hashes in upload order.
+pub async fn hash_inputs(inputs: &[PathBuf]) -> Result<String, ReceiptError> {
+    let inputs = inputs.to_vec();
+    let hash = crate::local_work::run(move || -> Result<String, std::io::Error> {
+        let hashes = inputs
+            .iter()
+            .map(|p| sha256_file(p))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(match hashes.as_slice() {
+            [single] => single.clone(),
+            _ => format!("{:x}", Sha256::digest(hashes.join("\n").as_bytes())),
+        })
+    })
+    .await??;
+    Ok(hash)
+}
+
+/// Hash the output, have pdf.dk sign the receipt and save it.
+/// Returns the path of the receipt file.
+pub async fn create_receipt(
+    client: &PdfDkClient,
+    job_uuid: &str,
+    tool_id: &str,
+    inputs: &[PathBuf],
+    input_sha256: String,
+    output_path: &Path,
+) -> Result<PathBuf, ReceiptError> {
+    let output = output_path.to_path_buf();
+    let output_sha256 = crate::local_work::run(move || sha256_file(&output)).await??;
+
+    let claims = ReceiptClaims {
+        job_uuid: job_uuid.to_string(),
+        input_sha256,
+        output_sha256,
+    };
+    let signature = match client.sign_receipt(&claims).await {
+        Ok(signature) => Some(signature),
+        Err(e) => {
+            crate::add_log(&format!("Receipt for job {} is unsigned: {}", job_uuid, e));
+            None
+        }
+    };
+
+    let receipt = Receipt {
+        version: RECEIPT_VERSION,
+        tool_id: tool_id.to_string(),
+        input_files: inputs.iter().map(|p| p.to_string_lossy().into_owned()).collect(),
+        output_path: output_path.to_string_lossy().into_owned(),
+        completed_at: chrono::Local::now().to_rfc3339(),
+        claims,
+        signature,
+    };
+
+    let dir = config::get_config_dir()?.join(RECEIPTS_DIR);
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.json", job_uuid));
+    fs::write(&path, serde_json::to_string_pretty(&receipt)?)?;
+    Ok(path)
+}
+
+/// Check a receipt: the output must still match its hash and pdf.dk must
+/// confirm the signature. `output` overrides the output path in the receipt,
+/// e.g. when the file has been moved since.
+pub async fn verify_receipt(
+    client: &PdfDkClient,
+    receipt_path: &Path,
+    output: Option<PathBuf>,
+) -> Result<ReceiptVerification, ReceiptError> {
+    let receipt: Receipt = serde_json::from_str(&fs::read_to_string(receipt_path)?)?;
+
+    let output = output.unwrap_or_else(|| PathBuf::from(&receipt.output_path));
+    let output_matches = if output.is_file() {
+        let expected = receipt.claims.output_sha256.clone();
+        Some(crate::local_work::run(move || sha256_file(&output)).await?? == expected)
+    } else {
+        None
+    };
+
+    let (signature_valid, message) = match &receipt.signature {
+        Some(signature) => {
+            let verdict = client.verify_receipt(&receipt.claims, signature).await?;
+            (Some(verdict.valid), verdict.message)
+        }
+        None => (None, Some("Receipt was not signed by pdf.dk".to_string())),
+    };
+
+    Ok(ReceiptVerification {
+        job_uuid: receipt.claims.job_uuid,
+        signature_valid,
+        output_matches,
+        message,
+    })
+}
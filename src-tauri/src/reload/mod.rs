@@ -0,0 +1,103 @@
+// Config reload module for PDF.dk Desktop
+// Notices when config.json is changed outside the app, e.g. edited by an IT
+// admin or pushed by a sync tool, so the new settings apply without a restart
+
+use crate::config::{self, AppConfig, ConfigError};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+/// Quiet time after the last change before the file is read, so editors and
+/// sync tools have finished writing it
+pub const SETTLE_TIME: Duration = Duration::from_millis(750);
+
+#[derive(Error, Debug)]
+pub enum ReloadError {
+    #[error("{0}")]
+    Config(#[from] ConfigError),
+    #[error("Notify error: {0}")]
+    Notify(#[from] notify::Error),
+}
+
+/// Tools that differ between two configs, by tool id
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl ToolChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Watch config.json. The receiver gets a message for every change to it, for
+/// as long as the watcher is kept.
+pub fn watch() -> Result<(RecommendedWatcher, mpsc::UnboundedReceiver<()>), ReloadError> {
+    let path = config::get_config_path()?;
+    let file_name = path.file_name().map(|name| name.to_os_string());
+    let (tx, rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+        let Ok(event) = result else {
+            return;
+        };
+        if event.kind.is_access() {
+            return;
+        }
+        if event.paths.iter().any(|p| p.file_name() == file_name.as_deref()) {
+            let _ = tx.send(());
+        }
+    })?;
+    // Editors and sync tools often replace the file, which ends a watch on
+    // the file itself, so watch its folder
+    watcher.watch(&config::get_config_dir()?, RecursiveMode::NonRecursive)?;
+    Ok((watcher, rx))
+}
+
+/// Whether two configs hold the same settings. Auth and window geometry are
+/// written to the file by the app as it runs, so they don't count.
+pub fn same_settings(a: &AppConfig, b: &AppConfig) -> bool {
+    let settings = |config: &AppConfig| {
+        let mut value = serde_json::to_value(config).ok()?;
+        let object = value.as_object_mut()?;
+        object.remove("auth");
+        object.remove("windows");
+        Some(value)
+    };
+    match (settings(a), settings(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// The tools added, removed or changed going from `old` to `new`
+pub fn tool_changes(old: &AppConfig, new: &AppConfig) -> ToolChanges {
+    let tools = |config: &AppConfig| -> HashMap<String, serde_json::Value> {
+        config
+            .tools
+            .iter()
+            .map(|tool| (tool.id.clone(), serde_json::to_value(tool).unwrap_or_default()))
+            .collect()
+    };
+    let (old_tools, new_tools) = (tools(old), tools(new));
+
+    let mut changes = ToolChanges::default();
+    for (id, tool) in &new_tools {
+        match old_tools.get(id) {
+            None => changes.added.push(id.clone()),
+            Some(old_tool) if old_tool != tool => changes.changed.push(id.clone()),
+            Some(_) => {}
+        }
+    }
+    changes.removed = old_tools.keys().filter(|id| !new_tools.contains_key(*id)).cloned().collect();
+    changes.added.sort();
+    changes.removed.sort();
+    changes.changed.sort();
+    changes
+}
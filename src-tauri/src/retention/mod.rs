@@ -0,0 +1,212 @@
+// Retention module for PDF.dk Desktop
+// Deletes the oldest processed inputs from the Originals folders once they
+// are past the configured age, count or size, so the folders don't grow forever
+
+use crate::config::{self, RetentionSettings, ToolConfig};
+use crate::watcher::ORIGINALS_FOLDER;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How often the Originals folders are cleaned up
+pub const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const BYTES_PER_MEGABYTE: u64 = 1024 * 1024;
+const ARRIVALS_FILE: &str = "originals-arrivals.json";
+
+// Path of an original -> when it was moved into its Originals folder (unix
+// seconds), loaded on first use
+static ARRIVALS: Lazy<Mutex<HashMap<String, u64>>> = Lazy::new(|| Mutex::new(load()));
+
+/// An original that is (or, in a dry run, would be) deleted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PurgedFile {
+    pub path: String,
+    pub size_bytes: u64,
+    /// When it was moved into the Originals folder, or when it was last
+    /// modified if that isn't known
+    pub arrived: String,
+    /// Which limit it is past
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CleanupReport {
+    /// Nothing was deleted, the report only shows what would be
+    pub dry_run: bool,
+    pub files: Vec<PurgedFile>,
+    pub total_bytes: u64,
+    /// Files that couldn't be deleted, with why
+    pub errors: Vec<String>,
+}
+
+/// Apply the retention limits to the Originals folders of the enabled tools.
+/// With `dry_run` nothing is deleted.
+pub async fn cleanup(tools: &[ToolConfig], settings: &RetentionSettings, dry_run: bool) -> CleanupReport {
+    let folders = originals_folders(tools);
+    let settings = settings.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut report = CleanupReport {
+            dry_run,
+            ..Default::default()
+        };
+        for folder in folders {
+            for (path, purged) in expired(&folder, &settings) {
+                if !dry_run {
+                    if let Err(e) = std::fs::remove_file(&path) {
+                        report.errors.push(format!("{}: {}", path.display(), e));
+                        continue;
+                    }
+                }
+                report.total_bytes += purged.size_bytes;
+                report.files.push(purged);
+            }
+        }
+        if !dry_run {
+            forget_missing();
+        }
+        report
+    })
+    .await
+    .unwrap_or_default()
+}
+
+/// Originals folders of the enabled tools' watched folders, and of their
+/// subfolders for recursive watches
+fn originals_folders(tools: &[ToolConfig]) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for config in tools.iter().filter(|t| t.enabled).flat_map(|t| t.watch_folders()) {
+        let Some(folder) = config.folder_path.as_deref().map(PathBuf::from) else {
+            continue;
+        };
+        let max_depth = if config.recursive { config.max_depth } else { 0 };
+        let mut dirs = vec![(folder, 0)];
+        while let Some((dir, depth)) = dirs.pop() {
+            let originals = dir.join(ORIGINALS_FOLDER);
+            if originals.is_dir() && !found.contains(&originals) {
+                found.push(originals);
+            }
+            if depth >= max_depth {
+                continue;
+            }
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for path in entries.flatten().map(|entry| entry.path()) {
+                if path.is_dir() && path.file_name().is_some_and(|n| n != ORIGINALS_FOLDER) {
+                    dirs.push((path, depth + 1));
+                }
+            }
+        }
+    }
+    found
+}
+
+/// Remember when an original was moved into an Originals folder. A move keeps
+/// the document's own modified time, which can be years before it arrived.
+pub fn record_arrival(path: &Path) {
+    let Ok(mut arrivals) = ARRIVALS.lock() else {
+        return;
+    };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    arrivals.insert(path.to_string_lossy().into_owned(), now);
+    save(&arrivals);
+}
+
+/// When an original arrived in its Originals folder. Files put there before
+/// arrivals were recorded, or by hand, go by their modified time.
+fn arrival(path: &Path, modified: SystemTime) -> SystemTime {
+    ARRIVALS
+        .lock()
+        .ok()
+        .and_then(|arrivals| arrivals.get(path.to_string_lossy().as_ref()).copied())
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+        .unwrap_or(modified)
+}
+
+/// Forget the arrivals of originals that have been deleted or moved away
+fn forget_missing() {
+    let Ok(mut arrivals) = ARRIVALS.lock() else {
+        return;
+    };
+    let before = arrivals.len();
+    arrivals.retain(|path, _| Path::new(path).exists());
+    if arrivals.len() != before {
+        save(&arrivals);
+    }
+}
+
+/// Files in one Originals folder past a limit, by how long they have been
+/// there. The newest files are kept first.
+fn expired(folder: &Path, settings: &RetentionSettings) -> Vec<(PathBuf, PurgedFile)> {
+    let Ok(entries) = std::fs::read_dir(folder) else {
+        return vec![];
+    };
+    let mut files: Vec<(PathBuf, u64, SystemTime)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+            let arrived = arrival(&entry.path(), metadata.modified().ok()?);
+            Some((entry.path(), metadata.len(), arrived))
+        })
+        .collect();
+    files.sort_by(|a, b| b.2.cmp(&a.2));
+
+    let cutoff = settings
+        .max_age_days
+        .and_then(|days| SystemTime::now().checked_sub(Duration::from_secs(u64::from(days) * 24 * 60 * 60)));
+    let max_bytes = settings.max_megabytes.map(|mb| mb * BYTES_PER_MEGABYTE);
+
+    let mut kept_bytes = 0;
+    let mut expired = Vec::new();
+    for (index, (path, size, arrived)) in files.into_iter().enumerate() {
+        let reason = if cutoff.is_some_and(|cutoff| arrived < cutoff) {
+            Some(format!("older than {} days", settings.max_age_days.unwrap_or_default()))
+        } else if settings.max_files.is_some_and(|max| index >= max) {
+            Some(format!("more than {} files", settings.max_files.unwrap_or_default()))
+        } else if max_bytes.is_some_and(|max| kept_bytes + size > max) {
+            Some(format!("more than {} MB", settings.max_megabytes.unwrap_or_default()))
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => expired.push((
+                path.clone(),
+                PurgedFile {
+                    path: path.to_string_lossy().into_owned(),
+                    size_bytes: size,
+                    arrived: chrono::DateTime::<chrono::Local>::from(arrived).to_rfc3339(),
+                    reason,
+                },
+            )),
+            None => kept_bytes += size,
+        }
+    }
+    expired
+}
+
+fn load() -> HashMap<String, u64> {
+    config::get_config_dir()
+        .ok()
+        .and_then(|dir| fs::read_to_string(dir.join(ARRIVALS_FILE)).ok())
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save(arrivals: &HashMap<String, u64>) {
+    let result = config::get_config_dir()
+        .map_err(|e| e.to_string())
+        .and_then(|dir| {
+            let text = serde_json::to_string(arrivals).map_err(|e| e.to_string())?;
+            fs::write(dir.join(ARRIVALS_FILE), text).map_err(|e| e.to_string())
+        });
+    if let Err(e) = result {
+        tracing::warn!("Could not save the arrival times of originals: {}", e);
+    }
+}
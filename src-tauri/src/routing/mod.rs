@@ -0,0 +1,81 @@
+// Routing module for PDF.dk Desktop
+// Sends files dropped in an inbox folder to a tool chosen by their file name
+
+use crate::config::{self, AppConfig, ToolConfig};
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum RoutingError {
+    #[error("Invalid pattern {0}: {1}")]
+    Pattern(String, regex::Error),
+    #[error("Tool not found: {0}")]
+    ToolNotFound(String),
+}
+
+/// A compiled routing rule
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub matcher: Regex,
+    pub tool_config: ToolConfig,
+}
+
+/// Routes per inbox folder, in rule order
+pub type Routes = HashMap<PathBuf, Vec<Route>>;
+
+/// Compile the config's routing rules.
+/// Files are processed with the settings of the tool's own config and its
+/// first folder; tools without a config use the defaults.
+pub fn routes(config: &AppConfig) -> Result<Routes, RoutingError> {
+    let mut routes = Routes::new();
+    for rule in &config.rules {
+        if config::get_tool_definition(&rule.tool_id).is_none() {
+            return Err(RoutingError::ToolNotFound(rule.tool_id.clone()));
+        }
+        let mut tool_config = config
+            .tools
+            .iter()
+            .find(|t| t.id == rule.tool_id)
+            .and_then(|t| t.watch_folders().into_iter().next())
+            .unwrap_or_else(|| ToolConfig::new(&rule.tool_id, &rule.folder_path));
+        tool_config.enabled = true;
+
+        routes.entry(PathBuf::from(&rule.folder_path)).or_default().push(Route {
+            matcher: compile(&rule.pattern)?,
+            tool_config,
+        });
+    }
+    Ok(routes)
+}
+
+/// The tool config for a file in an inbox, from the first rule matching its name
+pub fn route<'a>(routes: &'a [Route], file_name: &str) -> Option<&'a ToolConfig> {
+    routes
+        .iter()
+        .find(|r| r.matcher.is_match(file_name))
+        .map(|r| &r.tool_config)
+}
+
+/// Compile a rule pattern, matched case-insensitively against the whole file name.
+/// Wrapped in slashes it is a regex, otherwise a glob where `*` matches any
+/// characters and `?` a single one.
+pub fn compile(pattern: &str) -> Result<Regex, RoutingError> {
+    let source = match pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/')) {
+        Some(regex) => format!("(?i){}", regex),
+        None => {
+            let mut regex = String::from("(?i)^");
+            for c in pattern.chars() {
+                match c {
+                    '*' => regex.push_str(".*"),
+                    '?' => regex.push('.'),
+                    c => regex.push_str(&regex::escape(&c.to_string())),
+                }
+            }
+            regex.push('$');
+            regex
+        }
+    };
+    Regex::new(&source).map_err(|e| RoutingError::Pattern(pattern.to_string(), e))
+}
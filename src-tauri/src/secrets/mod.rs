@@ -0,0 +1,468 @@
+// Secrets vault for PDF.dk Desktop
+// Stores tokens and passwords in the OS keyring, with an encrypted file fallback.
+// Portable installs keep both in the user profile too: the file's key has to sit
+// next to it, so on a USB stick anyone holding the stick could read every secret.
+
+use crate::config::{self, AppConfig, ToolConfig};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use thiserror::Error;
+
+const KEYRING_SERVICE: &str = "dk.pdf.desktop";
+const VAULT_FILE: &str = "secrets.vault";
+const VAULT_KEY_FILE: &str = "secrets.key";
+const NONCE_LEN: usize = 12;
+
+/// Namespace for auth tokens and saved passwords
+pub const NS_AUTH: &str = "auth";
+/// Namespace for secret tool options (keyed by "<tool id>.<option>", or
+/// "<tool id>.<option>@<tool id>" for a step in another tool's chain)
+pub const NS_TOOL_OPTIONS: &str = "tool-options";
+
+/// Namespace for the secret tool options of saved profiles (keyed by
+/// "<profile>/<tool option key>", the profile name in lower case)
+pub const NS_PROFILES: &str = "profiles";
+
+/// Namespace for the passwords, S3 keys and OAuth refresh tokens of destinations (keyed by destination id)
+pub const NS_DESTINATIONS: &str = "destinations";
+
+/// Namespace for the password of the mail server (key "password")
+pub const NS_SMTP: &str = "smtp";
+
+/// Value stored in config in place of a secret tool option
+pub const SECRET_PLACEHOLDER: &str = "__pdfdk_secret__";
+
+// Set to false the first time the OS keyring reports it is unavailable
+static KEYRING_AVAILABLE: AtomicBool = AtomicBool::new(true);
+
+// Serializes access to the encrypted vault file
+static VAULT_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+#[derive(Error, Debug)]
+pub enum SecretsError {
+    #[error("Keyring error: {0}")]
+    Keyring(#[from] keyring::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Config error: {0}")]
+    Config(#[from] config::ConfigError),
+    #[error("Vault error: {0}")]
+    Vault(String),
+}
+
+/// Store a secret
+pub fn set_secret(namespace: &str, key: &str, value: &str) -> Result<(), SecretsError> {
+    let name = entry_name(namespace, key);
+
+    if let Some(entry) = keyring_entry(&name) {
+        match entry.set_password(value) {
+            Ok(()) => {
+                // Drop any older copy from the fallback file
+                return vault_remove(&name);
+            }
+            Err(e) => keyring_failed(&e),
+        }
+    }
+
+    vault_set(&name, value)
+}
+
+/// Read a secret, returning None if it has not been stored
+pub fn get_secret(namespace: &str, key: &str) -> Result<Option<String>, SecretsError> {
+    let name = entry_name(namespace, key);
+
+    if let Some(entry) = keyring_entry(&name) {
+        match entry.get_password() {
+            Ok(value) => return Ok(Some(value)),
+            Err(keyring::Error::NoEntry) => {}
+            Err(e) => keyring_failed(&e),
+        }
+    }
+
+    vault_get(&name)
+}
+
+/// Delete a secret (no error if it does not exist)
+pub fn delete_secret(namespace: &str, key: &str) -> Result<(), SecretsError> {
+    let name = entry_name(namespace, key);
+
+    if let Some(entry) = keyring_entry(&name) {
+        match entry.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => {}
+            Err(e) => keyring_failed(&e),
+        }
+    }
+
+    vault_remove(&name)
+}
+
+/// Check whether a tool option holds a secret and should live in the vault
+pub fn is_secret_option(key: &str) -> bool {
+    let key = key.to_lowercase();
+    key.contains("password") || key.contains("secret") || key.ends_with("token")
+}
+
+/// Move secret values out of a tool's options into the vault, leaving placeholders.
+/// Secrets are stored per tool, and per chain for chained steps, so they
+/// survive the tool's folder being changed. `owner` is the tool whose chain
+/// the options are from. Returns true if any option was changed.
+pub fn store_tool_option_secrets(
+    tool_id: &str,
+    owner: Option<&str>,
+    options: &mut serde_json::Value,
+) -> Result<bool, SecretsError> {
+    let mut changed = false;
+
+    if let Some(obj) = options.as_object_mut() {
+        for (key, value) in obj.iter_mut() {
+            if !is_secret_option(key) {
+                continue;
+            }
+            let secret_key = tool_option_key(tool_id, owner, key);
+            match value.as_str() {
+                Some(SECRET_PLACEHOLDER) => {}
+                Some("") => delete_secret(NS_TOOL_OPTIONS, &secret_key)?,
+                Some(secret) => {
+                    set_secret(NS_TOOL_OPTIONS, &secret_key, secret)?;
+                    *value = serde_json::Value::String(SECRET_PLACEHOLDER.to_string());
+                    changed = true;
+                }
+                None => {}
+            }
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Move the secret options of every tool and chained step of a config into
+/// the vault, e.g. before a config from the settings window is saved.
+/// Returns true if any option was changed.
+pub fn store_config_secrets(cfg: &mut AppConfig) -> Result<bool, SecretsError> {
+    let mut changed = false;
+    for tool in cfg.tools.iter_mut() {
+        changed |= store_tool_option_secrets(&tool.id, None, &mut tool.options)?;
+        for step in tool.chain.iter_mut() {
+            changed |= store_tool_option_secrets(&step.tool_id, Some(&tool.id), &mut step.options)?;
+        }
+    }
+    Ok(changed)
+}
+
+/// Replace secret placeholders in a tool's options with the real values from the vault
+pub fn resolve_tool_option_secrets(
+    tool_id: &str,
+    owner: Option<&str>,
+    options: &mut serde_json::Value,
+) -> Result<(), SecretsError> {
+    if let Some(obj) = options.as_object_mut() {
+        for (key, value) in obj.iter_mut() {
+            if value.as_str() != Some(SECRET_PLACEHOLDER) {
+                continue;
+            }
+            let mut secret = get_secret(NS_TOOL_OPTIONS, &tool_option_key(tool_id, owner, key))?;
+            // Chained steps saved before they were kept per chain
+            if secret.is_none() && owner.is_some() {
+                secret = get_secret(NS_TOOL_OPTIONS, &tool_option_key(tool_id, None, key))?;
+            }
+            *value = serde_json::Value::String(secret.unwrap_or_default());
+        }
+    }
+
+    Ok(())
+}
+
+/// Move secrets still stored in plain text in the config into the vault, and
+/// tool secrets still keyed by folder to their current key.
+/// Returns true if the config was changed and should be saved.
+pub fn migrate_plaintext_secrets(cfg: &mut AppConfig) -> Result<bool, SecretsError> {
+    let mut changed = false;
+
+    if let Some(ref mut auth) = cfg.auth {
+        if let Some(ref token) = auth.token {
+            set_secret(NS_AUTH, "token", token)?;
+            auth.token = None;
+            changed = true;
+        }
+        if let Some(ref password) = auth.password {
+            set_secret(NS_AUTH, "password", password)?;
+            auth.password = None;
+            changed = true;
+        }
+    }
+
+    for tool in cfg.tools.iter_mut() {
+        let folder = tool.main_folder();
+        if store_tool_option_secrets(&tool.id, None, &mut tool.options)? {
+            changed = true;
+        }
+        move_folder_keyed_secrets(&tool.id, None, folder, &tool.options)?;
+        for step in tool.chain.iter_mut() {
+            if store_tool_option_secrets(&step.tool_id, Some(&tool.id), &mut step.options)? {
+                changed = true;
+            }
+            move_folder_keyed_secrets(&step.tool_id, Some(&tool.id), folder, &step.options)?;
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Move secrets stored under their tool's folder, as they were before they
+/// were kept per tool, to their current key
+fn move_folder_keyed_secrets(
+    tool_id: &str,
+    owner: Option<&str>,
+    folder: Option<&str>,
+    options: &serde_json::Value,
+) -> Result<(), SecretsError> {
+    let (Some(folder), Some(obj)) = (folder, options.as_object()) else {
+        return Ok(());
+    };
+    for (key, value) in obj {
+        if value.as_str() != Some(SECRET_PLACEHOLDER) {
+            continue;
+        }
+        let old_key = format!("{}.{}@{}", tool_id, key, folder);
+        if let Some(secret) = get_secret(NS_TOOL_OPTIONS, &old_key)? {
+            set_secret(NS_TOOL_OPTIONS, &tool_option_key(tool_id, owner, key), &secret)?;
+            delete_secret(NS_TOOL_OPTIONS, &old_key)?;
+        }
+    }
+    Ok(())
+}
+
+/// Keep the secrets `tools` use with profile `profile`, so switching back to
+/// the profile brings them back
+pub fn save_profile_secrets(profile: &str, tools: &[ToolConfig]) -> Result<(), SecretsError> {
+    for key in tool_secret_keys(tools) {
+        let profile_key = profile_secret_key(profile, &key);
+        match get_secret(NS_TOOL_OPTIONS, &key)? {
+            Some(secret) => set_secret(NS_PROFILES, &profile_key, &secret)?,
+            None => delete_secret(NS_PROFILES, &profile_key)?,
+        }
+    }
+    Ok(())
+}
+
+/// Give `tools` the secrets kept with profile `profile`. Options the profile
+/// has no secret for are cleared rather than left with another profile's.
+pub fn load_profile_secrets(profile: &str, tools: &[ToolConfig]) -> Result<(), SecretsError> {
+    for key in tool_secret_keys(tools) {
+        match get_secret(NS_PROFILES, &profile_secret_key(profile, &key))? {
+            Some(secret) => set_secret(NS_TOOL_OPTIONS, &key, &secret)?,
+            None => delete_secret(NS_TOOL_OPTIONS, &key)?,
+        }
+    }
+    Ok(())
+}
+
+/// Forget the secrets kept with profile `profile`
+pub fn delete_profile_secrets(profile: &str, tools: &[ToolConfig]) -> Result<(), SecretsError> {
+    for key in tool_secret_keys(tools) {
+        delete_secret(NS_PROFILES, &profile_secret_key(profile, &key))?;
+    }
+    Ok(())
+}
+
+/// Vault keys of the secret options of `tools` and their chains
+fn tool_secret_keys(tools: &[ToolConfig]) -> Vec<String> {
+    let mut keys = Vec::new();
+    for tool in tools {
+        let steps = std::iter::once((&tool.id, &tool.options)).chain(tool.chain.iter().map(|s| (&s.tool_id, &s.options)));
+        for (tool_id, options) in steps {
+            let Some(obj) = options.as_object() else {
+                continue;
+            };
+            keys.extend(
+                obj.iter()
+                    .filter(|(_, value)| value.as_str() == Some(SECRET_PLACEHOLDER))
+                    .map(|(option, _)| tool_option_key(tool_id, Some(tool.id.as_str()), option)),
+            );
+        }
+    }
+    keys
+}
+
+fn profile_secret_key(profile: &str, key: &str) -> String {
+    format!("{}/{}", profile.trim().to_lowercase(), key)
+}
+
+fn tool_option_key(tool_id: &str, owner: Option<&str>, option: &str) -> String {
+    match owner {
+        Some(owner) if owner != tool_id => format!("{}.{}@{}", tool_id, option, owner),
+        _ => format!("{}.{}", tool_id, option),
+    }
+}
+
+fn entry_name(namespace: &str, key: &str) -> String {
+    format!("{}/{}", namespace, key)
+}
+
+fn keyring_entry(name: &str) -> Option<keyring::Entry> {
+    if !KEYRING_AVAILABLE.load(Ordering::Relaxed) {
+        return None;
+    }
+    match keyring::Entry::new(KEYRING_SERVICE, name) {
+        Ok(entry) => Some(entry),
+        Err(e) => {
+            keyring_failed(&e);
+            None
+        }
+    }
+}
+
+fn keyring_failed(e: &keyring::Error) {
+    // Platform problems mean there is no usable keyring - stop trying for this session
+    if matches!(e, keyring::Error::PlatformFailure(_) | keyring::Error::NoStorageAccess(_)) {
+        KEYRING_AVAILABLE.store(false, Ordering::Relaxed);
+    }
+    crate::add_log(&format!("Keyring unavailable, using encrypted file: {}", e));
+}
+
+// Encrypted file fallback
+
+fn vault_get(name: &str) -> Result<Option<String>, SecretsError> {
+    let _lock = VAULT_LOCK.lock().map_err(|_| SecretsError::Vault("Vault lock poisoned".to_string()))?;
+    Ok(read_vault()?.remove(name))
+}
+
+fn vault_set(name: &str, value: &str) -> Result<(), SecretsError> {
+    let _lock = VAULT_LOCK.lock().map_err(|_| SecretsError::Vault("Vault lock poisoned".to_string()))?;
+    let mut entries = read_vault()?;
+    entries.insert(name.to_string(), value.to_string());
+    write_vault(&entries)
+}
+
+fn vault_remove(name: &str) -> Result<(), SecretsError> {
+    let _lock = VAULT_LOCK.lock().map_err(|_| SecretsError::Vault("Vault lock poisoned".to_string()))?;
+    let mut entries = read_vault()?;
+    if entries.remove(name).is_some() {
+        write_vault(&entries)?;
+    }
+    Ok(())
+}
+
+fn vault_paths() -> Result<(PathBuf, PathBuf), SecretsError> {
+    let dir = config::get_user_config_dir()?;
+    Ok((dir.join(VAULT_FILE), dir.join(VAULT_KEY_FILE)))
+}
+
+/// Move secrets that older portable versions kept in the data folder into this
+/// PC's keyring or vault, and delete the vault and its key from the data folder.
+/// Returns how many secrets were moved.
+pub fn move_portable_vault() -> Result<usize, SecretsError> {
+    let Some(dir) = crate::portable::data_dir() else {
+        return Ok(0);
+    };
+    let (vault_path, key_path) = (dir.join(VAULT_FILE), dir.join(VAULT_KEY_FILE));
+    if !vault_path.exists() || !key_path.exists() {
+        return Ok(0);
+    }
+
+    let entries = read_vault_at(&vault_path, &key_path)?;
+    for (name, value) in &entries {
+        if let Some((namespace, key)) = name.split_once('/') {
+            set_secret(namespace, key, value)?;
+        }
+    }
+    fs::remove_file(&vault_path)?;
+    fs::remove_file(&key_path)?;
+    Ok(entries.len())
+}
+
+/// The vault's cipher, with a new key if there is none. A vault left without
+/// its key can't be decrypted with a new one, so it is moved aside first.
+fn vault_cipher(vault_path: &Path, key_path: &Path) -> Result<ChaCha20Poly1305, SecretsError> {
+    let key = if key_path.exists() {
+        let encoded = fs::read_to_string(key_path)?;
+        let bytes = BASE64
+            .decode(encoded.trim())
+            .map_err(|e| SecretsError::Vault(format!("Invalid vault key: {}", e)))?;
+        if bytes.len() != 32 {
+            return Err(SecretsError::Vault("Invalid vault key length".to_string()));
+        }
+        Key::clone_from_slice(&bytes)
+    } else {
+        if vault_path.exists() {
+            let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+            let orphaned = vault_path.with_file_name(format!("secrets.{}.orphaned.vault", stamp));
+            fs::rename(vault_path, &orphaned)?;
+            crate::add_log(&format!(
+                "The vault key is missing - moved the secrets it encrypted to {:?}, they have to be entered again",
+                orphaned
+            ));
+        }
+        let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        write_private(key_path, BASE64.encode(key).as_bytes())?;
+        key
+    };
+
+    Ok(ChaCha20Poly1305::new(&key))
+}
+
+fn read_vault() -> Result<HashMap<String, String>, SecretsError> {
+    let (vault_path, key_path) = vault_paths()?;
+    if !vault_path.exists() {
+        return Ok(HashMap::new());
+    }
+    read_vault_at(&vault_path, &key_path)
+}
+
+fn read_vault_at(vault_path: &Path, key_path: &Path) -> Result<HashMap<String, String>, SecretsError> {
+    let cipher = vault_cipher(vault_path, key_path)?;
+    if !vault_path.exists() {
+        return Ok(HashMap::new());
+    }
+    let data = BASE64
+        .decode(fs::read_to_string(vault_path)?.trim())
+        .map_err(|e| SecretsError::Vault(format!("Invalid vault file: {}", e)))?;
+    if data.len() < NONCE_LEN {
+        return Err(SecretsError::Vault("Vault file is truncated".to_string()));
+    }
+
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| SecretsError::Vault("Could not decrypt vault file".to_string()))?;
+
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+fn write_vault(entries: &HashMap<String, String>) -> Result<(), SecretsError> {
+    let (vault_path, key_path) = vault_paths()?;
+    let cipher = vault_cipher(&vault_path, &key_path)?;
+
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let plaintext = serde_json::to_vec(entries)?;
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_slice())
+        .map_err(|_| SecretsError::Vault("Could not encrypt vault file".to_string()))?;
+
+    let mut data = nonce.to_vec();
+    data.extend(ciphertext);
+    write_private(&vault_path, BASE64.encode(data).as_bytes())?;
+    Ok(())
+}
+
+/// Write a file readable only by the current user
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), std::io::Error> {
+    fs::write(path, contents)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,192 @@
+// Self-test module for PDF.dk Desktop
+// Runs a tiny bundled PDF through every enabled tool at night, so a broken tool
+// is noticed before the morning shift rather than by the first real file
+
+use crate::api::{ApiError, PdfDkClient};
+use crate::config::{SelfTestSettings, ToolConfig};
+use crate::watcher::{self, FileEvent, PipelineStage};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often to check whether the nightly self-test is due
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Latest a missed run may still start after its time (minutes), e.g. after a
+/// reboot; later it waits for the next night instead of running into the workday
+const MAX_START_DELAY_MINUTES: i64 = 60;
+
+const SAMPLE_PDF: &[u8] = include_bytes!("sample.pdf");
+const TEST_DIR: &str = "pdfdk-self-test";
+
+static LAST_REPORT: Lazy<Mutex<Option<SelfTestReport>>> = Lazy::new(|| Mutex::new(None));
+// Day the scheduled run last started, so it runs once per night
+static LAST_RUN: Lazy<Mutex<Option<NaiveDate>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Passed,
+    Failed,
+    /// The tool doesn't take PDFs, so the sample can't test it
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolResult {
+    pub tool_id: String,
+    pub outcome: Outcome,
+    pub error: Option<String>,
+    pub seconds: u64,
+}
+
+/// Results of one self-test run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfTestReport {
+    pub started_at: String,
+    pub finished_at: String,
+    /// The server was told these were test jobs, so they didn't count against the quota
+    pub test_jobs: bool,
+    pub results: Vec<ToolResult>,
+}
+
+impl SelfTestReport {
+    pub fn failed(&self) -> Vec<&ToolResult> {
+        self.results.iter().filter(|r| r.outcome == Outcome::Failed).collect()
+    }
+}
+
+/// Result of the latest self-test, if one has run since startup
+pub fn last_report() -> Option<SelfTestReport> {
+    LAST_REPORT.lock().ok().and_then(|report| report.clone())
+}
+
+/// Check whether the nightly run is due at `now` and, if so, mark it as started.
+/// A run not started within MAX_START_DELAY_MINUTES of its time is skipped for the day.
+pub fn take_due(settings: &SelfTestSettings, now: NaiveDateTime) -> bool {
+    if !settings.enabled {
+        return false;
+    }
+    let Ok(at) = NaiveTime::parse_from_str(&settings.time, "%H:%M") else {
+        return false;
+    };
+    let Ok(mut last_run) = LAST_RUN.lock() else {
+        return false;
+    };
+    let late = now.time().signed_duration_since(at);
+    if late < chrono::Duration::zero()
+        || late > chrono::Duration::minutes(MAX_START_DELAY_MINUTES)
+        || *last_run == Some(now.date())
+    {
+        return false;
+    }
+    *last_run = Some(now.date());
+    true
+}
+
+/// Run the sample through each enabled tool, one at a time.
+/// With `test_jobs` the jobs are flagged so the server doesn't bill them.
+pub async fn run(tools: &[ToolConfig], token: Option<String>, test_jobs: bool) -> SelfTestReport {
+    let started_at = chrono::Local::now().to_rfc3339();
+    let client = PdfDkClient::new(token);
+    let mut results = Vec::new();
+
+    // Each tool runs with the settings of its first folder
+    let tools: Vec<ToolConfig> = tools
+        .iter()
+        .filter(|t| t.enabled)
+        .filter_map(|t| t.watch_folders().into_iter().next())
+        .collect();
+    for tool in &tools {
+        let started = Instant::now();
+        let takes_pdf = crate::config::get_tool_definition(&tool.id)
+            .is_some_and(|t| t.input_extensions.iter().any(|e| e == "pdf"));
+        let (outcome, error) = if !takes_pdf {
+            (Outcome::Skipped, None)
+        } else {
+            match test_tool(&client, tool, test_jobs).await {
+                Ok(()) => (Outcome::Passed, None),
+                Err(e) => (Outcome::Failed, Some(e.to_string())),
+            }
+        };
+        crate::add_log(&format!("Self-test of {}: {:?}", tool.id, outcome));
+        results.push(ToolResult {
+            tool_id: tool.id.clone(),
+            outcome,
+            error,
+            seconds: started.elapsed().as_secs(),
+        });
+    }
+
+    let report = SelfTestReport {
+        started_at,
+        finished_at: chrono::Local::now().to_rfc3339(),
+        test_jobs,
+        results,
+    };
+    if let Ok(mut last) = LAST_REPORT.lock() {
+        *last = Some(report.clone());
+    }
+    report
+}
+
+/// Upload the sample with the tool's own options and check a result comes back
+async fn test_tool(client: &PdfDkClient, tool: &ToolConfig, test_jobs: bool) -> Result<(), ApiError> {
+    let dir = std::env::temp_dir().join(TEST_DIR).join(&tool.id);
+    let result = test_tool_in(client, tool, test_jobs, &dir).await;
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    result
+}
+
+async fn test_tool_in(client: &PdfDkClient, tool: &ToolConfig, test_jobs: bool, dir: &Path) -> Result<(), ApiError> {
+    tokio::fs::create_dir_all(dir).await?;
+    let input = write_sample(dir, "sample.pdf").await?;
+
+    // Collecting and comparing folders get two copies, sent as one job
+    let stage = if watcher::is_multi_input(tool) {
+        PipelineStage::Merge(vec![input.clone(), write_sample(dir, "sample-2.pdf").await?])
+    } else if watcher::is_paired_input(tool) {
+        PipelineStage::Compare { old: write_sample(dir, "sample-2.pdf").await?, new: input.clone() }
+    } else {
+        PipelineStage::Process
+    };
+    let event = FileEvent {
+        path: input,
+        tool_id: tool.id.clone(),
+        tool_config: tool.clone(),
+        stage,
+    };
+
+    let (mut options, attachments) = watcher::job_options(&event)?;
+    if test_jobs {
+        if let Some(obj) = options.as_object_mut() {
+            obj.insert("test_job".to_string(), serde_json::Value::Bool(true));
+        }
+    }
+
+    let endpoint = watcher::tool_endpoint(&tool.id);
+    let job_uuid = match &event.stage {
+        PipelineStage::Merge(_) | PipelineStage::Compare { .. } => {
+            client.process_files(&event.input_paths(), &endpoint, options).await?
+        }
+        _ => client.process_file(&event.path, &attachments, &endpoint, options).await?,
+    };
+    client.poll_job(&job_uuid).await?;
+
+    let output = dir.join("result");
+    client.download_result(&job_uuid, &output).await?;
+    if tokio::fs::metadata(&output).await?.len() == 0 {
+        return Err(ApiError::JobFailed("the result was empty".to_string()));
+    }
+    Ok(())
+}
+
+async fn write_sample(dir: &Path, name: &str) -> Result<PathBuf, std::io::Error> {
+    let path = dir.join(name);
+    tokio::fs::write(&path, SAMPLE_PDF).await?;
+    Ok(path)
+}
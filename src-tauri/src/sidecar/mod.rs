@@ -0,0 +1,65 @@
+// Sidecar module for PDF.dk Desktop
+// Writes the metadata of the job behind an output to `<output>.pdfdk.json`
+// next to it, for downstream automation
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const SIDECAR_EXTENSION: &str = "pdfdk.json";
+pub const SIDECAR_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sidecar {
+    pub version: u32,
+    /// The app's id of the job, as in the job history
+    pub job_id: String,
+    /// pdf.dk's id of the job that produced the output
+    pub job_uuid: String,
+    pub tool_id: String,
+    /// Every tool the input went through, in order, for chained folders
+    pub tools: Vec<String>,
+    /// The tool's options, with secret options left out
+    pub options: serde_json::Value,
+    pub input_files: Vec<String>,
+    pub input_size_bytes: u64,
+    pub output_file: String,
+    /// None for a folder of unpacked results
+    pub output_size_bytes: Option<u64>,
+    pub queued_at: Option<String>,
+    pub completed_at: String,
+}
+
+/// Where the sidecar of an output goes
+pub fn path_for(output: &Path) -> PathBuf {
+    let mut name = output.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(SIDECAR_EXTENSION);
+    output.with_file_name(name)
+}
+
+/// Write the sidecar of an output. Returns where it went.
+pub async fn write(output: &Path, mut sidecar: Sidecar) -> Result<PathBuf, std::io::Error> {
+    if let Some(options) = sidecar.options.as_object_mut() {
+        options.retain(|_, value| value.as_str() != Some(crate::secrets::SECRET_PLACEHOLDER));
+    }
+    let path = path_for(output);
+    let json = serde_json::to_vec_pretty(&sidecar).map_err(std::io::Error::other)?;
+    tokio::fs::write(&path, json).await?;
+    Ok(path)
+}
+
+/// Move the sidecar of `from` to go with `to`, e.g. when a reviewed output is
+/// delivered, pointing it at the new location
+pub async fn move_along(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+    let source = path_for(from);
+    if !source.exists() {
+        return Ok(());
+    }
+    let mut sidecar: Sidecar =
+        serde_json::from_slice(&tokio::fs::read(&source).await?).map_err(std::io::Error::other)?;
+    sidecar.output_file = to.to_string_lossy().into_owned();
+    sidecar.output_size_bytes = tokio::fs::metadata(to).await.ok().filter(|m| m.is_file()).map(|m| m.len());
+    write(to, sidecar).await?;
+    tokio::fs::remove_file(&source).await
+}
@@ -0,0 +1,112 @@
+// Staging module for PDF.dk Desktop
+// Snapshots job inputs when a job starts, so edits during the upload can't reach the server
+
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const STAGING_DIR: &str = "pdfdk-staging";
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Copies of a job's inputs, removed when dropped
+pub struct Snapshot {
+    dir: PathBuf,
+    /// (original, copy, SHA-256 of the copy)
+    files: Vec<(PathBuf, PathBuf, String)>,
+}
+
+impl Snapshot {
+    /// The copies, in the order of the inputs
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.files.iter().map(|(_, copy, _)| copy.clone()).collect()
+    }
+
+    /// Originals that no longer match their snapshot, or are gone
+    pub async fn changed_sources(&self) -> Vec<PathBuf> {
+        let files: Vec<(PathBuf, String)> = self
+            .files
+            .iter()
+            .map(|(original, _, hash)| (original.clone(), hash.clone()))
+            .collect();
+        crate::local_work::run(move || {
+            files
+                .into_iter()
+                .filter(|(original, hash)| crate::receipts::sha256_file(original).ok().as_ref() != Some(hash))
+                .map(|(original, _)| original)
+                .collect()
+        })
+        .await
+        .unwrap_or_default()
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.dir) {
+            tracing::warn!("Could not remove input snapshot {:?}: {}", self.dir, e);
+        }
+    }
+}
+
+/// Turn snapshots for new jobs on or off (general setting `snapshotInputs`)
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Copy the inputs of job `job_id` into staging, hashing them on the way.
+/// A copy rather than a hard link, as a link would still see edits saved in place.
+pub async fn snapshot(job_id: &str, inputs: &[PathBuf]) -> Result<Snapshot, std::io::Error> {
+    let dir = std::env::temp_dir().join(STAGING_DIR).join(job_id);
+    let inputs = inputs.to_vec();
+    let target = dir.clone();
+    let files = crate::local_work::run(move || -> Result<Vec<(PathBuf, PathBuf, String)>, std::io::Error> {
+        fs::create_dir_all(&target)?;
+        inputs
+            .iter()
+            .enumerate()
+            .map(|(i, original)| {
+                // Numbered, as merge inputs from different folders may share a name
+                let copy = target
+                    .join(i.to_string())
+                    .join(original.file_name().unwrap_or_default());
+                fs::create_dir_all(copy.parent().unwrap_or(&target))?;
+                let hash = copy_hashed(original, &copy)?;
+                Ok((original.clone(), copy, hash))
+            })
+            .collect()
+    })
+    .await
+    .map_err(std::io::Error::other)?;
+
+    match files {
+        Ok(files) => Ok(Snapshot { dir, files }),
+        Err(e) => {
+            let _ = fs::remove_dir_all(&dir);
+            Err(e)
+        }
+    }
+}
+
+/// Copy a file and return the SHA-256 of what was copied
+fn copy_hashed(from: &Path, to: &Path) -> Result<String, std::io::Error> {
+    let mut source = fs::File::open(from)?;
+    let mut target = fs::File::create(to)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = source.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        target.write_all(&buffer[..read])?;
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,310 @@
+// Statistics module for PDF.dk Desktop
+// Keeps running totals of what the tools have done - jobs, failures, durations
+// and bytes transferred per tool and day, and how much space compression saved
+
+use crate::config;
+use crate::processor::{Job, JobStatus};
+use chrono::{Datelike, Months, NaiveDate};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::Mutex;
+
+const STATS_FILE: &str = "statistics.json";
+/// Tool whose jobs count towards the compression savings, also as the last step of a chain
+pub const COMPRESS_TOOL_ID: &str = "compress";
+/// Days of per-tool totals kept; older days are forgotten
+const MAX_DAYS: i64 = 400;
+/// Buckets returned for each period, ending with the current one
+const DAILY_BUCKETS: u32 = 30;
+const WEEKLY_BUCKETS: u32 = 12;
+const MONTHLY_BUCKETS: u32 = 12;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredStats {
+    /// "2024-03" -> compression totals of that month
+    #[serde(default)]
+    compression: BTreeMap<String, CompressionTotals>,
+    /// "2024-03-31" -> tool id -> totals of that day
+    #[serde(default)]
+    days: BTreeMap<String, BTreeMap<String, ToolTotals>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CompressionTotals {
+    jobs: u64,
+    input_bytes: u64,
+    output_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ToolTotals {
+    completed: u64,
+    failed: u64,
+    /// Summed over the completed jobs that know when they started
+    duration_secs: u64,
+    timed_jobs: u64,
+    bytes_uploaded: u64,
+    bytes_downloaded: u64,
+}
+
+impl ToolTotals {
+    fn add(&mut self, other: &ToolTotals) {
+        self.completed += other.completed;
+        self.failed += other.failed;
+        self.duration_secs += other.duration_secs;
+        self.timed_jobs += other.timed_jobs;
+        self.bytes_uploaded += other.bytes_uploaded;
+        self.bytes_downloaded += other.bytes_downloaded;
+    }
+}
+
+/// How statistics are rolled up
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsPeriod {
+    /// The last 30 days
+    Daily,
+    /// The last 12 ISO weeks
+    Weekly,
+    /// The last 12 months
+    #[default]
+    Monthly,
+}
+
+/// Space saved by compressing
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompressionSavings {
+    pub jobs: u64,
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+    /// Negative if the outputs came out larger
+    pub saved_bytes: i64,
+    pub saved_percent: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolStatistics {
+    pub tool_id: String,
+    pub completed: u64,
+    pub failed: u64,
+    /// Share of finished jobs that completed, 0 to 1. None without jobs.
+    pub success_rate: Option<f64>,
+    pub average_duration_secs: Option<f64>,
+    pub bytes_uploaded: u64,
+    pub bytes_downloaded: u64,
+}
+
+/// One day, week or month
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatisticsBucket {
+    /// "2024-03-31", "2024-W13" or "2024-03"
+    pub label: String,
+    pub tools: Vec<ToolStatistics>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Statistics {
+    pub period: StatsPeriod,
+    /// Oldest first, including buckets without jobs
+    pub buckets: Vec<StatisticsBucket>,
+    /// Totals per tool over all the buckets
+    pub tools: Vec<ToolStatistics>,
+    pub compression_this_month: CompressionSavings,
+    pub compression_total: CompressionSavings,
+}
+
+static STATS: Lazy<Mutex<StoredStats>> = Lazy::new(|| Mutex::new(load()));
+
+/// Count a finished job. `last_step` is the tool that made the output, the
+/// last of the folder's chain. Returns how many bytes the job saved, for jobs
+/// that ran and ended with the compress tool.
+pub fn record(job: &Job, last_step: &str) -> Option<i64> {
+    let Ok(mut stats) = STATS.lock() else {
+        return None;
+    };
+    let today = chrono::Local::now().date_naive();
+    let totals = stats
+        .days
+        .entry(today.format("%Y-%m-%d").to_string())
+        .or_default()
+        .entry(job.tool_id.clone())
+        .or_default();
+    match job.status {
+        JobStatus::Completed => {
+            totals.completed += 1;
+            if job.skipped.is_none() {
+                totals.bytes_uploaded += job.size_bytes.unwrap_or(0);
+                totals.bytes_downloaded += job.output_size_bytes.unwrap_or(0);
+            }
+            if let (Some(started), Some(completed)) = (job.started_at, job.completed_at) {
+                totals.duration_secs += completed.saturating_sub(started);
+                totals.timed_jobs += 1;
+            }
+        }
+        JobStatus::Failed => totals.failed += 1,
+        _ => return None,
+    }
+
+    let oldest = (today - chrono::Duration::days(MAX_DAYS)).format("%Y-%m-%d").to_string();
+    stats.days.retain(|day, _| *day >= oldest);
+
+    let saved = match (job.size_bytes, job.output_size_bytes) {
+        (Some(input), Some(output))
+            if last_step == COMPRESS_TOOL_ID && job.status == JobStatus::Completed && job.skipped.is_none() =>
+        {
+            let month = stats.compression.entry(today.format("%Y-%m").to_string()).or_default();
+            month.jobs += 1;
+            month.input_bytes += input;
+            month.output_bytes += output;
+            Some(input as i64 - output as i64)
+        }
+        _ => None,
+    };
+    save(&stats);
+    saved
+}
+
+/// Per-tool totals rolled up by `period`, and the compression savings
+pub fn statistics(period: StatsPeriod) -> Statistics {
+    let stats = STATS.lock().map(|s| s.clone()).unwrap_or_default();
+    let today = chrono::Local::now().date_naive();
+
+    let mut buckets: Vec<(String, BTreeMap<String, ToolTotals>)> = Vec::new();
+    let mut day = first_day(period, today);
+    while day <= today {
+        let label = label(period, day);
+        if buckets.last().map(|(last, _)| last) != Some(&label) {
+            buckets.push((label, BTreeMap::new()));
+        }
+        let tools = stats.days.get(&day.format("%Y-%m-%d").to_string());
+        if let (Some((_, bucket)), Some(tools)) = (buckets.last_mut(), tools) {
+            for (tool_id, totals) in tools {
+                bucket.entry(tool_id.clone()).or_default().add(totals);
+            }
+        }
+        let Some(next) = day.succ_opt() else {
+            break;
+        };
+        day = next;
+    }
+
+    let mut overall: BTreeMap<String, ToolTotals> = BTreeMap::new();
+    for (_, tools) in &buckets {
+        for (tool_id, totals) in tools {
+            overall.entry(tool_id.clone()).or_default().add(totals);
+        }
+    }
+
+    let this_month = stats.compression.get(&today.format("%Y-%m").to_string()).copied().unwrap_or_default();
+    let total = stats
+        .compression
+        .values()
+        .fold(CompressionTotals::default(), |sum, month| CompressionTotals {
+            jobs: sum.jobs + month.jobs,
+            input_bytes: sum.input_bytes + month.input_bytes,
+            output_bytes: sum.output_bytes + month.output_bytes,
+        });
+
+    Statistics {
+        period,
+        buckets: buckets
+            .into_iter()
+            .map(|(label, tools)| StatisticsBucket {
+                label,
+                tools: tool_statistics(&tools),
+            })
+            .collect(),
+        tools: tool_statistics(&overall),
+        compression_this_month: savings(&this_month),
+        compression_total: savings(&total),
+    }
+}
+
+/// First day of the oldest bucket returned for `period`
+fn first_day(period: StatsPeriod, today: NaiveDate) -> NaiveDate {
+    match period {
+        StatsPeriod::Daily => today - chrono::Duration::days(i64::from(DAILY_BUCKETS) - 1),
+        StatsPeriod::Weekly => {
+            let monday = today - chrono::Duration::days(i64::from(today.weekday().num_days_from_monday()));
+            monday - chrono::Duration::weeks(i64::from(WEEKLY_BUCKETS) - 1)
+        }
+        StatsPeriod::Monthly => today
+            .with_day(1)
+            .and_then(|first| first.checked_sub_months(Months::new(MONTHLY_BUCKETS - 1)))
+            .unwrap_or(today),
+    }
+}
+
+fn label(period: StatsPeriod, day: NaiveDate) -> String {
+    match period {
+        StatsPeriod::Daily => day.format("%Y-%m-%d").to_string(),
+        StatsPeriod::Weekly => {
+            let week = day.iso_week();
+            format!("{}-W{:02}", week.year(), week.week())
+        }
+        StatsPeriod::Monthly => day.format("%Y-%m").to_string(),
+    }
+}
+
+fn tool_statistics(tools: &BTreeMap<String, ToolTotals>) -> Vec<ToolStatistics> {
+    tools
+        .iter()
+        .map(|(tool_id, totals)| {
+            let finished = totals.completed + totals.failed;
+            ToolStatistics {
+                tool_id: tool_id.clone(),
+                completed: totals.completed,
+                failed: totals.failed,
+                success_rate: (finished > 0).then(|| totals.completed as f64 / finished as f64),
+                average_duration_secs: (totals.timed_jobs > 0)
+                    .then(|| totals.duration_secs as f64 / totals.timed_jobs as f64),
+                bytes_uploaded: totals.bytes_uploaded,
+                bytes_downloaded: totals.bytes_downloaded,
+            }
+        })
+        .collect()
+}
+
+fn savings(totals: &CompressionTotals) -> CompressionSavings {
+    let saved_bytes = totals.input_bytes as i64 - totals.output_bytes as i64;
+    CompressionSavings {
+        jobs: totals.jobs,
+        input_bytes: totals.input_bytes,
+        output_bytes: totals.output_bytes,
+        saved_bytes,
+        saved_percent: if totals.input_bytes > 0 {
+            saved_bytes as f64 * 100.0 / totals.input_bytes as f64
+        } else {
+            0.0
+        },
+    }
+}
+
+fn load() -> StoredStats {
+    config::get_config_dir()
+        .ok()
+        .and_then(|dir| fs::read_to_string(dir.join(STATS_FILE)).ok())
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save(stats: &StoredStats) {
+    let result = config::get_config_dir()
+        .map_err(|e| e.to_string())
+        .and_then(|dir| {
+            let text = serde_json::to_string(stats).map_err(|e| e.to_string())?;
+            fs::write(dir.join(STATS_FILE), text).map_err(|e| e.to_string())
+        });
+    if let Err(e) = result {
+        tracing::warn!("Could not save statistics: {}", e);
+    }
+}
@@ -2,16 +2,61 @@
 // Watches folders for new PDF files and triggers processing
 
 use crate::api::PdfDkClient;
-use crate::config::{OutputMode, ToolConfig};
-use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use crate::config::{
+    ConflictPolicy, DuplicatePolicy, GeneralSettings, OriginalsHandling, OutputDecoration, OutputMode, ToolConfig, WatchMode, REPAIR_TOOL_ID,
+};
+use crate::processor::{JobRef, JobTimings};
+use crate::routing::{self, Routes};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Config, Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 use thiserror::Error;
-use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio::sync::{mpsc, RwLock};
 use tracing::{error, info, warn};
 
+/// Creating this file in a merge folder merges the collected files
+pub const MERGE_SENTINEL: &str = "merge.now";
+
+// Quiet time before a file counts as written, and how often waiting files are checked
+static DEBOUNCE_MS: AtomicU64 = AtomicU64::new(crate::config::DEFAULT_DEBOUNCE_SECONDS * 1000);
+static POLL_INTERVAL_MS: AtomicU64 = AtomicU64::new(crate::config::DEFAULT_POLL_INTERVAL_MS);
+// Checks in a row a file's size must stay the same before it is processed
+static STABLE_SIZE_CHECKS: AtomicU32 = AtomicU32::new(crate::config::DEFAULT_STABLE_SIZE_CHECKS);
+// Queue files already in a folder when it is added, without asking first
+static PROCESS_EXISTING: AtomicBool = AtomicBool::new(false);
+
+// Files and folders the app wrote, so their events aren't taken for new inputs
+static GENERATED_OUTPUTS: Lazy<Mutex<HashMap<PathBuf, Instant>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+/// How long a written output is remembered - long enough for its last events
+const GENERATED_OUTPUT_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Shortest poll interval accepted, so a typo can't make the watcher spin
+const MIN_POLL_INTERVAL_MS: u64 = 100;
+
+/// Temporary folder for the intermediate outputs of tool chains
+const CHAIN_DIR: &str = "pdfdk-chain";
+
+/// NTFS alternate data stream the Explorer overlay helper looks for
+#[cfg(target_os = "windows")]
+const OVERLAY_STREAM: &str = "pdfdk.overlay";
+
+// Merge and compare options handled by the app rather than sent to the API
+const LOCAL_OPTIONS: [&str; 5] = ["idleTimeoutSecs", "order", "pairing", "oldSuffix", "newSuffix"];
+
+// Subfolders next to each input for its outputs and the processed original
+const PROCESSED_FOLDER: &str = "Processed";
+pub const ORIGINALS_FOLDER: &str = "Originals";
+
+// Subfolders holding the old and new versions when compare pairs by folder
+const COMPARE_OLD_FOLDER: &str = "A";
+const COMPARE_NEW_FOLDER: &str = "B";
+
 #[derive(Error, Debug)]
 pub enum WatcherError {
     #[error("Notify error: {0}")]
@@ -20,6 +65,20 @@ pub enum WatcherError {
     Io(#[from] std::io::Error),
     #[error("Channel error")]
     ChannelError,
+    #[error("No permission to access folder: {0}")]
+    PermissionDenied(PathBuf),
+}
+
+/// Whether the app can read a watched folder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FolderAccess {
+    Granted,
+    /// Blocked by file permissions or, on macOS, by privacy settings
+    Denied,
+    Missing,
+    /// Other errors, e.g. a disconnected network volume
+    Unavailable,
 }
 
 #[derive(Debug, Clone)]
@@ -27,81 +86,593 @@ pub struct FileEvent {
     pub path: PathBuf,
     pub tool_id: String,
     pub tool_config: ToolConfig,
+    pub stage: PipelineStage,
+}
+
+/// Which pipeline step a file event triggers
+#[derive(Debug, Clone, PartialEq)]
+pub enum PipelineStage {
+    /// New input file - upload and process it
+    Process,
+    /// Reviewed output moved into the approved folder - deliver it
+    Deliver,
+    /// Files collected in a merge folder - upload them together as one job
+    Merge(Vec<PathBuf>),
+    /// Old and new version of a document matched in a compare folder
+    Compare { old: PathBuf, new: PathBuf },
+}
+
+/// What processing a file event left behind
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobOutput {
+    /// A new output, for the output actions (mail, print, hooks, destinations) to handle
+    Written(PathBuf),
+    /// Nothing new was written; the output of an earlier run is kept
+    Kept(PathBuf),
+}
+
+impl FileEvent {
+    /// Input files of the job (several for merge jobs)
+    pub fn input_paths(&self) -> Vec<PathBuf> {
+        match &self.stage {
+            PipelineStage::Merge(inputs) => inputs.clone(),
+            PipelineStage::Compare { old, new } => vec![old.clone(), new.clone()],
+            _ => vec![self.path.clone()],
+        }
+    }
+}
+
+/// Why a detected file has not been queued yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WaitReason {
+    /// Still open in another application (Acrobat, scanner software...)
+    Locked,
+    /// Still growing, e.g. while being copied from a network share
+    Growing,
+    /// A cloud placeholder (OneDrive, iCloud, Dropbox) whose content is still downloading
+    Downloading,
+}
+
+/// A detected file that is not ready to be queued yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WaitingFile {
+    pub path: String,
+    pub reason: WaitReason,
+    pub since: String,
+    pub attempts: u32,
+}
+
+/// Files that were already in a folder when it started being watched.
+/// They use quota, so they wait for the user to confirm.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderBacklog {
+    pub tool_id: String,
+    pub folder: String,
+    pub files: Vec<String>,
+}
+
+/// A watched folder that went missing, or came back and is watched again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderWatchChange {
+    pub tool_id: String,
+    pub folder: String,
+    pub restored: bool,
+    /// The folder is on a removable drive that isn't connected
+    pub offline: bool,
+    pub since: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchState {
+    Active,
+    /// On a removable drive that isn't connected; watched again once it is
+    Offline,
+    /// Deleted or unreachable; watched again once it is back
+    Missing,
+}
+
+/// How a watched folder is doing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchedFolderStatus {
+    pub tool_id: String,
+    pub folder: String,
+    pub state: WatchState,
+    /// Since when the folder is offline or missing
+    pub since: Option<String>,
+    pub removable: bool,
+    pub polled: bool,
+}
+
+/// How reconcile changed the watched folders
+#[derive(Debug, Default)]
+pub struct Reconciled {
+    /// Folders no longer configured, not watched anymore
+    pub removed: Vec<PathBuf>,
+    /// Folders watched now, with the files already in them or why watching failed
+    pub added: Vec<(ToolConfig, Result<Option<FolderBacklog>, WatcherError>)>,
+    /// Folders watched again with changed settings
+    pub changed: Vec<(ToolConfig, Result<Option<FolderBacklog>, WatcherError>)>,
+}
+
+/// Whether the watcher is still picking up changes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatcherStatus {
+    pub running: bool,
+    /// The last health check found the watcher working
+    pub healthy: bool,
+    pub processor_running: bool,
+    /// The probe folder is watched, so a stalled system watcher is noticed
+    pub probing: bool,
+    pub last_healthy_at: Option<String>,
+    pub restarts: u32,
+    pub last_restart_at: Option<String>,
+    pub last_failure: Option<String>,
+}
+
+/// What the supervisor knows about the watcher
+struct WatcherHealth {
+    healthy: bool,
+    /// Number of the probe file written last, until its event arrives
+    probe: Option<u64>,
+    last_probe: u64,
+    last_healthy: SystemTime,
+    restarts: u32,
+    last_restart: Option<String>,
+    last_failure: Option<String>,
+}
+
+/// What the watchdog knows about a watched folder
+struct WatchRoot {
+    /// Tells a recreated folder from the one being watched
+    identity: Option<u64>,
+    /// When the folder went missing, while it is missing
+    missing_since: Option<String>,
+}
+
+/// How often the watchdog checks that watched folders still exist
+pub const WATCHDOG_INTERVAL: Duration = Duration::from_secs(10);
+/// A watchdog check this much later than due means the computer was asleep
+pub const SLEEP_THRESHOLD: Duration = Duration::from_secs(30);
+/// How often the supervisor writes a probe file and checks the last one was seen
+pub const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+// Folder in the config dir the supervisor writes its probe files to
+const PROBE_DIR: &str = "watch-probe";
+// Hidden, so a probe is never taken for an input
+const PROBE_PREFIX: &str = ".pdfdk-probe-";
+// Highest probe number the event processor has seen
+static PROBE_SEEN: AtomicU64 = AtomicU64::new(0);
+
+/// Result of checking whether a file can be picked up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileReadiness {
+    Ready,
+    Locked,
+    Unreadable,
+    /// Only a placeholder of a file in a cloud-synced folder is on disk
+    Placeholder,
+}
+
+// Back-off while a file is locked: 2, 4, 8... seconds, at most a minute
+const LOCK_RETRY_BASE: Duration = Duration::from_secs(2);
+const LOCK_RETRY_MAX: Duration = Duration::from_secs(60);
+/// How often a cloud placeholder is checked for its downloaded content
+const PLACEHOLDER_RETRY: Duration = Duration::from_secs(5);
+/// Checks before giving up on a placeholder that never downloads (30 minutes)
+const MAX_PLACEHOLDER_CHECKS: u32 = 360;
+
+type WaitingFiles = Arc<std::sync::Mutex<HashMap<PathBuf, WaitingFile>>>;
+
+/// A detected file being watched until it is fully written
+struct PendingFile {
+    /// Last change, or when a locked file is checked again
+    since: Instant,
+    /// Size at the previous check
+    size: Option<u64>,
+    /// Consecutive checks the size hasn't changed
+    stable_checks: u32,
+}
+
+impl PendingFile {
+    fn new(since: Instant) -> Self {
+        Self { since, size: None, stable_checks: 0 }
+    }
+}
+
+type PendingFiles = HashMap<PathBuf, PendingFile>;
+
+/// Files waiting in a merge folder
+#[derive(Debug)]
+struct MergeCollection {
+    tool_config: ToolConfig,
+    files: Vec<PathBuf>,
+    last_added: Instant,
 }
 
+/// Which version of a document a file in a compare folder is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PairSide {
+    Old,
+    New,
+}
+
+/// A document in a compare folder waiting for its other version
+#[derive(Debug, Default)]
+struct PendingPair {
+    old: Option<PathBuf>,
+    new: Option<PathBuf>,
+}
+
+// Keyed by watched folder and the document name the versions share
+type PendingPairs = HashMap<(PathBuf, String), PendingPair>;
+
 /// Folder watcher that monitors multiple folders for new PDF files
 pub struct FolderWatcher {
     watcher: RecommendedWatcher,
+    // Folders scanned at an interval instead, e.g. network shares, keyed by folder
+    pollers: HashMap<PathBuf, PollWatcher>,
+    notify_sender: mpsc::UnboundedSender<Event>,
     watched_folders: Arc<RwLock<HashMap<PathBuf, ToolConfig>>>,
-    #[allow(dead_code)]
-    event_sender: broadcast::Sender<FileEvent>,
+    // Approved folders of tools with a review stage, keyed by approved folder path
+    approved_folders: Arc<RwLock<HashMap<PathBuf, ToolConfig>>>,
+    // Inbox folders whose files are routed to a tool by name
+    routes: Arc<RwLock<Routes>>,
+    // Asks the event processor to merge a folder's collected files now
+    merge_sender: mpsc::UnboundedSender<PathBuf>,
+    // Hands files found in a folder's backlog to the event processor
+    backlog_sender: mpsc::UnboundedSender<PathBuf>,
+    // Backlogs waiting for confirmation, keyed by folder
+    backlogs: HashMap<PathBuf, FolderBacklog>,
+    // Watched folders as the watchdog last saw them
+    roots: HashMap<PathBuf, WatchRoot>,
+    waiting_files: WaitingFiles,
+    event_sender: mpsc::UnboundedSender<FileEvent>,
+    // Files removed or moved away, whose queued jobs are cancelled
+    removed_sender: mpsc::UnboundedSender<PathBuf>,
+    // The event processor task, finished early only if it panicked
+    processor: Option<tokio::task::JoinHandle<()>>,
+    // Watched folder the probe files are written to
+    probe_dir: Option<PathBuf>,
+    health: WatcherHealth,
 }
 
 impl FolderWatcher {
-    pub fn new() -> Result<(Self, broadcast::Receiver<FileEvent>), WatcherError> {
-        let (event_tx, event_rx) = broadcast::channel(100);
-        let (notify_tx, mut notify_rx) = mpsc::channel(100);
+    /// Create the watcher. Its channels are unbounded, so a burst of
+    /// thousands of files can't push events out of them.
+    /// Returns it with the file events it reports and the files removed from watched folders.
+    #[allow(clippy::type_complexity)]
+    pub fn new() -> Result<(Self, mpsc::UnboundedReceiver<FileEvent>, mpsc::UnboundedReceiver<PathBuf>), WatcherError> {
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (removed_tx, removed_rx) = mpsc::unbounded_channel();
+        let (notify_tx, notify_rx) = mpsc::unbounded_channel();
+        let (merge_tx, merge_rx) = mpsc::unbounded_channel();
+        let (backlog_tx, backlog_rx) = mpsc::unbounded_channel();
 
         let watcher = RecommendedWatcher::new(
-            move |res: Result<Event, notify::Error>| {
-                match res {
-                    Ok(event) => {
-                        // Log every event we receive
-                        crate::add_log(&format!("File system event: {:?}", event.kind));
-                        // Use blocking_send since we're in a sync callback
-                        if let Err(e) = notify_tx.blocking_send(event) {
-                            crate::add_log(&format!("Failed to send event to channel: {}", e));
-                        }
-                    }
-                    Err(e) => {
-                        crate::add_log(&format!("File watcher error: {}", e));
-                    }
-                }
-            },
+            forward_events(notify_tx.clone()),
             Config::default().with_poll_interval(Duration::from_secs(2)),
         )?;
 
-        let watched_folders = Arc::new(RwLock::new(HashMap::new()));
-
-        let folder_watcher = Self {
+        let mut folder_watcher = Self {
             watcher,
-            watched_folders: watched_folders.clone(),
-            event_sender: event_tx.clone(),
+            pollers: HashMap::new(),
+            notify_sender: notify_tx,
+            watched_folders: Arc::new(RwLock::new(HashMap::new())),
+            approved_folders: Arc::new(RwLock::new(HashMap::new())),
+            routes: Arc::default(),
+            merge_sender: merge_tx,
+            backlog_sender: backlog_tx,
+            backlogs: HashMap::new(),
+            roots: HashMap::new(),
+            waiting_files: Arc::default(),
+            event_sender: event_tx,
+            removed_sender: removed_tx,
+            processor: None,
+            probe_dir: None,
+            health: WatcherHealth {
+                healthy: true,
+                probe: None,
+                last_probe: 0,
+                last_healthy: SystemTime::now(),
+                restarts: 0,
+                last_restart: None,
+                last_failure: None,
+            },
         };
+        folder_watcher.processor = Some(folder_watcher.spawn_processor(notify_rx, merge_rx, backlog_rx));
+        folder_watcher.watch_probe();
 
-        // Spawn event processor with shared watched_folders
-        let event_sender = event_tx;
-        let wf = watched_folders.clone();
+        Ok((folder_watcher, event_rx, removed_rx))
+    }
+
+    /// Spawn the event processor, sharing the watched folders with it
+    fn spawn_processor(
+        &self,
+        mut notify_rx: mpsc::UnboundedReceiver<Event>,
+        mut merge_rx: mpsc::UnboundedReceiver<PathBuf>,
+        mut backlog_rx: mpsc::UnboundedReceiver<PathBuf>,
+    ) -> tokio::task::JoinHandle<()> {
+        let wf = self.watched_folders.clone();
+        let af = self.approved_folders.clone();
+        let routes = self.routes.clone();
+        let waiting_files = self.waiting_files.clone();
+        let event_sender = self.event_sender.clone();
+        let removed_sender = self.removed_sender.clone();
 
         tokio::spawn(async move {
-            Self::process_events(&mut notify_rx, wf, event_sender).await;
-        });
+            Self::process_events(
+                &mut notify_rx,
+                &mut merge_rx,
+                &mut backlog_rx,
+                wf,
+                af,
+                routes,
+                waiting_files,
+                event_sender,
+                removed_sender,
+            )
+            .await;
+        })
+    }
+
+    /// Watch the folder the supervisor writes its probe files to
+    fn watch_probe(&mut self) {
+        self.health.probe = None;
+        let dir = match crate::config::get_config_dir() {
+            Ok(dir) => dir.join(PROBE_DIR),
+            Err(e) => {
+                warn!("No folder for watcher probes: {}", e);
+                return;
+            }
+        };
+        let watched = std::fs::create_dir_all(&dir)
+            .map_err(WatcherError::from)
+            .and_then(|_| {
+                self.watcher
+                    .watch(&dir, RecursiveMode::NonRecursive)
+                    .map_err(WatcherError::from)
+            });
+        match watched {
+            Ok(()) => self.probe_dir = Some(dir),
+            Err(e) => {
+                warn!("Could not watch the probe folder {:?}: {}", dir, e);
+                self.probe_dir = None;
+            }
+        }
+    }
+
+    /// Write the next probe file, whose event the next health check expects
+    fn send_probe(&mut self) {
+        let Some(dir) = &self.probe_dir else {
+            return;
+        };
+        let next = self.health.last_probe + 1;
+        let _ = std::fs::remove_file(dir.join(format!("{}{}", PROBE_PREFIX, self.health.last_probe)));
+        match std::fs::write(dir.join(format!("{}{}", PROBE_PREFIX, next)), b"") {
+            Ok(()) => {
+                self.health.last_probe = next;
+                self.health.probe = Some(next);
+            }
+            Err(e) => warn!("Could not write watcher probe: {}", e),
+        }
+    }
+
+    /// Check the event processor is running and the last probe file was seen,
+    /// restarting the watcher if not, then write the next probe
+    pub async fn check_health(&mut self) {
+        let processor_running = self.processor.as_ref().is_some_and(|p| !p.is_finished());
+        let failure = if !processor_running {
+            Some("the event processor stopped".to_string())
+        } else if self.health.probe.is_some_and(|probe| PROBE_SEEN.load(Ordering::Relaxed) < probe) {
+            Some("the system watcher stopped reporting changes".to_string())
+        } else {
+            None
+        };
+
+        match &failure {
+            Some(reason) => {
+                crate::add_log(&format!("WARNING: Restarting the folder watcher: {}", reason));
+                self.health.restarts += 1;
+                self.health.last_restart = Some(chrono::Local::now().to_rfc3339());
+                self.health.last_failure = Some(reason.clone());
+                // Files that arrived while it was stalled are picked up again
+                match self.restart(self.health.last_healthy).await {
+                    Ok(0) => {}
+                    Ok(queued) => crate::add_log(&format!("Queued {} file(s) missed while the watcher was stalled", queued)),
+                    Err(e) => crate::add_log(&format!("ERROR: Could not restart the folder watcher: {}", e)),
+                }
+            }
+            None => self.health.last_healthy = SystemTime::now(),
+        }
+        self.health.healthy = failure.is_none();
+        self.send_probe();
+    }
+
+    /// Replace the event processor and the system watcher, then queue files
+    /// that arrived in watched folders since `since`
+    pub async fn restart(&mut self, since: SystemTime) -> Result<usize, WatcherError> {
+        if let Some(processor) = self.processor.take() {
+            processor.abort();
+        }
+        let (notify_tx, notify_rx) = mpsc::unbounded_channel();
+        let (merge_tx, merge_rx) = mpsc::unbounded_channel();
+        let (backlog_tx, backlog_rx) = mpsc::unbounded_channel();
+        self.notify_sender = notify_tx;
+        self.merge_sender = merge_tx;
+        self.backlog_sender = backlog_tx;
+        if let Ok(mut waiting) = self.waiting_files.lock() {
+            waiting.clear();
+        }
+        self.processor = Some(self.spawn_processor(notify_rx, merge_rx, backlog_rx));
+        self.rebuild(since).await
+    }
+
+    /// How the watcher is doing, as of the last health check
+    pub fn status(&self) -> WatcherStatus {
+        WatcherStatus {
+            running: true,
+            healthy: self.health.healthy,
+            processor_running: self.processor.as_ref().is_some_and(|p| !p.is_finished()),
+            probing: self.probe_dir.is_some(),
+            last_healthy_at: Some(chrono::DateTime::<chrono::Local>::from(self.health.last_healthy).to_rfc3339()),
+            restarts: self.health.restarts,
+            last_restart_at: self.health.last_restart.clone(),
+            last_failure: self.health.last_failure.clone(),
+        }
+    }
+
+    /// Have the system watcher, or the folder's poller, report changes in a
+    /// folder, its compare subfolders and its approved folder
+    fn attach(&mut self, folder_path: &Path, tool_config: &ToolConfig) -> Result<(), WatcherError> {
+        crate::add_log(&format!("Starting watch on folder: {:?}", folder_path));
+        let mode = if tool_config.recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        let polled = match tool_config.watch_mode {
+            WatchMode::Polling => true,
+            WatchMode::Native => false,
+            WatchMode::Auto => is_network_path(folder_path),
+        };
+        if polled {
+            let interval = tool_config
+                .polling_interval_seconds
+                .unwrap_or(crate::config::DEFAULT_POLLING_INTERVAL_SECONDS)
+                .max(1);
+            let poller = PollWatcher::new(
+                forward_events(self.notify_sender.clone()),
+                Config::default().with_poll_interval(Duration::from_secs(interval)),
+            )?;
+            self.pollers.insert(folder_path.to_path_buf(), poller);
+            crate::add_log(&format!("Polling {:?} every {} s", folder_path, interval));
+        } else {
+            self.pollers.remove(folder_path);
+        }
+        self.watch(folder_path, folder_path, mode)?;
+        self.roots.insert(
+            folder_path.to_path_buf(),
+            WatchRoot {
+                identity: folder_identity(folder_path),
+                missing_since: None,
+            },
+        );
+        crate::add_log(&format!("Successfully watching: {:?} for tool: {}", folder_path, tool_config.id));
+
+        // Compare folders pairing by folder take their versions from subfolders
+        for subfolder in compare_folders(tool_config) {
+            std::fs::create_dir_all(&subfolder).map_err(|e| folder_error(e, &subfolder))?;
+            self.watch(folder_path, &subfolder, RecursiveMode::NonRecursive)?;
+            crate::add_log(&format!("Watching compare folder: {:?} for tool: {}", subfolder, tool_config.id));
+        }
+
+        // Watch the approved folder of the review stage
+        if let (Some(review_folder), Some(approved_folder)) =
+            (tool_config.review_folder(), tool_config.approved_folder())
+        {
+            std::fs::create_dir_all(&review_folder)?;
+            std::fs::create_dir_all(&approved_folder)?;
+            self.watch(folder_path, &approved_folder, RecursiveMode::NonRecursive)?;
+            crate::add_log(&format!("Watching approved folder: {:?} for tool: {}", approved_folder, tool_config.id));
+        }
+        Ok(())
+    }
 
-        Ok((folder_watcher, event_rx))
+    /// Replace the system watcher and watch every folder again. After the
+    /// computer has slept, the old watcher often stops reporting changes.
+    /// Files that arrived in the folders since `since` are queued like new ones.
+    /// Returns how many were queued.
+    pub async fn rebuild(&mut self, since: SystemTime) -> Result<usize, WatcherError> {
+        self.watcher = RecommendedWatcher::new(
+            forward_events(self.notify_sender.clone()),
+            Config::default().with_poll_interval(Duration::from_secs(2)),
+        )?;
+        self.pollers.clear();
+        self.watch_probe();
+
+        let folders: Vec<(PathBuf, ToolConfig)> = self
+            .watched_folders
+            .read()
+            .await
+            .iter()
+            .map(|(folder, config)| (folder.clone(), config.clone()))
+            .collect();
+        let mut queued = 0;
+        for (folder, config) in folders {
+            // Missing folders are attached by the watchdog once they are back
+            if !folder.exists() {
+                continue;
+            }
+            if let Err(e) = self.attach(&folder, &config) {
+                crate::add_log(&format!("ERROR: Could not watch {:?} again: {}", folder, e));
+                continue;
+            }
+            for file in self.scan_backlog(&folder, &config, Some(since)).await {
+                self.backlog_sender.send(file).map_err(|_| WatcherError::ChannelError)?;
+                queued += 1;
+            }
+        }
+
+        let watched = self.watched_folders.read().await;
+        let inboxes: Vec<PathBuf> = self
+            .routes
+            .read()
+            .await
+            .keys()
+            .filter(|inbox| !watched.contains_key(*inbox))
+            .cloned()
+            .collect();
+        for inbox in inboxes {
+            self.watcher.watch(&inbox, RecursiveMode::NonRecursive)?;
+        }
+        Ok(queued)
     }
 
-    /// Add a folder to watch
-    pub async fn add_folder(&mut self, tool_config: ToolConfig) -> Result<(), WatcherError> {
+    /// Add a folder to watch.
+    /// Returns the files already in it, unless they are queued right away.
+    pub async fn add_folder(&mut self, tool_config: ToolConfig) -> Result<Option<FolderBacklog>, WatcherError> {
         let folder_path = match &tool_config.folder_path {
             Some(path) => PathBuf::from(path),
-            None => return Ok(()), // No folder configured
+            None => return Ok(None), // No folder configured
         };
 
         if !tool_config.enabled {
-            return Ok(()); // Tool disabled
+            return Ok(None); // Tool disabled
         }
 
-        // Create folder if it doesn't exist
+        // Create folder if it doesn't exist - unless its drive isn't connected,
+        // which would create it on the system drive instead
         if !folder_path.exists() {
-            std::fs::create_dir_all(&folder_path)?;
+            if removable_volume(&folder_path).is_some_and(|volume| !volume.exists()) {
+                crate::add_log(&format!("Drive of {:?} is not connected - watching it once it is", folder_path));
+                self.roots.insert(
+                    folder_path.clone(),
+                    WatchRoot {
+                        identity: None,
+                        missing_since: Some(chrono::Local::now().to_rfc3339()),
+                    },
+                );
+                self.watched_folders.write().await.insert(folder_path, tool_config);
+                return Ok(None);
+            }
+            std::fs::create_dir_all(&folder_path).map_err(|e| folder_error(e, &folder_path))?;
             info!("Created watch folder: {:?}", folder_path);
         }
 
-        // Start watching
-        crate::add_log(&format!("Starting watch on folder: {:?}", folder_path));
-        self.watcher
-            .watch(&folder_path, RecursiveMode::NonRecursive)?;
-        crate::add_log(&format!("Successfully watching: {:?} for tool: {}", folder_path, tool_config.id));
+        // macOS privacy settings block protected folders without failing the watch,
+        // so check read access up front (this also shows the system prompt)
+        if check_folder_access(&folder_path) == FolderAccess::Denied {
+            return Err(WatcherError::PermissionDenied(folder_path));
+        }
+
+        self.attach(&folder_path, &tool_config)?;
 
         // Add to shared watched_folders
         {
@@ -112,184 +683,1419 @@ impl FolderWatcher {
                 crate::add_log(&format!("  - {} -> {:?}", config.id, path));
             }
         }
+        if let Some(approved_folder) = tool_config.approved_folder() {
+            let mut approved = self.approved_folders.write().await;
+            approved.insert(approved_folder, tool_config.clone());
+        }
+
+        let files = self.scan_backlog(&folder_path, &tool_config, None).await;
+        if files.is_empty() {
+            self.backlogs.remove(&folder_path);
+            return Ok(None);
+        }
+        crate::add_log(&format!("Found {} existing file(s) in {:?}", files.len(), folder_path));
+        let backlog = FolderBacklog {
+            tool_id: tool_config.id.clone(),
+            folder: folder_path.to_string_lossy().into_owned(),
+            files: files.iter().map(|f| f.to_string_lossy().into_owned()).collect(),
+        };
+        self.backlogs.insert(folder_path.clone(), backlog.clone());
+        if PROCESS_EXISTING.load(Ordering::Relaxed) {
+            self.process_backlog(&folder_path)?;
+            return Ok(None);
+        }
+        Ok(Some(backlog))
+    }
+
+    /// Files the folder's tool would pick up that are already in it, or only
+    /// those that arrived after `arrived_since`
+    async fn scan_backlog(&self, folder: &Path, tool_config: &ToolConfig, arrived_since: Option<SystemTime>) -> Vec<PathBuf> {
+        let watched = self.watched_folders.read().await;
+        let routes = self.routes.read().await;
+        scan_folder(&watched, &routes, folder, tool_config, arrived_since)
+    }
+
+    /// Backlogs waiting for the user to confirm them
+    pub fn backlogs(&self) -> Vec<FolderBacklog> {
+        self.backlogs.values().cloned().collect()
+    }
+
+    /// Queue the files of a folder's backlog. Returns how many were queued.
+    pub fn process_backlog(&mut self, folder: &Path) -> Result<usize, WatcherError> {
+        let Some(backlog) = self.backlogs.remove(folder) else {
+            return Ok(0);
+        };
+        for file in &backlog.files {
+            self.backlog_sender
+                .send(PathBuf::from(file))
+                .map_err(|_| WatcherError::ChannelError)?;
+        }
+        crate::add_log(&format!("Queued {} existing file(s) in {:?}", backlog.files.len(), folder));
+        Ok(backlog.files.len())
+    }
+
+    /// Leave a folder's backlog alone. Returns whether there was one.
+    pub fn dismiss_backlog(&mut self, folder: &Path) -> bool {
+        self.backlogs.remove(folder).is_some()
+    }
+
+    /// Replace the routing rules, watching the inbox folders they use
+    pub async fn set_routes(&mut self, new_routes: Routes) -> Result<(), WatcherError> {
+        let mut routes = self.routes.write().await;
+        let watched = self.watched_folders.read().await;
+
+        for inbox in routes.keys().filter(|inbox| !new_routes.contains_key(*inbox)) {
+            if !watched.contains_key(inbox) {
+                let _ = self.watcher.unwatch(inbox);
+            }
+        }
+        for inbox in new_routes.keys().filter(|inbox| !routes.contains_key(*inbox)) {
+            std::fs::create_dir_all(inbox).map_err(|e| folder_error(e, inbox))?;
+            if check_folder_access(inbox) == FolderAccess::Denied {
+                return Err(WatcherError::PermissionDenied(inbox.clone()));
+            }
+            if !watched.contains_key(inbox) {
+                self.watcher.watch(inbox, RecursiveMode::NonRecursive)?;
+            }
+            crate::add_log(&format!("Watching inbox folder: {:?}", inbox));
+        }
+
+        *routes = new_routes;
+        Ok(())
+    }
+
+    /// Merge the files collected in a merge folder now
+    pub fn trigger_merge(&self, folder_path: &Path) -> Result<(), WatcherError> {
+        self.merge_sender
+            .send(folder_path.to_path_buf())
+            .map_err(|_| WatcherError::ChannelError)
+    }
+
+    /// Detected files that are not queued yet, e.g. because they are still open elsewhere
+    pub fn waiting_files(&self) -> Vec<WaitingFile> {
+        self.waiting_files
+            .lock()
+            .map(|w| w.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Check that the watched folders still exist. The system stops reporting
+    /// changes in a folder that is deleted and recreated, or whose drive is
+    /// reformatted, so such folders are watched again once they are back.
+    /// Returns what changed, with the files found in folders that came back.
+    pub async fn check_roots(&mut self) -> Vec<(FolderWatchChange, Option<FolderBacklog>)> {
+        let folders: Vec<(PathBuf, ToolConfig)> = self
+            .watched_folders
+            .read()
+            .await
+            .iter()
+            .map(|(folder, config)| (folder.clone(), config.clone()))
+            .collect();
+
+        let mut changes = Vec::new();
+        for (folder, config) in folders {
+            let Some(root) = self.roots.get_mut(&folder) else {
+                continue;
+            };
+            let identity = folder_identity(&folder);
+            let Some(identity) = identity else {
+                if root.missing_since.is_none() {
+                    let since = chrono::Local::now().to_rfc3339();
+                    warn!("Watched folder is missing: {:?}", folder);
+                    root.missing_since = Some(since.clone());
+                    changes.push((watch_change(&config, &folder, false, since), None));
+                    // Dropping the poller of a folder on an unplugged drive keeps it
+                    // from failing every scan until the drive is back
+                    self.pollers.remove(&folder);
+                }
+                continue;
+            };
+            if root.missing_since.is_none() && root.identity == Some(identity) {
+                continue;
+            }
+
+            let since = root.missing_since.clone().unwrap_or_else(|| chrono::Local::now().to_rfc3339());
+            crate::add_log(&format!("Watched folder is back, watching it again: {:?}", folder));
+            let _ = self.watcher.unwatch(&folder);
+            match self.add_folder(config.clone()).await {
+                Ok(backlog) => changes.push((watch_change(&config, &folder, true, since), backlog)),
+                // Tried again at the next check
+                Err(e) => crate::add_log(&format!("ERROR: Could not watch {:?} again: {}", folder, e)),
+            }
+        }
+        changes
+    }
+
+    /// Status of every watched folder
+    pub async fn folder_status(&self) -> Vec<WatchedFolderStatus> {
+        let folders = self.watched_folders.read().await;
+        folders
+            .iter()
+            .map(|(folder, config)| {
+                let since = self.roots.get(folder).and_then(|root| root.missing_since.clone());
+                let state = match since {
+                    None => WatchState::Active,
+                    Some(_) if is_volume_offline(folder) => WatchState::Offline,
+                    Some(_) => WatchState::Missing,
+                };
+                WatchedFolderStatus {
+                    tool_id: config.id.clone(),
+                    folder: folder.to_string_lossy().into_owned(),
+                    state,
+                    since,
+                    removable: removable_volume(folder).is_some(),
+                    polled: self.pollers.contains_key(folder),
+                }
+            })
+            .collect()
+    }
+
+    /// Watched folders that are missing, e.g. deleted or on a disconnected drive
+    pub async fn lost_folders(&self) -> Vec<FolderWatchChange> {
+        let folders = self.watched_folders.read().await;
+        self.roots
+            .iter()
+            .filter_map(|(folder, root)| {
+                let config = folders.get(folder)?;
+                Some(watch_change(config, folder, false, root.missing_since.clone()?))
+            })
+            .collect()
+    }
 
+    /// Watch `path`, which belongs to the watched folder `folder`, with the
+    /// folder's poller if it is polled
+    fn watch(&mut self, folder: &Path, path: &Path, mode: RecursiveMode) -> Result<(), WatcherError> {
+        match self.pollers.get_mut(folder) {
+            Some(poller) => poller.watch(path, mode)?,
+            None => self.watcher.watch(path, mode)?,
+        }
         Ok(())
     }
 
+    /// Watch exactly the folders of `folders`, one config per folder as from
+    /// ToolConfig::watch_folders. Folders that are no longer in it stop being
+    /// watched, new ones are added and folders whose settings changed are
+    /// added again; the others keep watching undisturbed.
+    pub async fn reconcile(&mut self, folders: Vec<ToolConfig>) -> Reconciled {
+        let current = self.watched_folders.read().await.clone();
+        let wanted: HashMap<PathBuf, ToolConfig> = folders
+            .into_iter()
+            .filter(|folder| folder.enabled)
+            .filter_map(|folder| Some((PathBuf::from(folder.folder_path.as_ref()?), folder)))
+            .collect();
+
+        let mut reconciled = Reconciled::default();
+        for (path, watched) in &current {
+            let unchanged = wanted.get(path).is_some_and(|folder| same_settings(folder, watched));
+            if unchanged {
+                continue;
+            }
+            if let Err(e) = self.remove_folder(path).await {
+                warn!("Could not stop watching {:?}: {}", path, e);
+            }
+            if !wanted.contains_key(path) {
+                reconciled.removed.push(path.clone());
+            }
+        }
+        for (path, folder) in wanted {
+            match current.get(&path) {
+                Some(watched) if same_settings(&folder, watched) => {}
+                Some(_) => {
+                    let result = self.add_folder(folder.clone()).await;
+                    reconciled.changed.push((folder, result));
+                }
+                None => {
+                    let result = self.add_folder(folder.clone()).await;
+                    reconciled.added.push((folder, result));
+                }
+            }
+        }
+        reconciled
+    }
+
     /// Remove a folder from watching
     pub async fn remove_folder(&mut self, folder_path: &Path) -> Result<(), WatcherError> {
-        self.watcher.unwatch(folder_path)?;
-        {
+        self.roots.remove(folder_path);
+        // Dropping a folder's poller stops it, subfolders included
+        let polled = self.pollers.remove(folder_path).is_some();
+        if !polled {
+            match self.watcher.unwatch(folder_path) {
+                // Folders whose drive wasn't connected were never watched
+                Err(e) if matches!(e.kind, notify::ErrorKind::WatchNotFound) => {}
+                result => result?,
+            }
+        }
+        self.backlogs.remove(folder_path);
+        let removed = {
             let mut folders = self.watched_folders.write().await;
-            folders.remove(folder_path);
+            folders.remove(folder_path)
+        };
+
+        let Some(removed) = removed else {
+            info!("Stopped watching folder: {:?}", folder_path);
+            return Ok(());
+        };
+        if !polled {
+            for subfolder in compare_folders(&removed) {
+                let _ = self.watcher.unwatch(&subfolder);
+            }
+        }
+
+        // Stop watching the approved folder of the review stage too
+        if let Some(approved_folder) = removed.approved_folder() {
+            let mut approved = self.approved_folders.write().await;
+            if approved.remove(&approved_folder).is_some() && !polled {
+                let _ = self.watcher.unwatch(&approved_folder);
+            }
         }
         info!("Stopped watching folder: {:?}", folder_path);
         Ok(())
     }
 
     /// Process notify events and emit file events
+    #[allow(clippy::too_many_arguments)]
     async fn process_events(
-        rx: &mut mpsc::Receiver<Event>,
+        rx: &mut mpsc::UnboundedReceiver<Event>,
+        merge_rx: &mut mpsc::UnboundedReceiver<PathBuf>,
+        backlog_rx: &mut mpsc::UnboundedReceiver<PathBuf>,
         watched_folders: Arc<RwLock<HashMap<PathBuf, ToolConfig>>>,
-        event_sender: broadcast::Sender<FileEvent>,
+        approved_folders: Arc<RwLock<HashMap<PathBuf, ToolConfig>>>,
+        routes: Arc<RwLock<Routes>>,
+        waiting_files: WaitingFiles,
+        event_sender: mpsc::UnboundedSender<FileEvent>,
+        removed_sender: mpsc::UnboundedSender<PathBuf>,
     ) {
         crate::add_log("File watcher event processor started - listening for file changes...");
-        let mut pending_files: HashMap<PathBuf, Instant> = HashMap::new();
-        let debounce_duration = Duration::from_secs(2);
+        let started = SystemTime::now();
+        let mut pending_files = PendingFiles::new();
+        let mut collections: HashMap<PathBuf, MergeCollection> = HashMap::new();
+        let mut pairs = PendingPairs::new();
 
         loop {
             // Use tokio::select to either receive an event or timeout
             tokio::select! {
                 Some(event) = rx.recv() => {
                     info!("Got event from notify channel: {:?}", event);
-                    Self::handle_notify_event(
+                    if let Some(probe) = event.paths.iter().find_map(|p| probe_number(p)) {
+                        PROBE_SEEN.fetch_max(probe, Ordering::Relaxed);
+                        continue;
+                    }
+                    // The system dropped events, e.g. its buffer overflowed when
+                    // thousands of files arrived at once
+                    if event.need_rescan() {
+                        Self::rescan(&mut pending_files, &watched_folders, &routes, started).await;
+                        continue;
+                    }
+                    let removed = Self::handle_notify_event(
                         event,
                         &mut pending_files,
+                        &watched_folders,
+                        &approved_folders,
+                        &routes,
                     )
                     .await;
+                    Self::forget_removed(removed, &mut pending_files, &mut collections, &mut pairs, &removed_sender);
+                }
+                Some(folder) = merge_rx.recv() => {
+                    crate::add_log(&format!("Merge requested for {:?}", folder));
+                    Self::flush_collection(&folder, &mut collections, &event_sender);
                 }
-                _ = tokio::time::sleep(Duration::from_millis(500)) => {
+                Some(path) = backlog_rx.recv() => {
+                    // Confirmed backlog files go through the same checks as new ones
+                    pending_files.insert(path, PendingFile::new(Instant::now()));
+                }
+                _ = tokio::time::sleep(Duration::from_millis(POLL_INTERVAL_MS.load(Ordering::Relaxed))) => {
                     // Check for files that have stabilized
                     Self::check_pending_files(
                         &mut pending_files,
+                        &mut collections,
+                        &mut pairs,
                         &watched_folders,
+                        &approved_folders,
+                        &routes,
+                        &waiting_files,
                         &event_sender,
-                        debounce_duration,
                     )
                     .await;
+                    Self::check_idle_collections(&mut collections, &event_sender);
+                }
+            }
+        }
+    }
+
+    /// Pick up the files whose events were dropped: those in the watched
+    /// folders that arrived since the watcher started and aren't waiting yet.
+    /// Files already queued are skipped by the job queue.
+    async fn rescan(
+        pending_files: &mut PendingFiles,
+        watched_folders: &Arc<RwLock<HashMap<PathBuf, ToolConfig>>>,
+        routes: &Arc<RwLock<Routes>>,
+        since: SystemTime,
+    ) {
+        let watched = watched_folders.read().await;
+        let routes = routes.read().await;
+        let mut found = 0;
+        for (folder, config) in watched.iter() {
+            for path in scan_folder(&watched, &routes, folder, config, Some(since)) {
+                if !pending_files.contains_key(&path) {
+                    pending_files.insert(path, PendingFile::new(Instant::now()));
+                    found += 1;
                 }
             }
         }
+        warn!("File events were dropped - rescanned the watched folders and found {} file(s)", found);
     }
 
+    /// Queue the files an event reports for debouncing.
+    /// Returns the paths it reports removed or moved away.
     async fn handle_notify_event(
         event: Event,
-        pending_files: &mut HashMap<PathBuf, Instant>,
-    ) {
+        pending_files: &mut PendingFiles,
+        watched_folders: &Arc<RwLock<HashMap<PathBuf, ToolConfig>>>,
+        approved_folders: &Arc<RwLock<HashMap<PathBuf, ToolConfig>>>,
+        routes: &Arc<RwLock<Routes>>,
+    ) -> Vec<PathBuf> {
         crate::add_log(&format!("Processing event: {:?}", event.kind));
 
+        // Many apps write "report.pdf.tmp" and rename it to "report.pdf". The old
+        // name is gone, so only the new one is picked up. Some systems don't say
+        // which side of the rename a path is, but only the new one exists.
+        let removed: Vec<PathBuf> = match event.kind {
+            EventKind::Remove(_) => event.paths.clone(),
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => event.paths.clone(),
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => event.paths.first().cloned().into_iter().collect(),
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => vec![],
+            EventKind::Modify(ModifyKind::Name(_)) => event.paths.iter().filter(|p| !p.exists()).cloned().collect(),
+            _ => vec![],
+        };
+
         // Only handle create and modify events
         match event.kind {
             EventKind::Create(_) | EventKind::Modify(_) => {}
             _ => {
                 crate::add_log(&format!("Skipping event type: {:?}", event.kind));
-                return;
+                return removed;
             }
         }
 
-        for path in event.paths {
+        let watched = watched_folders.read().await;
+        let approved = approved_folders.read().await;
+        let routes = routes.read().await;
+
+        for path in event.paths.into_iter().filter(|p| !removed.contains(p)) {
             let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("unknown");
             crate::add_log(&format!("Checking file: {}", file_name));
 
-            // Skip if not a PDF file
-            if !Self::is_pdf_file(&path) {
-                crate::add_log(&format!("Skipping non-PDF: {}", file_name));
+            // iCloud keeps evicted files as ".<name>.icloud" stubs until they are downloaded
+            if let Some(real_path) = icloud_stub_target(&path) {
+                let wanted = Self::find_watched_folder(&real_path, &watched, &routes)
+                    .is_some_and(|(_, tool)| accepts_input(&tool.id, &real_path));
+                if !wanted {
+                    continue;
+                }
+                crate::add_log(&format!("Requesting download of iCloud file: {:?}", real_path));
+                request_icloud_download(&real_path);
                 continue;
             }
 
-            // Skip if in a "Processed" subfolder
-            if Self::is_in_processed_folder(&path) {
-                crate::add_log(&format!("Skipping file in Processed/Originals folder: {}", file_name));
+            if is_temporary(file_name) {
+                crate::add_log(&format!("Skipping temp file: {}", file_name));
                 continue;
             }
 
-            // Skip temporary/partial files
-            if file_name.starts_with('.') || file_name.ends_with(".tmp") || file_name.ends_with(".part") {
-                crate::add_log(&format!("Skipping temp file: {}", file_name));
+            // Sentinel that triggers a merge of the collected files
+            if file_name.eq_ignore_ascii_case(MERGE_SENTINEL) {
+                pending_files.insert(path, PendingFile::new(Instant::now()));
+                continue;
+            }
+
+            // Reviewed outputs can be any file type (docx, xlsx, zip...)
+            if Self::find_approved_folder(&path, &approved).is_some() {
+                crate::add_log(&format!("Approved file detected, queueing delivery: {}", file_name));
+                pending_files.insert(path, PendingFile::new(Instant::now()));
+                continue;
+            }
+
+            // Skip files the folder's tool doesn't take, including its own outputs
+            // in subfolders of a recursive watch
+            let accepted = Self::find_watched_folder(&path, &watched, &routes)
+                .is_some_and(|(_, tool)| accepts_input(&tool.id, &path) && !is_own_output(&path, tool));
+            if !accepted {
+                crate::add_log(&format!("Skipping file the tool doesn't take: {}", file_name));
                 continue;
             }
 
-            crate::add_log(&format!("PDF detected, adding to queue: {}", file_name));
+            crate::add_log(&format!("File detected, adding to queue: {}", file_name));
 
             // Add to pending files for debouncing
-            pending_files.insert(path, Instant::now());
+            pending_files.insert(path, PendingFile::new(Instant::now()));
+        }
+        removed
+    }
+
+    /// Forget files that were removed or moved away before they were processed,
+    /// including those in a removed subfolder, and report them so their queued
+    /// jobs are cancelled
+    fn forget_removed(
+        removed: Vec<PathBuf>,
+        pending_files: &mut PendingFiles,
+        collections: &mut HashMap<PathBuf, MergeCollection>,
+        pairs: &mut PendingPairs,
+        removed_sender: &mpsc::UnboundedSender<PathBuf>,
+    ) {
+        for path in removed {
+            pending_files.retain(|pending, _| {
+                let gone = pending.starts_with(&path);
+                if gone {
+                    crate::add_log(&format!("File removed before it was ready, dropping it: {:?}", pending));
+                }
+                !gone
+            });
+            for collection in collections.values_mut() {
+                collection.files.retain(|f| !f.starts_with(&path));
+            }
+            pairs.retain(|_, pair| {
+                if pair.old.as_ref().is_some_and(|old| old.starts_with(&path)) {
+                    pair.old = None;
+                }
+                if pair.new.as_ref().is_some_and(|new| new.starts_with(&path)) {
+                    pair.new = None;
+                }
+                pair.old.is_some() || pair.new.is_some()
+            });
+            let _ = removed_sender.send(path);
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn check_pending_files(
-        pending_files: &mut HashMap<PathBuf, Instant>,
+        pending_files: &mut PendingFiles,
+        collections: &mut HashMap<PathBuf, MergeCollection>,
+        pairs: &mut PendingPairs,
         watched_folders: &Arc<RwLock<HashMap<PathBuf, ToolConfig>>>,
-        event_sender: &broadcast::Sender<FileEvent>,
-        debounce_duration: Duration,
+        approved_folders: &Arc<RwLock<HashMap<PathBuf, ToolConfig>>>,
+        routes: &Arc<RwLock<Routes>>,
+        waiting_files: &WaitingFiles,
+        event_sender: &mpsc::UnboundedSender<FileEvent>,
     ) {
         let now = Instant::now();
         let mut ready_files = Vec::new();
+        let mut locked_files = Vec::new();
+        let mut growing_files = Vec::new();
+        let mut placeholders = Vec::new();
+        let folders = watched_folders.read().await;
+        let approved = approved_folders.read().await;
+        let routes = routes.read().await;
+        let default_debounce = Duration::from_millis(DEBOUNCE_MS.load(Ordering::Relaxed));
+        let required_checks = STABLE_SIZE_CHECKS.load(Ordering::Relaxed);
 
         // Find files that have stabilized
-        for (path, last_event) in pending_files.iter() {
-            if now.duration_since(*last_event) >= debounce_duration {
+        for (path, pending) in pending_files.iter_mut() {
+            let debounce_duration = Self::find_watched_folder(path, &folders, &routes)
+                .and_then(|(_, tool)| tool.debounce_seconds)
+                .map(Duration::from_secs)
+                .unwrap_or(default_debounce);
+            // Locked files are re-checked once their back-off has passed (stamps in the future)
+            if now.saturating_duration_since(pending.since) >= debounce_duration {
                 // Check if file still exists and is readable
-                if path.exists() && Self::is_file_ready(path) {
-                    ready_files.push(path.clone());
+                let Ok(metadata) = std::fs::metadata(path) else {
+                    continue;
+                };
+
+                // A file being copied over a network can open fine long before it
+                // is complete, so wait until its size stops changing
+                if pending.size == Some(metadata.len()) {
+                    pending.stable_checks += 1;
+                } else {
+                    if pending.size.is_some() {
+                        growing_files.push(path.clone());
+                    }
+                    pending.size = Some(metadata.len());
+                    pending.stable_checks = 0;
+                }
+                if pending.stable_checks < required_checks {
+                    continue;
+                }
+
+                let readiness = if is_online_only(&metadata) {
+                    FileReadiness::Placeholder
+                } else {
+                    Self::file_readiness(path)
+                };
+                match readiness {
+                    FileReadiness::Ready => ready_files.push(path.clone()),
+                    FileReadiness::Locked => locked_files.push(path.clone()),
+                    FileReadiness::Placeholder => placeholders.push(path.clone()),
+                    FileReadiness::Unreadable => {}
+                }
+            }
+        }
+
+        if let Ok(mut waiting) = waiting_files.lock() {
+            for path in locked_files {
+                let entry = waiting.entry(path.clone()).or_insert_with(|| {
+                    crate::add_log(&format!("Waiting for file to be closed: {:?}", path));
+                    WaitingFile {
+                        path: path.to_string_lossy().into_owned(),
+                        reason: WaitReason::Locked,
+                        since: chrono::Local::now().to_rfc3339(),
+                        attempts: 0,
+                    }
+                });
+                entry.attempts += 1;
+                let backoff = LOCK_RETRY_BASE
+                    .saturating_mul(1 << entry.attempts.min(6))
+                    .min(LOCK_RETRY_MAX);
+                pending_files.insert(path, PendingFile::new(now + backoff));
+            }
+            for path in placeholders {
+                let entry = waiting.entry(path.clone()).or_insert_with(|| {
+                    crate::add_log(&format!("Waiting for cloud file to download: {:?}", path));
+                    request_download(&path);
+                    WaitingFile {
+                        path: path.to_string_lossy().into_owned(),
+                        reason: WaitReason::Downloading,
+                        since: chrono::Local::now().to_rfc3339(),
+                        attempts: 0,
+                    }
+                });
+                entry.attempts += 1;
+                if entry.attempts > MAX_PLACEHOLDER_CHECKS {
+                    // Left alone until it changes again, e.g. once it is made available offline
+                    crate::add_log(&format!("WARNING: Cloud file never downloaded, not processing it: {:?}", path));
+                    waiting.remove(&path);
+                    pending_files.remove(&path);
+                    continue;
+                }
+                pending_files.insert(path, PendingFile::new(now + PLACEHOLDER_RETRY));
+            }
+            for path in growing_files {
+                waiting.entry(path.clone()).or_insert_with(|| {
+                    crate::add_log(&format!("Waiting for file to stop growing: {:?}", path));
+                    WaitingFile {
+                        path: path.to_string_lossy().into_owned(),
+                        reason: WaitReason::Growing,
+                        since: chrono::Local::now().to_rfc3339(),
+                        attempts: 0,
+                    }
+                }).attempts += 1;
+            }
+
+            // Files deleted while they were locked
+            waiting.retain(|path, _| pending_files.contains_key(path));
+            for path in &ready_files {
+                if waiting.remove(path).is_some() {
+                    crate::add_log(&format!("File ready, queueing: {:?}", path));
                 }
             }
         }
 
         // Process ready files
-        let folders = watched_folders.read().await;
         for path in ready_files {
             pending_files.remove(&path);
 
-            // Find which watched folder this file belongs to
-            if let Some((_folder_path, tool_config)) = Self::find_watched_folder(&path, &folders) {
-                info!("Processing file: {:?} with tool: {}", path, tool_config.id);
-
-                let file_event = FileEvent {
+            // Approved folders are checked first as they may live inside a watched folder
+            let file_event = if let Some(tool_config) = Self::find_approved_folder(&path, &approved) {
+                info!("Delivering approved file: {:?} for tool: {}", path, tool_config.id);
+                FileEvent {
                     path: path.clone(),
                     tool_id: tool_config.id.clone(),
                     tool_config: tool_config.clone(),
-                };
+                    stage: PipelineStage::Deliver,
+                }
+            } else if let Some((folder_path, tool_config)) = Self::find_watched_folder(&path, &folders, &routes) {
+                let is_sentinel = path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.eq_ignore_ascii_case(MERGE_SENTINEL));
+
+                if !is_sentinel {
+                    if let Some(reason) = outside_size_limits(&path, tool_config) {
+                        crate::add_log(&format!("Skipping {:?}: {}", path, reason));
+                        continue;
+                    }
+                }
+
+                // Merge folders collect files until a merge is triggered
+                if is_multi_input(tool_config) {
+                    if is_sentinel {
+                        crate::add_log(&format!("Merge sentinel found in {:?}", folder_path));
+                        let _ = std::fs::remove_file(&path);
+                        Self::flush_collection(folder_path, collections, event_sender);
+                    } else {
+                        let collection = collections
+                            .entry(folder_path.clone())
+                            .or_insert_with(|| MergeCollection {
+                                tool_config: tool_config.clone(),
+                                files: Vec::new(),
+                                last_added: now,
+                            });
+                        if !collection.files.contains(&path) {
+                            collection.files.push(path.clone());
+                        }
+                        collection.last_added = now;
+                        crate::add_log(&format!(
+                            "Collected {:?} for merge ({} file(s) waiting)",
+                            path,
+                            collection.files.len()
+                        ));
+                    }
+                    continue;
+                }
+                if is_sentinel {
+                    continue;
+                }
+
+                // Compare folders hold each version until the other one arrives
+                if is_paired_input(tool_config) {
+                    Self::collect_pair(&path, folder_path, tool_config, pairs, event_sender);
+                    continue;
+                }
 
-                if let Err(e) = event_sender.send(file_event) {
-                    error!("Failed to send file event: {}", e);
+                if left_and_processed(&path, tool_config) {
+                    crate::add_log(&format!("Already processed and unchanged since, skipping: {:?}", path));
+                    continue;
                 }
+
+                // Find which watched folder this file belongs to
+                info!("Processing file: {:?} with tool: {}", path, tool_config.id);
+                FileEvent {
+                    path: path.clone(),
+                    tool_id: tool_config.id.clone(),
+                    tool_config: tool_config.clone(),
+                    stage: PipelineStage::Process,
+                }
+            } else {
+                continue;
+            };
+
+            if let Err(e) = event_sender.send(file_event) {
+                error!("Failed to send file event: {}", e);
             }
         }
     }
 
-    fn is_pdf_file(path: &Path) -> bool {
-        path.extension()
-            .and_then(|e| e.to_str())
-            .map(|e| e.eq_ignore_ascii_case("pdf"))
-            .unwrap_or(false)
+    /// Merge folders whose idle timeout has passed since the last file arrived
+    fn check_idle_collections(
+        collections: &mut HashMap<PathBuf, MergeCollection>,
+        event_sender: &mpsc::UnboundedSender<FileEvent>,
+    ) {
+        let idle_folders: Vec<PathBuf> = collections
+            .iter()
+            .filter(|(_, c)| {
+                let idle_secs = c.tool_config.options.get("idleTimeoutSecs").and_then(|v| v.as_u64()).unwrap_or(0);
+                idle_secs > 0 && c.last_added.elapsed() >= Duration::from_secs(idle_secs)
+            })
+            .map(|(folder, _)| folder.clone())
+            .collect();
+
+        for folder in idle_folders {
+            crate::add_log(&format!("Merge folder {:?} idle - merging", folder));
+            Self::flush_collection(&folder, collections, event_sender);
+        }
     }
 
-    fn is_in_processed_folder(path: &Path) -> bool {
-        path.components().any(|c| {
-            c.as_os_str()
-                .to_str()
-                .map(|s| s.eq_ignore_ascii_case("processed") || s.eq_ignore_ascii_case("originals"))
-                .unwrap_or(false)
-        })
+    /// Send the files collected in a merge folder as one merge job
+    fn flush_collection(
+        folder: &Path,
+        collections: &mut HashMap<PathBuf, MergeCollection>,
+        event_sender: &mpsc::UnboundedSender<FileEvent>,
+    ) {
+        let Some(collection) = collections.remove(folder) else {
+            crate::add_log(&format!("Nothing to merge in {:?}", folder));
+            return;
+        };
+
+        let mut files: Vec<PathBuf> = collection.files.into_iter().filter(|f| f.exists()).collect();
+        if files.is_empty() {
+            return;
+        }
+        // Files are merged in name order unless arrival order is configured
+        if collection.tool_config.options.get("order").and_then(|v| v.as_str()) != Some("added") {
+            files.sort_by_key(|f| f.file_name().map(|n| n.to_ascii_lowercase()));
+        }
+
+        info!("Merging {} files from {:?}", files.len(), folder);
+        let event = FileEvent {
+            path: folder.to_path_buf(),
+            tool_id: collection.tool_config.id.clone(),
+            tool_config: collection.tool_config,
+            stage: PipelineStage::Merge(files),
+        };
+        if let Err(e) = event_sender.send(event) {
+            error!("Failed to send merge event: {}", e);
+        }
+    }
+
+    /// Add a file to its compare pair, and send the pair as one job once both versions are in
+    fn collect_pair(
+        path: &Path,
+        folder: &Path,
+        tool_config: &ToolConfig,
+        pairs: &mut PendingPairs,
+        event_sender: &mpsc::UnboundedSender<FileEvent>,
+    ) {
+        let Some((side, name)) = pair_role(path, folder, tool_config) else {
+            crate::add_log(&format!("Skipping {:?} - it is not an old or new version to compare", path));
+            return;
+        };
+        let key = (folder.to_path_buf(), name.to_lowercase());
+        let pair = pairs.entry(key.clone()).or_default();
+        match side {
+            PairSide::Old => pair.old = Some(path.to_path_buf()),
+            PairSide::New => pair.new = Some(path.to_path_buf()),
+        }
+
+        let (Some(old), Some(new)) = (pair.old.clone(), pair.new.clone()) else {
+            crate::add_log(&format!("Waiting for the other version of {:?} to compare", path));
+            return;
+        };
+        pairs.remove(&key);
+
+        info!("Comparing {:?} with {:?}", old, new);
+        let event = FileEvent {
+            path: new.clone(),
+            tool_id: tool_config.id.clone(),
+            tool_config: tool_config.clone(),
+            stage: PipelineStage::Compare { old, new },
+        };
+        if let Err(e) = event_sender.send(event) {
+            error!("Failed to send compare event: {}", e);
+        }
+    }
+
+    /// Check that a file can be read and is not held open for writing by another
+    /// application. A plain open succeeds on Windows even while a scanner is still
+    /// writing, so deny write sharing to detect that.
+    #[cfg(target_os = "windows")]
+    fn file_readiness(path: &Path) -> FileReadiness {
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_SHARE_READ: u32 = 0x1;
+        const ERROR_SHARING_VIOLATION: i32 = 32;
+        const ERROR_LOCK_VIOLATION: i32 = 33;
+
+        match std::fs::OpenOptions::new().read(true).share_mode(FILE_SHARE_READ).open(path) {
+            Ok(_) => FileReadiness::Ready,
+            Err(e) if matches!(e.raw_os_error(), Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)) => {
+                FileReadiness::Locked
+            }
+            Err(_) => FileReadiness::Unreadable,
+        }
     }
 
-    fn is_file_ready(path: &Path) -> bool {
-        // Try to open the file for reading to check if it's accessible and not being written
-        match std::fs::OpenOptions::new().read(true).open(path) {
-            Ok(_) => true,
-            Err(_) => false,
+    /// Check that a file can be read and no other application holds an exclusive lock on it
+    #[cfg(unix)]
+    fn file_readiness(path: &Path) -> FileReadiness {
+        use std::os::unix::io::AsRawFd;
+
+        let Ok(file) = std::fs::File::open(path) else {
+            return FileReadiness::Unreadable;
+        };
+        // Closing the file releases our shared lock again
+        let locked = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_SH | libc::LOCK_NB) } != 0
+            && std::io::Error::last_os_error().raw_os_error() == Some(libc::EWOULDBLOCK);
+        if locked {
+            FileReadiness::Locked
+        } else {
+            FileReadiness::Ready
         }
     }
 
+    fn find_approved_folder<'a>(
+        file_path: &Path,
+        approved_folders: &'a HashMap<PathBuf, ToolConfig>,
+    ) -> Option<&'a ToolConfig> {
+        file_path
+            .parent()
+            .and_then(|parent| approved_folders.get(parent))
+    }
+
+    /// The folder a file belongs to and the tool that processes it. Files in an
+    /// inbox go to the tool of the first routing rule matching their name.
+    /// Folders may be nested, e.g. tool subfolders in a base folder watched by
+    /// another tool, so a file belongs to the nearest folder above it, and only
+    /// if that folder takes it: directly, from its compare A/B subfolders, or
+    /// from subfolders within reach of a recursive watch that aren't the tool's
+    /// own Processed/Originals/review folders.
     fn find_watched_folder<'a>(
         file_path: &Path,
         watched_folders: &'a HashMap<PathBuf, ToolConfig>,
+        routes: &'a Routes,
     ) -> Option<(&'a PathBuf, &'a ToolConfig)> {
-        // Find the most specific (longest) matching folder path
-        // This is important because HashMap iteration order is not guaranteed
-        let mut best_match: Option<(&'a PathBuf, &'a ToolConfig)> = None;
-        let mut best_len = 0;
-
-        for (folder_path, config) in watched_folders {
-            if file_path.starts_with(folder_path) {
-                let path_len = folder_path.as_os_str().len();
-                if path_len > best_len {
-                    best_len = path_len;
-                    best_match = Some((folder_path, config));
+        let file_name = file_path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        let routed = file_path
+            .parent()
+            .and_then(|parent| routes.get_key_value(parent))
+            .and_then(|(inbox, rules)| Some((inbox, routing::route(rules, file_name)?)));
+        if routed.is_some() {
+            return routed;
+        }
+
+        let parent = file_path.parent()?;
+        let (depth, (folder, config)) = parent
+            .ancestors()
+            .enumerate()
+            .find_map(|(depth, folder)| Some((depth, watched_folders.get_key_value(folder)?)))?;
+
+        let taken = depth == 0
+            || (depth == 1 && compare_folders(config).iter().any(|f| f == parent))
+            || (config.recursive && depth <= config.max_depth as usize && !is_tool_output(file_path, folder, config));
+        taken.then_some((folder, config))
+    }
+}
+
+/// Check whether a folder collects files and processes them together: always
+/// for multi-input tools (e.g. merge), or when an option marked
+/// `"x-multi-input": <value>` in the tool's schema is set to that value
+/// (e.g. image-to-pdf with all images in one PDF)
+pub(crate) fn is_multi_input(config: &ToolConfig) -> bool {
+    let Some(tool) = crate::config::get_tool_definition(&config.id) else {
+        return false;
+    };
+    if tool.multi_input {
+        return true;
+    }
+    tool.options_schema
+        .as_ref()
+        .and_then(|schema| schema.get("properties"))
+        .and_then(|properties| properties.as_object())
+        .is_some_and(|properties| {
+            properties.iter().any(|(name, spec)| {
+                spec.get("x-multi-input")
+                    .is_some_and(|collect_when| config.options.get(name) == Some(collect_when))
+            })
+        })
+}
+
+pub(crate) fn is_paired_input(config: &ToolConfig) -> bool {
+    crate::config::get_tool_definition(&config.id).is_some_and(|t| t.paired_input)
+}
+
+fn option_str<'a>(config: &'a ToolConfig, name: &str, default: &'a str) -> &'a str {
+    config
+        .options
+        .get(name)
+        .and_then(|v| v.as_str())
+        .filter(|v| !v.is_empty())
+        .unwrap_or(default)
+}
+
+/// Subfolders a compare folder takes its versions from, when it pairs by folder
+fn compare_folders(config: &ToolConfig) -> Vec<PathBuf> {
+    let Some(folder) = config.folder_path.as_ref().map(PathBuf::from) else {
+        return vec![];
+    };
+    if !is_paired_input(config) || option_str(config, "pairing", "suffix") != "folders" {
+        return vec![];
+    }
+    vec![folder.join(COMPARE_OLD_FOLDER), folder.join(COMPARE_NEW_FOLDER)]
+}
+
+/// Which version a file in a compare folder is, and the name of the document
+/// both versions share: "offer" for "offer_v1.pdf" and "offer_v2.pdf", or
+/// "offer" for "A/offer.pdf" and "B/offer.pdf"
+fn pair_role(path: &Path, folder: &Path, config: &ToolConfig) -> Option<(PairSide, String)> {
+    let stem = path.file_stem()?.to_str()?;
+
+    if option_str(config, "pairing", "suffix") == "folders" {
+        let parent = path.parent()?;
+        let side = if parent == folder.join(COMPARE_OLD_FOLDER) {
+            PairSide::Old
+        } else if parent == folder.join(COMPARE_NEW_FOLDER) {
+            PairSide::New
+        } else {
+            return None;
+        };
+        return Some((side, stem.to_string()));
+    }
+
+    // Only files directly in the folder pair by suffix
+    if path.parent() != Some(folder) {
+        return None;
+    }
+    let lower = stem.to_lowercase();
+    [
+        (PairSide::Old, option_str(config, "oldSuffix", "_v1")),
+        (PairSide::New, option_str(config, "newSuffix", "_v2")),
+    ]
+    .into_iter()
+    .find(|(_, suffix)| lower.ends_with(&suffix.to_lowercase()) && stem.len() > suffix.len())
+    .and_then(|(side, suffix)| Some((side, stem.get(..stem.len() - suffix.len())?.to_string())))
+}
+
+/// Check whether a file below a recursively watched folder is in a folder the
+/// tool writes to: the Processed, Originals and Failed folders next to each
+/// input, or its custom output and review folders
+fn is_tool_output(file_path: &Path, folder: &Path, config: &ToolConfig) -> bool {
+    let custom_output = match &config.output_mode {
+        OutputMode::Custom(path) => Some(PathBuf::from(path)),
+        _ => None,
+    };
+    if custom_output
+        .into_iter()
+        .chain(config.review_folder())
+        .chain(config.approved_folder())
+        .any(|dir| file_path.starts_with(dir))
+    {
+        return true;
+    }
+
+    // Only the subfolders below the watched folder count - the folder itself may
+    // well be inside one named e.g. "Originals"
+    let subfolders = file_path.parent().and_then(|p| p.strip_prefix(folder).ok());
+    subfolders.is_some_and(|relative| {
+        relative.components().any(|c| {
+            c.as_os_str()
+                .to_str()
+                .is_some_and(|s| {
+                    [PROCESSED_FOLDER, ORIGINALS_FOLDER, crate::poison::FAILED_FOLDER]
+                        .iter()
+                        .any(|name| s.eq_ignore_ascii_case(name))
+                })
+        })
+    })
+}
+
+/// Remember a file or folder the app wrote, e.g. an output in the input's folder
+pub fn record_output(path: &Path) {
+    if let Ok(mut outputs) = GENERATED_OUTPUTS.lock() {
+        outputs.retain(|_, written| written.elapsed() < GENERATED_OUTPUT_TTL);
+        outputs.insert(path.to_path_buf(), Instant::now());
+    }
+}
+
+/// Check whether a file is one of the tool's own results: written by the app
+/// recently, or named like its outputs ("invoice_compress.pdf"). With the
+/// same-folder output mode the output lands right next to the inputs.
+fn is_own_output(path: &Path, config: &ToolConfig) -> bool {
+    let written = GENERATED_OUTPUTS.lock().is_ok_and(|outputs| {
+        outputs
+            .iter()
+            .any(|(output, written)| path.starts_with(output) && written.elapsed() < GENERATED_OUTPUT_TTL)
+    });
+    written || matches_output_name(path, config)
+}
+
+/// Check whether a file name follows the output naming of the folder's last tool
+fn matches_output_name(path: &Path, config: &ToolConfig) -> bool {
+    let steps = config.steps();
+    let final_step = steps.last().unwrap_or(config);
+    let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    let file_name = match &final_step.decoration {
+        OutputDecoration::Prefix { .. } => {
+            let marker = decorate_file_name("", &final_step.decoration);
+            match file_name.strip_prefix(&marker) {
+                Some(rest) => rest,
+                None => return false,
+            }
+        }
+        _ => file_name,
+    };
+    if let Some(template) = &final_step.output_template {
+        return crate::naming::is_match(template, &final_step.id, &output_extension(final_step), file_name);
+    }
+    let Some((stem, extension)) = file_name.rsplit_once('.') else {
+        return false;
+    };
+    extension.eq_ignore_ascii_case(&output_extension(final_step))
+        && stem
+            .strip_suffix(&format!("_{}", final_step.id))
+            .is_some_and(|name| !name.is_empty())
+}
+
+/// Files the folder's tool would pick up that are already in it, or only
+/// those that arrived after `arrived_since`
+fn scan_folder(
+    watched: &HashMap<PathBuf, ToolConfig>,
+    routes: &Routes,
+    folder: &Path,
+    tool_config: &ToolConfig,
+    arrived_since: Option<SystemTime>,
+) -> Vec<PathBuf> {
+    // One level down covers the A/B subfolders of compare folders
+    let max_depth = if tool_config.recursive { tool_config.max_depth } else { 1 };
+    // Old archive files aren't offered again
+    let max_age = tool_config
+        .backlog_max_age_days
+        .and_then(|days| SystemTime::now().checked_sub(Duration::from_secs(u64::from(days) * 24 * 60 * 60)));
+    let cutoff = max_age.max(arrived_since);
+
+    let mut files = Vec::new();
+    let mut dirs = vec![(folder.to_path_buf(), 0)];
+    while let Some((dir, depth)) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.is_dir() {
+                if depth < max_depth {
+                    dirs.push((path, depth + 1));
+                }
+                continue;
+            }
+            let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            // Copies keep their modified time on some systems, but get a new creation time
+            let recent = cutoff.is_none_or(|cutoff| {
+                std::fs::metadata(&path).is_ok_and(|m| {
+                    let modified = m.modified().ok();
+                    modified.max(m.created().ok()).is_some_and(|arrived| arrived >= cutoff)
+                })
+            });
+            let taken = recent
+                && !is_temporary(file_name)
+                && FolderWatcher::find_watched_folder(&path, watched, routes).is_some_and(|(f, tool)| {
+                    f == folder
+                        && accepts_input(&tool.id, &path)
+                        && !is_own_output(&path, tool)
+                        && outside_size_limits(&path, tool).is_none()
+                        && !left_and_processed(&path, tool)
+                });
+            if taken {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Whether an input its folder leaves in place was processed since it last changed
+fn left_and_processed(path: &Path, config: &ToolConfig) -> bool {
+    config.originals == OriginalsHandling::Leave
+        && std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .is_ok_and(|modified| crate::dedup::processed_since(&config.id, path, modified))
+}
+
+/// Why a file is outside the folder's size limits, if it is
+fn outside_size_limits(path: &Path, config: &ToolConfig) -> Option<String> {
+    if config.min_size_kb.is_none() && config.max_size_mb.is_none() {
+        return None;
+    }
+    let size = std::fs::metadata(path).ok()?.len();
+    if let Some(min_kb) = config.min_size_kb.filter(|kb| size < kb * 1024) {
+        return Some(format!("smaller than the folder's minimum of {} KB", min_kb));
+    }
+    if let Some(max_mb) = config.max_size_mb.filter(|mb| size > mb * 1024 * 1024) {
+        return Some(format!("larger than the folder's maximum of {} MB", max_mb));
+    }
+    None
+}
+
+/// Check whether only a placeholder of a cloud-synced file is on disk, going by
+/// the marks the sync client sets. Empty files aren't placeholders: a file
+/// copied in empty is picked up like any other once its size is stable.
+///
+/// OneDrive and Dropbox mark online-only files through the Cloud Files API.
+#[cfg(target_os = "windows")]
+fn is_online_only(metadata: &std::fs::Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_OFFLINE: u32 = 0x1000;
+    const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x40000;
+    const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x400000;
+
+    metadata.file_attributes()
+        & (FILE_ATTRIBUTE_OFFLINE | FILE_ATTRIBUTE_RECALL_ON_OPEN | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS)
+        != 0
+}
+
+/// Check whether only a placeholder of a cloud-synced file is on disk. File
+/// Provider clients (iCloud Drive, OneDrive, Dropbox) leave evicted files dataless.
+#[cfg(target_os = "macos")]
+fn is_online_only(metadata: &std::fs::Metadata) -> bool {
+    use std::os::macos::fs::MetadataExt;
+    const SF_DATALESS: u32 = 0x4000_0000;
+
+    metadata.st_flags() & SF_DATALESS != 0
+}
+
+/// Check whether only a placeholder of a cloud-synced file is on disk. Sync
+/// clients here don't mark placeholders.
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn is_online_only(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+/// Have the sync client download a placeholder's content by reading it
+fn request_download(path: &Path) {
+    let path = path.to_path_buf();
+    tokio::spawn(async move {
+        let file = path.clone();
+        let result = crate::local_work::run(move || {
+            std::fs::File::open(&file).and_then(|mut file| std::io::copy(&mut file, &mut std::io::sink()))
+        })
+        .await
+        .map_err(std::io::Error::other)
+        .and_then(|result| result);
+        if let Err(e) = result {
+            warn!("Could not download cloud file {:?}: {}", path, e);
+        }
+    });
+}
+
+/// The file an iCloud stub (".invoice.pdf.icloud") stands for
+fn icloud_stub_target(path: &Path) -> Option<PathBuf> {
+    if !cfg!(target_os = "macos") {
+        return None;
+    }
+    let name = path.file_name()?.to_str()?;
+    let real_name = name.strip_prefix('.')?.strip_suffix(".icloud")?;
+    (!real_name.is_empty()).then(|| path.with_file_name(real_name))
+}
+
+/// Ask iCloud Drive to download an evicted file. The downloaded file then
+/// shows up in the folder like any new file.
+fn request_icloud_download(path: &Path) {
+    if let Err(e) = std::process::Command::new("brctl").arg("download").arg(path).spawn() {
+        warn!("Could not request download of iCloud file {:?}: {}", path, e);
+    }
+}
+
+/// Temporary/partial files and Office lock files (~$report.docx)
+fn is_temporary(file_name: &str) -> bool {
+    file_name.starts_with('.')
+        || file_name.starts_with("~$")
+        || file_name.ends_with(".tmp")
+        || file_name.ends_with(".part")
+}
+
+fn is_report_output(tool_id: &str) -> bool {
+    crate::config::get_tool_definition(tool_id).is_some_and(|t| t.report_output)
+}
+
+/// Check whether a tool's watched folder picks up this file, by extension
+fn accepts_input(tool_id: &str, path: &Path) -> bool {
+    let Some(extension) = path.extension().and_then(|e| e.to_str()) else {
+        return false;
+    };
+    crate::config::get_tool_definition(tool_id)
+        .map(|t| t.input_extensions)
+        .unwrap_or_else(crate::config::default_input_extensions)
+        .iter()
+        .any(|e| e.eq_ignore_ascii_case(extension))
+}
+
+/// Whether a tool's zip results are unpacked, as set for the folder or else by the tool
+fn extracts_zip(config: &ToolConfig) -> bool {
+    let Some(tool) = crate::config::get_tool_definition(&config.id) else {
+        return false;
+    };
+    config.extract_zip.unwrap_or(tool.extract_zip) && output_extension(config) == "zip"
+}
+
+/// Two folder configs would watch the same way
+fn same_settings(a: &ToolConfig, b: &ToolConfig) -> bool {
+    match (serde_json::to_value(a), serde_json::to_value(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Apply the watcher's general settings
+pub fn configure(general: &GeneralSettings) {
+    DEBOUNCE_MS.store(general.debounce_seconds.saturating_mul(1000), Ordering::Relaxed);
+    POLL_INTERVAL_MS.store(general.poll_interval_ms.max(MIN_POLL_INTERVAL_MS), Ordering::Relaxed);
+    STABLE_SIZE_CHECKS.store(general.stable_size_checks, Ordering::Relaxed);
+    PROCESS_EXISTING.store(general.process_existing_files, Ordering::Relaxed);
+}
+
+/// Check whether a folder can be read.
+/// On macOS, the first read of a protected folder shows the system permission prompt.
+pub fn check_folder_access(path: &Path) -> FolderAccess {
+    match std::fs::read_dir(path) {
+        Ok(_) => FolderAccess::Granted,
+        Err(e) => match e.kind() {
+            std::io::ErrorKind::PermissionDenied => FolderAccess::Denied,
+            std::io::ErrorKind::NotFound => FolderAccess::Missing,
+            _ => FolderAccess::Unavailable,
+        },
+    }
+}
+
+/// System settings page where access to `path` can be granted, if the platform has one
+pub fn permission_settings_url(path: &Path) -> Option<&'static str> {
+    if !cfg!(target_os = "macos") {
+        return None;
+    }
+
+    // Desktop, Documents, Downloads and volumes have their own toggles,
+    // anything else needs Full Disk Access
+    let home = dirs::home_dir().unwrap_or_default();
+    let per_folder = ["Desktop", "Documents", "Downloads"]
+        .iter()
+        .any(|name| path.starts_with(home.join(name)))
+        || path.starts_with("/Volumes");
+
+    Some(if per_folder {
+        "x-apple.systempreferences:com.apple.preference.security?Privacy_FilesAndFolders"
+    } else {
+        "x-apple.systempreferences:com.apple.preference.security?Privacy_AllFiles"
+    })
+}
+
+fn watch_change(config: &ToolConfig, folder: &Path, restored: bool, since: String) -> FolderWatchChange {
+    FolderWatchChange {
+        tool_id: config.id.clone(),
+        folder: folder.to_string_lossy().into_owned(),
+        restored,
+        offline: !restored && is_volume_offline(folder),
+        since,
+    }
+}
+
+fn is_volume_offline(folder: &Path) -> bool {
+    removable_volume(folder).is_some_and(|volume| !volume.exists())
+}
+
+/// Where the removable drive a folder is on is mounted, if it is on one:
+/// its drive letter on Windows (other than the system drive), /Volumes/<name>
+/// on macOS, /media/<user>/<name> or /run/media/<user>/<name> on Linux
+#[cfg(target_os = "windows")]
+fn removable_volume(path: &Path) -> Option<PathBuf> {
+    use std::path::{Component, Prefix};
+
+    let Some(Component::Prefix(prefix)) = path.components().next() else {
+        return None;
+    };
+    let Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) = prefix.kind() else {
+        return None;
+    };
+    let system_drive = std::env::var("SystemDrive").unwrap_or_else(|_| "C:".to_string());
+    if system_drive.as_bytes().first().is_some_and(|d| d.eq_ignore_ascii_case(&letter)) {
+        return None;
+    }
+    Some(PathBuf::from(format!("{}:\\", letter as char)))
+}
+
+/// Where the removable drive a folder is on is mounted, if it is on one:
+/// its drive letter on Windows (other than the system drive), /Volumes/<name>
+/// on macOS, /media/<user>/<name> or /run/media/<user>/<name> on Linux
+#[cfg(not(target_os = "windows"))]
+fn removable_volume(path: &Path) -> Option<PathBuf> {
+    let depth = if path.starts_with("/Volumes") {
+        2
+    } else if path.starts_with("/run/media") {
+        4
+    } else if path.starts_with("/media") {
+        3
+    } else {
+        return None;
+    };
+    let volume: PathBuf = path.components().take(depth).collect();
+    (volume.components().count() == depth).then_some(volume)
+}
+
+/// Identifies a folder, so one recreated under the same path is told apart.
+/// None if it doesn't exist (or can't be reached).
+#[cfg(unix)]
+fn folder_identity(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(path).ok().filter(|m| m.is_dir()).map(|m| m.ino())
+}
+
+/// Identifies a folder, so one recreated under the same path is told apart.
+/// None if it doesn't exist (or can't be reached).
+#[cfg(windows)]
+fn folder_identity(path: &Path) -> Option<u64> {
+    use std::os::windows::fs::MetadataExt;
+    std::fs::metadata(path).ok().filter(|m| m.is_dir()).map(|m| m.creation_time())
+}
+
+/// Number of a supervisor probe file, for paths that are one
+fn probe_number(path: &Path) -> Option<u64> {
+    path.file_name()?.to_str()?.strip_prefix(PROBE_PREFIX)?.parse().ok()
+}
+
+/// Hand the events of a notify watcher to the event processor. Sending never
+/// blocks, so the system's event buffer keeps being drained under load.
+fn forward_events(notify_tx: mpsc::UnboundedSender<Event>) -> impl FnMut(Result<Event, notify::Error>) + Send + 'static {
+    move |res| {
+        match res {
+            Ok(event) => {
+                // Log every event we receive
+                crate::add_log(&format!("File system event: {:?}", event.kind));
+                if let Err(e) = notify_tx.send(event) {
+                    crate::add_log(&format!("Failed to send event to channel: {}", e));
                 }
             }
+            Err(e) => {
+                crate::add_log(&format!("File watcher error: {}", e));
+            }
         }
-        best_match
+    }
+}
+
+/// Check whether a folder is on a network share (SMB, NFS...), where the
+/// system's change notifications are unreliable
+#[cfg(target_os = "windows")]
+fn is_network_path(path: &Path) -> bool {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDriveTypeW;
+    const DRIVE_REMOTE: u32 = 4;
+
+    // UNC paths (\\server\share), also in their verbatim form (\\?\UNC\...)
+    let text = path.to_string_lossy();
+    if (text.starts_with(r"\\") && !text.starts_with(r"\\?\")) || text.starts_with(r"\\?\UNC\") {
+        return true;
+    }
+    // Mapped drive letters
+    let Some(root) = path.ancestors().last() else {
+        return false;
+    };
+    let wide: Vec<u16> = root.join("").as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    unsafe { GetDriveTypeW(wide.as_ptr()) == DRIVE_REMOTE }
+}
+
+/// Check whether a folder is on a network share (SMB, NFS...), where the
+/// system's change notifications are unreliable
+#[cfg(target_os = "macos")]
+fn is_network_path(path: &Path) -> bool {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 {
+        return false;
+    }
+    let fs_type = unsafe { CStr::from_ptr(stats.f_fstypename.as_ptr()) };
+    matches!(fs_type.to_bytes(), b"smbfs" | b"nfs" | b"afpfs" | b"webdav")
+}
+
+/// Check whether a folder is on a network share (SMB, NFS...), where the
+/// system's change notifications are unreliable
+#[cfg(all(unix, not(target_os = "macos")))]
+fn is_network_path(path: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    const NFS_SUPER_MAGIC: u32 = 0x6969;
+    const SMB_SUPER_MAGIC: u32 = 0x517b;
+    const CIFS_MAGIC_NUMBER: u32 = 0xff53_4d42;
+    const SMB2_MAGIC_NUMBER: u32 = 0xfe53_4d42;
+
+    let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 {
+        return false;
+    }
+    matches!(
+        stats.f_type as u32,
+        NFS_SUPER_MAGIC | SMB_SUPER_MAGIC | CIFS_MAGIC_NUMBER | SMB2_MAGIC_NUMBER
+    )
+}
+
+fn folder_error(e: std::io::Error, path: &Path) -> WatcherError {
+    if e.kind() == std::io::ErrorKind::PermissionDenied {
+        WatcherError::PermissionDenied(path.to_path_buf())
+    } else {
+        WatcherError::Io(e)
     }
 }
 
@@ -297,42 +2103,559 @@ impl FolderWatcher {
 pub async fn process_file_event(
     event: FileEvent,
     auth_token: Option<String>,
-) -> Result<PathBuf, crate::api::ApiError> {
+    job: &JobRef,
+) -> Result<JobOutput, crate::api::ApiError> {
     let client = PdfDkClient::new(auth_token);
+    let inputs = event.input_paths();
+
+    // A chained folder delivers the output of its last tool
+    let steps = event.tool_config.steps();
+    let final_step = steps.last().unwrap_or(&event.tool_config);
 
     // Determine output path
-    let output_path = get_output_path(&event.path, &event.tool_config);
+    let output_path = match event.stage {
+        PipelineStage::Merge(_) => get_merge_output_path(&event.path, final_step),
+        PipelineStage::Compare { .. } => get_compare_output_path(&event.path, final_step),
+        _ => get_output_path(&event.path, final_step),
+    };
+
+    // Exact copies of inputs the tool already processed aren't billed again
+    let input_hash = if event.stage == PipelineStage::Process {
+        crate::dedup::hash(&event.path).await
+    } else {
+        None
+    };
+    let previous = match input_hash.as_deref() {
+        Some(hash) => {
+            let folder = event.tool_config.folder_path.as_deref().map(Path::new);
+            crate::dedup::find(&event.tool_id, hash, folder).await
+        }
+        None => None,
+    };
+    if let Some(previous) = previous {
+        let reason = previous.describe();
+        match event.tool_config.duplicates {
+            DuplicatePolicy::Skip => {
+                crate::add_log(&format!("Not processing {:?} again: {}", event.path, reason));
+                job.update(|j| j.skipped = Some(reason));
+                if let Err(e) = dispose_original(&event.path, event.tool_config.originals).await {
+                    info!("Could not put away duplicate file: {}", e);
+                }
+                return Ok(JobOutput::Kept(PathBuf::from(previous.output)));
+            }
+            DuplicatePolicy::Warn => {
+                crate::add_log(&format!("WARNING: Processing {:?} again: {}", event.path, reason));
+                job.update(|j| j.duplicate_of = Some(reason));
+            }
+            DuplicatePolicy::Process => {}
+        }
+    }
+
+    // Files that don't need the tool are delivered as they are, without using quota
+    if event.stage == PipelineStage::Process {
+        if let Some(reason) = crate::conditions::unmet(&event.tool_config.conditions, &event.path).await {
+            crate::add_log(&format!("Not running {} on {:?}: {}", event.tool_id, event.path, reason));
+            job.update(|j| j.skipped = Some(reason));
+            let output_dir = output_path.parent().unwrap_or(Path::new("."));
+            let leave = event.tool_config.originals == OriginalsHandling::Leave;
+            let delivered = put_into_folder(&event.path, output_dir, leave).await?;
+            record_output(&delivered);
+            // The input stays, so remember it isn't to be picked up again
+            if let Some(hash) = input_hash.as_deref().filter(|_| leave) {
+                crate::dedup::record(&event.tool_id, hash, &event.path, &delivered);
+            }
+            return Ok(JobOutput::Written(delivered));
+        }
+    }
+
+    // An output of an earlier run may be in the way
+    let output_path = match event.tool_config.on_conflict {
+        ConflictPolicy::Rename if output_path.exists() => numbered_path(&output_path),
+        ConflictPolicy::Skip if output_path.exists() => {
+            let reason = format!("{} already exists", output_path.display());
+            crate::add_log(&format!("Not processing {:?}: {}", event.path, reason));
+            job.update(|j| j.skipped = Some(reason));
+            for input in &inputs {
+                if let Err(e) = dispose_original(input, event.tool_config.originals).await {
+                    info!("Could not put away original file: {}", e);
+                }
+            }
+            return Ok(JobOutput::Kept(output_path));
+        }
+        _ => output_path,
+    };
+
+    let (options, attachments) = job_options(&event)?;
+
+    // Upload from a snapshot, so edits saved meanwhile can't reach the server half-written
+    let snapshot = if crate::staging::is_enabled() {
+        match crate::staging::snapshot(&job.id, &inputs).await {
+            Ok(snapshot) => Some(snapshot),
+            Err(e) => {
+                warn!("Could not snapshot {:?}, uploading the original: {}", event.path, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let upload_inputs = snapshot.as_ref().map(|s| s.paths()).unwrap_or_else(|| inputs.clone());
+
+    let mut timings = JobTimings {
+        upload_bytes: inputs
+            .iter()
+            .filter_map(|p| std::fs::metadata(p).ok())
+            .map(|m| m.len())
+            .sum(),
+        ..Default::default()
+    };
+
+    // Hash the input now - it is moved to Originals once processed
+    let input_sha256 = if crate::receipts::is_enabled() {
+        match crate::receipts::hash_inputs(&upload_inputs).await {
+            Ok(hash) => Some(hash),
+            Err(e) => {
+                warn!("Could not hash input for the job receipt: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let endpoint = tool_endpoint(&event.tool_id);
+    let can_repair = event.stage == PipelineStage::Process
+        && event.tool_config.auto_repair
+        && event.tool_id != REPAIR_TOOL_ID;
+
+    let submitted = run_job(&client, &event, &upload_inputs, &attachments, &endpoint, options.clone(), job, &mut timings).await;
+    let (job_uuid, result) = match submitted {
+        Err(crate::api::ApiError::DamagedPdf(reason)) if can_repair => {
+            crate::add_log(&format!("{:?} is damaged ({}) - repairing it first", event.path, reason));
+            let repaired = repair_file(&client, upload_inputs.first().unwrap_or(&event.path)).await?;
+            let retried = run_job(&client, &event, std::slice::from_ref(&repaired), &attachments, &endpoint, options, job, &mut timings).await;
+            if let Some(dir) = repaired.parent() {
+                let _ = tokio::fs::remove_dir_all(dir).await;
+            }
+            retried?
+        }
+        submitted => submitted?,
+    };
+    note_redactions(job, &result);
+    let (job_uuid, result) = run_chain(&client, &event, &steps[1..], (job_uuid, result), job, &mut timings).await?;
+
+    // Download result
+    job.update(|j| j.set_downloading());
+    let started = Instant::now();
+    record_output(&output_path);
+    client.download_result(&job_uuid, &output_path).await?;
+    if let Some(report_name) = &result.report_filename {
+        // The result is fine without its report, so don't fail the job
+        let report_path = get_report_path(&output_path, report_name);
+        record_output(&report_path);
+        if let Err(e) = client.download_report(&job_uuid, &report_path).await {
+            warn!("Could not download report for {:?}: {}", output_path, e);
+        }
+    }
+    timings.download = started.elapsed();
+
+    job.record_timings(&event.tool_id, &timings);
+    if let Some(hash) = &input_hash {
+        crate::dedup::record(&event.tool_id, hash, &event.path, &output_path);
+    }
+
+    if let Some(snapshot) = &snapshot {
+        let changed = snapshot.changed_sources().await;
+        if !changed.is_empty() {
+            crate::add_log(&format!(
+                "WARNING: {:?} changed while being processed - the result is from the version at the start",
+                changed
+            ));
+            job.update(|j| j.source_changed = true);
+        }
+    }
 
-    // Get tool options
-    let options = event.tool_config.options.clone();
+    if let Some(input_sha256) = input_sha256 {
+        match crate::receipts::create_receipt(&client, &job_uuid, &event.tool_id, &inputs, input_sha256, &output_path).await {
+            Ok(path) => info!("Saved job receipt: {:?}", path),
+            Err(e) => warn!("Could not save job receipt: {}", e),
+        }
+    }
 
-    // Process the file
-    client
-        .process_and_download(&event.path, &output_path, &event.tool_id, options)
-        .await?;
+    if event.tool_config.decoration == OutputDecoration::Overlay {
+        // Only cosmetic - e.g. FAT32 and network shares have no alternate data streams
+        if let Err(e) = apply_overlay(&output_path, &event.tool_id).await {
+            warn!("Could not tag output for the icon overlay: {}", e);
+        }
+    }
 
-    // Move original file to Originals folder after successful processing
-    if let Err(e) = move_to_originals(&event.path).await {
-        // Log warning but don't fail - the processing was successful
-        info!("Could not move original file to Originals folder: {}", e);
+    if crate::folder_index::is_enabled() {
+        if let Some(folder) = event.tool_config.folder_path.as_deref().map(Path::new) {
+            // Only bookkeeping - the job itself succeeded
+            if let Err(e) = crate::folder_index::record(folder, &inputs, &job.id, &event.tool_id, &output_path).await {
+                warn!("Could not update the folder index in {:?}: {}", folder, e);
+            }
+        }
     }
 
-    Ok(output_path)
+    // Reviewed zips are unpacked once approved
+    let output_path = if extracts_zip(final_step) && event.tool_config.review_folder().is_none() {
+        unpack_zip_result(&output_path, &final_step.id).await?
+    } else {
+        output_path
+    };
+
+    if event.tool_config.sidecar {
+        // Only bookkeeping - the job itself succeeded
+        record_output(&crate::sidecar::path_for(&output_path));
+        let queued_at = job
+            .job()
+            .and_then(|j| chrono::DateTime::from_timestamp(j.created_at as i64, 0))
+            .map(|d| d.with_timezone(&chrono::Local).to_rfc3339());
+        let sidecar = crate::sidecar::Sidecar {
+            version: crate::sidecar::SIDECAR_VERSION,
+            job_id: job.id.clone(),
+            job_uuid: job_uuid.clone(),
+            tool_id: event.tool_id.clone(),
+            tools: steps.iter().map(|step| step.id.clone()).collect(),
+            options: event.tool_config.options.clone(),
+            input_files: inputs.iter().map(|p| p.to_string_lossy().into_owned()).collect(),
+            input_size_bytes: timings.upload_bytes,
+            output_file: output_path.to_string_lossy().into_owned(),
+            output_size_bytes: std::fs::metadata(&output_path).ok().filter(|m| m.is_file()).map(|m| m.len()),
+            queued_at,
+            completed_at: chrono::Local::now().to_rfc3339(),
+        };
+        if let Err(e) = crate::sidecar::write(&output_path, sidecar).await {
+            warn!("Could not write the job sidecar for {:?}: {}", output_path, e);
+        }
+    }
+
+    if is_report_output(&final_step.id) {
+        // The original is the result - deliver it unchanged next to its report
+        let output_dir = output_path.parent().unwrap_or(Path::new("."));
+        if event.path.parent() != Some(output_dir) {
+            let leave = event.tool_config.originals == OriginalsHandling::Leave;
+            match put_into_folder(&event.path, output_dir, leave).await {
+                Ok(delivered) => record_output(&delivered),
+                Err(e) => warn!("Could not move original file next to its report: {}", e),
+            }
+        }
+        return Ok(JobOutput::Written(output_path));
+    }
+
+    // Move original files to Originals folder (or as the folder says) after successful processing
+    for input in &inputs {
+        if let Err(e) = dispose_original(input, event.tool_config.originals).await {
+            // Log warning but don't fail - the processing was successful
+            info!("Could not put away original file: {}", e);
+        }
+    }
+
+    Ok(JobOutput::Written(output_path))
+}
+
+/// Run the result of a folder's first tool through the rest of its chain,
+/// downloading each intermediate output to a temporary folder.
+/// Returns the last job, whose result is the one delivered.
+async fn run_chain(
+    client: &PdfDkClient,
+    event: &FileEvent,
+    steps: &[ToolConfig],
+    first: (String, crate::api::JobStatusData),
+    job: &JobRef,
+    timings: &mut JobTimings,
+) -> Result<(String, crate::api::JobStatusData), crate::api::ApiError> {
+    if steps.is_empty() {
+        return Ok(first);
+    }
+    let work_dir = std::env::temp_dir().join(CHAIN_DIR).join(&job.id);
+    let result = run_chain_steps(client, event, steps, first, &work_dir, job, timings).await;
+    let _ = tokio::fs::remove_dir_all(&work_dir).await;
+    result
+}
+
+async fn run_chain_steps(
+    client: &PdfDkClient,
+    event: &FileEvent,
+    steps: &[ToolConfig],
+    first: (String, crate::api::JobStatusData),
+    work_dir: &Path,
+    job: &JobRef,
+    timings: &mut JobTimings,
+) -> Result<(String, crate::api::JobStatusData), crate::api::ApiError> {
+    // Intermediate files keep the document's name, for templates like {filename}
+    let stem = event.path.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+    let mut last = first;
+    let mut previous = &event.tool_config;
+
+    for (i, step) in steps.iter().enumerate() {
+        let input = work_dir
+            .join(i.to_string())
+            .join(format!("{}.{}", stem, output_extension(previous)));
+        job.update(|j| j.set_downloading());
+        client.download_result(&last.0, &input).await?;
+
+        crate::add_log(&format!("Chain step {} of {} for {:?}: {}", i + 2, steps.len() + 1, event.path, step.id));
+        let step_event = FileEvent {
+            path: input.clone(),
+            tool_id: step.id.clone(),
+            tool_config: step.clone(),
+            stage: PipelineStage::Process,
+        };
+        let (options, attachments) = job_options(&step_event)?;
+        last = run_job(client, &step_event, std::slice::from_ref(&input), &attachments, &tool_endpoint(&step.id), options, job, timings).await?;
+        note_redactions(job, &last.1);
+        previous = step;
+    }
+    Ok(last)
+}
+
+/// Flag redacted files in the job history
+fn note_redactions(job: &JobRef, result: &crate::api::JobStatusData) {
+    if let Some(count) = result.extra.get("redaction_count").and_then(|c| c.as_u64()) {
+        job.update(|j| j.redactions = Some(count));
+    }
+}
+
+/// Options to upload with a job, with secrets resolved from the vault and
+/// templates expanded, plus the files the options point to as attachments
+pub(crate) fn job_options(
+    event: &FileEvent,
+) -> Result<(serde_json::Value, Vec<(String, PathBuf)>), crate::api::ApiError> {
+    let mut options = event.tool_config.options.clone();
+    let owner = event.tool_config.secrets_owner();
+    if let Err(e) = crate::secrets::resolve_tool_option_secrets(&event.tool_id, owner, &mut options) {
+        warn!("Could not resolve secret options for {}: {}", event.tool_id, e);
+    }
+
+    crate::options::expand_templates(&event.tool_id, &mut options, &event.path);
+    crate::options::expand_pattern_presets(&event.tool_id, &mut options);
+
+    // Don't spend a job on options the server would reject
+    let option_errors = crate::options::validate_tool_options(&event.tool_id, &options);
+    if !option_errors.is_empty() {
+        return Err(crate::api::ApiError::InvalidOptions(crate::options::describe(&option_errors)));
+    }
+
+    let attachments = match event.stage {
+        PipelineStage::Merge(_) | PipelineStage::Compare { .. } => vec![],
+        _ => crate::options::take_file_options(&event.tool_id, &mut options),
+    };
+    // Collection and pairing settings are used by the watcher, not the server
+    if let Some(obj) = options.as_object_mut() {
+        for key in LOCAL_OPTIONS {
+            obj.remove(key);
+        }
+    }
+    Ok((options, attachments))
+}
+
+/// API endpoint of a tool, falling back to its id
+pub(crate) fn tool_endpoint(tool_id: &str) -> String {
+    crate::config::get_tool_definition(tool_id)
+        .map(|t| t.api_endpoint)
+        .unwrap_or_else(|| tool_id.to_string())
+}
+
+/// Upload `inputs` (one file, or the merge inputs) and wait until the server has finished
+#[allow(clippy::too_many_arguments)]
+async fn run_job(
+    client: &PdfDkClient,
+    event: &FileEvent,
+    inputs: &[PathBuf],
+    attachments: &[(String, PathBuf)],
+    endpoint: &str,
+    options: serde_json::Value,
+    job: &JobRef,
+    timings: &mut JobTimings,
+) -> Result<(String, crate::api::JobStatusData), crate::api::ApiError> {
+    job.update(|j| j.set_uploading());
+    let started = Instant::now();
+    let job_uuid = match &event.stage {
+        // Compare inputs go old version first
+        PipelineStage::Merge(_) | PipelineStage::Compare { .. } => client.process_files(inputs, endpoint, options).await?,
+        _ => client.process_file(inputs.first().unwrap_or(&event.path), attachments, endpoint, options).await?,
+    };
+    timings.upload = started.elapsed();
+
+    // Poll until complete
+    job.update(|j| j.set_processing());
+    let started = Instant::now();
+    let result = client.poll_job(&job_uuid).await?;
+    timings.processing = started.elapsed();
+
+    Ok((job_uuid, result))
+}
+
+/// Run a damaged file through the repair tool into a temporary folder.
+/// The repaired copy keeps the original file name.
+async fn repair_file(client: &PdfDkClient, path: &Path) -> Result<PathBuf, crate::api::ApiError> {
+    let endpoint = tool_endpoint(REPAIR_TOOL_ID);
+    let job_uuid = client.process_file(path, &[], &endpoint, serde_json::json!({})).await?;
+    client.poll_job(&job_uuid).await?;
+
+    let repaired = std::env::temp_dir()
+        .join("pdfdk-repair")
+        .join(&job_uuid)
+        .join(path.file_name().unwrap_or_default());
+    client.download_result(&job_uuid, &repaired).await?;
+    Ok(repaired)
+}
+
+/// Folder the output folders of an event's outputs are placed in: the
+/// input's folder, or the watched folder for jobs of several files
+pub fn source_folder(event: &FileEvent) -> PathBuf {
+    match (&event.stage, &event.tool_config.folder_path) {
+        (PipelineStage::Process, _) | (_, None) => event.path.parent().unwrap_or(Path::new(".")).to_path_buf(),
+        (_, Some(folder)) => PathBuf::from(folder),
+    }
+}
+
+/// Deliver a reviewed output that was moved into the approved folder
+/// to the tool's normal output location
+pub async fn deliver_approved_file(event: &FileEvent) -> Result<PathBuf, std::io::Error> {
+    let source_folder = event
+        .tool_config
+        .folder_path
+        .as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| event.path.parent().unwrap_or(Path::new(".")).to_path_buf());
+
+    let dest_dir = get_output_dir(&source_folder, &event.tool_config);
+    tokio::fs::create_dir_all(&dest_dir).await?;
+
+    let dest_path = dest_dir.join(event.path.file_name().unwrap_or_default());
+    record_output(&dest_path);
+
+    // Rename fails across drives - fall back to copy and delete
+    if tokio::fs::rename(&event.path, &dest_path).await.is_err() {
+        tokio::fs::copy(&event.path, &dest_path).await?;
+        tokio::fs::remove_file(&event.path).await?;
+    }
+    info!("Delivered approved file to: {:?}", dest_path);
+
+    // A chained folder's output comes from its last tool
+    let steps = event.tool_config.steps();
+    let final_step = steps.last().unwrap_or(&event.tool_config);
+    let delivered = if extracts_zip(final_step) {
+        unpack_zip_result(&dest_path, &final_step.id).await?
+    } else {
+        dest_path
+    };
+
+    // The sidecar stays in the review folder when only the output is approved
+    if event.tool_config.sidecar {
+        let mut reviewed = vec![event.path.clone()];
+        reviewed.extend(
+            event
+                .tool_config
+                .review_folder()
+                .map(|folder| folder.join(event.path.file_name().unwrap_or_default())),
+        );
+        record_output(&crate::sidecar::path_for(&delivered));
+        for from in reviewed {
+            if let Err(e) = crate::sidecar::move_along(&from, &delivered).await {
+                warn!("Could not move the job sidecar of {:?}: {}", from, e);
+            }
+        }
+    }
+    Ok(delivered)
+}
+
+/// Unpack a zip result into a subfolder named after the source document
+/// ("invoice_extract-images.zip" -> "invoice/") and delete the archive.
+/// Returns the subfolder.
+async fn unpack_zip_result(zip_path: &Path, tool_id: &str) -> Result<PathBuf, std::io::Error> {
+    let stem = zip_path.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+    let name = stem.strip_suffix(&format!("_{}", tool_id)).unwrap_or(stem);
+    let parent = zip_path.parent().unwrap_or(Path::new("."));
+    let mut folder = parent.join(name);
+    if folder.exists() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        folder = parent.join(format!("{}_{}", name, timestamp));
+    }
+
+    record_output(&folder);
+
+    let archive = zip_path.to_path_buf();
+    let target = folder.clone();
+    crate::local_work::run(move || extract_zip(&archive, &target))
+        .await
+        .map_err(std::io::Error::other)??;
+
+    tokio::fs::remove_file(zip_path).await?;
+    info!("Unpacked {:?} into {:?}", zip_path, folder);
+    Ok(folder)
+}
+
+fn extract_zip(archive: &Path, target: &Path) -> Result<(), std::io::Error> {
+    let mut zip = zip::ZipArchive::new(std::fs::File::open(archive)?).map_err(std::io::Error::other)?;
+    std::fs::create_dir_all(target)?;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(std::io::Error::other)?;
+        // Skip entries that would land outside the target ("../", absolute paths)
+        let Some(relative) = entry.enclosed_name() else {
+            warn!("Skipping unsafe zip entry {:?} in {:?}", entry.name(), archive);
+            continue;
+        };
+        let path = target.join(relative);
+        if entry.is_dir() {
+            std::fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::io::copy(&mut entry, &mut std::fs::File::create(&path)?)?;
+    }
+    Ok(())
+}
+
+/// Put away a processed input as its folder's originals setting says
+async fn dispose_original(file_path: &Path, handling: OriginalsHandling) -> Result<(), std::io::Error> {
+    match handling {
+        OriginalsHandling::Move => move_to_originals(file_path).await.map(|_| ()),
+        OriginalsHandling::Delete => tokio::fs::remove_file(file_path).await,
+        OriginalsHandling::Trash => {
+            let path = file_path.to_path_buf();
+            tokio::task::spawn_blocking(move || trash::delete(&path))
+                .await
+                .map_err(std::io::Error::other)?
+                .map_err(std::io::Error::other)
+        }
+        OriginalsHandling::Leave => Ok(()),
+    }
 }
 
 /// Move the original file to an "Originals" subfolder
-async fn move_to_originals(file_path: &Path) -> Result<(), std::io::Error> {
+async fn move_to_originals(file_path: &Path) -> Result<PathBuf, std::io::Error> {
     let parent = file_path.parent().unwrap_or(Path::new("."));
-    let originals_folder = parent.join("Originals");
+    let moved = move_into_folder(file_path, &parent.join(ORIGINALS_FOLDER)).await?;
+    // Retention ages originals by when they arrived
+    crate::retention::record_arrival(&moved);
+    Ok(moved)
+}
 
-    // Create Originals folder if it doesn't exist
-    tokio::fs::create_dir_all(&originals_folder).await?;
+/// Move a file into `folder`, adding a timestamp instead of overwriting.
+/// Returns where it ended up.
+async fn move_into_folder(file_path: &Path, folder: &Path) -> Result<PathBuf, std::io::Error> {
+    put_into_folder(file_path, folder, false).await
+}
+
+/// Move a file into `folder`, or copy it there with `keep_source`
+async fn put_into_folder(file_path: &Path, folder: &Path, keep_source: bool) -> Result<PathBuf, std::io::Error> {
+    // Create the folder if it doesn't exist
+    tokio::fs::create_dir_all(folder).await?;
 
     // Get filename
     let filename = file_path.file_name().unwrap_or_default();
-    let dest_path = originals_folder.join(filename);
+    let dest_path = folder.join(filename);
 
-    // If file already exists in Originals, add timestamp to avoid overwrite
+    // If file already exists in the folder, add timestamp to avoid overwrite
     let final_dest = if dest_path.exists() {
         let stem = file_path.file_stem().and_then(|s| s.to_str()).unwrap_or("file");
         let ext = file_path.extension().and_then(|s| s.to_str()).unwrap_or("pdf");
@@ -340,16 +2663,48 @@ async fn move_to_originals(file_path: &Path) -> Result<(), std::io::Error> {
             .duration_since(std::time::UNIX_EPOCH)
             .map(|d| d.as_secs())
             .unwrap_or(0);
-        originals_folder.join(format!("{}_{}.{}", stem, timestamp, ext))
+        folder.join(format!("{}_{}.{}", stem, timestamp, ext))
     } else {
         dest_path
     };
 
-    // Move the file
-    tokio::fs::rename(file_path, &final_dest).await?;
+    if keep_source {
+        tokio::fs::copy(file_path, &final_dest).await?;
+        info!("Copied original file to: {:?}", final_dest);
+        return Ok(final_dest);
+    }
+
+    // Move the file - rename fails across drives, so fall back to copy and delete
+    if tokio::fs::rename(file_path, &final_dest).await.is_err() {
+        tokio::fs::copy(file_path, &final_dest).await?;
+        tokio::fs::remove_file(file_path).await?;
+    }
     info!("Moved original file to: {:?}", final_dest);
 
-    Ok(())
+    Ok(final_dest)
+}
+
+/// Extension of the files a tool produces. Tools that offer several output
+/// formats mark the choosing option with `"x-output-extension": true` in their
+/// schema; otherwise it's the tool's fixed output extension.
+fn output_extension(config: &ToolConfig) -> String {
+    let Some(tool) = crate::config::get_tool_definition(&config.id) else {
+        return "pdf".to_string();
+    };
+    tool.options_schema
+        .as_ref()
+        .and_then(|schema| schema.get("properties"))
+        .and_then(|properties| properties.as_object())
+        .and_then(|properties| {
+            properties
+                .iter()
+                .find(|(_, spec)| spec.get("x-output-extension").and_then(|x| x.as_bool()) == Some(true))
+        })
+        .and_then(|(name, _)| config.options.get(name))
+        .and_then(|value| value.as_str())
+        .map(|ext| ext.trim_start_matches('.').to_lowercase())
+        .filter(|ext| !ext.is_empty() && ext.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap_or(tool.output_extension)
 }
 
 /// Get the output path for a processed file
@@ -359,26 +2714,118 @@ fn get_output_path(input_path: &Path, config: &ToolConfig) -> PathBuf {
         .and_then(|s| s.to_str())
         .unwrap_or("output");
 
-    // Determine output extension based on tool type
-    let extension = match config.id.as_str() {
-        "pdf-to-word" => "docx",
-        "pdf-to-excel" => "xlsx",
-        "pdf-to-jpg" => "zip",  // Returns zip of images
-        _ => "pdf",  // All other tools output PDF
+    // Outputs wait in the review folder until approved
+    let dir = config
+        .review_folder()
+        .unwrap_or_else(|| get_output_dir(input_path.parent().unwrap_or(Path::new(".")), config));
+    dir.join(output_file_name(file_stem, config, &dir))
+}
+
+/// Name an output after `name` with the tool's template, or "{name}_{tool}"
+fn output_file_name(name: &str, config: &ToolConfig, dir: &Path) -> String {
+    let extension = output_extension(config);
+    let file_name = match &config.output_template {
+        Some(template) => crate::naming::render(template, name, &config.id, &extension, |file_name| {
+            dir.join(decorate_file_name(file_name, &config.decoration)).exists()
+        }),
+        None => format!("{}_{}.{}", name, config.id, extension),
     };
+    decorate_file_name(&file_name, &config.decoration)
+}
+
+/// Get the output path for the result of merging the files in `folder`
+fn get_merge_output_path(folder: &Path, config: &ToolConfig) -> PathBuf {
+    let name = format!("merged_{}", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+    let dir = config.review_folder().unwrap_or_else(|| get_output_dir(folder, config));
+    match &config.output_template {
+        Some(_) => dir.join(output_file_name(&name, config, &dir)),
+        // Merged outputs aren't named after the tool by default
+        None => dir.join(decorate_file_name(&format!("{}.{}", name, output_extension(config)), &config.decoration)),
+    }
+}
 
-    let output_filename = format!("{}_{}.{}", file_stem, config.id, extension);
+/// Get the output path for the comparison of the two versions of a document,
+/// named after the document rather than either version
+fn get_compare_output_path(new_version: &Path, config: &ToolConfig) -> PathBuf {
+    let folder = config
+        .folder_path
+        .as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| new_version.parent().unwrap_or(Path::new(".")).to_path_buf());
+    let name = pair_role(new_version, &folder, config)
+        .map(|(_, name)| name)
+        .or_else(|| new_version.file_stem().and_then(|s| s.to_str()).map(String::from))
+        .unwrap_or_else(|| "output".to_string());
 
-    match &config.output_mode {
-        OutputMode::SameFolder => {
-            input_path.parent().unwrap_or(Path::new(".")).join(&output_filename)
-        }
-        OutputMode::Subfolder => {
-            let parent = input_path.parent().unwrap_or(Path::new("."));
-            parent.join("Processed").join(&output_filename)
-        }
-        OutputMode::Custom(custom_path) => {
-            PathBuf::from(custom_path).join(&output_filename)
+    let dir = config.review_folder().unwrap_or_else(|| get_output_dir(&folder, config));
+    dir.join(output_file_name(&name, config, &dir))
+}
+
+/// `path` with the lowest number from 2 that isn't taken added to its name,
+/// e.g. "invoice_compress_2.pdf"
+fn numbered_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+    let extension = path.extension().and_then(|e| e.to_str());
+    (2..)
+        .map(|n| match extension {
+            Some(extension) => path.with_file_name(format!("{}_{}.{}", stem, n, extension)),
+            None => path.with_file_name(format!("{}_{}", stem, n)),
+        })
+        .find(|candidate| !candidate.exists())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Place a job's report next to its output, e.g. report_name "validation.xml"
+/// for "invoice_pdf-to-pdfa.pdf" gives "invoice_pdf-to-pdfa_report.xml"
+fn get_report_path(output_path: &Path, report_name: &str) -> PathBuf {
+    let stem = output_path.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+    let extension = Path::new(report_name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("txt");
+    output_path.with_file_name(format!("{}_report.{}", stem, extension))
+}
+
+/// Apply the prefix marker, if the tool uses one, to an output file name
+fn decorate_file_name(file_name: &str, decoration: &OutputDecoration) -> String {
+    match decoration {
+        OutputDecoration::Prefix { marker } => {
+            // Drop characters that are not allowed in file names on any platform
+            let marker: String = marker
+                .chars()
+                .filter(|c| !c.is_control() && !matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|'))
+                .collect();
+            format!("{}{}", marker.trim_start(), file_name)
         }
+        _ => file_name.to_string(),
+    }
+}
+
+/// Tag a finished output for the Explorer icon overlay. The overlay itself is
+/// drawn by the separately installed PDF.dk shell extension helper, which
+/// shows it for files carrying the tag stream.
+#[cfg(target_os = "windows")]
+async fn apply_overlay(output_path: &Path, tool_id: &str) -> Result<(), std::io::Error> {
+    let stream = format!("{}:{}", output_path.display(), OVERLAY_STREAM);
+    tokio::fs::write(stream, tool_id).await
+}
+
+/// Icon overlays are a Windows Explorer feature
+#[cfg(not(target_os = "windows"))]
+async fn apply_overlay(_output_path: &Path, _tool_id: &str) -> Result<(), std::io::Error> {
+    Ok(())
+}
+
+/// Get the output folder for files coming from `source_folder`
+pub fn get_output_dir(source_folder: &Path, config: &ToolConfig) -> PathBuf {
+    output_dir(source_folder, &config.output_mode)
+}
+
+/// Output folder of `mode` for files coming from `source_folder`
+pub fn output_dir(source_folder: &Path, mode: &OutputMode) -> PathBuf {
+    match mode {
+        OutputMode::SameFolder => source_folder.to_path_buf(),
+        OutputMode::Subfolder => source_folder.join(PROCESSED_FOLDER),
+        OutputMode::Custom(custom_path) => PathBuf::from(custom_path),
     }
 }
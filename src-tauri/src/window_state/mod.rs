@@ -0,0 +1,196 @@
+// Window state module for PDF.dk Desktop
+// Remembers window size, position and monitor between runs
+
+use crate::config::{self, WindowGeometry, WindowStates};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::{Monitor, PhysicalPosition, PhysicalSize, Runtime, WebviewWindow, WindowEvent};
+
+pub const MAIN_WINDOW: &str = "main";
+pub const MINI_WINDOW: &str = "mini";
+
+/// Size of the main window on first run and after a reset (logical pixels)
+const DEFAULT_MAIN_SIZE: (f64, f64) = (900.0, 650.0);
+
+// Latest geometry per window label, written to disk when windows close and on exit
+static GEOMETRY: Lazy<Mutex<HashMap<String, WindowGeometry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Restore the saved geometry of a window and keep track of later changes
+pub fn manage<R: Runtime>(window: &WebviewWindow<R>) {
+    if let Some(geometry) = saved_geometry(window.label()) {
+        restore(window, &geometry);
+    }
+
+    let tracked = window.clone();
+    window.on_window_event(move |event| match event {
+        WindowEvent::Moved(_) | WindowEvent::Resized(_) => remember(&tracked),
+        WindowEvent::CloseRequested { .. } | WindowEvent::Destroyed => {
+            if let Err(e) = save(tracked.label()) {
+                crate::add_log(&format!("Failed to save window state: {}", e));
+            }
+        }
+        _ => {}
+    });
+}
+
+/// Write the geometry of every tracked window to the config
+pub fn save_all() -> Result<(), config::ConfigError> {
+    let geometry = GEOMETRY.lock().map(|g| g.clone()).unwrap_or_default();
+    update(|windows| {
+        for (label, g) in geometry {
+            set_geometry(windows, &label, Some(g));
+        }
+    })
+}
+
+/// Remember whether the mini status window is open, to reopen it on startup
+pub fn set_mini_open(open: bool) -> Result<(), config::ConfigError> {
+    update(|windows| windows.mini_open = open)
+}
+
+pub fn mini_was_open() -> bool {
+    config::load_config().map(|c| c.windows.mini_open).unwrap_or(false)
+}
+
+/// Forget saved geometry and move the windows back to the primary monitor,
+/// e.g. when a remembered position is on a disconnected monitor
+pub fn reset<R: Runtime>(windows: &[WebviewWindow<R>]) -> Result<(), config::ConfigError> {
+    if let Ok(mut geometry) = GEOMETRY.lock() {
+        geometry.clear();
+    }
+    update(|w| {
+        w.main = None;
+        w.mini = None;
+    })?;
+
+    for window in windows {
+        if window.label() == MAIN_WINDOW {
+            let _ = window.unmaximize();
+            let _ = window.set_size(tauri::LogicalSize::new(DEFAULT_MAIN_SIZE.0, DEFAULT_MAIN_SIZE.1));
+        }
+        let _ = window.center();
+    }
+    Ok(())
+}
+
+fn saved_geometry(label: &str) -> Option<WindowGeometry> {
+    let windows = config::load_config().ok()?.windows;
+    match label {
+        MAIN_WINDOW => windows.main,
+        MINI_WINDOW => windows.mini,
+        _ => None,
+    }
+}
+
+fn set_geometry(windows: &mut WindowStates, label: &str, geometry: Option<WindowGeometry>) {
+    match label {
+        MAIN_WINDOW => windows.main = geometry,
+        MINI_WINDOW => windows.mini = geometry,
+        _ => {}
+    }
+}
+
+/// Save one window's latest geometry
+fn save(label: &str) -> Result<(), config::ConfigError> {
+    let geometry = GEOMETRY.lock().ok().and_then(|g| g.get(label).cloned());
+    match geometry {
+        Some(geometry) => update(|windows| set_geometry(windows, label, Some(geometry))),
+        None => Ok(()),
+    }
+}
+
+fn update(f: impl FnOnce(&mut WindowStates)) -> Result<(), config::ConfigError> {
+    let mut cfg = config::load_config()?;
+    f(&mut cfg.windows);
+    config::save_config(&cfg)
+}
+
+/// Record the current geometry of a window
+fn remember<R: Runtime>(window: &WebviewWindow<R>) {
+    // Minimized windows report bogus positions
+    if window.is_minimized().unwrap_or(false) {
+        return;
+    }
+
+    let maximized = window.is_maximized().unwrap_or(false);
+    let Ok(mut geometry) = GEOMETRY.lock() else {
+        return;
+    };
+    let label = window.label().to_string();
+
+    // Keep the normal size and position of maximized windows so unmaximizing restores them
+    if maximized {
+        if let Some(existing) = geometry.get_mut(&label) {
+            existing.maximized = true;
+            return;
+        }
+    }
+
+    let (Ok(position), Ok(size)) = (window.outer_position(), window.inner_size()) else {
+        return;
+    };
+    let monitor = window
+        .current_monitor()
+        .ok()
+        .flatten()
+        .and_then(|m| m.name().cloned());
+
+    geometry.insert(
+        label,
+        WindowGeometry {
+            x: position.x,
+            y: position.y,
+            width: size.width,
+            height: size.height,
+            maximized,
+            monitor,
+        },
+    );
+}
+
+/// Apply saved geometry, unless it would put the window on a monitor that is gone
+fn restore<R: Runtime>(window: &WebviewWindow<R>, geometry: &WindowGeometry) {
+    let monitors = window.available_monitors().unwrap_or_default();
+    if !is_visible_on(&monitors, geometry) {
+        crate::add_log(&format!(
+            "Saved position of the {} window is off-screen - using the default",
+            window.label()
+        ));
+        return;
+    }
+
+    let _ = window.set_size(PhysicalSize::new(geometry.width, geometry.height));
+    let _ = window.set_position(PhysicalPosition::new(geometry.x, geometry.y));
+    if geometry.maximized {
+        let _ = window.maximize();
+    }
+
+    if let Ok(mut saved) = GEOMETRY.lock() {
+        saved.insert(window.label().to_string(), geometry.clone());
+    }
+}
+
+/// Check that the window's top-left area lands on a connected monitor,
+/// preferring the monitor it was saved on
+fn is_visible_on(monitors: &[Monitor], geometry: &WindowGeometry) -> bool {
+    // Leave room to grab the title bar
+    const MARGIN: i32 = 50;
+
+    let contains = |m: &Monitor| {
+        let pos = m.position();
+        let size = m.size();
+        geometry.x + MARGIN >= pos.x
+            && geometry.y >= pos.y
+            && geometry.x + MARGIN <= pos.x + size.width as i32
+            && geometry.y + MARGIN <= pos.y + size.height as i32
+    };
+
+    match &geometry.monitor {
+        Some(name) if monitors.iter().any(|m| m.name() == Some(name)) => monitors
+            .iter()
+            .filter(|m| m.name() == Some(name))
+            .any(contains),
+        _ => monitors.iter().any(contains),
+    }
+}
@@ -0,0 +1,111 @@
+// Decoding of API error responses, using bodies recorded from the PDF.dk API
+
+use pdfdk_desktop_lib::api::{decode_error_response, ApiError};
+use reqwest::StatusCode;
+
+fn fixture(name: &str) -> String {
+    let path = format!("{}/tests/fixtures/api_errors/{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("Missing fixture {}: {}", path, e))
+}
+
+#[test]
+fn file_too_large_reads_plan_limit_from_body() {
+    let err = decode_error_response(StatusCode::PAYLOAD_TOO_LARGE, None, &fixture("413_file_too_large.json"));
+    assert!(matches!(err, ApiError::FileTooLarge(25)), "{:?}", err);
+    assert_eq!(err.to_string(), "File too large for your plan (max 25 MB)");
+}
+
+#[test]
+fn file_too_large_from_proxy_falls_back_to_default_limit() {
+    let err = decode_error_response(StatusCode::PAYLOAD_TOO_LARGE, None, &fixture("413_nginx.html"));
+    assert!(matches!(err, ApiError::FileTooLarge(100)), "{:?}", err);
+}
+
+#[test]
+fn job_limit_includes_limit_and_reset() {
+    let err = decode_error_response(StatusCode::TOO_MANY_REQUESTS, None, &fixture("429_job_limit.json"));
+    match &err {
+        ApiError::JobLimitExceeded { limit, resets_at } => {
+            assert_eq!(*limit, Some(20));
+            assert_eq!(resets_at.as_deref(), Some("2026-11-01T00:00:00+01:00"));
+        }
+        other => panic!("Expected JobLimitExceeded, got {:?}", other),
+    }
+    assert_eq!(
+        err.to_string(),
+        "Monthly job limit exceeded (20 jobs) - resets 2026-11-01T00:00:00+01:00"
+    );
+}
+
+#[test]
+fn rate_limit_is_not_reported_as_job_limit() {
+    let err = decode_error_response(StatusCode::TOO_MANY_REQUESTS, None, &fixture("429_rate_limited.json"));
+    assert!(
+        matches!(err, ApiError::RateLimited { retry_after_secs: Some(42) }),
+        "{:?}",
+        err
+    );
+    assert_eq!(err.to_string(), "Too many requests - try again in 42 seconds");
+}
+
+#[test]
+fn retry_after_header_wins_over_body() {
+    let err = decode_error_response(StatusCode::TOO_MANY_REQUESTS, Some("7"), &fixture("429_rate_limited.json"));
+    assert!(
+        matches!(err, ApiError::RateLimited { retry_after_secs: Some(7) }),
+        "{:?}",
+        err
+    );
+}
+
+#[test]
+fn retry_after_header_accepts_http_date() {
+    let when = (chrono::Utc::now() + chrono::Duration::seconds(120)).to_rfc2822();
+    let err = decode_error_response(StatusCode::TOO_MANY_REQUESTS, Some(&when), "");
+    match err {
+        ApiError::RateLimited { retry_after_secs: Some(secs) } => assert!((115..=120).contains(&secs), "{}", secs),
+        other => panic!("Expected RateLimited, got {:?}", other),
+    }
+}
+
+#[test]
+fn maintenance_keeps_server_message() {
+    let err = decode_error_response(StatusCode::SERVICE_UNAVAILABLE, None, &fixture("503_maintenance.json"));
+    assert_eq!(
+        err.to_string(),
+        "PDF.dk is down for maintenance: Scheduled maintenance - back shortly. - try again in 600 seconds"
+    );
+}
+
+#[test]
+fn unauthorized_ignores_body() {
+    let err = decode_error_response(StatusCode::UNAUTHORIZED, None, "{\"message\":\"Unauthenticated.\"}");
+    assert!(matches!(err, ApiError::Unauthorized), "{:?}", err);
+}
+
+#[test]
+fn other_errors_use_error_field() {
+    let err = decode_error_response(StatusCode::INTERNAL_SERVER_ERROR, None, &fixture("500_server_error.json"));
+    assert_eq!(
+        err.to_string(),
+        "Server error: Server returned 500 Internal Server Error: Conversion engine crashed"
+    );
+}
+
+#[test]
+fn raw_body_fallback_is_redacted_and_truncated() {
+    let body = format!("{{\"access_token\":\"abc123\",\"trace\":\"{}\"}}", "x".repeat(1000));
+    let err = decode_error_response(StatusCode::BAD_GATEWAY, None, &body);
+    let text = err.to_string();
+    assert!(!text.contains("abc123"), "{}", text);
+    assert!(text.len() < 400, "{}", text);
+}
+
+#[test]
+fn parse_error_code_is_damaged_pdf() {
+    let err = decode_error_response(StatusCode::UNPROCESSABLE_ENTITY, None, &fixture("422_damaged_pdf.json"));
+    match err {
+        ApiError::DamagedPdf(message) => assert_eq!(message, "Invalid cross-reference table"),
+        other => panic!("Expected DamagedPdf, got {:?}", other),
+    }
+}
@@ -0,0 +1,158 @@
+// Closed days, working time and upload windows, with holidays imported from ICS files
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use pdfdk_desktop_lib::calendar::{
+    closed_reason, import_ics, merge_days, next_working_day, window_open, working_time, CalendarError,
+};
+use pdfdk_desktop_lib::config::{ClosedDay, UploadWindow, WorkCalendar};
+use std::path::PathBuf;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(format!("{}/tests/fixtures/calendar/{}", env!("CARGO_MANIFEST_DIR"), name))
+}
+
+fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+fn at(y: i32, m: u32, d: u32, hour: u32) -> NaiveDateTime {
+    date(y, m, d).and_hms_opt(hour, 0, 0).unwrap()
+}
+
+fn time(text: &str) -> NaiveTime {
+    NaiveTime::parse_from_str(text, "%H:%M").unwrap()
+}
+
+fn window(start: &str, end: &str) -> UploadWindow {
+    UploadWindow {
+        start: start.to_string(),
+        end: end.to_string(),
+    }
+}
+
+fn closed(date: NaiveDate, name: &str) -> ClosedDay {
+    ClosedDay {
+        date,
+        name: Some(name.to_string()),
+    }
+}
+
+#[test]
+fn ics_all_day_events_become_closed_days() {
+    let days = import_ics(&fixture("closures.ics")).unwrap();
+    assert_eq!(
+        days,
+        vec![
+            closed(date(2026, 12, 24), "Juleaften"),
+            // DTEND is exclusive, and escaped commas are unescaped
+            closed(date(2026, 12, 25), "Juledag, 2. juledag"),
+            closed(date(2026, 12, 26), "Juledag, 2. juledag"),
+            // Without DTEND, and with the summary folded right after a space
+            closed(date(2026, 5, 14), "Kristi himmelfartsdag"),
+            // DTEND on the start date still closes that day; an empty summary is no name
+            ClosedDay {
+                date: date(2026, 12, 31),
+                name: None,
+            },
+        ]
+    );
+}
+
+#[test]
+fn ics_timed_and_long_events_are_skipped() {
+    let days = import_ics(&fixture("closures.ics")).unwrap();
+    // Timed events on the nights the clocks change
+    assert!(!days.iter().any(|d| d.date == date(2026, 10, 25)));
+    assert!(!days.iter().any(|d| d.date == date(2026, 3, 29)));
+    // A two-month "busy" block
+    assert!(!days.iter().any(|d| d.date == date(2026, 7, 1)));
+}
+
+#[test]
+fn ics_without_all_day_events_is_refused() {
+    let err = import_ics(&fixture("meetings.ics")).unwrap_err();
+    assert!(matches!(err, CalendarError::NoEvents), "{:?}", err);
+}
+
+#[test]
+fn merge_days_skips_known_dates_and_sorts() {
+    let mut calendar = WorkCalendar {
+        closed_dates: vec![closed(date(2026, 12, 25), "Closed for Christmas")],
+        ..WorkCalendar::default()
+    };
+    let added = merge_days(&mut calendar, import_ics(&fixture("closures.ics")).unwrap());
+    assert_eq!(added, 4);
+    let dates: Vec<_> = calendar.closed_dates.iter().map(|d| d.date).collect();
+    assert_eq!(
+        dates,
+        vec![date(2026, 5, 14), date(2026, 12, 24), date(2026, 12, 25), date(2026, 12, 26), date(2026, 12, 31)]
+    );
+    // The day already in the calendar keeps its name
+    assert_eq!(calendar.closed_dates[2].name.as_deref(), Some("Closed for Christmas"));
+}
+
+#[test]
+fn closed_days_and_weekends_are_not_working_days() {
+    let calendar = WorkCalendar {
+        closed_dates: vec![closed(date(2026, 12, 28), "Company closed")],
+        ..WorkCalendar::default()
+    };
+    assert_eq!(closed_reason(&calendar, date(2026, 12, 26)).as_deref(), Some("Weekend"));
+    assert_eq!(closed_reason(&calendar, date(2026, 12, 28)).as_deref(), Some("Company closed"));
+    assert_eq!(closed_reason(&calendar, date(2026, 12, 29)), None);
+    // Friday is followed by a weekend and a closed Monday
+    assert_eq!(next_working_day(&calendar, date(2026, 12, 25)), date(2026, 12, 29));
+}
+
+#[test]
+fn working_time_leaves_out_weekends_and_closed_days() {
+    let calendar = WorkCalendar {
+        closed_dates: vec![closed(date(2026, 12, 24), "Juleaften")],
+        ..WorkCalendar::default()
+    };
+    // Wednesday 18:00 to Monday 09:00: Wednesday evening, Friday and Monday morning
+    assert_eq!(
+        working_time(&calendar, at(2026, 12, 23, 18), at(2026, 12, 28, 9)),
+        Duration::hours(6 + 24 + 9)
+    );
+    assert_eq!(working_time(&calendar, at(2026, 12, 28, 9), at(2026, 12, 28, 11)), Duration::hours(2));
+    assert_eq!(working_time(&calendar, at(2026, 12, 28, 11), at(2026, 12, 28, 9)), Duration::zero());
+}
+
+#[test]
+fn working_time_counts_wall_clock_hours_across_dst_changes() {
+    let calendar = WorkCalendar {
+        weekends_closed: false,
+        ..WorkCalendar::default()
+    };
+    // Local times: the day the clocks go forward or back still counts as 24 hours
+    assert_eq!(working_time(&calendar, at(2026, 3, 28, 12), at(2026, 3, 29, 12)), Duration::hours(24));
+    assert_eq!(working_time(&calendar, at(2026, 10, 24, 12), at(2026, 10, 25, 12)), Duration::hours(24));
+}
+
+#[test]
+fn daytime_window_includes_its_start_but_not_its_end() {
+    let window = window("08:00", "16:00");
+    assert!(!window_open(&window, time("07:59")));
+    assert!(window_open(&window, time("08:00")));
+    assert!(window_open(&window, time("15:59")));
+    assert!(!window_open(&window, time("16:00")));
+}
+
+#[test]
+fn night_window_runs_past_midnight() {
+    let window = window("18:00", "06:00");
+    assert!(window_open(&window, time("18:00")));
+    assert!(window_open(&window, time("23:59")));
+    assert!(window_open(&window, time("00:00")));
+    // Times the clocks skip or repeat are still inside the window
+    assert!(window_open(&window, time("02:30")));
+    assert!(!window_open(&window, time("06:00")));
+    assert!(!window_open(&window, time("12:00")));
+}
+
+#[test]
+fn window_with_unreadable_times_is_always_open() {
+    assert!(window_open(&window("8", "16:00"), time("03:00")));
+    assert!(window_open(&window("08:00", "25:00"), time("03:00")));
+}
@@ -0,0 +1,145 @@
+// Upgrading configs written by older versions of the app
+
+use pdfdk_desktop_lib::migrations::{
+    migrate, migrate_to, version_of, Migration, MigrationError, CONFIG_VERSION, MIGRATIONS,
+};
+use serde_json::{json, Value};
+
+fn fixture(name: &str) -> Value {
+    let path = format!("{}/tests/fixtures/config_migrations/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("Missing fixture {}: {}", path, e));
+    serde_json::from_str(&text).unwrap_or_else(|e| panic!("Invalid fixture {}: {}", path, e))
+}
+
+fn rename_language(config: &mut Value) -> Result<(), String> {
+    let general = config["general"].as_object_mut().ok_or("no general section")?;
+    let language = general.remove("language").ok_or("no language")?;
+    general.insert("locale".to_string(), language);
+    Ok(())
+}
+
+fn add_marker(config: &mut Value) -> Result<(), String> {
+    // Proves the steps run in order: only runs after the rename
+    if config["general"].get("locale").is_none() {
+        return Err("ran before the rename".to_string());
+    }
+    config["marker"] = json!(true);
+    Ok(())
+}
+
+const STEPS: &[Migration] = &[
+    Migration {
+        from: 2,
+        description: "add a marker",
+        apply: add_marker,
+    },
+    Migration {
+        from: 1,
+        description: "rename general.language to general.locale",
+        apply: rename_language,
+    },
+];
+
+#[test]
+fn every_version_up_to_the_current_one_has_a_step() {
+    let mut config = fixture("v1.json");
+    let from = migrate(&mut config).expect("a v1 config migrates");
+    assert_eq!(from, (CONFIG_VERSION > 1).then_some(1));
+    assert_eq!(version_of(&config).unwrap(), CONFIG_VERSION);
+}
+
+#[test]
+fn current_config_is_left_alone() {
+    let mut config = fixture("v1.json");
+    config["version"] = json!(CONFIG_VERSION);
+    let before = config.clone();
+    assert_eq!(migrate(&mut config).unwrap(), None);
+    assert_eq!(config, before);
+}
+
+#[test]
+fn steps_run_in_order_and_bump_the_version() {
+    let mut config = fixture("v1.json");
+    assert_eq!(migrate_to(&mut config, 3, STEPS).unwrap(), Some(1));
+    assert_eq!(config["version"], json!(3));
+    assert_eq!(config["general"]["locale"], json!("da"));
+    assert!(config["general"].get("language").is_none());
+    assert_eq!(config["marker"], json!(true));
+    // The rest of the config is kept
+    assert_eq!(config["tools"][0]["options"]["quality"], json!("medium"));
+}
+
+#[test]
+fn migration_starts_at_the_config_version() {
+    let mut config = fixture("v1.json");
+    config["version"] = json!(2);
+    config["general"]["locale"] = json!("en");
+    assert_eq!(migrate_to(&mut config, 3, STEPS).unwrap(), Some(2));
+    assert_eq!(config["general"]["language"], json!("da"), "the v1 step must not run again");
+    assert_eq!(config["marker"], json!(true));
+}
+
+#[test]
+fn config_without_version_counts_as_version_1() {
+    let mut config = fixture("v1.json");
+    config.as_object_mut().unwrap().remove("version");
+    assert_eq!(version_of(&config).unwrap(), 1);
+    assert_eq!(migrate_to(&mut config, 2, STEPS).unwrap(), Some(1));
+    assert_eq!(config["version"], json!(2));
+}
+
+#[test]
+fn newer_config_is_refused() {
+    let mut config = fixture("v1.json");
+    config["version"] = json!(CONFIG_VERSION + 1);
+    let err = migrate(&mut config).unwrap_err();
+    assert!(matches!(err, MigrationError::TooNew(v, _) if v == CONFIG_VERSION + 1), "{:?}", err);
+}
+
+#[test]
+fn missing_step_is_reported() {
+    let mut config = fixture("v1.json");
+    let err = migrate_to(&mut config, 2, &STEPS[..1]).unwrap_err();
+    assert!(matches!(err, MigrationError::MissingStep(1)), "{:?}", err);
+}
+
+#[test]
+fn failed_step_names_its_version() {
+    let mut config = fixture("v1.json");
+    config["general"].as_object_mut().unwrap().remove("language");
+    let err = migrate_to(&mut config, 3, STEPS).unwrap_err();
+    assert!(matches!(&err, MigrationError::Failed(1, reason) if reason == "no language"), "{:?}", err);
+    assert_eq!(err.to_string(), "Migrating from schema version 1 failed: no language");
+}
+
+#[test]
+fn schema_1_tool_folders_move_into_a_folder_list() {
+    let mut config = fixture("v1.json");
+    assert_eq!(migrate_to(&mut config, 2, MIGRATIONS).unwrap(), Some(1));
+
+    let compress = &config["tools"][0];
+    assert_eq!(
+        compress["folders"],
+        json!([{ "path": "C:\\Users\\anna\\Documents\\PDF.dk\\Compress", "outputMode": "subfolder" }])
+    );
+    assert!(compress.get("folderPath").is_none());
+    assert!(compress.get("outputMode").is_none());
+    assert_eq!(compress["options"]["quality"], json!("medium"));
+
+    let ocr = &config["tools"][1];
+    assert_eq!(
+        ocr["folders"],
+        json!([{ "path": "\\\\fileserver\\scans\\OCR", "outputMode": { "custom": "\\\\fileserver\\archive" } }])
+    );
+    assert_eq!(ocr["options"]["language"], json!("dan"));
+}
+
+#[test]
+fn schema_1_tool_without_folder_gets_an_empty_list() {
+    let mut config = fixture("v1.json");
+    migrate_to(&mut config, 2, MIGRATIONS).unwrap();
+    let rotate = &config["tools"][2];
+    assert_eq!(rotate["folders"], json!([]));
+    assert!(rotate.get("outputMode").is_none());
+    assert_eq!(rotate["enabled"], json!(false));
+}
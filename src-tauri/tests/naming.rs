@@ -0,0 +1,103 @@
+// Output file names from a tool's template: validation, expansion and
+// recognising outputs the template named
+
+use pdfdk_desktop_lib::naming::{is_match, render, validate, NamingError};
+
+fn nothing_taken(_: &str) -> bool {
+    false
+}
+
+#[test]
+fn templates_with_a_unique_and_distinguishable_part_are_valid() {
+    for template in ["{name}_{tool}", "{date}_{name}", "scan_{counter}", "{name} ({time})", "{ NAME }-pdfdk"] {
+        assert_eq!(validate(template), Ok(()), "{}", template);
+    }
+}
+
+#[test]
+fn empty_template_is_refused() {
+    assert_eq!(validate(""), Err(NamingError::Empty));
+    assert_eq!(validate("   "), Err(NamingError::Empty));
+}
+
+#[test]
+fn unknown_placeholder_is_named() {
+    let err = validate("{nmae}_{tool}").unwrap_err();
+    assert_eq!(err, NamingError::UnknownPlaceholder("nmae".to_string()));
+    assert_eq!(
+        err.to_string(),
+        "Unknown placeholder {nmae} - use {name}, {tool}, {date}, {time} or {counter}"
+    );
+}
+
+#[test]
+fn unclosed_brace_is_refused() {
+    assert_eq!(validate("{name}_{tool"), Err(NamingError::UnclosedBrace));
+}
+
+#[test]
+fn characters_file_names_cant_hold_are_refused() {
+    assert_eq!(validate("{name}:{tool}"), Err(NamingError::IllegalCharacter(':')));
+    assert_eq!(validate("out/{name}"), Err(NamingError::IllegalCharacter('/')));
+    assert_eq!(validate("{name}\t{tool}"), Err(NamingError::IllegalCharacter('\t')));
+}
+
+#[test]
+fn template_without_name_or_counter_is_not_unique() {
+    assert_eq!(validate("scan_{date}"), Err(NamingError::NotUnique));
+}
+
+#[test]
+fn template_that_looks_like_an_input_is_refused() {
+    assert_eq!(validate("{name}"), Err(NamingError::NotDistinguishable));
+    assert_eq!(validate("{counter}"), Err(NamingError::NotDistinguishable));
+    assert_eq!(validate(" {name} "), Err(NamingError::NotDistinguishable));
+}
+
+#[test]
+fn render_fills_in_name_and_tool() {
+    assert_eq!(render("{name}_{tool}", "invoice", "compress", "pdf", nothing_taken), "invoice_compress.pdf");
+    // Placeholders ignore case and spaces, and the name is trimmed before the extension
+    assert_eq!(render(" { Tool }-{name} ", "invoice", "ocr", "pdf", nothing_taken), "ocr-invoice.pdf");
+}
+
+#[test]
+fn render_counts_past_taken_names() {
+    let taken = |file_name: &str| ["scan (1).pdf", "scan (2).pdf"].contains(&file_name);
+    assert_eq!(render("{name} ({counter})", "scan", "compress", "pdf", taken), "scan (3).pdf");
+    assert_eq!(render("{name} ({counter})", "other", "compress", "pdf", taken), "other (1).pdf");
+}
+
+#[test]
+fn render_falls_back_to_the_default_for_invalid_templates() {
+    assert_eq!(render("{nmae}", "invoice", "compress", "pdf", nothing_taken), "invoice_compress.pdf");
+}
+
+#[test]
+fn rendered_dates_and_times_are_recognised() {
+    let template = "{date}_{time}_{name}";
+    let file_name = render(template, "invoice", "compress", "pdf", nothing_taken);
+    assert!(file_name.ends_with("_invoice.pdf"), "{}", file_name);
+    assert!(is_match(template, "compress", "pdf", &file_name), "{}", file_name);
+}
+
+#[test]
+fn outputs_are_told_apart_from_inputs() {
+    assert!(is_match("{name}_{tool}", "compress", "pdf", "invoice_compress.pdf"));
+    assert!(is_match("{name}_{tool}", "compress", "pdf", "INVOICE_COMPRESS.PDF"));
+    assert!(!is_match("{name}_{tool}", "compress", "pdf", "invoice.pdf"));
+    assert!(!is_match("{name}_{tool}", "compress", "pdf", "invoice_ocr.pdf"));
+    assert!(!is_match("{name}_{tool}", "compress", "pdf", "invoice_compress.docx"));
+
+    assert!(is_match("{date}_{name}", "compress", "pdf", "2026-10-15_invoice.pdf"));
+    assert!(!is_match("{date}_{name}", "compress", "pdf", "20261015_invoice.pdf"));
+    assert!(is_match("{name} ({counter})", "compress", "pdf", "scan (3).pdf"));
+    assert!(!is_match("{name} ({counter})", "compress", "pdf", "scan (x).pdf"));
+}
+
+#[test]
+fn templates_that_look_like_inputs_match_nothing() {
+    assert!(!is_match("{name}", "compress", "pdf", "invoice.pdf"));
+    assert!(!is_match("{counter}", "compress", "pdf", "1.pdf"));
+    assert!(!is_match("{nmae}_{tool}", "compress", "pdf", "invoice_compress.pdf"));
+}
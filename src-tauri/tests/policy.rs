@@ -0,0 +1,130 @@
+// Laying the machine policy over the user's config, and taking it back out
+// before the config is written to the user's file
+
+use pdfdk_desktop_lib::config::{AppConfig, OutputMode, ToolConfig, WatchFolder};
+use pdfdk_desktop_lib::policy::Policy;
+use serde_json::{json, Value};
+
+fn policy() -> Policy {
+    let path = format!("{}/tests/fixtures/policy/policy.json", env!("CARGO_MANIFEST_DIR"));
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("Missing fixture {}: {}", path, e));
+    serde_json::from_str(&text).unwrap_or_else(|e| panic!("Invalid fixture {}: {}", path, e))
+}
+
+fn tool(id: &str, folder: &str) -> ToolConfig {
+    let mut tool = ToolConfig::new(id, folder);
+    tool.folders = vec![WatchFolder::new(folder, OutputMode::Subfolder)];
+    tool
+}
+
+/// The user's config.json: their own OCR folder, rotate enabled and a health port
+fn user_file() -> Value {
+    let mut config = AppConfig {
+        tools: vec![tool("compress", "C:\\Compress"), tool("ocr", "C:\\OCR"), tool("rotate", "C:\\Rotate")],
+        ..AppConfig::default()
+    };
+    config.general.health_port = Some(8080);
+    serde_json::to_value(config).unwrap()
+}
+
+fn tool_ids(config: &Value) -> Vec<&str> {
+    config["tools"].as_array().unwrap().iter().map(|tool| tool["id"].as_str().unwrap()).collect()
+}
+
+#[test]
+fn fresh_config_takes_the_policy_defaults() {
+    let mut config = serde_json::to_value(AppConfig::default()).unwrap();
+    policy().apply(&mut config, true);
+    assert_eq!(config["general"]["language"], "en");
+    assert_eq!(config["general"]["startMinimized"], false);
+    // Settings the policy has no default for keep the app's
+    assert_eq!(config["general"]["startOnLogin"], true);
+}
+
+#[test]
+fn users_own_values_win_over_the_policy_defaults() {
+    let mut config = user_file();
+    config["general"].as_object_mut().unwrap().remove("startMinimized");
+    policy().apply(&mut config, false);
+    assert_eq!(config["general"]["language"], "da");
+    // Only settings the user's config doesn't have are filled in
+    assert_eq!(config["general"]["startMinimized"], false);
+}
+
+#[test]
+fn locked_settings_win_over_the_users() {
+    let mut config = user_file();
+    policy().apply(&mut config, false);
+    assert_eq!(config["general"]["showNotifications"], false);
+    assert_eq!(config["general"]["healthPort"], 9123);
+    assert_eq!(config["general"]["language"], "da");
+}
+
+#[test]
+fn forced_tools_replace_the_users_and_are_enabled() {
+    let mut config = user_file();
+    policy().apply(&mut config, false);
+    assert_eq!(tool_ids(&config), vec!["compress", "ocr", "rotate", "watermark"]);
+
+    let ocr = &config["tools"][1];
+    assert_eq!(ocr["enabled"], true);
+    assert_eq!(ocr["folders"][0]["path"], "\\\\fileserver\\scans\\OCR");
+    assert_eq!(ocr["options"], json!({ "language": "dan" }));
+    assert_eq!(config["tools"][0]["folders"][0]["path"], "C:\\Compress");
+}
+
+#[test]
+fn disabled_tools_are_turned_off() {
+    let mut config = user_file();
+    policy().apply(&mut config, false);
+    assert_eq!(config["tools"][2]["id"], "rotate");
+    assert_eq!(config["tools"][2]["enabled"], false);
+}
+
+#[test]
+fn enforce_undoes_changes_to_what_the_policy_sets() {
+    let policy = policy();
+    let mut config: AppConfig = serde_json::from_value(user_file()).unwrap();
+    config.general.show_notifications = true;
+    config.general.language = "en".to_string();
+    policy.enforce(&mut config);
+
+    assert!(!config.general.show_notifications);
+    assert_eq!(config.general.language, "en");
+    let rotate = config.tools.iter().find(|tool| tool.id == "rotate").unwrap();
+    assert!(!rotate.enabled);
+    let ocr = config.tools.iter().find(|tool| tool.id == "ocr").unwrap();
+    assert_eq!(ocr.main_folder(), Some("\\\\fileserver\\scans\\OCR"));
+}
+
+#[test]
+fn restore_round_trip_gives_back_the_users_file() {
+    let policy = policy();
+    let user = user_file();
+    let mut config = user.clone();
+    policy.apply(&mut config, false);
+    // The user changes a setting the policy doesn't lock while the app runs
+    config["general"]["language"] = json!("en");
+
+    policy.restore(&mut config, &user);
+
+    let mut expected = user.clone();
+    expected["general"]["language"] = json!("en");
+    assert_eq!(config, expected);
+}
+
+#[test]
+fn restore_falls_back_to_the_app_defaults() {
+    let policy = policy();
+    let mut user = user_file();
+    let general = user["general"].as_object_mut().unwrap();
+    general.remove("showNotifications");
+    general.remove("healthPort");
+    let mut config = user.clone();
+    policy.apply(&mut config, false);
+
+    policy.restore(&mut config, &user);
+    assert_eq!(config["general"]["showNotifications"], AppConfig::default().general.show_notifications);
+    // The app has no default health port, so the setting is left out
+    assert!(config["general"].get("healthPort").is_none());
+}
@@ -0,0 +1,92 @@
+// Comparing the config on disk with the running one when it is changed outside the app
+
+use pdfdk_desktop_lib::config::{AppConfig, AuthConfig, OutputMode, ToolConfig, WatchFolder};
+use pdfdk_desktop_lib::reload::{same_settings, tool_changes};
+
+fn tool(id: &str, folder: &str) -> ToolConfig {
+    let mut tool = ToolConfig::new(id, folder);
+    tool.folders = vec![WatchFolder::new(folder, OutputMode::Subfolder)];
+    tool
+}
+
+fn config(tools: Vec<ToolConfig>) -> AppConfig {
+    AppConfig {
+        tools,
+        ..AppConfig::default()
+    }
+}
+
+#[test]
+fn identical_configs_have_no_tool_changes() {
+    let config = config(vec![tool("compress", "C:\\Compress"), tool("ocr", "C:\\OCR")]);
+    let changes = tool_changes(&config, &config.clone());
+    assert!(changes.is_empty(), "{:?}", changes);
+}
+
+#[test]
+fn tools_are_sorted_into_added_removed_and_changed() {
+    let old = config(vec![tool("compress", "C:\\Compress"), tool("ocr", "C:\\OCR"), tool("rotate", "C:\\Rotate")]);
+    let mut rotate = tool("rotate", "C:\\Rotate");
+    rotate.options = serde_json::json!({ "angle": 90 });
+    let new = config(vec![
+        rotate,
+        tool("watermark", "C:\\Watermark"),
+        tool("merge", "C:\\Merge"),
+        tool("compress", "C:\\Compress"),
+    ]);
+
+    let changes = tool_changes(&old, &new);
+    assert_eq!(changes.added, vec!["merge", "watermark"]);
+    assert_eq!(changes.removed, vec!["ocr"]);
+    // A tool only moved in the list is unchanged
+    assert_eq!(changes.changed, vec!["rotate"]);
+}
+
+#[test]
+fn changed_folder_list_changes_the_tool() {
+    let old = config(vec![tool("ocr", "C:\\OCR")]);
+    let mut ocr = tool("ocr", "C:\\OCR");
+    ocr.folders.push(WatchFolder::new("\\\\fileserver\\scans", OutputMode::SameFolder));
+    let new = config(vec![ocr]);
+    assert_eq!(tool_changes(&old, &new).changed, vec!["ocr"]);
+}
+
+#[test]
+fn fields_that_are_not_saved_dont_change_a_tool() {
+    let old = config(vec![tool("ocr", "C:\\OCR")]);
+    let mut ocr = tool("ocr", "C:\\OCR");
+    // The per-folder view the watcher gets, which is never written to the file
+    ocr.folder_path = Some("D:\\Elsewhere".to_string());
+    ocr.recursive = true;
+    let new = config(vec![ocr]);
+    assert!(tool_changes(&old, &new).is_empty());
+}
+
+#[test]
+fn auth_and_window_geometry_are_not_settings() {
+    let old = config(vec![tool("compress", "C:\\Compress")]);
+    let mut new = old.clone();
+    new.auth = Some(AuthConfig {
+        email: Some("anna@example.dk".to_string()),
+        ..AuthConfig::default()
+    });
+    new.windows.mini_open = true;
+    assert!(same_settings(&old, &new));
+}
+
+#[test]
+fn other_changes_are_settings() {
+    let old = config(vec![tool("compress", "C:\\Compress")]);
+
+    let mut general = old.clone();
+    general.general.show_notifications = !old.general.show_notifications;
+    assert!(!same_settings(&old, &general));
+
+    let mut calendar = old.clone();
+    calendar.calendar.weekends_closed = !old.calendar.weekends_closed;
+    assert!(!same_settings(&old, &calendar));
+
+    let mut tools = old.clone();
+    tools.tools[0].enabled = false;
+    assert!(!same_settings(&old, &tools));
+}
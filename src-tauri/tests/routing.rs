@@ -0,0 +1,125 @@
+// Routing of files dropped in an inbox folder to a tool chosen by their file name
+
+use pdfdk_desktop_lib::config::{AppConfig, OutputMode, RoutingRule, ToolConfig, WatchFolder};
+use pdfdk_desktop_lib::routing::{compile, route, routes, RoutingError};
+use std::path::PathBuf;
+
+const INBOX: &str = "C:\\Scans\\Inbox";
+const OTHER_INBOX: &str = "C:\\Scans\\Reception";
+
+fn rule(folder_path: &str, pattern: &str, tool_id: &str) -> RoutingRule {
+    RoutingRule {
+        folder_path: folder_path.to_string(),
+        pattern: pattern.to_string(),
+        tool_id: tool_id.to_string(),
+    }
+}
+
+fn config(rules: Vec<RoutingRule>) -> AppConfig {
+    AppConfig {
+        tools: vec![],
+        rules,
+        ..AppConfig::default()
+    }
+}
+
+fn routed_tool(config: &AppConfig, inbox: &str, file_name: &str) -> Option<String> {
+    let routes = routes(config).unwrap();
+    route(&routes[&PathBuf::from(inbox)], file_name).map(|tool| tool.id.clone())
+}
+
+#[test]
+fn glob_matches_the_whole_name_ignoring_case() {
+    let glob = compile("invoice_*.pdf").unwrap();
+    assert!(glob.is_match("invoice_2026-01.pdf"));
+    assert!(glob.is_match("INVOICE_7.PDF"));
+    assert!(glob.is_match("invoice_.pdf"));
+    assert!(!glob.is_match("old_invoice_7.pdf"));
+    assert!(!glob.is_match("invoice_7.pdf.bak"));
+}
+
+#[test]
+fn glob_question_mark_matches_one_character() {
+    let glob = compile("scan??.pdf").unwrap();
+    assert!(glob.is_match("scan01.pdf"));
+    assert!(!glob.is_match("scan1.pdf"));
+    assert!(!glob.is_match("scan001.pdf"));
+}
+
+#[test]
+fn glob_takes_regex_characters_literally() {
+    let glob = compile("report (final)+v1.pdf").unwrap();
+    assert!(glob.is_match("Report (Final)+v1.pdf"));
+    assert!(!glob.is_match("report final+v1.pdf"));
+    assert!(!compile("a.pdf").unwrap().is_match("axpdf"));
+}
+
+#[test]
+fn pattern_in_slashes_is_an_unanchored_regex() {
+    let dated = compile(r"/^\d{8}_.*\.pdf$/").unwrap();
+    assert!(dated.is_match("20260115_scan.pdf"));
+    assert!(!dated.is_match("scan_20260115.pdf"));
+
+    let contract = compile("/contract/").unwrap();
+    assert!(contract.is_match("Signed_Contract_v2.pdf"));
+    assert!(!contract.is_match("offer.pdf"));
+}
+
+#[test]
+fn invalid_regex_names_the_pattern() {
+    let err = compile("/[unclosed/").unwrap_err();
+    assert!(matches!(&err, RoutingError::Pattern(pattern, _) if pattern == "/[unclosed/"), "{:?}", err);
+}
+
+#[test]
+fn first_matching_rule_of_the_inbox_wins() {
+    let config = config(vec![
+        rule(INBOX, "invoice_*.pdf", "compress"),
+        rule(OTHER_INBOX, "*", "rotate"),
+        rule(INBOX, "*.pdf", "ocr"),
+    ]);
+    assert_eq!(routed_tool(&config, INBOX, "invoice_1.pdf").as_deref(), Some("compress"));
+    assert_eq!(routed_tool(&config, INBOX, "scan.pdf").as_deref(), Some("ocr"));
+    assert_eq!(routed_tool(&config, INBOX, "notes.txt"), None);
+    // Rules of other inboxes don't apply
+    assert_eq!(routed_tool(&config, OTHER_INBOX, "invoice_1.pdf").as_deref(), Some("rotate"));
+}
+
+#[test]
+fn routed_tool_uses_its_own_config_and_first_folder() {
+    let mut compress = ToolConfig::new("compress", "");
+    compress.enabled = false;
+    compress.options = serde_json::json!({ "quality": "high" });
+    compress.folders = vec![
+        WatchFolder::new("C:\\Compress", OutputMode::Custom("C:\\Compressed".to_string())),
+        WatchFolder::new("D:\\Compress", OutputMode::SameFolder),
+    ];
+    let mut config = config(vec![rule(INBOX, "*.pdf", "compress")]);
+    config.tools = vec![compress];
+
+    let routes = routes(&config).unwrap();
+    let tool = route(&routes[&PathBuf::from(INBOX)], "scan.pdf").unwrap();
+    // A disabled tool still takes routed files
+    assert!(tool.enabled);
+    assert_eq!(tool.options["quality"], "high");
+    assert_eq!(tool.folder_path.as_deref(), Some("C:\\Compress"));
+    assert!(matches!(&tool.output_mode, OutputMode::Custom(path) if path == "C:\\Compressed"));
+}
+
+#[test]
+fn tool_without_config_works_in_the_inbox_with_defaults() {
+    let config = config(vec![rule(INBOX, "*.pdf", "rotate")]);
+    let routes = routes(&config).unwrap();
+    let tool = route(&routes[&PathBuf::from(INBOX)], "scan.pdf").unwrap();
+    assert_eq!(tool.id, "rotate");
+    assert!(tool.enabled);
+    assert_eq!(tool.folder_path.as_deref(), Some(INBOX));
+    assert!(matches!(tool.output_mode, OutputMode::Subfolder));
+}
+
+#[test]
+fn unknown_tool_is_refused() {
+    let config = config(vec![rule(INBOX, "*.pdf", "no-such-tool")]);
+    let err = routes(&config).unwrap_err();
+    assert!(matches!(&err, RoutingError::ToolNotFound(id) if id == "no-such-tool"), "{:?}", err);
+}
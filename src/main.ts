@@ -1,4 +1,8 @@
 import { createApp } from "vue";
 import App from "./App.vue";
+import MiniStatus from "./MiniStatus.vue";
 
-createApp(App).mount("#app");
+// The compact status window loads the same page with ?view=mini
+const view = new URLSearchParams(window.location.search).get("view");
+
+createApp(view === "mini" ? MiniStatus : App).mount("#app");